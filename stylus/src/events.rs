@@ -0,0 +1,156 @@
+use crate::order_execution::{Order, OrderDirection, VirtualExecutionResult};
+use alloy_sol_types::{sol, SolEvent};
+use stylus_sdk::alloy_primitives::{Address, U256};
+
+// Event definitions for the TWAMM order pool
+// Logged so indexers can track order lifecycle and virtual execution on chain
+
+sol! {
+    event OrderCreated(
+        uint256 indexed orderId,
+        address indexed owner,
+        uint8 direction,
+        uint256 sellRate,
+        uint256 startBlock,
+        uint256 endBlock
+    );
+
+    event OrderCancelled(
+        uint256 indexed orderId,
+        address indexed owner,
+        uint8 direction,
+        uint256 sellRate,
+        uint256 startBlock,
+        uint256 endBlock
+    );
+
+    event VirtualOrdersExecuted(
+        uint256 fromBlock,
+        uint256 toBlock,
+        uint256 amount0Sold,
+        uint256 amount1Sold,
+        uint256 newReserve0,
+        uint256 newReserve1
+    );
+
+    event FeesCollected(
+        address indexed recipient,
+        uint256 amount0,
+        uint256 amount1,
+        uint256 blockNumber
+    );
+
+    event KeeperRewardPaid(
+        address indexed keeper,
+        uint256 amount0,
+        uint256 amount1,
+        uint256 blockNumber
+    );
+
+    event InstantSwap(
+        address indexed owner,
+        uint8 direction,
+        uint256 amountIn,
+        uint256 amountOut,
+        uint256 blockNumber
+    );
+}
+
+pub fn order_created(order: &Order) -> OrderCreated {
+    OrderCreated {
+        orderId: order.id,
+        owner: order.owner,
+        direction: order.direction as u8,
+        sellRate: order.sell_rate,
+        startBlock: order.start_block,
+        endBlock: order.end_block,
+    }
+}
+
+pub fn order_cancelled(order: &Order) -> OrderCancelled {
+    OrderCancelled {
+        orderId: order.id,
+        owner: order.owner,
+        direction: order.direction as u8,
+        sellRate: order.sell_rate,
+        startBlock: order.start_block,
+        endBlock: order.end_block,
+    }
+}
+
+pub fn virtual_orders_executed(
+    from_block: U256,
+    to_block: U256,
+    result: &VirtualExecutionResult,
+) -> VirtualOrdersExecuted {
+    VirtualOrdersExecuted {
+        fromBlock: from_block,
+        toBlock: to_block,
+        amount0Sold: result.amount_0_sold,
+        amount1Sold: result.amount_1_sold,
+        newReserve0: result.new_reserve_0,
+        newReserve1: result.new_reserve_1,
+    }
+}
+
+/// Build a fee collection log, or None when nothing was collected
+pub fn fees_collected(
+    recipient: Address,
+    amount_0: U256,
+    amount_1: U256,
+    block_number: U256,
+) -> Option<FeesCollected> {
+    if amount_0 == U256::ZERO && amount_1 == U256::ZERO {
+        return None;
+    }
+
+    Some(FeesCollected {
+        recipient,
+        amount0: amount_0,
+        amount1: amount_1,
+        blockNumber: block_number,
+    })
+}
+
+/// Build a keeper reward log for an execution, or None when it paid no reward
+pub fn keeper_reward_paid(
+    keeper: Address,
+    result: &VirtualExecutionResult,
+    block_number: U256,
+) -> Option<KeeperRewardPaid> {
+    if result.keeper_reward_0 == U256::ZERO && result.keeper_reward_1 == U256::ZERO {
+        return None;
+    }
+
+    Some(KeeperRewardPaid {
+        keeper,
+        amount0: result.keeper_reward_0,
+        amount1: result.keeper_reward_1,
+        blockNumber: block_number,
+    })
+}
+
+pub fn instant_swap(
+    owner: Address,
+    direction: OrderDirection,
+    amount_in: U256,
+    amount_out: U256,
+    block_number: U256,
+) -> InstantSwap {
+    InstantSwap {
+        owner,
+        direction: direction as u8,
+        amountIn: amount_in,
+        amountOut: amount_out,
+        blockNumber: block_number,
+    }
+}
+
+/// Emit an event log; host logging only exists when running inside the Stylus VM
+pub fn emit<E: SolEvent>(event: E) {
+    #[cfg(target_arch = "wasm32")]
+    stylus_sdk::evm::log(event);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let _ = event;
+}
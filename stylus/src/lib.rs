@@ -3,14 +3,832 @@
 
 #![no_std]
 
-use stylus_sdk::alloy_primitives::U256;
+extern crate alloc;
+
+pub mod events;
+pub mod order_execution;
+pub mod pool_registry;
+pub mod snapshot;
+pub mod twamm_math;
+
+use crate::order_execution::{
+    LimitSide, LiquidityPolicy, Order, OrderDirection, OrderError, OrderIdScheme, OrderPool,
+    OrderStatus, OrderType, OrderView, RateRounding, VirtualExecutionResult, MAX_ORDER_HISTORY,
+};
+use crate::pool_registry::Pool;
+use crate::twamm_math::{GasModel, TWAMMath};
+use alloc::vec::Vec;
+use stylus_sdk::alloy_primitives::{Address, B256, U256, U8};
 use stylus_sdk::prelude::*;
+use stylus_sdk::storage::{StorageMap, StorageU256, StorageVec};
+use stylus_sdk::{block, msg};
 
 sol_storage! {
     #[entrypoint]
     pub struct TWAMMCalculator {
         uint256 total_calculations;
         uint256 total_volume_processed;
+
+        // Persistent order pool state
+        mapping(uint256 => StoredOrder) orders;
+        uint256[] active_order_ids;
+        uint256 next_order_id;
+        uint256 total_sell_rate_0;
+        uint256 total_sell_rate_1;
+        uint256 last_virtual_order_block;
+        uint256 order_block_interval;
+        uint256 fee_bps;
+        uint256 collected_fees_0;
+        uint256 collected_fees_1;
+        uint256 min_reserve;
+
+        // Per-token volume, since total_volume_processed mixes both tokens
+        uint256 total_volume_0;
+        uint256 total_volume_1;
+        uint8 order_id_scheme;
+        uint256 block_time_secs;
+        uint256 keeper_reward_bps;
+        uint256 keeper_gas_price;
+        uint256 max_total_sell_rate_0;
+        uint256 max_total_sell_rate_1;
+        bool saturating_execution;
+        uint256 max_blocks_per_execution;
+
+        // Isolated token pairs keyed by pool_registry::pair_id
+        mapping(bytes32 => PairStorage) pairs;
+
+        // Order duration bounds; zero until first stored, meaning the defaults
+        uint256 min_duration_blocks;
+        uint256 max_duration_blocks;
+        bool reserve_tracking;
+        uint256 stored_reserve_0;
+        uint256 stored_reserve_1;
+        uint256 reserve_tolerance_bps;
+
+        // Gas model; unset until first stored, meaning the default model
+        bool gas_model_set;
+        uint256 execution_base_gas;
+        uint256 execution_per_block_gas;
+        uint256 execution_per_order_gas;
+        uint256 gas_used_per_executed_block;
+        uint256 submit_gas;
+        uint256 execute_gas;
+        uint256 cancel_gas;
+        uint256 other_operation_gas;
+        uint256 per_complexity_gas;
+
+        // Zero until first stored, meaning the default maximum
+        uint256 max_order_block_interval;
+        uint256 max_price_move_bps;
+        bool strict_execution;
+        uint8 rate_rounding;
+        uint256 sweep_base_gas;
+        uint256 sweep_per_order_gas;
+        uint256 ramp_blocks;
+        uint256 last_execution_price;
+        uint256 max_price_deviation_bps;
+        uint8 liquidity_policy;
+        uint256 cumulative_volume_0;
+        uint256 cumulative_volume_1;
+        uint256 reserve_swap_tolerance_bps;
+        uint256 min_blocks_before_cancel;
+
+        // Emergency stop over every pool, and the address allowed to toggle it; the owner
+        // is zero until initialize is called
+        bool paused;
+        address owner;
+
+        // Zero until first stored, meaning the defaults
+        uint256 max_orders_per_owner;
+        uint256 max_orders;
+
+        // Proceeds splits keyed by order id, for the ids in beneficiary_order_ids
+        mapping(uint256 => StoredBeneficiaries) beneficiaries;
+        uint256[] beneficiary_order_ids;
+
+        // Finished orders, capped like OrderPool::history; once full the ids form a ring
+        // whose oldest entry sits at history_start
+        mapping(uint256 => StoredOrder) history_orders;
+        uint256[] history_order_ids;
+
+        // Proceeds of completed orders awaiting a claim, for the ids in unclaimed_order_ids
+        mapping(uint256 => StoredClaim) unclaimed;
+        uint256[] unclaimed_order_ids;
+
+        uint256 history_start;
+    }
+
+    // State of one pair's order pool; configuration is shared with the main pool apart
+    // from its token-denominated limits, which stay off
+    pub struct PairStorage {
+        // Zero until the pair is created
+        address token_0;
+        address token_1;
+        mapping(uint256 => StoredOrder) orders;
+        uint256[] active_order_ids;
+        uint256 next_order_id;
+        uint256 total_sell_rate_0;
+        uint256 total_sell_rate_1;
+        uint256 last_virtual_order_block;
+        uint256 collected_fees_0;
+        uint256 collected_fees_1;
+        uint256 cumulative_volume_0;
+        uint256 cumulative_volume_1;
+        uint256 last_execution_price;
+        mapping(uint256 => StoredBeneficiaries) beneficiaries;
+        uint256[] beneficiary_order_ids;
+        mapping(uint256 => StoredOrder) history_orders;
+        uint256[] history_order_ids;
+        mapping(uint256 => StoredClaim) unclaimed;
+        uint256[] unclaimed_order_ids;
+        uint256 history_start;
+    }
+
+    pub struct StoredClaim {
+        address owner;
+        uint256 amount;
+    }
+
+    // Recipients and weights, index for index
+    pub struct StoredBeneficiaries {
+        address[] recipients;
+        uint256[] weights;
+    }
+
+    pub struct StoredOrder {
+        uint256 id;
+        address owner;
+        uint8 direction;
+        uint256 sell_rate;
+        uint256 initial_amount;
+        uint256 remaining_amount;
+        uint256 start_block;
+        uint256 end_block;
+        uint256 last_virtual_order_block;
+        uint256 accumulated_out;
+        uint256 min_output_total;
+        uint8 status;
+        // Zero means the order has no price limit
+        uint256 price_limit;
+        uint8 limit_side;
+        uint256 dust_remainder;
+        // Stored inverted so orders written before pausing existed read as active
+        bool paused;
+        uint256 paused_at;
+        uint256 quality_weighted_sum;
+        uint256 quality_weight;
+        // Zero means the order streams at its fixed sell rate
+        uint256 target_price;
+        uint256 benchmark_out;
+        uint256 matched_volume;
+        uint256 curve_volume;
+        // Zero, LongTerm, for every open order; finished Instant orders only reach the history
+        uint8 order_type;
+    }
+}
+
+/// Read a persisted order
+fn load_stored_order(stored: &StoredOrder) -> Order {
+    Order {
+        id: stored.id.get(),
+        owner: stored.owner.get(),
+        order_type: OrderType::try_from(stored.order_type.get().to::<u8>())
+            .unwrap_or(OrderType::LongTerm),
+        direction: if stored.direction.get() == U8::from(1u8) {
+            OrderDirection::SellToken1
+        } else {
+            OrderDirection::SellToken0
+        },
+        sell_rate: stored.sell_rate.get(),
+        initial_amount: stored.initial_amount.get(),
+        remaining_amount: stored.remaining_amount.get(),
+        dust_remainder: stored.dust_remainder.get(),
+        start_block: stored.start_block.get(),
+        end_block: stored.end_block.get(),
+        last_virtual_order_block: stored.last_virtual_order_block.get(),
+        accumulated_out: stored.accumulated_out.get(),
+        min_output_total: stored.min_output_total.get(),
+        status: OrderStatus::try_from(stored.status.get().to::<u8>())
+            .unwrap_or(OrderStatus::Active),
+        price_limit: Some(stored.price_limit.get()).filter(|limit| *limit > U256::ZERO),
+        limit_side: if stored.limit_side.get() == U8::from(LimitSide::Below as u8) {
+            LimitSide::Below
+        } else {
+            LimitSide::Above
+        },
+        active: !stored.paused.get(),
+        paused_at: stored.paused_at.get(),
+        quality_weighted_sum: stored.quality_weighted_sum.get(),
+        quality_weight: stored.quality_weight.get(),
+        target_price: Some(stored.target_price.get()).filter(|target| *target > U256::ZERO),
+        benchmark_out: stored.benchmark_out.get(),
+        matched_volume: stored.matched_volume.get(),
+        curve_volume: stored.curve_volume.get(),
+    }
+}
+
+/// Persist an order
+fn write_stored_order(stored: &mut StoredOrder, order: &Order) {
+    stored.id.set(order.id);
+    stored.owner.set(order.owner);
+    stored.direction.set(U8::from(order.direction as u8));
+    stored.sell_rate.set(order.sell_rate);
+    stored.initial_amount.set(order.initial_amount);
+    stored.remaining_amount.set(order.remaining_amount);
+    stored.start_block.set(order.start_block);
+    stored.end_block.set(order.end_block);
+    stored
+        .last_virtual_order_block
+        .set(order.last_virtual_order_block);
+    stored.accumulated_out.set(order.accumulated_out);
+    stored.min_output_total.set(order.min_output_total);
+    stored.status.set(U8::from(order.status as u8));
+    stored
+        .price_limit
+        .set(order.price_limit.unwrap_or_default());
+    stored.limit_side.set(U8::from(order.limit_side as u8));
+    stored.dust_remainder.set(order.dust_remainder);
+    stored.paused.set(!order.active);
+    stored.paused_at.set(order.paused_at);
+    stored.quality_weighted_sum.set(order.quality_weighted_sum);
+    stored.quality_weight.set(order.quality_weight);
+    stored
+        .target_price
+        .set(order.target_price.unwrap_or_default());
+    stored.benchmark_out.set(order.benchmark_out);
+    stored.matched_volume.set(order.matched_volume);
+    stored.curve_volume.set(order.curve_volume);
+    stored.order_type.set(U8::from(order.order_type as u8));
+}
+
+/// Read a persisted proceeds split
+fn load_stored_beneficiaries(stored: &StoredBeneficiaries) -> Vec<(Address, U256)> {
+    (0..stored.recipients.len())
+        .map(|index| {
+            (
+                stored.recipients.get(index).unwrap_or_default(),
+                stored.weights.get(index).unwrap_or_default(),
+            )
+        })
+        .collect()
+}
+
+/// Persist a proceeds split
+fn write_stored_beneficiaries(stored: &mut StoredBeneficiaries, beneficiaries: &[(Address, U256)]) {
+    stored.recipients.erase();
+    stored.weights.erase();
+    for (recipient, weight) in beneficiaries {
+        stored.recipients.push(*recipient);
+        stored.weights.push(*weight);
+    }
+}
+
+/// Bring a stored id list in line with ids, writing only the positions that change
+fn sync_id_list(stored: &mut StorageVec<StorageU256>, ids: &[U256]) {
+    for (index, &id) in ids.iter().enumerate() {
+        if index >= stored.len() {
+            stored.push(id);
+        } else if stored.get(index) != Some(id) {
+            if let Some(mut slot) = stored.setter(index) {
+                slot.set(id);
+            }
+        }
+    }
+    while stored.len() > ids.len() {
+        stored.pop();
+    }
+}
+
+/// Persist orders, writing only the ones that changed and clearing the removed ones
+fn sync_orders(
+    stored_ids: &mut StorageVec<StorageU256>,
+    stored_orders: &mut StorageMap<U256, StoredOrder>,
+    orders: &[Order],
+) {
+    for index in 0..stored_ids.len() {
+        let order_id = stored_ids.get(index).unwrap_or_default();
+        if !orders.iter().any(|order| order.id == order_id) {
+            stored_orders.delete(order_id);
+        }
+    }
+
+    sync_orders_by_id(stored_orders, orders);
+
+    let order_ids: Vec<U256> = orders.iter().map(|order| order.id).collect();
+    sync_id_list(stored_ids, &order_ids);
+}
+
+/// Read the stored history ids, oldest first
+fn load_history_ids(stored_ids: &StorageVec<StorageU256>, start: &StorageU256) -> Vec<U256> {
+    let len = stored_ids.len();
+    let start = start.get().to::<usize>();
+    (0..len)
+        .map(|offset| stored_ids.get((start + offset) % len).unwrap_or_default())
+        .collect()
+}
+
+/// Persist the history ring
+/// The history only drops its oldest entries and appends new ones, so each new id takes the
+/// slot of the one it evicted; any other change rebuilds the ring from the start
+fn sync_history(
+    stored_ids: &mut StorageVec<StorageU256>,
+    start: &mut StorageU256,
+    stored_orders: &mut StorageMap<U256, StoredOrder>,
+    history: &[Order],
+) {
+    let mut ring_ids = load_history_ids(stored_ids, start);
+    let history_ids: Vec<U256> = history.iter().map(|order| order.id).collect();
+
+    for &order_id in &ring_ids {
+        if !history_ids.contains(&order_id) {
+            stored_orders.delete(order_id);
+        }
+    }
+    sync_orders_by_id(stored_orders, history);
+
+    let appended: Vec<U256> = history_ids
+        .iter()
+        .filter(|order_id| !ring_ids.contains(order_id))
+        .copied()
+        .collect();
+    ring_ids.extend_from_slice(&appended);
+    let evicted = ring_ids.len().saturating_sub(history_ids.len());
+    if history_ids.len() != ring_ids.len().min(MAX_ORDER_HISTORY)
+        || ring_ids[evicted..] != history_ids[..]
+    {
+        sync_id_list(stored_ids, &history_ids);
+        start.set(U256::ZERO);
+        return;
+    }
+
+    let mut first = start.get().to::<usize>();
+    for order_id in appended {
+        if stored_ids.len() < MAX_ORDER_HISTORY {
+            stored_ids.push(order_id);
+        } else {
+            if let Some(mut slot) = stored_ids.setter(first) {
+                slot.set(order_id);
+            }
+            first = (first + 1) % MAX_ORDER_HISTORY;
+        }
+    }
+    if start.get() != U256::from(first) {
+        start.set(U256::from(first));
+    }
+}
+
+/// Write the orders whose stored copy differs
+fn sync_orders_by_id(stored_orders: &mut StorageMap<U256, StoredOrder>, orders: &[Order]) {
+    for order in orders {
+        let mut stored = stored_orders.setter(order.id);
+        if load_stored_order(&stored) != *order {
+            write_stored_order(&mut stored, order);
+        }
+    }
+}
+
+/// Persist proceeds splits, writing only the ones that changed and clearing the removed ones
+fn sync_beneficiaries(
+    stored_ids: &mut StorageVec<StorageU256>,
+    stored: &mut StorageMap<U256, StoredBeneficiaries>,
+    order_beneficiaries: &[(U256, Vec<(Address, U256)>)],
+) {
+    for index in 0..stored_ids.len() {
+        let order_id = stored_ids.get(index).unwrap_or_default();
+        if !order_beneficiaries.iter().any(|(id, _)| *id == order_id) {
+            stored.delete(order_id);
+        }
+    }
+
+    for (order_id, beneficiaries) in order_beneficiaries {
+        let mut split = stored.setter(*order_id);
+        if load_stored_beneficiaries(&split) != *beneficiaries {
+            write_stored_beneficiaries(&mut split, beneficiaries);
+        }
+    }
+
+    let order_ids: Vec<U256> = order_beneficiaries.iter().map(|(id, _)| *id).collect();
+    sync_id_list(stored_ids, &order_ids);
+}
+
+/// Persist unclaimed proceeds, writing only the ones that changed and clearing the removed ones
+fn sync_unclaimed(
+    stored_ids: &mut StorageVec<StorageU256>,
+    stored: &mut StorageMap<U256, StoredClaim>,
+    unclaimed: &[(U256, Address, U256)],
+) {
+    for index in 0..stored_ids.len() {
+        let order_id = stored_ids.get(index).unwrap_or_default();
+        if !unclaimed.iter().any(|(id, _, _)| *id == order_id) {
+            stored.delete(order_id);
+        }
+    }
+
+    for (order_id, owner, amount) in unclaimed {
+        let mut claim = stored.setter(*order_id);
+        if claim.owner.get() != *owner {
+            claim.owner.set(*owner);
+        }
+        if claim.amount.get() != *amount {
+            claim.amount.set(*amount);
+        }
+    }
+
+    let order_ids: Vec<U256> = unclaimed.iter().map(|(id, _, _)| *id).collect();
+    sync_id_list(stored_ids, &order_ids);
+}
+
+/// Parse an external direction argument (0 sells token0, 1 sells token1)
+fn parse_direction(direction: u8) -> Result<OrderDirection, Vec<u8>> {
+    match direction {
+        0 => Ok(OrderDirection::SellToken0),
+        1 => Ok(OrderDirection::SellToken1),
+        _ => Err(b"Invalid direction".to_vec()),
+    }
+}
+
+/// Flatten an order for get_order and get_pair_order
+fn order_summary(order: &Order) -> (Address, u8, U256, U256, U256, U256, U256) {
+    (
+        order.owner,
+        order.direction as u8,
+        order.sell_rate,
+        order.remaining_amount,
+        order.start_block,
+        order.end_block,
+        order.accumulated_out,
+    )
+}
+
+/// OrderView as an ABI tuple, fields in declaration order
+type OrderViewTuple = (
+    U256,
+    Address,
+    u8,
+    u8,
+    U256,
+    U256,
+    U256,
+    U256,
+    U256,
+    U256,
+    U256,
+    U256,
+    u8,
+    U256,
+    u8,
+    bool,
+    U256,
+);
+
+/// Encode an order view for get_orders_by_owner and get_pair_orders_by_owner
+fn order_view_tuple(view: OrderView) -> OrderViewTuple {
+    (
+        view.id,
+        view.owner,
+        view.order_type,
+        view.direction,
+        view.sell_rate,
+        view.initial_amount,
+        view.remaining_amount,
+        view.dust_remainder,
+        view.start_block,
+        view.end_block,
+        view.accumulated_out,
+        view.min_output_total,
+        view.status,
+        view.price_limit,
+        view.limit_side,
+        view.paused,
+        view.target_price,
+    )
+}
+
+impl TWAMMCalculator {
+    /// Load the persisted order pool into memory
+    fn load_order_pool(&self) -> OrderPool {
+        let mut pool = OrderPool::default();
+
+        for index in 0..self.active_order_ids.len() {
+            let order_id = self.active_order_ids.get(index).unwrap_or_default();
+            let stored = self.orders.get(order_id);
+
+            pool.orders.push(load_stored_order(&stored));
+        }
+        for order_id in load_history_ids(&self.history_order_ids, &self.history_start) {
+            pool.history
+                .push(load_stored_order(&self.history_orders.get(order_id)));
+        }
+        for index in 0..self.unclaimed_order_ids.len() {
+            let order_id = self.unclaimed_order_ids.get(index).unwrap_or_default();
+            let claim = self.unclaimed.get(order_id);
+            pool.unclaimed_proceeds
+                .push((order_id, claim.owner.get(), claim.amount.get()));
+        }
+
+        for index in 0..self.beneficiary_order_ids.len() {
+            let order_id = self.beneficiary_order_ids.get(index).unwrap_or_default();
+            let beneficiaries = load_stored_beneficiaries(&self.beneficiaries.get(order_id));
+            pool.order_beneficiaries.push((order_id, beneficiaries));
+        }
+
+        // Unset storage reads as zero, so keep the in-memory defaults until first write
+        if self.next_order_id.get() > U256::ZERO {
+            pool.next_order_id = self.next_order_id.get();
+        }
+        pool.total_sell_rate_0 = self.total_sell_rate_0.get();
+        pool.total_sell_rate_1 = self.total_sell_rate_1.get();
+        pool.virtual_order_state.last_virtual_order_block = self.last_virtual_order_block.get();
+        pool.collected_fees_0 = self.collected_fees_0.get();
+        pool.collected_fees_1 = self.collected_fees_1.get();
+        pool.min_reserve = self.min_reserve.get();
+        pool.keeper_reward_bps = self.keeper_reward_bps.get();
+        pool.keeper_gas_price = self.keeper_gas_price.get();
+        pool.max_total_sell_rate_0 = self.max_total_sell_rate_0.get();
+        pool.max_total_sell_rate_1 = self.max_total_sell_rate_1.get();
+        pool.reserve_tracking = self.reserve_tracking.get();
+        pool.stored_reserve_0 = self.stored_reserve_0.get();
+        pool.stored_reserve_1 = self.stored_reserve_1.get();
+        pool.cumulative_volume_0 = self.cumulative_volume_0.get();
+        pool.cumulative_volume_1 = self.cumulative_volume_1.get();
+        pool.virtual_order_state.last_execution_price = self.last_execution_price.get();
+        self.load_pool_config(&mut pool);
+
+        pool
+    }
+
+    /// Apply the configuration shared by the main pool and every pair
+    /// Token-denominated limits and the keeper reward are the main pool's own
+    fn load_pool_config(&self, pool: &mut OrderPool) {
+        // Unset storage reads as zero, so keep the in-memory defaults until first write
+        if self.order_block_interval.get() > U256::ZERO {
+            pool.virtual_order_state.order_block_interval = self.order_block_interval.get();
+        }
+        pool.fee_bps = self.fee_bps.get();
+        pool.max_blocks_per_execution = self.max_blocks_per_execution.get();
+        if self.block_time_secs.get() > U256::ZERO {
+            pool.block_time_secs = self.block_time_secs.get();
+        }
+        pool.reserve_tolerance_bps = self.reserve_tolerance_bps.get();
+        if self.max_orders_per_owner.get() > U256::ZERO {
+            pool.max_orders_per_owner = self.max_orders_per_owner.get().to::<usize>();
+        }
+        if self.max_orders.get() > U256::ZERO {
+            pool.max_orders = self.max_orders.get().to::<usize>();
+        }
+        if self.max_duration_blocks.get() > U256::ZERO {
+            pool.min_duration_blocks = self.min_duration_blocks.get();
+            pool.max_duration_blocks = self.max_duration_blocks.get();
+        }
+        if self.max_order_block_interval.get() > U256::ZERO {
+            pool.max_order_block_interval = self.max_order_block_interval.get();
+        }
+        pool.max_price_move_bps = self.max_price_move_bps.get();
+        pool.strict_execution = self.strict_execution.get();
+        pool.ramp_blocks = self.ramp_blocks.get();
+        pool.reserve_swap_tolerance_bps = self.reserve_swap_tolerance_bps.get();
+        pool.min_blocks_before_cancel = self.min_blocks_before_cancel.get();
+        pool.paused = self.paused.get();
+        pool.virtual_order_state.max_price_deviation_bps = self.max_price_deviation_bps.get();
+        if self.gas_model_set.get() {
+            pool.gas_model = GasModel {
+                execution_base_gas: self.execution_base_gas.get(),
+                execution_per_block_gas: self.execution_per_block_gas.get(),
+                execution_per_order_gas: self.execution_per_order_gas.get(),
+                gas_used_per_executed_block: self.gas_used_per_executed_block.get(),
+                submit_gas: self.submit_gas.get(),
+                execute_gas: self.execute_gas.get(),
+                cancel_gas: self.cancel_gas.get(),
+                other_operation_gas: self.other_operation_gas.get(),
+                per_complexity_gas: self.per_complexity_gas.get(),
+                sweep_base_gas: self.sweep_base_gas.get(),
+                sweep_per_order_gas: self.sweep_per_order_gas.get(),
+            };
+        }
+        if self.order_id_scheme.get() == U8::from(OrderIdScheme::Hashed as u8) {
+            pool.order_id_scheme = OrderIdScheme::Hashed;
+        }
+        if self.rate_rounding.get() == U8::from(RateRounding::Ceil as u8) {
+            pool.rate_rounding = RateRounding::Ceil;
+        }
+        // saturating_execution predates liquidity_policy and still stands for Throttle
+        if self.liquidity_policy.get() == U8::from(LiquidityPolicy::Cancel as u8) {
+            pool.liquidity_policy = LiquidityPolicy::Cancel;
+        } else if self.saturating_execution.get() {
+            pool.liquidity_policy = LiquidityPolicy::Throttle;
+        }
+    }
+
+    /// Write the in-memory order pool back to storage
+    fn store_order_pool(&mut self, pool: &OrderPool) {
+        // Only entries that were added, changed or removed are written
+        sync_orders(&mut self.active_order_ids, &mut self.orders, &pool.orders);
+        sync_history(
+            &mut self.history_order_ids,
+            &mut self.history_start,
+            &mut self.history_orders,
+            &pool.history,
+        );
+        sync_unclaimed(
+            &mut self.unclaimed_order_ids,
+            &mut self.unclaimed,
+            &pool.unclaimed_proceeds,
+        );
+        sync_beneficiaries(
+            &mut self.beneficiary_order_ids,
+            &mut self.beneficiaries,
+            &pool.order_beneficiaries,
+        );
+
+        self.next_order_id.set(pool.next_order_id);
+        self.total_sell_rate_0.set(pool.total_sell_rate_0);
+        self.total_sell_rate_1.set(pool.total_sell_rate_1);
+        self.last_virtual_order_block
+            .set(pool.virtual_order_state.last_virtual_order_block);
+        self.order_block_interval
+            .set(pool.virtual_order_state.order_block_interval);
+        self.fee_bps.set(pool.fee_bps);
+        self.collected_fees_0.set(pool.collected_fees_0);
+        self.collected_fees_1.set(pool.collected_fees_1);
+        self.min_reserve.set(pool.min_reserve);
+        self.block_time_secs.set(pool.block_time_secs);
+        self.keeper_reward_bps.set(pool.keeper_reward_bps);
+        self.keeper_gas_price.set(pool.keeper_gas_price);
+        self.max_total_sell_rate_0.set(pool.max_total_sell_rate_0);
+        self.max_total_sell_rate_1.set(pool.max_total_sell_rate_1);
+        self.saturating_execution
+            .set(pool.liquidity_policy == LiquidityPolicy::Throttle);
+        self.liquidity_policy
+            .set(U8::from(pool.liquidity_policy as u8));
+        self.max_blocks_per_execution
+            .set(pool.max_blocks_per_execution);
+        self.min_duration_blocks.set(pool.min_duration_blocks);
+        self.max_duration_blocks.set(pool.max_duration_blocks);
+        self.reserve_tracking.set(pool.reserve_tracking);
+        self.stored_reserve_0.set(pool.stored_reserve_0);
+        self.stored_reserve_1.set(pool.stored_reserve_1);
+        self.reserve_tolerance_bps.set(pool.reserve_tolerance_bps);
+        self.gas_model_set.set(true);
+        self.execution_base_gas
+            .set(pool.gas_model.execution_base_gas);
+        self.execution_per_block_gas
+            .set(pool.gas_model.execution_per_block_gas);
+        self.execution_per_order_gas
+            .set(pool.gas_model.execution_per_order_gas);
+        self.gas_used_per_executed_block
+            .set(pool.gas_model.gas_used_per_executed_block);
+        self.submit_gas.set(pool.gas_model.submit_gas);
+        self.execute_gas.set(pool.gas_model.execute_gas);
+        self.cancel_gas.set(pool.gas_model.cancel_gas);
+        self.other_operation_gas
+            .set(pool.gas_model.other_operation_gas);
+        self.per_complexity_gas
+            .set(pool.gas_model.per_complexity_gas);
+        self.sweep_base_gas.set(pool.gas_model.sweep_base_gas);
+        self.sweep_per_order_gas
+            .set(pool.gas_model.sweep_per_order_gas);
+        self.max_order_block_interval
+            .set(pool.max_order_block_interval);
+        self.max_price_move_bps.set(pool.max_price_move_bps);
+        self.strict_execution.set(pool.strict_execution);
+        self.ramp_blocks.set(pool.ramp_blocks);
+        self.cumulative_volume_0.set(pool.cumulative_volume_0);
+        self.cumulative_volume_1.set(pool.cumulative_volume_1);
+        self.reserve_swap_tolerance_bps
+            .set(pool.reserve_swap_tolerance_bps);
+        self.min_blocks_before_cancel
+            .set(pool.min_blocks_before_cancel);
+        self.paused.set(pool.paused);
+        self.last_execution_price
+            .set(pool.virtual_order_state.last_execution_price);
+        self.max_price_deviation_bps
+            .set(pool.virtual_order_state.max_price_deviation_bps);
+        self.order_id_scheme
+            .set(U8::from(pool.order_id_scheme as u8));
+        self.rate_rounding.set(U8::from(pool.rate_rounding as u8));
+        self.max_orders_per_owner
+            .set(U256::from(pool.max_orders_per_owner));
+        self.max_orders.set(U256::from(pool.max_orders));
+    }
+
+    /// Load a pair's persisted order pool into memory
+    fn load_pair_pool(&self, pair_id: B256) -> Result<OrderPool, Vec<u8>> {
+        let pair = self.pairs.get(pair_id);
+        if pair.token_0.get() == Address::ZERO {
+            return Err(b"Pool not found".to_vec());
+        }
+
+        let mut pool = OrderPool::default();
+        for index in 0..pair.active_order_ids.len() {
+            let order_id = pair.active_order_ids.get(index).unwrap_or_default();
+            pool.orders
+                .push(load_stored_order(&pair.orders.get(order_id)));
+        }
+        for order_id in load_history_ids(&pair.history_order_ids, &pair.history_start) {
+            pool.history
+                .push(load_stored_order(&pair.history_orders.get(order_id)));
+        }
+        for index in 0..pair.unclaimed_order_ids.len() {
+            let order_id = pair.unclaimed_order_ids.get(index).unwrap_or_default();
+            let claim = pair.unclaimed.get(order_id);
+            pool.unclaimed_proceeds
+                .push((order_id, claim.owner.get(), claim.amount.get()));
+        }
+        for index in 0..pair.beneficiary_order_ids.len() {
+            let order_id = pair.beneficiary_order_ids.get(index).unwrap_or_default();
+            let beneficiaries = load_stored_beneficiaries(&pair.beneficiaries.get(order_id));
+            pool.order_beneficiaries.push((order_id, beneficiaries));
+        }
+
+        if pair.next_order_id.get() > U256::ZERO {
+            pool.next_order_id = pair.next_order_id.get();
+        }
+        pool.total_sell_rate_0 = pair.total_sell_rate_0.get();
+        pool.total_sell_rate_1 = pair.total_sell_rate_1.get();
+        pool.virtual_order_state.last_virtual_order_block = pair.last_virtual_order_block.get();
+        pool.collected_fees_0 = pair.collected_fees_0.get();
+        pool.collected_fees_1 = pair.collected_fees_1.get();
+        pool.cumulative_volume_0 = pair.cumulative_volume_0.get();
+        pool.cumulative_volume_1 = pair.cumulative_volume_1.get();
+        pool.virtual_order_state.last_execution_price = pair.last_execution_price.get();
+        self.load_pool_config(&mut pool);
+
+        Ok(pool)
+    }
+
+    /// Write a pair's in-memory order pool back to storage
+    fn store_pair_pool(&mut self, pair_id: B256, pool: &OrderPool) {
+        let mut guard = self.pairs.setter(pair_id);
+        let pair = &mut *guard;
+
+        sync_orders(&mut pair.active_order_ids, &mut pair.orders, &pool.orders);
+        sync_history(
+            &mut pair.history_order_ids,
+            &mut pair.history_start,
+            &mut pair.history_orders,
+            &pool.history,
+        );
+        sync_unclaimed(
+            &mut pair.unclaimed_order_ids,
+            &mut pair.unclaimed,
+            &pool.unclaimed_proceeds,
+        );
+        sync_beneficiaries(
+            &mut pair.beneficiary_order_ids,
+            &mut pair.beneficiaries,
+            &pool.order_beneficiaries,
+        );
+
+        pair.next_order_id.set(pool.next_order_id);
+        pair.total_sell_rate_0.set(pool.total_sell_rate_0);
+        pair.total_sell_rate_1.set(pool.total_sell_rate_1);
+        pair.last_virtual_order_block
+            .set(pool.virtual_order_state.last_virtual_order_block);
+        pair.collected_fees_0.set(pool.collected_fees_0);
+        pair.collected_fees_1.set(pool.collected_fees_1);
+        pair.cumulative_volume_0.set(pool.cumulative_volume_0);
+        pair.cumulative_volume_1.set(pool.cumulative_volume_1);
+        pair.last_execution_price
+            .set(pool.virtual_order_state.last_execution_price);
+    }
+
+    /// Reject callers other than the contract owner
+    fn only_owner(&self) -> Result<(), Vec<u8>> {
+        if msg::sender() != self.owner.get() {
+            return Err(b"Not owner".to_vec());
+        }
+        Ok(())
+    }
+
+    /// Apply an owner-only configuration change to the main pool and persist it
+    /// Pairs pick up the shared settings the next time they are loaded
+    fn configure(
+        &mut self,
+        apply: impl FnOnce(&mut OrderPool) -> Result<(), OrderError>,
+    ) -> Result<(), Vec<u8>> {
+        self.only_owner()?;
+        let mut pool = self.load_order_pool();
+        apply(&mut pool)?;
+        self.store_order_pool(&pool);
+        Ok(())
+    }
+
+    /// Add an execution to the statistics and log the caller's keeper reward
+    fn record_execution(&mut self, result: &VirtualExecutionResult) {
+        if let Some(event) =
+            events::keeper_reward_paid(msg::sender(), result, U256::from(block::number()))
+        {
+            events::emit(event);
+        }
+
+        self.total_calculations
+            .set(self.total_calculations.get() + U256::from(1u64));
+        self.total_volume_processed
+            .set(self.total_volume_processed.get() + result.amount_0_sold + result.amount_1_sold);
+        self.total_volume_0
+            .set(self.total_volume_0.get() + result.amount_0_sold);
+        self.total_volume_1
+            .set(self.total_volume_1.get() + result.amount_1_sold);
     }
 }
 
@@ -44,11 +862,15 @@ impl TWAMMCalculator {
         // Update statistics
         self.total_calculations += U256::from(1u64);
         self.total_volume_processed += amount_0_out + amount_1_out;
+        self.total_volume_0
+            .set(self.total_volume_0.get() + amount_0_out);
+        self.total_volume_1
+            .set(self.total_volume_1.get() + amount_1_out);
 
         Ok((amount_0_out, amount_1_out))
     }
 
-    /// Calculate price impact
+    /// Estimate price impact as trade_size relative to reserve_in, in basis points
     pub fn calculate_price_impact(
         trade_size: U256,
         reserve_in: U256,
@@ -59,8 +881,8 @@ impl TWAMMCalculator {
         }
 
         // Simple price impact calculation
-        let impact = (trade_size * U256::from(10000u64)) / reserve_in;
-        Ok(impact)
+        TWAMMath::mul_div(trade_size, U256::from(10000u64), reserve_in)
+            .map_err(|_| b"Impact calculation overflow".to_vec())
     }
 
     /// Get total calculations
@@ -69,13 +891,532 @@ impl TWAMMCalculator {
     }
 
     /// Get total volume processed
+    /// This sums token0 and token1 amounts together; use get_volume_breakdown for per-token volume
     pub fn get_total_volume_processed(&self) -> U256 {
         self.total_volume_processed
     }
 
+    /// Get volume processed per token as (token0_volume, token1_volume)
+    pub fn get_volume_breakdown(&self) -> (U256, U256) {
+        (self.total_volume_0.get(), self.total_volume_1.get())
+    }
+
+    /// Submit a long-term order for the caller (direction 0 sells token0, 1 sells token1)
+    /// Only sell_rate * duration_blocks is streamed; the truncated dust is refunded at creation
+    pub fn submit_order(
+        &mut self,
+        direction: u8,
+        sell_amount: U256,
+        duration_blocks: U256,
+        min_output_total: U256,
+    ) -> Result<U256, Vec<u8>> {
+        let direction = parse_direction(direction)?;
+
+        let mut pool = self.load_order_pool();
+        let order_id = pool.create_long_term_order(
+            msg::sender(),
+            direction,
+            sell_amount,
+            duration_blocks,
+            U256::from(block::number()),
+            min_output_total,
+        )?;
+        self.store_order_pool(&pool);
+
+        Ok(order_id)
+    }
+
+    /// Submit a long-term order for the caller that starts streaming at start_block
+    pub fn submit_scheduled_order(
+        &mut self,
+        direction: u8,
+        sell_amount: U256,
+        duration_blocks: U256,
+        start_block: U256,
+        min_output_total: U256,
+    ) -> Result<U256, Vec<u8>> {
+        let direction = parse_direction(direction)?;
+
+        let mut pool = self.load_order_pool();
+        let order_id = pool.create_scheduled_order(
+            msg::sender(),
+            direction,
+            sell_amount,
+            duration_blocks,
+            (U256::from(block::number()), start_block),
+            min_output_total,
+        )?;
+        self.store_order_pool(&pool);
+
+        Ok(order_id)
+    }
+
+    /// Cancel one of the caller's orders, returning its unsold amount
+    pub fn cancel_order(&mut self, order_id: U256) -> Result<U256, Vec<u8>> {
+        let mut pool = self.load_order_pool();
+        let order = pool.cancel_order(order_id, msg::sender(), U256::from(block::number()))?;
+        self.store_order_pool(&pool);
+
+        Ok(order.remaining_amount)
+    }
+
+    /// Pause one of the caller's orders from the last executed block
+    pub fn pause_order(&mut self, order_id: U256) -> Result<(), Vec<u8>> {
+        let mut pool = self.load_order_pool();
+        pool.pause_order(order_id, msg::sender())?;
+        self.store_order_pool(&pool);
+
+        Ok(())
+    }
+
+    /// Resume one of the caller's paused orders, extending it by the paused blocks
+    pub fn resume_order(&mut self, order_id: U256) -> Result<(), Vec<u8>> {
+        let mut pool = self.load_order_pool();
+        pool.resume_order(order_id, msg::sender())?;
+        self.store_order_pool(&pool);
+
+        Ok(())
+    }
+
+    /// Split the proceeds of one of the caller's orders between recipients by weight
+    /// weights pairs up with recipients index for index
+    pub fn set_order_beneficiaries(
+        &mut self,
+        order_id: U256,
+        recipients: Vec<Address>,
+        weights: Vec<U256>,
+    ) -> Result<(), Vec<u8>> {
+        if recipients.len() != weights.len() {
+            return Err(b"Length mismatch".to_vec());
+        }
+
+        let mut pool = self.load_order_pool();
+        pool.set_order_beneficiaries(
+            order_id,
+            msg::sender(),
+            recipients.into_iter().zip(weights).collect(),
+        )?;
+        self.store_order_pool(&pool);
+
+        Ok(())
+    }
+
+    /// Get an order's beneficiaries as (recipients, weights); both empty when the owner is
+    /// paid directly
+    pub fn get_order_beneficiaries(&self, order_id: U256) -> (Vec<Address>, Vec<U256>) {
+        self.load_order_pool()
+            .get_order_beneficiaries(order_id)
+            .unwrap_or_default()
+            .into_iter()
+            .unzip()
+    }
+
+    /// Claim the accumulated proceeds of one of the caller's orders
+    /// Returns the payouts as (recipients, amounts)
+    pub fn claim_proceeds(&mut self, order_id: U256) -> Result<(Vec<Address>, Vec<U256>), Vec<u8>> {
+        let mut pool = self.load_order_pool();
+        let payouts = pool.claim_proceeds(order_id, msg::sender())?;
+        self.store_order_pool(&pool);
+
+        Ok(payouts.into_iter().unzip())
+    }
+
+    /// Execute virtual orders up to the current block against the given reserves
+    /// Returns the new reserves (reserve_0, reserve_1)
+    pub fn execute(&mut self, reserve_0: U256, reserve_1: U256) -> Result<(U256, U256), Vec<u8>> {
+        let mut pool = self.load_order_pool();
+        let result =
+            pool.execute_virtual_orders(U256::from(block::number()), reserve_0, reserve_1)?;
+        self.store_order_pool(&pool);
+        self.record_execution(&result);
+
+        Ok((result.new_reserve_0, result.new_reserve_1))
+    }
+
+    /// Get an open or recently finished order as (owner, direction, sell_rate,
+    /// remaining_amount, start_block, end_block, accumulated_out)
+    pub fn get_order(
+        &self,
+        order_id: U256,
+    ) -> Result<(Address, u8, U256, U256, U256, U256, U256), Vec<u8>> {
+        let order = self
+            .load_order_pool()
+            .get_order(order_id)
+            .ok_or(b"Order not found".to_vec())?;
+
+        Ok(order_summary(&order))
+    }
+
+    /// Get every open order of owner with all its fields; see OrderView for the encoding
+    pub fn get_orders_by_owner(&self, owner: Address) -> Vec<OrderViewTuple> {
+        self.load_order_pool()
+            .get_order_views_by_owner(owner)
+            .into_iter()
+            .map(order_view_tuple)
+            .collect()
+    }
+
+    /// Register a token pair with its own order pool, returning the pair id
+    /// The lower token address is token0 for the pair's order directions
+    pub fn create_pair(&mut self, token_a: Address, token_b: Address) -> Result<B256, Vec<u8>> {
+        let pool = Pool::new(token_a, token_b)?;
+
+        let mut pair = self.pairs.setter(pool.id);
+        if pair.token_0.get() != Address::ZERO {
+            return Err(b"Pool exists".to_vec());
+        }
+        pair.token_0.set(pool.token_0);
+        pair.token_1.set(pool.token_1);
+
+        Ok(pool.id)
+    }
+
+    /// Submit a long-term order for the caller in the given pair
+    pub fn submit_pair_order(
+        &mut self,
+        pair_id: B256,
+        direction: u8,
+        sell_amount: U256,
+        duration_blocks: U256,
+        min_output_total: U256,
+    ) -> Result<U256, Vec<u8>> {
+        let direction = parse_direction(direction)?;
+
+        let mut pool = self.load_pair_pool(pair_id)?;
+        let order_id = pool.create_long_term_order(
+            msg::sender(),
+            direction,
+            sell_amount,
+            duration_blocks,
+            U256::from(block::number()),
+            min_output_total,
+        )?;
+        self.store_pair_pool(pair_id, &pool);
+
+        Ok(order_id)
+    }
+
+    /// Cancel one of the caller's orders in the given pair, returning its unsold amount
+    pub fn cancel_pair_order(&mut self, pair_id: B256, order_id: U256) -> Result<U256, Vec<u8>> {
+        let mut pool = self.load_pair_pool(pair_id)?;
+        let order = pool.cancel_order(order_id, msg::sender(), U256::from(block::number()))?;
+        self.store_pair_pool(pair_id, &pool);
+
+        Ok(order.remaining_amount)
+    }
+
+    /// Execute the pair's virtual orders up to the current block against its reserves
+    /// Returns the new reserves (reserve_0, reserve_1)
+    pub fn execute_pair(
+        &mut self,
+        pair_id: B256,
+        reserve_0: U256,
+        reserve_1: U256,
+    ) -> Result<(U256, U256), Vec<u8>> {
+        let mut pool = self.load_pair_pool(pair_id)?;
+        let result =
+            pool.execute_virtual_orders(U256::from(block::number()), reserve_0, reserve_1)?;
+        self.store_pair_pool(pair_id, &pool);
+        self.record_execution(&result);
+
+        Ok((result.new_reserve_0, result.new_reserve_1))
+    }
+
+    /// Get an open or recently finished order in the given pair, in the same shape as get_order
+    pub fn get_pair_order(
+        &self,
+        pair_id: B256,
+        order_id: U256,
+    ) -> Result<(Address, u8, U256, U256, U256, U256, U256), Vec<u8>> {
+        let order = self
+            .load_pair_pool(pair_id)?
+            .get_order(order_id)
+            .ok_or(b"Order not found".to_vec())?;
+
+        Ok(order_summary(&order))
+    }
+
+    /// Get every open order of owner in the given pair; see OrderView for the encoding
+    pub fn get_pair_orders_by_owner(
+        &self,
+        pair_id: B256,
+        owner: Address,
+    ) -> Result<Vec<OrderViewTuple>, Vec<u8>> {
+        Ok(self
+            .load_pair_pool(pair_id)?
+            .get_order_views_by_owner(owner)
+            .into_iter()
+            .map(order_view_tuple)
+            .collect())
+    }
+
+    /// Make the caller the contract owner; only possible once
+    pub fn initialize(&mut self) -> Result<(), Vec<u8>> {
+        if self.owner.get() != Address::ZERO {
+            return Err(b"Already initialized".to_vec());
+        }
+
+        self.owner.set(msg::sender());
+        Ok(())
+    }
+
+    /// Halt order submission and execution in every pool
+    /// Cancelling orders stays available so users can always exit
+    pub fn pause(&mut self) -> Result<(), Vec<u8>> {
+        self.only_owner()?;
+        self.paused.set(true);
+        Ok(())
+    }
+
+    /// Resume order submission and execution in every pool
+    pub fn unpause(&mut self) -> Result<(), Vec<u8>> {
+        self.only_owner()?;
+        self.paused.set(false);
+        Ok(())
+    }
+
+    /// Whether the emergency stop is on
+    pub fn is_paused(&self) -> bool {
+        self.paused.get()
+    }
+
+    /// Update the protocol fee applied to virtual execution
+    pub fn set_fee_bps(&mut self, fee_bps: U256) -> Result<(), Vec<u8>> {
+        self.configure(|pool| pool.set_fee_bps(fee_bps))
+    }
+
+    /// Update the blocks between virtual executions
+    pub fn set_order_block_interval(&mut self, interval: U256) -> Result<(), Vec<u8>> {
+        self.configure(|pool| pool.set_order_block_interval(interval))
+    }
+
+    /// Update the upper bound on the block interval
+    pub fn set_max_order_block_interval(&mut self, max_interval: U256) -> Result<(), Vec<u8>> {
+        self.configure(|pool| pool.set_max_order_block_interval(max_interval))
+    }
+
+    /// Update the price drift, in basis points, that makes execution due early (zero disables)
+    pub fn set_max_price_deviation(&mut self, max_deviation_bps: U256) -> Result<(), Vec<u8>> {
+        self.configure(|pool| {
+            pool.set_max_price_deviation(max_deviation_bps);
+            Ok(())
+        })
+    }
+
+    /// Choose what execution does with segments the reserves cannot absorb
+    /// 0 halts, 1 throttles and 2 cancels the stranded orders
+    pub fn set_liquidity_policy(&mut self, policy: u8) -> Result<(), Vec<u8>> {
+        let policy = match policy {
+            0 => LiquidityPolicy::Halt,
+            1 => LiquidityPolicy::Throttle,
+            2 => LiquidityPolicy::Cancel,
+            _ => return Err(b"Invalid liquidity policy".to_vec()),
+        };
+        self.configure(|pool| {
+            pool.set_liquidity_policy(policy);
+            Ok(())
+        })
+    }
+
+    /// Choose whether zero-output executions fail instead of advancing
+    pub fn set_strict_execution(&mut self, enabled: bool) -> Result<(), Vec<u8>> {
+        self.configure(|pool| {
+            pool.set_strict_execution(enabled);
+            Ok(())
+        })
+    }
+
+    /// Choose how new orders round their sell rate: 0 rounds down, 1 rounds up
+    pub fn set_rate_rounding(&mut self, rounding: u8) -> Result<(), Vec<u8>> {
+        let rounding = match rounding {
+            0 => RateRounding::Floor,
+            1 => RateRounding::Ceil,
+            _ => return Err(b"Invalid rate rounding".to_vec()),
+        };
+        self.configure(|pool| {
+            pool.set_rate_rounding(rounding);
+            Ok(())
+        })
+    }
+
+    /// Choose how new order ids are derived: 0 counts up, 1 hashes owner, block and nonce
+    pub fn set_order_id_scheme(&mut self, scheme: u8) -> Result<(), Vec<u8>> {
+        let scheme = match scheme {
+            0 => OrderIdScheme::Sequential,
+            1 => OrderIdScheme::Hashed,
+            _ => return Err(b"Invalid id scheme".to_vec()),
+        };
+        self.configure(|pool| pool.set_order_id_scheme(scheme))
+    }
+
+    /// Update the seconds per block used for second-denominated durations
+    pub fn set_block_time(&mut self, block_time_secs: U256) -> Result<(), Vec<u8>> {
+        self.configure(|pool| pool.set_block_time(block_time_secs))
+    }
+
+    /// Set the blocks over which orders ramp their rate in (zero disables)
+    pub fn set_ramp_blocks(&mut self, ramp_blocks: U256) -> Result<(), Vec<u8>> {
+        self.configure(|pool| {
+            pool.set_ramp_blocks(ramp_blocks);
+            Ok(())
+        })
+    }
+
+    /// Update the most blocks a single execution may advance (zero removes the cap)
+    pub fn set_max_blocks_per_execution(&mut self, max_blocks: U256) -> Result<(), Vec<u8>> {
+        self.configure(|pool| {
+            pool.set_max_blocks_per_execution(max_blocks);
+            Ok(())
+        })
+    }
+
+    /// Update the bounds on new order durations
+    pub fn set_duration_bounds(
+        &mut self,
+        min_duration_blocks: U256,
+        max_duration_blocks: U256,
+    ) -> Result<(), Vec<u8>> {
+        self.configure(|pool| pool.set_duration_bounds(min_duration_blocks, max_duration_blocks))
+    }
+
+    /// Update the maximum number of open orders per owner
+    pub fn set_max_orders_per_owner(&mut self, max_orders: U256) -> Result<(), Vec<u8>> {
+        let max_orders =
+            usize::try_from(max_orders).map_err(|_| b"Invalid order limit".to_vec())?;
+        self.configure(|pool| pool.set_max_orders_per_owner(max_orders))
+    }
+
+    /// Update the maximum number of open orders in each pool
+    pub fn set_max_orders(&mut self, max_orders: U256) -> Result<(), Vec<u8>> {
+        let max_orders =
+            usize::try_from(max_orders).map_err(|_| b"Invalid order limit".to_vec())?;
+        self.configure(|pool| pool.set_max_orders(max_orders))
+    }
+
+    /// Update the keeper reward paid on each execution of the main pool
+    pub fn set_keeper_reward(&mut self, reward_bps: U256, gas_price: U256) -> Result<(), Vec<u8>> {
+        self.configure(|pool| pool.set_keeper_reward(reward_bps, gas_price))
+    }
+
+    /// Update the cap on one direction's aggregate sell rate in the main pool (zero disables)
+    pub fn set_max_total_sell_rate(
+        &mut self,
+        direction: u8,
+        max_rate: U256,
+    ) -> Result<(), Vec<u8>> {
+        let direction = parse_direction(direction)?;
+        self.configure(|pool| {
+            pool.set_max_total_sell_rate(direction, max_rate);
+            Ok(())
+        })
+    }
+
+    /// Update the reserve floor execution keeps in the main pool
+    pub fn set_min_reserve(&mut self, min_reserve: U256) -> Result<(), Vec<u8>> {
+        self.configure(|pool| {
+            pool.set_min_reserve(min_reserve);
+            Ok(())
+        })
+    }
+
+    /// Start tracking the main pool's reserves from the given values
+    pub fn sync_reserves(&mut self, reserve_0: U256, reserve_1: U256) -> Result<(), Vec<u8>> {
+        self.configure(|pool| {
+            pool.sync_reserves(reserve_0, reserve_1);
+            Ok(())
+        })
+    }
+
+    /// Stop tracking the main pool's reserves
+    pub fn disable_reserve_tracking(&mut self) -> Result<(), Vec<u8>> {
+        self.configure(|pool| {
+            pool.disable_reserve_tracking();
+            Ok(())
+        })
+    }
+
+    /// Update how far caller reserves may drift from the stored ones
+    pub fn set_reserve_tolerance(&mut self, tolerance_bps: U256) -> Result<(), Vec<u8>> {
+        self.configure(|pool| pool.set_reserve_tolerance(tolerance_bps))
+    }
+
+    /// Update the tolerance execution uses to detect swapped reserves (zero disables)
+    pub fn set_reserve_swap_tolerance(&mut self, tolerance_bps: U256) -> Result<(), Vec<u8>> {
+        self.configure(|pool| pool.set_reserve_swap_tolerance(tolerance_bps))
+    }
+
+    /// Update the cap on a new order's cumulative price move (zero disables)
+    pub fn set_max_price_move(&mut self, max_move_bps: U256) -> Result<(), Vec<u8>> {
+        self.configure(|pool| pool.set_max_price_move(max_move_bps))
+    }
+
+    /// Update the blocks an order must run before it can be cancelled
+    pub fn set_min_blocks_before_cancel(&mut self, blocks: U256) -> Result<(), Vec<u8>> {
+        self.configure(|pool| {
+            pool.set_min_blocks_before_cancel(blocks);
+            Ok(())
+        })
+    }
+
+    /// Update the gas costs of virtual execution
+    pub fn set_execution_gas(
+        &mut self,
+        base_gas: U256,
+        per_block_gas: U256,
+        per_order_gas: U256,
+        gas_used_per_executed_block: U256,
+    ) -> Result<(), Vec<u8>> {
+        self.configure(|pool| {
+            pool.set_gas_model(GasModel {
+                execution_base_gas: base_gas,
+                execution_per_block_gas: per_block_gas,
+                execution_per_order_gas: per_order_gas,
+                gas_used_per_executed_block,
+                ..pool.gas_model
+            });
+            Ok(())
+        })
+    }
+
+    /// Update the base costs estimate_gas_cost uses for each operation type
+    pub fn set_operation_gas(
+        &mut self,
+        submit_gas: U256,
+        execute_gas: U256,
+        cancel_gas: U256,
+        other_operation_gas: U256,
+        per_complexity_gas: U256,
+    ) -> Result<(), Vec<u8>> {
+        self.configure(|pool| {
+            pool.set_gas_model(GasModel {
+                submit_gas,
+                execute_gas,
+                cancel_gas,
+                other_operation_gas,
+                per_complexity_gas,
+                ..pool.gas_model
+            });
+            Ok(())
+        })
+    }
+
+    /// Update the gas costs of sweeping expired orders
+    pub fn set_sweep_gas(&mut self, base_gas: U256, per_order_gas: U256) -> Result<(), Vec<u8>> {
+        self.configure(|pool| {
+            pool.set_gas_model(GasModel {
+                sweep_base_gas: base_gas,
+                sweep_per_order_gas: per_order_gas,
+                ..pool.gas_model
+            });
+            Ok(())
+        })
+    }
+
     /// Reset statistics
     pub fn reset_statistics(&mut self) {
         self.total_calculations = U256::ZERO;
         self.total_volume_processed = U256::ZERO;
+        self.total_volume_0.set(U256::ZERO);
+        self.total_volume_1.set(U256::ZERO);
     }
 }
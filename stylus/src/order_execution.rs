@@ -1,8 +1,12 @@
-use crate::twamm_math::{MathError, TWAMMath};
+use crate::events;
+use crate::twamm_math::{
+    GasModel, MathError, TWAMMath, Unscaled, DEFAULT_MAX_RATE_BPS_PER_BLOCK, PRECISION,
+};
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::vec::Vec;
 use stylus_sdk::{
-    alloy_primitives::{Address, U256},
-    console,
+    alloy_primitives::{Address, B256, U256},
+    console, crypto,
 };
 
 /// Order execution logic for TWAMM
@@ -20,18 +24,194 @@ pub enum OrderDirection {
     SellToken1 = 1,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Which side of price_limit an order streams on
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LimitSide {
+    /// Stream only while the pool price is at or above the limit
+    Above = 0,
+    /// Stream only while the pool price is at or below the limit
+    Below = 1,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OrderStatus {
+    Active = 0,
+    Completed = 1,
+    Cancelled = 2,
+    /// Halted for falling short of min_output_total
+    Underfilled = 3,
+    /// Waiting for its start block; not yet counted in the pool's rate totals
+    Scheduled = 4,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OrderIdScheme {
+    /// Ids count up from next_order_id
+    Sequential = 0,
+    /// Ids are keccak(owner, start_block, nonce), so they stay unique across pool resets
+    Hashed = 1,
+}
+
+/// How a new order's sell rate is rounded from sell_amount / duration_blocks
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RateRounding {
+    /// Round down: the order runs its full duration and the truncated dust is refunded
+    Floor = 0,
+    /// Round up: the full amount is sold with no dust, finishing up to a few blocks early
+    Ceil = 1,
+}
+
+/// What execution does with flow the reserves cannot absorb, such as after the pool was
+/// drained by other activity
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LiquidityPolicy {
+    /// Fail the whole execution, leaving state as it was
+    Halt = 0,
+    /// Skip the segments that cannot execute, reporting their volume as unfilled
+    Throttle = 1,
+    /// Skip them as Throttle does, then cancel the orders that were streaming in them and
+    /// pay back their remaining principal and proceeds
+    Cancel = 2,
+}
+
+/// Error returned by OrderPool and OrderManager
+/// Variants carrying a message keep the exact text the contract has always reverted with;
+/// the conversion into Vec<u8> happens at the external boundary
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OrderError {
+    /// Caller does not own the order
+    NotOwner,
+    /// No open order has the given id
+    NotFound,
+    /// The pool is paused
+    Paused,
+    /// A per-owner, per-pool or aggregate rate cap was hit
+    LimitReached(&'static str),
+    /// Checked arithmetic overflowed or underflowed
+    Overflow(&'static str),
+    /// A math routine failed or divided by zero
+    MathFailed(&'static str),
+    /// An argument was rejected
+    InvalidParams(&'static str),
+    /// The order or pool is not in a state that allows the operation
+    InvalidState(&'static str),
+}
+
+impl OrderError {
+    /// Revert message for this error
+    pub fn message(&self) -> &'static str {
+        match self {
+            OrderError::NotOwner => "Not order owner",
+            OrderError::NotFound => "Order not found",
+            OrderError::Paused => "Pool paused",
+            OrderError::LimitReached(message)
+            | OrderError::Overflow(message)
+            | OrderError::MathFailed(message)
+            | OrderError::InvalidParams(message)
+            | OrderError::InvalidState(message) => message,
+        }
+    }
+}
+
+impl From<OrderError> for Vec<u8> {
+    fn from(error: OrderError) -> Self {
+        error.message().as_bytes().to_vec()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Order {
     pub id: U256,
     pub owner: Address,
     pub order_type: OrderType,
     pub direction: OrderDirection,
     pub sell_rate: U256,
+    /// Sell amount the order was created with, never updated afterwards
+    pub initial_amount: U256,
     pub remaining_amount: U256,
+    /// Part of the initial amount lost to sell rate truncation, refunded at creation
+    pub dust_remainder: U256,
     pub start_block: U256,
     pub end_block: U256,
     pub last_virtual_order_block: U256,
     pub accumulated_out: U256,
+    /// Worst acceptable total output; orders finishing below it are halted
+    pub min_output_total: U256,
+    pub status: OrderStatus,
+    /// Pool price (token1 per token0, LIMIT_PRICE_PRECISION decimals) the order streams against
+    pub price_limit: Option<U256>,
+    pub limit_side: LimitSide,
+    /// False while the owner has paused the order; paused orders neither stream nor expire
+    pub active: bool,
+    /// Block the current pause took effect from
+    pub paused_at: U256,
+    /// Execution quality of each credited segment, weighted by its blocks
+    /// See TWAMMath::calculate_execution_quality and OrderPool::order_lifetime_quality
+    pub quality_weighted_sum: U256,
+    pub quality_weight: U256,
+    /// Price (token1 per token0, LIMIT_PRICE_PRECISION decimals) the order paces its stream
+    /// against, speeding up when the pool price is better and slowing down when it is worse
+    pub target_price: Option<U256>,
+    /// What accumulated_out would be had each fill traded at its segment's starting spot
+    /// price with no fee; cleared with accumulated_out on claims
+    /// See OrderPool::order_effective_cost_bps
+    pub benchmark_out: U256,
+    /// Sold volume crossed against opposing orders and volume that went through the curve,
+    /// split by each segment's matched share; see OrderPool::order_match_ratio
+    pub matched_volume: U256,
+    pub curve_volume: U256,
+}
+
+impl Order {
+    /// Whether the order streams at the given pool price; orders without a limit always do
+    pub fn within_price_limit(&self, spot_price: Option<U256>) -> bool {
+        match (self.price_limit, spot_price) {
+            (Some(limit), Some(price)) => match self.limit_side {
+                LimitSide::Above => price >= limit,
+                LimitSide::Below => price <= limit,
+            },
+            _ => true,
+        }
+    }
+
+    /// Factor in basis points a target-priced order scales its scheduled rate by at the given
+    /// pool price, in proportion to how much better or worse than target the price is and
+    /// clamped between STREAMING_RATE_FLOOR_BPS and STREAMING_RATE_CAP_BPS
+    pub fn rate_scale_bps(&self, spot_price: Option<U256>) -> U256 {
+        let (Some(target), Some(price)) = (self.target_price, spot_price) else {
+            return U256::from(FEE_DENOMINATOR);
+        };
+
+        // Selling token0 is better at a higher price, selling token1 at a lower one
+        let (better, worse) = match self.direction {
+            OrderDirection::SellToken0 => (price, target),
+            OrderDirection::SellToken1 => (target, price),
+        };
+        TWAMMath::mul_div(better, U256::from(FEE_DENOMINATOR), worse)
+            .unwrap_or(U256::MAX)
+            .clamp(
+                U256::from(STREAMING_RATE_FLOOR_BPS),
+                U256::from(STREAMING_RATE_CAP_BPS),
+            )
+    }
+
+    /// Rate the order streams at during the given block under a ramp of ramp_blocks
+    /// It grows linearly from zero at start_block to the full sell rate at
+    /// start_block + ramp_blocks; a zero ramp always gives the full sell rate
+    pub fn effective_sell_rate(&self, block: U256, ramp_blocks: U256) -> U256 {
+        let elapsed = block.saturating_sub(self.start_block);
+        if elapsed >= ramp_blocks {
+            return self.sell_rate;
+        }
+        TWAMMath::mul_div(self.sell_rate, elapsed, ramp_blocks).unwrap_or(self.sell_rate)
+    }
+
+    /// Amount sold so far, excluding the refunded dust
+    pub fn amount_sold(&self) -> U256 {
+        self.initial_amount
+            .saturating_sub(self.remaining_amount)
+            .saturating_sub(self.dust_remainder)
+    }
 }
 
 impl Default for Order {
@@ -42,42 +222,272 @@ impl Default for Order {
             order_type: OrderType::Instant,
             direction: OrderDirection::SellToken0,
             sell_rate: U256::ZERO,
+            initial_amount: U256::ZERO,
             remaining_amount: U256::ZERO,
+            dust_remainder: U256::ZERO,
             start_block: U256::ZERO,
             end_block: U256::ZERO,
             last_virtual_order_block: U256::ZERO,
             accumulated_out: U256::ZERO,
+            min_output_total: U256::ZERO,
+            status: OrderStatus::Active,
+            price_limit: None,
+            limit_side: LimitSide::Above,
+            active: true,
+            paused_at: U256::ZERO,
+            quality_weighted_sum: U256::ZERO,
+            quality_weight: U256::ZERO,
+            target_price: None,
+            benchmark_out: U256::ZERO,
+            matched_volume: U256::ZERO,
+            curve_volume: U256::ZERO,
+        }
+    }
+}
+
+/// Order flattened to ABI-encodable primitives for returning from the contract
+/// Enums are their u8 discriminants, decoded with the TryFrom<u8> impls below, and unset
+/// price limits and targets are zero as in storage
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OrderView {
+    pub id: U256,
+    pub owner: Address,
+    pub order_type: u8,
+    pub direction: u8,
+    pub sell_rate: U256,
+    pub initial_amount: U256,
+    pub remaining_amount: U256,
+    pub dust_remainder: U256,
+    pub start_block: U256,
+    pub end_block: U256,
+    pub accumulated_out: U256,
+    pub min_output_total: U256,
+    pub status: u8,
+    pub price_limit: U256,
+    pub limit_side: u8,
+    pub paused: bool,
+    pub target_price: U256,
+}
+
+impl From<Order> for OrderView {
+    fn from(order: Order) -> Self {
+        Self {
+            id: order.id,
+            owner: order.owner,
+            order_type: order.order_type as u8,
+            direction: order.direction as u8,
+            sell_rate: order.sell_rate,
+            initial_amount: order.initial_amount,
+            remaining_amount: order.remaining_amount,
+            dust_remainder: order.dust_remainder,
+            start_block: order.start_block,
+            end_block: order.end_block,
+            accumulated_out: order.accumulated_out,
+            min_output_total: order.min_output_total,
+            status: order.status as u8,
+            price_limit: order.price_limit.unwrap_or(U256::ZERO),
+            limit_side: order.limit_side as u8,
+            paused: !order.active,
+            target_price: order.target_price.unwrap_or(U256::ZERO),
+        }
+    }
+}
+
+impl TryFrom<u8> for OrderType {
+    type Error = OrderError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(OrderType::LongTerm),
+            1 => Ok(OrderType::Instant),
+            _ => Err(OrderError::InvalidParams("Invalid order type")),
+        }
+    }
+}
+
+impl TryFrom<u8> for OrderDirection {
+    type Error = OrderError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(OrderDirection::SellToken0),
+            1 => Ok(OrderDirection::SellToken1),
+            _ => Err(OrderError::InvalidParams("Invalid direction")),
+        }
+    }
+}
+
+impl TryFrom<u8> for OrderStatus {
+    type Error = OrderError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(OrderStatus::Active),
+            1 => Ok(OrderStatus::Completed),
+            2 => Ok(OrderStatus::Cancelled),
+            3 => Ok(OrderStatus::Underfilled),
+            4 => Ok(OrderStatus::Scheduled),
+            _ => Err(OrderError::InvalidParams("Invalid status")),
+        }
+    }
+}
+
+impl TryFrom<u8> for LimitSide {
+    type Error = OrderError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(LimitSide::Above),
+            1 => Ok(LimitSide::Below),
+            _ => Err(OrderError::InvalidParams("Invalid limit side")),
         }
     }
 }
 
 /// Virtual order execution state
-#[derive(Clone, Copy, Debug)]
+/// Sell rates are tracked by OrderPool::total_sell_rate_0/1
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct VirtualOrderState {
     pub last_virtual_order_block: U256,
-    pub sell_rate_0_to_1: U256,
-    pub sell_rate_1_to_0: U256,
     pub order_block_interval: U256,
+    /// Spot price (LIMIT_PRICE_PRECISION decimals) after the last execution, zero if unknown
+    pub last_execution_price: U256,
+    /// Price drift from last_execution_price, in basis points, that makes execution due
+    /// before the interval has elapsed (zero disables)
+    pub max_price_deviation_bps: U256,
 }
 
 impl Default for VirtualOrderState {
     fn default() -> Self {
         Self {
             last_virtual_order_block: U256::ZERO,
-            sell_rate_0_to_1: U256::ZERO,
-            sell_rate_1_to_0: U256::ZERO,
             order_block_interval: U256::from(100u32), // Default 100 blocks
+            last_execution_price: U256::ZERO,
+            max_price_deviation_bps: U256::ZERO,
         }
     }
 }
 
+// The gas defaults moved to twamm_math alongside GasModel
+pub use crate::twamm_math::{
+    VIRTUAL_EXECUTION_BASE_GAS, VIRTUAL_EXECUTION_PER_BLOCK_GAS, VIRTUAL_EXECUTION_PER_ORDER_GAS,
+};
+
+/// Default cap on concurrently open orders per owner
+pub const DEFAULT_MAX_ORDERS_PER_OWNER: usize = 50;
+
+/// Default cap on concurrently open orders across the whole pool
+pub const DEFAULT_MAX_ORDERS: usize = 1000;
+
+/// Total weight that beneficiary weights must sum to (basis points)
+pub const BENEFICIARY_WEIGHT_TOTAL: u32 = 10000;
+
+/// Gas price (wei) at which the optimal interval matches the frequency target
+pub const REFERENCE_GAS_PRICE: u64 = 100_000_000;
+/// Bounds for the optimal order block interval
+pub const MIN_ORDER_BLOCK_INTERVAL: u32 = 10;
+pub const MAX_ORDER_BLOCK_INTERVAL: u32 = 1000;
+
+/// Default bounds for an order's duration in blocks
+pub const MIN_ORDER_DURATION: u32 = 10;
+pub const MAX_ORDER_DURATION: u32 = 1000000;
+
+/// Default block time used to convert timed order durations to blocks
+pub const DEFAULT_BLOCK_TIME_SECS: u32 = 12;
+
+/// Decimals of the spot price that order price limits are compared against
+pub const LIMIT_PRICE_PRECISION: u32 = PRECISION;
+
+/// Number of finished orders kept for status queries, oldest dropped first
+pub const MAX_ORDER_HISTORY: usize = 100;
+
+/// Denominator for the protocol fee (basis points, so 30 is 0.3%)
+pub const FEE_DENOMINATOR: u32 = 10000;
+
+/// Fastest a target-priced order streams, in basis points of its scheduled rate
+pub const STREAMING_RATE_CAP_BPS: u32 = 20000;
+
+/// Slowest a target-priced order streams, in basis points of its scheduled rate
+pub const STREAMING_RATE_FLOOR_BPS: u32 = 5000;
+
 /// Order pool for managing active long-term orders
+#[derive(Clone, Debug, PartialEq)]
 pub struct OrderPool {
+    /// Open orders in the order they were added
+    /// Removing an order shifts the ones after it down by one but never reorders them, so
+    /// positions are only stable between removals; see order_positions
     pub orders: Vec<Order>,
     pub next_order_id: U256,
     pub virtual_order_state: VirtualOrderState,
     pub total_sell_rate_0: U256,
     pub total_sell_rate_1: U256,
+    /// Optional proceeds split per order id as (beneficiary, weight) pairs
+    pub order_beneficiaries: Vec<(U256, Vec<(Address, U256)>)>,
+    pub max_orders_per_owner: usize,
+    pub paused: bool,
+    /// Fee taken from each sell amount before it reaches the curve, in basis points
+    pub fee_bps: U256,
+    /// Fees kept in the reserves since the last collection
+    pub collected_fees_0: U256,
+    pub collected_fees_1: U256,
+    /// Recently completed or cancelled orders with their final status
+    pub history: Vec<Order>,
+    /// Floor neither reserve may be pushed below by virtual execution (zero disables)
+    pub min_reserve: U256,
+    /// How new order ids are derived; next_order_id is the nonce for both schemes
+    pub order_id_scheme: OrderIdScheme,
+    /// Seconds per block for create_long_term_order_timed
+    pub block_time_secs: U256,
+    /// Share of executed volume paid to the caller of execution, in basis points (zero disables)
+    pub keeper_reward_bps: U256,
+    /// Token units per unit of gas used to cap each token's keeper reward
+    pub keeper_gas_price: U256,
+    /// Caps on the aggregate sell rate per direction, rejecting orders beyond them (zero disables)
+    pub max_total_sell_rate_0: U256,
+    pub max_total_sell_rate_1: U256,
+    /// What execution does with segments the reserves cannot absorb
+    pub liquidity_policy: LiquidityPolicy,
+    /// Most blocks a single execution advances, leaving the rest for later calls (zero disables)
+    pub max_blocks_per_execution: U256,
+    /// Bounds on a new order's duration in blocks
+    pub min_duration_blocks: U256,
+    pub max_duration_blocks: U256,
+    /// Whether execution runs against stored_reserve_0/1 instead of the caller's reserves
+    pub reserve_tracking: bool,
+    /// Reserves as of the last sync or tracked execution
+    pub stored_reserve_0: U256,
+    pub stored_reserve_1: U256,
+    /// How far caller reserves may drift from the stored ones, in basis points
+    pub reserve_tolerance_bps: U256,
+    /// Gas costs used by estimate_virtual_execution_gas and execution results
+    pub gas_model: GasModel,
+    /// Largest interval set_order_block_interval accepts
+    pub max_order_block_interval: U256,
+    /// Largest cumulative price move a new order may cause over its life, in basis points
+    /// Checked against the stored reserves, so it needs reserve tracking (zero disables)
+    pub max_price_move_bps: U256,
+    /// Cap on open orders across all owners, bounding the work of a single execution
+    pub max_orders: usize,
+    /// Reject executions that stream sell volume but pay out nothing, leaving state as it was
+    pub strict_execution: bool,
+    /// Rounding of new orders' sell rates; see RateRounding for the trade-off
+    pub rate_rounding: RateRounding,
+    /// Blocks over which a new order's rate ramps linearly from zero to its full sell rate
+    /// (zero disables); see set_ramp_blocks
+    pub ramp_blocks: U256,
+    /// Lifetime sell volume executed by virtual orders per token, kept across order
+    /// completion and cancellation
+    pub cumulative_volume_0: U256,
+    pub cumulative_volume_1: U256,
+    /// How close the inverse of the caller's price may come to the last execution price
+    /// before execution treats the reserves as swapped, in basis points (zero disables)
+    pub reserve_swap_tolerance_bps: U256,
+    /// Blocks an order must have been streaming before its owner may cancel it, deterring
+    /// create-cancel cycling of the aggregate rates (zero disables)
+    pub min_blocks_before_cancel: U256,
+    /// Proceeds of completed orders awaiting claim_proceeds, as (order_id, owner, amount)
+    /// Kept apart from the capped history so dropping an entry there never loses funds
+    pub unclaimed_proceeds: Vec<(U256, Address, U256)>,
 }
 
 impl Default for OrderPool {
@@ -88,25 +498,113 @@ impl Default for OrderPool {
             virtual_order_state: VirtualOrderState::default(),
             total_sell_rate_0: U256::ZERO,
             total_sell_rate_1: U256::ZERO,
+            order_beneficiaries: Vec::new(),
+            max_orders_per_owner: DEFAULT_MAX_ORDERS_PER_OWNER,
+            paused: false,
+            fee_bps: U256::ZERO,
+            collected_fees_0: U256::ZERO,
+            collected_fees_1: U256::ZERO,
+            history: Vec::new(),
+            min_reserve: U256::ZERO,
+            order_id_scheme: OrderIdScheme::Sequential,
+            block_time_secs: U256::from(DEFAULT_BLOCK_TIME_SECS),
+            keeper_reward_bps: U256::ZERO,
+            keeper_gas_price: U256::ZERO,
+            max_total_sell_rate_0: U256::ZERO,
+            max_total_sell_rate_1: U256::ZERO,
+            liquidity_policy: LiquidityPolicy::Halt,
+            max_blocks_per_execution: U256::ZERO,
+            min_duration_blocks: U256::from(MIN_ORDER_DURATION),
+            max_duration_blocks: U256::from(MAX_ORDER_DURATION),
+            reserve_tracking: false,
+            stored_reserve_0: U256::ZERO,
+            stored_reserve_1: U256::ZERO,
+            reserve_tolerance_bps: U256::ZERO,
+            gas_model: GasModel::default(),
+            max_order_block_interval: U256::from(MAX_ORDER_BLOCK_INTERVAL),
+            max_price_move_bps: U256::ZERO,
+            max_orders: DEFAULT_MAX_ORDERS,
+            strict_execution: false,
+            rate_rounding: RateRounding::Floor,
+            ramp_blocks: U256::ZERO,
+            cumulative_volume_0: U256::ZERO,
+            cumulative_volume_1: U256::ZERO,
+            reserve_swap_tolerance_bps: U256::ZERO,
+            min_blocks_before_cancel: U256::ZERO,
+            unclaimed_proceeds: Vec::new(),
         }
     }
 }
 
 /// Execution result for virtual orders
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct VirtualExecutionResult {
     pub blocks_executed: U256,
+    /// Token0 streamed in by token0 sellers (rate * blocks, including any fee)
     pub amount_0_sold: U256,
+    /// Token1 streamed in by token1 sellers (rate * blocks, including any fee)
     pub amount_1_sold: U256,
+    /// Token0 paid out to token1 sellers by the curve
     pub amount_0_received: U256,
+    /// Token1 paid out to token0 sellers by the curve
     pub amount_1_received: U256,
     pub new_reserve_0: U256,
     pub new_reserve_1: U256,
     pub gas_used_estimate: U256,
+    /// Paid to the caller out of the reserves; new_reserve_0/1 are net of it
+    pub keeper_reward_0: U256,
+    pub keeper_reward_1: U256,
+    /// Sell volume skipped because the reserves could not absorb it (saturating mode only)
+    /// It is not counted as sold and stays in the orders' remaining amounts
+    pub unfilled_0: U256,
+    pub unfilled_1: U256,
+    /// Post-fee volume crossed between opposing sellers, estimated by netting each segment's
+    /// flows at its starting price
+    pub matched_0: U256,
+    pub matched_1: U256,
+    /// Blocks up to the requested block still pending after this execution, whether left by
+    /// max_blocks_per_execution or by the reserve floor
+    pub blocks_remaining: U256,
+    /// Constant-product invariant of the reserves before and after execution
+    /// See OrderPool::pool_invariant; fees only grow it, keeper rewards paid out shrink it
+    pub k_before: U256,
+    pub k_after: U256,
+    /// Token0 and token1 the sellers would have received at the pre-execution spot price,
+    /// the no-slippage counterparts of amount_0_received and amount_1_received
+    pub expected_0: U256,
+    pub expected_1: U256,
+    /// Shortfall of the amounts received against the expected ones, both valued in token1
+    /// at the pre-execution price, in basis points; fees count toward it and executions
+    /// that beat the spot price report zero
+    pub realized_slippage_bps: U256,
+    /// Owed to the owners of orders LiquidityPolicy::Cancel cancelled, per token: their
+    /// unsold principal and unclaimed proceeds. The caller pays these out of the pool
+    pub cancelled_payout_0: U256,
+    pub cancelled_payout_1: U256,
+}
+
+/// Outcome of OrderPool::execute_instant_order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstantSwapResult {
+    /// Id of the completed Instant order kept in the history
+    pub order_id: U256,
+    pub amount_out: U256,
+    /// Reserves after both the pending virtual orders and the swap
+    pub new_reserve_0: U256,
+    pub new_reserve_1: U256,
+    /// Virtual execution run first to bring the pool up to current_block
+    pub virtual_execution: VirtualExecutionResult,
 }
 
+/// Per-order fills for one execution round as (order_id, amount received)
+pub type OrderFills = Vec<(U256, U256)>;
+
+/// Per-block impact of each direction's aggregate sell flow in basis points as
+/// (impact_0, impact_1)
+pub type FlowImpact = (U256, U256);
+
 impl OrderPool {
-    /// Create a new long-term order
+    /// Create a new long-term order starting at current_block
     pub fn create_long_term_order(
         &mut self,
         owner: Address,
@@ -114,407 +612,3784 @@ impl OrderPool {
         sell_amount: U256,
         duration_blocks: U256,
         current_block: U256,
-    ) -> Result<U256, Vec<u8>> {
+        min_output_total: U256,
+    ) -> Result<U256, OrderError> {
+        self.create_scheduled_order(
+            owner,
+            direction,
+            sell_amount,
+            duration_blocks,
+            (current_block, current_block),
+            min_output_total,
+        )
+    }
+
+    /// Create a long-term order that starts streaming at a later block
+    /// blocks is (current_block, start_block). An order starting after current_block is
+    /// Scheduled: it is checked against the rate cap now but only joins the rate totals once
+    /// virtual execution reaches its start block.
+    pub fn create_scheduled_order(
+        &mut self,
+        owner: Address,
+        direction: OrderDirection,
+        sell_amount: U256,
+        duration_blocks: U256,
+        blocks: (U256, U256),
+        min_output_total: U256,
+    ) -> Result<U256, OrderError> {
+        let (current_block, start_block) = blocks;
+        if self.paused {
+            return Err(OrderError::Paused);
+        }
+
+        if self.count_orders_by_owner(owner) >= self.max_orders_per_owner {
+            return Err(OrderError::LimitReached("Order limit reached"));
+        }
+        if self.capacity_remaining() == 0 {
+            return Err(OrderError::LimitReached("pool order limit reached"));
+        }
+        if start_block < current_block {
+            return Err(OrderError::InvalidParams("Start block in the past"));
+        }
+        self.check_duration(duration_blocks)?;
+        self.check_price_move(direction, sell_amount)?;
+
+        let mut order = Self::build_order(
+            self.derive_order_id(self.next_order_id, owner, current_block),
+            owner,
+            direction,
+            sell_amount,
+            duration_blocks,
+            start_block,
+            min_output_total,
+        )?;
+        self.round_sell_rate(&mut order)?;
+        if start_block > current_block {
+            order.status = OrderStatus::Scheduled;
+        }
+        let order_id = self.insert_order(order)?;
+
+        console!(
+            "Created long-term order {} for {} blocks from block {}",
+            order_id,
+            duration_blocks,
+            start_block
+        );
+        Ok(order_id)
+    }
+
+    /// Create a long-term order whose duration is given in seconds
+    /// The duration is converted with the pool's block_time_secs; see
+    /// OrderManager::blocks_for_seconds
+    pub fn create_long_term_order_timed(
+        &mut self,
+        owner: Address,
+        direction: OrderDirection,
+        sell_amount: U256,
+        duration_secs: U256,
+        current_block: U256,
+        min_output_total: U256,
+    ) -> Result<U256, OrderError> {
+        let duration_blocks =
+            OrderManager::blocks_for_seconds(duration_secs, self.block_time_secs)?;
+
+        self.create_long_term_order(
+            owner,
+            direction,
+            sell_amount,
+            duration_blocks,
+            current_block,
+            min_output_total,
+        )
+    }
+
+    /// Create several long-term orders for one owner, all or nothing
+    /// Each entry is (direction, sell_amount, duration_blocks); returns the assigned ids in order
+    pub fn create_long_term_orders_batch(
+        &mut self,
+        owner: Address,
+        orders: Vec<(OrderDirection, U256, U256)>,
+        current_block: U256,
+    ) -> Result<Vec<U256>, OrderError> {
+        if self.paused {
+            return Err(OrderError::Paused);
+        }
+
+        let open_orders = self
+            .count_orders_by_owner(owner)
+            .checked_add(orders.len())
+            .ok_or(OrderError::LimitReached("Order limit reached"))?;
+        if open_orders > self.max_orders_per_owner {
+            return Err(OrderError::LimitReached("Order limit reached"));
+        }
+        if orders.len() > self.capacity_remaining() {
+            return Err(OrderError::LimitReached("pool order limit reached"));
+        }
+
+        // Validate every entry, including the combined rate totals, before inserting any
+        let mut new_orders = Vec::with_capacity(orders.len());
+        let mut next_order_id = self.next_order_id;
+        let mut total_sell_rate_0 = self.total_sell_rate_0;
+        let mut total_sell_rate_1 = self.total_sell_rate_1;
+
+        for (direction, sell_amount, duration_blocks) in orders {
+            self.check_duration(duration_blocks)?;
+            self.check_price_move(direction, sell_amount)?;
+            let mut order = Self::build_order(
+                self.derive_order_id(next_order_id, owner, current_block),
+                owner,
+                direction,
+                sell_amount,
+                duration_blocks,
+                current_block,
+                U256::ZERO,
+            )?;
+            self.round_sell_rate(&mut order)?;
+
+            match direction {
+                OrderDirection::SellToken0 => {
+                    total_sell_rate_0 = total_sell_rate_0
+                        .checked_add(order.sell_rate)
+                        .ok_or(OrderError::Overflow("Rate overflow"))?;
+                    self.check_rate_cap(direction, total_sell_rate_0, current_block)?;
+                }
+                OrderDirection::SellToken1 => {
+                    total_sell_rate_1 = total_sell_rate_1
+                        .checked_add(order.sell_rate)
+                        .ok_or(OrderError::Overflow("Rate overflow"))?;
+                    self.check_rate_cap(direction, total_sell_rate_1, current_block)?;
+                }
+            }
+
+            next_order_id = next_order_id
+                .checked_add(U256::from(1u32))
+                .ok_or(OrderError::Overflow("Order ID overflow"))?;
+            new_orders.push(order);
+        }
+
+        let mut order_ids = Vec::with_capacity(new_orders.len());
+        for order in new_orders {
+            order_ids.push(self.insert_order(order)?);
+        }
+
+        console!("Created {} long-term orders in batch", order_ids.len());
+        Ok(order_ids)
+    }
+
+    /// Check a new order's duration against the pool's bounds
+    fn check_duration(&self, duration_blocks: U256) -> Result<(), OrderError> {
+        if duration_blocks < self.min_duration_blocks {
+            return Err(OrderError::InvalidParams("Duration below minimum"));
+        }
+        if duration_blocks > self.max_duration_blocks {
+            return Err(OrderError::InvalidParams("Duration above maximum"));
+        }
+        Ok(())
+    }
+
+    /// Reject an order whose full sell amount would move the stored price by more than
+    /// max_price_move_bps, judged on its own against the current reserves
+    fn check_price_move(
+        &self,
+        direction: OrderDirection,
+        sell_amount: U256,
+    ) -> Result<(), OrderError> {
+        if self.max_price_move_bps == U256::ZERO {
+            return Ok(());
+        }
+        if !self.reserve_tracking {
+            return Err(OrderError::InvalidParams(
+                "Price move cap needs reserve tracking",
+            ));
+        }
+
+        let (reserve_in, reserve_out) = match direction {
+            OrderDirection::SellToken0 => (self.stored_reserve_0, self.stored_reserve_1),
+            OrderDirection::SellToken1 => (self.stored_reserve_1, self.stored_reserve_0),
+        };
+        if OrderManager::would_invert_price(
+            sell_amount,
+            reserve_in,
+            reserve_out,
+            self.max_price_move_bps,
+        )? {
+            return Err(OrderError::InvalidParams("Price move too large"));
+        }
+        Ok(())
+    }
+
+    /// Get the id for the order created with the given nonce under the pool's id scheme
+    pub fn derive_order_id(&self, nonce: U256, owner: Address, start_block: U256) -> U256 {
+        match self.order_id_scheme {
+            OrderIdScheme::Sequential => nonce,
+            OrderIdScheme::Hashed => {
+                let mut preimage = Vec::with_capacity(84);
+                preimage.extend_from_slice(owner.as_slice());
+                preimage.extend_from_slice(&start_block.to_be_bytes::<32>());
+                preimage.extend_from_slice(&nonce.to_be_bytes::<32>());
+                U256::from_be_bytes(crypto::keccak(preimage).0)
+            }
+        }
+    }
+
+    /// Validate order parameters and build the order without touching pool state
+    fn build_order(
+        id: U256,
+        owner: Address,
+        direction: OrderDirection,
+        sell_amount: U256,
+        duration_blocks: U256,
+        start_block: U256,
+        min_output_total: U256,
+    ) -> Result<Order, OrderError> {
         if sell_amount == U256::ZERO || duration_blocks == U256::ZERO {
-            return Err(b"Invalid order parameters".to_vec());
+            return Err(OrderError::InvalidParams("Invalid order parameters"));
         }
 
         // Calculate sell rate
         let sell_rate = sell_amount
             .checked_div(duration_blocks)
-            .ok_or(b"Division overflow".to_vec())?;
+            .ok_or(OrderError::Overflow("Division overflow"))?;
 
-        let order = Order {
-            id: self.next_order_id,
+        // An order that would never sell anything is a mistake, not a valid order
+        if sell_rate == U256::ZERO {
+            return Err(OrderError::InvalidParams(
+                "sell rate rounds to zero; increase amount or shorten duration",
+            ));
+        }
+
+        // Only sell_rate * duration is ever streamed; the truncated rest is refunded
+        let streamed_amount = sell_rate
+            .checked_mul(duration_blocks)
+            .ok_or(OrderError::Overflow("Amount overflow"))?;
+
+        Ok(Order {
+            id,
             owner,
             order_type: OrderType::LongTerm,
             direction,
             sell_rate,
-            remaining_amount: sell_amount,
-            start_block: current_block,
-            end_block: current_block
+            initial_amount: sell_amount,
+            remaining_amount: streamed_amount,
+            dust_remainder: sell_amount - streamed_amount,
+            start_block,
+            end_block: start_block
                 .checked_add(duration_blocks)
-                .ok_or(b"Block overflow".to_vec())?,
-            last_virtual_order_block: current_block,
+                .ok_or(OrderError::Overflow("Block overflow"))?,
+            last_virtual_order_block: start_block,
             accumulated_out: U256::ZERO,
+            min_output_total,
+            status: OrderStatus::Active,
+            price_limit: None,
+            limit_side: LimitSide::Above,
+            active: true,
+            paused_at: U256::ZERO,
+            quality_weighted_sum: U256::ZERO,
+            quality_weight: U256::ZERO,
+            target_price: None,
+            benchmark_out: U256::ZERO,
+            matched_volume: U256::ZERO,
+            curve_volume: U256::ZERO,
+        })
+    }
+
+    /// Apply the pool's rate rounding to an order built with the floored rate
+    /// Under Ceil the rate rounds up, nothing is left as dust and end_block moves in to the
+    /// block the full amount is sold by
+    fn round_sell_rate(&self, order: &mut Order) -> Result<(), OrderError> {
+        if self.rate_rounding == RateRounding::Floor || order.dust_remainder == U256::ZERO {
+            return Ok(());
+        }
+
+        let sell_rate = order
+            .sell_rate
+            .checked_add(U256::from(1u32))
+            .ok_or(OrderError::Overflow("Rate overflow"))?;
+        let duration_blocks = order.initial_amount.div_ceil(sell_rate);
+
+        order.sell_rate = sell_rate;
+        order.remaining_amount = order.initial_amount;
+        order.dust_remainder = U256::ZERO;
+        order.end_block = order.start_block + duration_blocks;
+        Ok(())
+    }
+
+    /// Reject a total sell rate above the direction's cap
+    /// Open orders still ramping in at block count at their effective rate, so orders
+    /// starting close together do not trip the cap before they reach full speed
+    fn check_rate_cap(
+        &self,
+        direction: OrderDirection,
+        total_sell_rate: U256,
+        block: U256,
+    ) -> Result<(), OrderError> {
+        self.check_rate_cap_excluding(direction, total_sell_rate, block, &[])
+    }
+
+    /// check_rate_cap as if the open orders in excluded were already gone
+    fn check_rate_cap_excluding(
+        &self,
+        direction: OrderDirection,
+        total_sell_rate: U256,
+        block: U256,
+        excluded: &[U256],
+    ) -> Result<(), OrderError> {
+        let cap = match direction {
+            OrderDirection::SellToken0 => self.max_total_sell_rate_0,
+            OrderDirection::SellToken1 => self.max_total_sell_rate_1,
         };
+        if cap == U256::ZERO {
+            return Ok(());
+        }
 
-        self.orders.push(order);
+        let ramping = self
+            .orders
+            .iter()
+            .filter(|order| {
+                order.direction == direction
+                    && order.status == OrderStatus::Active
+                    && order.active
+                    && !excluded.contains(&order.id)
+            })
+            .fold(U256::ZERO, |total, order| {
+                total.saturating_add(
+                    order.sell_rate - order.effective_sell_rate(block, self.ramp_blocks),
+                )
+            });
 
-        // Update total sell rates
-        match direction {
+        if total_sell_rate.saturating_sub(ramping) > cap {
+            return Err(OrderError::LimitReached("aggregate rate cap exceeded"));
+        }
+        Ok(())
+    }
+
+    /// Add a built order to the pool and advance the order id
+    fn insert_order(&mut self, order: Order) -> Result<U256, OrderError> {
+        // Update total sell rates, checking the cap before the order is added; scheduled
+        // orders are only checked and join the totals at activation
+        match order.direction {
             OrderDirection::SellToken0 => {
-                self.total_sell_rate_0 = self
+                let total_sell_rate_0 = self
                     .total_sell_rate_0
-                    .checked_add(sell_rate)
-                    .ok_or(b"Rate overflow".to_vec())?;
+                    .checked_add(order.sell_rate)
+                    .ok_or(OrderError::Overflow("Rate overflow"))?;
+                self.check_rate_cap(order.direction, total_sell_rate_0, order.start_block)?;
+                if order.status == OrderStatus::Active {
+                    self.total_sell_rate_0 = total_sell_rate_0;
+                }
             }
             OrderDirection::SellToken1 => {
-                self.total_sell_rate_1 = self
+                let total_sell_rate_1 = self
                     .total_sell_rate_1
-                    .checked_add(sell_rate)
-                    .ok_or(b"Rate overflow".to_vec())?;
+                    .checked_add(order.sell_rate)
+                    .ok_or(OrderError::Overflow("Rate overflow"))?;
+                self.check_rate_cap(order.direction, total_sell_rate_1, order.start_block)?;
+                if order.status == OrderStatus::Active {
+                    self.total_sell_rate_1 = total_sell_rate_1;
+                }
             }
         }
 
-        let order_id = self.next_order_id;
+        self.orders.push(order);
+        events::emit(events::order_created(&order));
+
         self.next_order_id = self
             .next_order_id
             .checked_add(U256::from(1u32))
-            .ok_or(b"Order ID overflow".to_vec())?;
+            .ok_or(OrderError::Overflow("Order ID overflow"))?;
 
-        console!(
-            "Created long-term order {} for {} blocks",
-            order_id,
-            duration_blocks
-        );
-        Ok(order_id)
+        Ok(order.id)
     }
 
     /// Cancel an existing order
-    pub fn cancel_order(&mut self, order_id: U256, caller: Address) -> Result<Order, Vec<u8>> {
+    /// Repeating a cancellation fails with "Order already cancelled" rather than "Order not
+    /// found" while the order is still in the history
+    /// Fails with "cancel too early" until the order has streamed min_blocks_before_cancel
+    /// blocks as of current_block. Scheduled orders have not started streaming, and a paused
+    /// pool must let users exit, so neither waits for the window
+    pub fn cancel_order(
+        &mut self,
+        order_id: U256,
+        caller: Address,
+        current_block: U256,
+    ) -> Result<Order, OrderError> {
         let order_index = self
             .orders
             .iter()
             .position(|order| order.id == order_id)
-            .ok_or(b"Order not found".to_vec())?;
+            .ok_or_else(|| self.missing_order_error(order_id))?;
 
         let order = self.orders[order_index];
 
         // Check ownership
         if order.owner != caller {
-            return Err(b"Not order owner".to_vec());
-        }
-
-        // Update total sell rates
-        match order.direction {
-            OrderDirection::SellToken0 => {
-                self.total_sell_rate_0 = self
-                    .total_sell_rate_0
-                    .checked_sub(order.sell_rate)
-                    .ok_or(b"Rate underflow".to_vec())?;
-            }
-            OrderDirection::SellToken1 => {
-                self.total_sell_rate_1 = self
-                    .total_sell_rate_1
-                    .checked_sub(order.sell_rate)
-                    .ok_or(b"Rate underflow".to_vec())?;
-            }
+            return Err(OrderError::NotOwner);
         }
 
-        // Remove order from active orders
-        self.orders.remove(order_index);
+        self.check_cancel_window(&order, current_block)?;
 
+        let order = self.remove_cancelled_order(order_index)?;
         console!("Cancelled order {}", order_id);
         Ok(order)
     }
 
-    /// Execute virtual orders up to current block
-    pub fn execute_virtual_orders(
-        &mut self,
-        current_block: U256,
-        current_reserve_0: U256,
-        current_reserve_1: U256,
-    ) -> Result<VirtualExecutionResult, Vec<u8>> {
-        let last_block = self.virtual_order_state.last_virtual_order_block;
+    /// Reject cancelling an order that has streamed fewer than min_blocks_before_cancel blocks
+    /// Scheduled orders have not started streaming, and a paused pool must let users exit
+    fn check_cancel_window(&self, order: &Order, current_block: U256) -> Result<(), OrderError> {
+        if order.status != OrderStatus::Scheduled
+            && !self.paused
+            && current_block.saturating_sub(order.start_block) < self.min_blocks_before_cancel
+        {
+            return Err(OrderError::InvalidState("cancel too early"));
+        }
+        Ok(())
+    }
 
-        if current_block <= last_block {
-            // No execution needed
-            return Ok(VirtualExecutionResult {
-                blocks_executed: U256::ZERO,
-                amount_0_sold: U256::ZERO,
-                amount_1_sold: U256::ZERO,
-                amount_0_received: U256::ZERO,
-                amount_1_received: U256::ZERO,
-                new_reserve_0: current_reserve_0,
-                new_reserve_1: current_reserve_1,
-                gas_used_estimate: U256::ZERO,
-            });
+    /// Check that the sell rate totals cover the streaming orders about to be removed, so
+    /// removing them one by one cannot fail part way
+    fn check_rates_cover(&self, orders: &[Order]) -> Result<(), OrderError> {
+        let mut rate_0 = U256::ZERO;
+        let mut rate_1 = U256::ZERO;
+        for order in orders
+            .iter()
+            .filter(|order| order.status == OrderStatus::Active && order.active)
+        {
+            let rate = match order.direction {
+                OrderDirection::SellToken0 => &mut rate_0,
+                OrderDirection::SellToken1 => &mut rate_1,
+            };
+            *rate = rate
+                .checked_add(order.sell_rate)
+                .ok_or(OrderError::Overflow("Rate overflow"))?;
         }
 
-        let blocks_elapsed = current_block
-            .checked_sub(last_block)
-            .ok_or(b"Block calculation error".to_vec())?;
+        if rate_0 > self.total_sell_rate_0 || rate_1 > self.total_sell_rate_1 {
+            return Err(OrderError::Overflow("Rate underflow"));
+        }
+        Ok(())
+    }
 
-        // Get active sell rates at the time of execution
-        let (active_sell_rate_0, active_sell_rate_1) =
-            self.get_active_sell_rates(last_block, current_block)?;
+    /// Remove the order at order_index as cancelled, recording it in the history
+    fn remove_cancelled_order(&mut self, order_index: usize) -> Result<Order, OrderError> {
+        let mut order = self.orders[order_index];
 
-        // Use closed-form solution to calculate virtual order execution
-        let (new_reserve_0, new_reserve_1, amount_0_received, amount_1_received) =
-            TWAMMath::execute_virtual_orders_closed_form(
-                active_sell_rate_0,
-                active_sell_rate_1,
-                blocks_elapsed,
-                current_reserve_0,
-                current_reserve_1,
-            )
-            .map_err(|e| match e {
-                MathError::Overflow => b"Math overflow in virtual execution".to_vec(),
-                MathError::DivisionByZero => b"Division by zero in virtual execution".to_vec(),
-                MathError::InvalidInput => b"Invalid input for virtual execution".to_vec(),
-                MathError::ComputationFailed => b"Virtual execution computation failed".to_vec(),
-            })?;
-
-        // Update order states and remove completed orders
-        self.update_orders_after_execution(
-            blocks_elapsed,
-            amount_0_received,
-            amount_1_received,
-            current_block,
-        )?;
+        // Update total sell rates (halted, paused and scheduled orders do not contribute)
+        if order.status == OrderStatus::Active && order.active {
+            match order.direction {
+                OrderDirection::SellToken0 => {
+                    self.total_sell_rate_0 = self
+                        .total_sell_rate_0
+                        .checked_sub(order.sell_rate)
+                        .ok_or(OrderError::Overflow("Rate underflow"))?;
+                }
+                OrderDirection::SellToken1 => {
+                    self.total_sell_rate_1 = self
+                        .total_sell_rate_1
+                        .checked_sub(order.sell_rate)
+                        .ok_or(OrderError::Overflow("Rate underflow"))?;
+                }
+            }
+        }
 
-        // Update virtual order state
-        self.virtual_order_state.last_virtual_order_block = current_block;
+        // Remove order from active orders
+        self.orders.remove(order_index);
+        self.remove_beneficiaries(order.id);
+        order.status = OrderStatus::Cancelled;
+        self.record_history(order);
+        events::emit(events::order_cancelled(&order));
+        Ok(order)
+    }
 
-        let amount_0_sold = active_sell_rate_0
-            .checked_mul(blocks_elapsed)
-            .ok_or(b"Calculation overflow".to_vec())?;
-        let amount_1_sold = active_sell_rate_1
-            .checked_mul(blocks_elapsed)
-            .ok_or(b"Calculation overflow".to_vec())?;
+    /// Cancel an existing order, returning what the owner gets back as
+    /// (refund_sold_token, refund_received_token)
+    /// The sold token refund is the unstreamed remaining_amount (dust was refunded at
+    /// creation); the received token refund is the unclaimed accumulated_out.
+    pub fn cancel_order_with_refund(
+        &mut self,
+        order_id: U256,
+        caller: Address,
+        current_block: U256,
+    ) -> Result<(U256, U256), OrderError> {
+        let order = self.cancel_order(order_id, caller, current_block)?;
+        Ok((order.remaining_amount, order.accumulated_out))
+    }
 
-        // Estimate gas used (approximation based on blocks executed)
-        let gas_estimate = blocks_elapsed
-            .checked_mul(U256::from(21000u32))
-            .ok_or(b"Gas calculation overflow".to_vec())?;
+    /// Combine two streaming orders of one owner and direction into a new order, returning
+    /// its id
+    ///
+    /// The new order streams both remaining amounts from current_block until the later end
+    /// block and carries over their proceeds, minimum outputs and execution quality. The
+    /// sell rate truncation joins dust_remainder for the caller to refund, as at creation.
+    /// Virtual orders must be executed up to current_block first so both remaining amounts
+    /// are current. Orders with beneficiaries or different price settings cannot be merged.
+    /// The originals are recorded in the history as cancelled.
+    pub fn merge_orders(
+        &mut self,
+        id_a: U256,
+        id_b: U256,
+        caller: Address,
+        current_block: U256,
+    ) -> Result<U256, OrderError> {
+        if self.paused {
+            return Err(OrderError::Paused);
+        }
+        if id_a == id_b {
+            return Err(OrderError::InvalidParams("Cannot merge order with itself"));
+        }
+        if self.virtual_order_state.last_virtual_order_block < current_block {
+            return Err(OrderError::InvalidState("Virtual orders pending"));
+        }
 
-        console!("Executed virtual orders for {} blocks", blocks_elapsed);
+        let mut merged = Vec::with_capacity(2);
+        for order_id in [id_a, id_b] {
+            let order = self
+                .orders
+                .iter()
+                .find(|order| order.id == order_id)
+                .ok_or_else(|| self.missing_order_error(order_id))?;
 
-        Ok(VirtualExecutionResult {
-            blocks_executed: blocks_elapsed,
-            amount_0_sold,
-            amount_1_sold,
-            amount_0_received,
-            amount_1_received,
+            if order.owner != caller {
+                return Err(OrderError::NotOwner);
+            }
+            if order.status != OrderStatus::Active || !order.active {
+                return Err(OrderError::InvalidState("Order not active"));
+            }
+            if order.end_block <= current_block {
+                return Err(OrderError::InvalidState("Order expired"));
+            }
+            if self
+                .order_beneficiaries
+                .iter()
+                .any(|(id, _)| *id == order_id)
+            {
+                return Err(OrderError::InvalidState("Order has beneficiaries"));
+            }
+            merged.push(*order);
+        }
+        let (a, b) = (merged[0], merged[1]);
+
+        if a.direction != b.direction {
+            return Err(OrderError::InvalidParams("Orders differ in direction"));
+        }
+        if a.price_limit != b.price_limit
+            || (a.price_limit.is_some() && a.limit_side != b.limit_side)
+            || a.target_price != b.target_price
+        {
+            return Err(OrderError::InvalidParams("Orders differ in price settings"));
+        }
+
+        let sum = |x: U256, y: U256| {
+            x.checked_add(y)
+                .ok_or(OrderError::Overflow("Amount overflow"))
+        };
+        let end_block = a.end_block.max(b.end_block);
+        let duration_blocks = end_block - current_block;
+        let combined_amount = sum(a.remaining_amount, b.remaining_amount)?;
+        let sell_rate = combined_amount / duration_blocks;
+        if sell_rate == U256::ZERO {
+            return Err(OrderError::InvalidParams(
+                "sell rate rounds to zero; increase amount or shorten duration",
+            ));
+        }
+        let streamed_amount = sell_rate * duration_blocks;
+
+        let order = Order {
+            id: self.derive_order_id(self.next_order_id, caller, current_block),
+            owner: caller,
+            order_type: OrderType::LongTerm,
+            direction: a.direction,
+            sell_rate,
+            initial_amount: sum(a.initial_amount, b.initial_amount)?,
+            remaining_amount: streamed_amount,
+            dust_remainder: sum(
+                sum(a.dust_remainder, b.dust_remainder)?,
+                combined_amount - streamed_amount,
+            )?,
+            start_block: current_block,
+            end_block,
+            last_virtual_order_block: current_block,
+            accumulated_out: sum(a.accumulated_out, b.accumulated_out)?,
+            min_output_total: sum(a.min_output_total, b.min_output_total)?,
+            status: OrderStatus::Active,
+            price_limit: a.price_limit,
+            limit_side: a.limit_side,
+            active: true,
+            paused_at: U256::ZERO,
+            quality_weighted_sum: sum(a.quality_weighted_sum, b.quality_weighted_sum)?,
+            quality_weight: sum(a.quality_weight, b.quality_weight)?,
+            target_price: a.target_price,
+            benchmark_out: sum(a.benchmark_out, b.benchmark_out)?,
+            matched_volume: sum(a.matched_volume, b.matched_volume)?,
+            curve_volume: sum(a.curve_volume, b.curve_volume)?,
+        };
+
+        // The merged rate replaces the originals' under the cap; check it before anything
+        // changes so a failure leaves the pool untouched
+        let retired_total = match a.direction {
+            OrderDirection::SellToken0 => self.total_sell_rate_0,
+            OrderDirection::SellToken1 => self.total_sell_rate_1,
+        }
+        .checked_sub(sum(a.sell_rate, b.sell_rate)?)
+        .ok_or(OrderError::Overflow("Rate underflow"))?;
+        let merged_total = retired_total
+            .checked_add(sell_rate)
+            .ok_or(OrderError::Overflow("Rate overflow"))?;
+        self.check_rate_cap_excluding(a.direction, merged_total, current_block, &[id_a, id_b])?;
+
+        match a.direction {
+            OrderDirection::SellToken0 => self.total_sell_rate_0 = retired_total,
+            OrderDirection::SellToken1 => self.total_sell_rate_1 = retired_total,
+        }
+        self.orders
+            .retain(|order| order.id != id_a && order.id != id_b);
+        let order_id = self.insert_order(order)?;
+
+        for mut retired in [a, b] {
+            retired.status = OrderStatus::Cancelled;
+            self.record_history(retired);
+            events::emit(events::order_cancelled(&retired));
+        }
+
+        console!("Merged orders {} and {} into {}", id_a, id_b, order_id);
+        Ok(order_id)
+    }
+
+    /// Explain why an order is not open, telling recent cancellations from unknown ids
+    /// The history is capped at MAX_ORDER_HISTORY, so this is best effort
+    fn missing_order_error(&self, order_id: U256) -> OrderError {
+        let cancelled = self
+            .history
+            .iter()
+            .any(|order| order.id == order_id && order.status == OrderStatus::Cancelled);
+
+        if cancelled {
+            OrderError::InvalidState("Order already cancelled")
+        } else {
+            OrderError::NotFound
+        }
+    }
+
+    /// Cancel every order owned by caller, all or nothing
+    /// Returns (order_id, refund_sold_token, refund_received_token) per order, as
+    /// cancel_order_with_refund does
+    pub fn cancel_all_orders(
+        &mut self,
+        caller: Address,
+        current_block: U256,
+    ) -> Result<Vec<(U256, U256, U256)>, OrderError> {
+        let owned: Vec<Order> = self
+            .orders
+            .iter()
+            .filter(|order| order.owner == caller)
+            .copied()
+            .collect();
+
+        // Check every cancellation before removing any, so a failure leaves the pool untouched
+        for order in &owned {
+            self.check_cancel_window(order, current_block)?;
+        }
+        self.check_rates_cover(&owned)?;
+
+        let mut refunds = Vec::with_capacity(owned.len());
+        for order in owned {
+            let order_index = self.order_index(order.id).ok_or(OrderError::NotFound)?;
+            let cancelled = self.remove_cancelled_order(order_index)?;
+            refunds.push((
+                cancelled.id,
+                cancelled.remaining_amount,
+                cancelled.accumulated_out,
+            ));
+        }
+
+        console!("Cancelled {} orders for owner", refunds.len());
+        Ok(refunds)
+    }
+
+    /// Cancel caller's orders that have reached their end block, returning their ids
+    /// Orders still streaming are left untouched, as are the halted and paused ones
+    /// sweep_expired keeps. All or nothing, like cancel_all_orders
+    pub fn cancel_expired_for_owner(
+        &mut self,
+        caller: Address,
+        current_block: U256,
+    ) -> Result<Vec<U256>, OrderError> {
+        let expired: Vec<Order> = self
+            .orders
+            .iter()
+            .filter(|order| order.owner == caller && Self::sweepable(order, current_block))
+            .copied()
+            .collect();
+        self.check_rates_cover(&expired)?;
+
+        let mut order_ids = Vec::with_capacity(expired.len());
+        for order in expired {
+            let order_index = self.order_index(order.id).ok_or(OrderError::NotFound)?;
+            order_ids.push(self.remove_cancelled_order(order_index)?.id);
+        }
+
+        console!("Cancelled {} expired orders for owner", order_ids.len());
+        Ok(order_ids)
+    }
+
+    /// Swap sell_amount at once against the pool, recording it as a completed Instant order
+    /// current_reserves is (reserve_0, reserve_1) before pending virtual orders
+    ///
+    /// Pending virtual orders are executed up to current_block first, so the swap trades
+    /// at the current price. The pool fee is charged as in TWAMMath::get_amount_out and
+    /// kept in the reserves like the virtual execution fee. Fails without changing state
+    /// if the output is below min_amount_out or virtual orders could not catch up.
+    pub fn execute_instant_order(
+        &mut self,
+        owner: Address,
+        direction: OrderDirection,
+        sell_amount: U256,
+        min_amount_out: U256,
+        current_block: U256,
+        current_reserves: (U256, U256),
+    ) -> Result<InstantSwapResult, OrderError> {
+        if self.paused {
+            return Err(OrderError::Paused);
+        }
+        if sell_amount == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid order parameters"));
+        }
+
+        // Work on a copy so a failed swap also undoes the virtual execution
+        let mut pool = self.fork();
+        let virtual_execution =
+            pool.execute_virtual_orders(current_block, current_reserves.0, current_reserves.1)?;
+        if virtual_execution.blocks_remaining > U256::ZERO {
+            return Err(OrderError::InvalidState("Virtual orders pending"));
+        }
+
+        let (reserve_in, reserve_out) = match direction {
+            OrderDirection::SellToken0 => (
+                virtual_execution.new_reserve_0,
+                virtual_execution.new_reserve_1,
+            ),
+            OrderDirection::SellToken1 => (
+                virtual_execution.new_reserve_1,
+                virtual_execution.new_reserve_0,
+            ),
+        };
+        let amount_out =
+            TWAMMath::get_amount_out(sell_amount, reserve_in, reserve_out, pool.fee_bps)
+                .map_err(|_| OrderError::MathFailed("Swap calculation failed"))?;
+        if amount_out == U256::ZERO || amount_out < min_amount_out {
+            return Err(OrderError::InvalidParams("Insufficient output"));
+        }
+
+        let fee = sell_amount - pool.apply_fee(sell_amount)?;
+        let new_reserve_in = reserve_in
+            .checked_add(sell_amount)
+            .ok_or(OrderError::Overflow("Reserve overflow"))?;
+        let new_reserve_out = reserve_out - amount_out;
+        let (new_reserve_0, new_reserve_1) = match direction {
+            OrderDirection::SellToken0 => {
+                pool.collected_fees_0 = pool
+                    .collected_fees_0
+                    .checked_add(fee)
+                    .ok_or(OrderError::Overflow("Fee accumulation overflow"))?;
+                (new_reserve_in, new_reserve_out)
+            }
+            OrderDirection::SellToken1 => {
+                pool.collected_fees_1 = pool
+                    .collected_fees_1
+                    .checked_add(fee)
+                    .ok_or(OrderError::Overflow("Fee accumulation overflow"))?;
+                (new_reserve_out, new_reserve_in)
+            }
+        };
+        if pool.reserve_tracking {
+            pool.stored_reserve_0 = new_reserve_0;
+            pool.stored_reserve_1 = new_reserve_1;
+        }
+
+        let order_id = pool.derive_order_id(pool.next_order_id, owner, current_block);
+        pool.next_order_id = pool
+            .next_order_id
+            .checked_add(U256::from(1u32))
+            .ok_or(OrderError::Overflow("Order ID overflow"))?;
+        pool.record_history(Order {
+            id: order_id,
+            owner,
+            order_type: OrderType::Instant,
+            direction,
+            initial_amount: sell_amount,
+            start_block: current_block,
+            end_block: current_block,
+            last_virtual_order_block: current_block,
+            accumulated_out: amount_out,
+            status: OrderStatus::Completed,
+            ..Order::default()
+        });
+        self.join_fork(pool);
+        events::emit(events::instant_swap(
+            owner,
+            direction,
+            sell_amount,
+            amount_out,
+            current_block,
+        ));
+
+        console!("Executed instant order {} for {}", order_id, amount_out);
+        Ok(InstantSwapResult {
+            order_id,
+            amount_out,
             new_reserve_0,
             new_reserve_1,
-            gas_used_estimate: gas_estimate,
+            virtual_execution,
         })
     }
 
-    /// Get active sell rates for a given time period
-    fn get_active_sell_rates(
-        &self,
-        start_block: U256,
-        end_block: U256,
-    ) -> Result<(U256, U256), Vec<u8>> {
-        let mut total_rate_0 = U256::ZERO;
-        let mut total_rate_1 = U256::ZERO;
+    /// Swap amount_in at once against the given reserves, returning the output
+    ///
+    /// No streaming order is created. The swap happens at the last executed block, so
+    /// execute virtual orders first for the reserves to reflect the current price; see
+    /// execute_instant_order to do both in one call.
+    pub fn execute_instant_swap(
+        &mut self,
+        owner: Address,
+        direction: OrderDirection,
+        amount_in: U256,
+        min_out: U256,
+        reserve_0: U256,
+        reserve_1: U256,
+    ) -> Result<U256, OrderError> {
+        let last_block = self.virtual_order_state.last_virtual_order_block;
+        let result = self.execute_instant_order(
+            owner,
+            direction,
+            amount_in,
+            min_out,
+            last_block,
+            (reserve_0, reserve_1),
+        )?;
 
-        for order in &self.orders {
-            if order.order_type != OrderType::LongTerm {
-                continue;
+        Ok(result.amount_out)
+    }
+
+    /// Pause an order so it stops streaming without losing its place
+    /// The pause takes effect from the last executed block, so execute virtual orders first
+    /// for it to apply from the current block
+    pub fn pause_order(&mut self, order_id: U256, caller: Address) -> Result<(), OrderError> {
+        let last_block = self.virtual_order_state.last_virtual_order_block;
+        let order = self
+            .orders
+            .iter_mut()
+            .find(|order| order.id == order_id)
+            .ok_or(OrderError::NotFound)?;
+
+        if order.owner != caller {
+            return Err(OrderError::NotOwner);
+        }
+        if order.status != OrderStatus::Active {
+            return Err(OrderError::InvalidState("Order not active"));
+        }
+        if !order.active {
+            return Err(OrderError::InvalidState("Order already paused"));
+        }
+
+        order.active = false;
+        order.paused_at = last_block.max(order.start_block);
+        let (direction, sell_rate) = (order.direction, order.sell_rate);
+
+        match direction {
+            OrderDirection::SellToken0 => {
+                self.total_sell_rate_0 = self
+                    .total_sell_rate_0
+                    .checked_sub(sell_rate)
+                    .ok_or(OrderError::Overflow("Rate underflow"))?;
+            }
+            OrderDirection::SellToken1 => {
+                self.total_sell_rate_1 = self
+                    .total_sell_rate_1
+                    .checked_sub(sell_rate)
+                    .ok_or(OrderError::Overflow("Rate underflow"))?;
             }
+        }
+
+        console!("Paused order {}", order_id);
+        Ok(())
+    }
+
+    /// Resume a paused order from the last executed block
+    /// end_block moves out by the blocks spent paused, so the full amount still streams
+    pub fn resume_order(&mut self, order_id: U256, caller: Address) -> Result<(), OrderError> {
+        let last_block = self.virtual_order_state.last_virtual_order_block;
+        let order_index = self
+            .orders
+            .iter()
+            .position(|order| order.id == order_id)
+            .ok_or(OrderError::NotFound)?;
+        let order = self.orders[order_index];
+
+        if order.owner != caller {
+            return Err(OrderError::NotOwner);
+        }
+        if order.active {
+            return Err(OrderError::InvalidState("Order not paused"));
+        }
 
-            // Check if order is active during this period
-            if order.end_block <= start_block || order.start_block >= end_block {
-                continue; // Order not active in this period
+        let end_block = order
+            .end_block
+            .checked_add(last_block.saturating_sub(order.paused_at))
+            .ok_or(OrderError::Overflow("Block overflow"))?;
+
+        match order.direction {
+            OrderDirection::SellToken0 => {
+                let total_sell_rate_0 = self
+                    .total_sell_rate_0
+                    .checked_add(order.sell_rate)
+                    .ok_or(OrderError::Overflow("Rate overflow"))?;
+                self.check_rate_cap(order.direction, total_sell_rate_0, last_block)?;
+                self.total_sell_rate_0 = total_sell_rate_0;
             }
+            OrderDirection::SellToken1 => {
+                let total_sell_rate_1 = self
+                    .total_sell_rate_1
+                    .checked_add(order.sell_rate)
+                    .ok_or(OrderError::Overflow("Rate overflow"))?;
+                self.check_rate_cap(order.direction, total_sell_rate_1, last_block)?;
+                self.total_sell_rate_1 = total_sell_rate_1;
+            }
+        }
 
-            // Calculate the effective rate for this time period
-            let effective_start = order.start_block.max(start_block);
-            let effective_end = order.end_block.min(end_block);
+        let order = &mut self.orders[order_index];
+        order.active = true;
+        order.end_block = end_block;
+        order.paused_at = U256::ZERO;
 
-            if effective_end > effective_start {
-                match order.direction {
-                    OrderDirection::SellToken0 => {
-                        total_rate_0 = total_rate_0
-                            .checked_add(order.sell_rate)
-                            .ok_or(b"Rate calculation overflow".to_vec())?;
-                    }
-                    OrderDirection::SellToken1 => {
-                        total_rate_1 = total_rate_1
-                            .checked_add(order.sell_rate)
-                            .ok_or(b"Rate calculation overflow".to_vec())?;
-                    }
-                }
+        console!("Resumed order {} until block {}", order_id, end_block);
+        Ok(())
+    }
+
+    /// Make an order stream only while the pool price is on the given side of price_limit
+    /// Passing None removes the limit
+    pub fn set_order_price_limit(
+        &mut self,
+        order_id: U256,
+        caller: Address,
+        price_limit: Option<U256>,
+        limit_side: LimitSide,
+    ) -> Result<(), OrderError> {
+        let order = self
+            .orders
+            .iter_mut()
+            .find(|order| order.id == order_id)
+            .ok_or(OrderError::NotFound)?;
+
+        if order.owner != caller {
+            return Err(OrderError::NotOwner);
+        }
+
+        order.price_limit = price_limit;
+        order.limit_side = limit_side;
+
+        console!("Updated price limit for order {}", order_id);
+        Ok(())
+    }
+
+    /// Make an order pace its stream against target_price instead of its fixed sell rate
+    /// Passing None restores the fixed rate
+    pub fn set_order_target_price(
+        &mut self,
+        order_id: U256,
+        caller: Address,
+        target_price: Option<U256>,
+    ) -> Result<(), OrderError> {
+        if target_price == Some(U256::ZERO) {
+            return Err(OrderError::InvalidParams("Invalid target price"));
+        }
+
+        let order = self
+            .orders
+            .iter_mut()
+            .find(|order| order.id == order_id)
+            .ok_or(OrderError::NotFound)?;
+
+        if order.owner != caller {
+            return Err(OrderError::NotOwner);
+        }
+
+        order.target_price = target_price;
+
+        console!("Updated target price for order {}", order_id);
+        Ok(())
+    }
+
+    /// Split an order's proceeds between several beneficiaries by weight
+    pub fn set_order_beneficiaries(
+        &mut self,
+        order_id: U256,
+        caller: Address,
+        beneficiaries: Vec<(Address, U256)>,
+    ) -> Result<(), OrderError> {
+        let order = self
+            .orders
+            .iter()
+            .find(|order| order.id == order_id)
+            .ok_or(OrderError::NotFound)?;
+
+        if order.owner != caller {
+            return Err(OrderError::NotOwner);
+        }
+
+        if beneficiaries.is_empty() {
+            return Err(OrderError::InvalidParams("No beneficiaries"));
+        }
+
+        let mut total_weight = U256::ZERO;
+        for (beneficiary, weight) in &beneficiaries {
+            if *beneficiary == Address::ZERO || *weight == U256::ZERO {
+                return Err(OrderError::InvalidParams("Invalid beneficiary"));
             }
+            total_weight = total_weight
+                .checked_add(*weight)
+                .ok_or(OrderError::Overflow("Weight overflow"))?;
+        }
+
+        if total_weight != U256::from(BENEFICIARY_WEIGHT_TOTAL) {
+            return Err(OrderError::InvalidParams(
+                "Beneficiary weights must sum to total",
+            ));
+        }
+
+        self.remove_beneficiaries(order_id);
+        self.order_beneficiaries.push((order_id, beneficiaries));
+
+        console!("Set beneficiaries for order {}", order_id);
+        Ok(())
+    }
+
+    /// Get the beneficiaries configured for an order, if any
+    pub fn get_order_beneficiaries(&self, order_id: U256) -> Option<Vec<(Address, U256)>> {
+        self.order_beneficiaries
+            .iter()
+            .find(|(id, _)| *id == order_id)
+            .map(|(_, beneficiaries)| beneficiaries.clone())
+    }
+
+    fn remove_beneficiaries(&mut self, order_id: U256) {
+        self.order_beneficiaries.retain(|(id, _)| *id != order_id);
+    }
+
+    /// Claim accumulated proceeds of an order
+    /// Returns the payouts as (recipient, amount), split by weight when beneficiaries are set
+    /// Completed orders are paid from unclaimed_proceeds, which drops them and their
+    /// beneficiaries once paid; cancelled orders were paid out when they were cancelled,
+    /// and instant orders when they swapped
+    pub fn claim_proceeds(
+        &mut self,
+        order_id: U256,
+        caller: Address,
+    ) -> Result<Vec<(Address, U256)>, OrderError> {
+        let (proceeds, completed) = match self.orders.iter_mut().find(|order| order.id == order_id)
+        {
+            Some(order) => {
+                if order.owner != caller {
+                    return Err(OrderError::NotOwner);
+                }
+
+                let proceeds = order.accumulated_out;
+                order.accumulated_out = U256::ZERO;
+                order.benchmark_out = U256::ZERO;
+                (proceeds, false)
+            }
+            None => {
+                let index = self
+                    .unclaimed_proceeds
+                    .iter()
+                    .position(|(id, _, _)| *id == order_id)
+                    .ok_or(OrderError::NotFound)?;
+                if self.unclaimed_proceeds[index].1 != caller {
+                    return Err(OrderError::NotOwner);
+                }
+
+                let (_, _, proceeds) = self.unclaimed_proceeds.remove(index);
+                if let Some(order) = self.history.iter_mut().find(|order| order.id == order_id) {
+                    order.accumulated_out = U256::ZERO;
+                    order.benchmark_out = U256::ZERO;
+                }
+                (proceeds, true)
+            }
+        };
+
+        let beneficiaries = self.get_order_beneficiaries(order_id);
+        if completed {
+            self.remove_beneficiaries(order_id);
+        }
+        let beneficiaries = match beneficiaries {
+            Some(beneficiaries) => beneficiaries,
+            None => {
+                console!("Claimed {} from order {}", proceeds, order_id);
+                return Ok(alloc::vec![(caller, proceeds)]);
+            }
+        };
+
+        let total_weight = U256::from(BENEFICIARY_WEIGHT_TOTAL);
+        let mut payouts = Vec::with_capacity(beneficiaries.len());
+        let mut distributed = U256::ZERO;
+
+        for (index, (beneficiary, weight)) in beneficiaries.iter().enumerate() {
+            // Last beneficiary receives the rounding remainder
+            let amount = if index == beneficiaries.len() - 1 {
+                proceeds - distributed
+            } else {
+                TWAMMath::mul_div(proceeds, *weight, total_weight)
+                    .map_err(|_| OrderError::Overflow("Payout calculation overflow"))?
+            };
+
+            distributed += amount;
+            payouts.push((*beneficiary, amount));
+        }
+
+        console!("Claimed {} from order {}", proceeds, order_id);
+        Ok(payouts)
+    }
+
+    /// Settle a single order up to current_block without executing the rest of the pool
+    /// Returns the proceeds newly credited to the order
+    ///
+    /// The order is credited with its share of a preview of full execution at the given
+    /// reserves, and later executions skip the blocks it has settled. The pool itself does
+    /// not advance, so if the reserves seen by the next full execution differ from these
+    /// (for example after swaps), what the pool pays for those blocks will not match what
+    /// was credited here.
+    pub fn settle_order(
+        &mut self,
+        order_id: U256,
+        current_block: U256,
+        current_reserve_0: U256,
+        current_reserve_1: U256,
+    ) -> Result<U256, OrderError> {
+        let order_index = self
+            .orders
+            .iter()
+            .position(|order| order.id == order_id)
+            .ok_or(OrderError::NotFound)?;
+
+        let mut preview = self.fork();
+        preview.execute_virtual_orders_inner(
+            current_block,
+            current_reserve_0,
+            current_reserve_1,
+            None,
+        )?;
+        let settled = preview.get_order(order_id).ok_or(OrderError::NotFound)?;
+
+        let order = &mut self.orders[order_index];
+        let credited = settled
+            .accumulated_out
+            .checked_sub(order.accumulated_out)
+            .ok_or(OrderError::MathFailed("Settlement calculation error"))?;
+
+        order.accumulated_out = settled.accumulated_out;
+        order.benchmark_out = settled.benchmark_out;
+        order.matched_volume = settled.matched_volume;
+        order.curve_volume = settled.curve_volume;
+        order.remaining_amount = settled.remaining_amount;
+        order.last_virtual_order_block = settled.last_virtual_order_block;
+
+        console!("Settled {} for order {}", credited, order_id);
+        Ok(credited)
+    }
+
+    /// Execute virtual orders up to current block
+    pub fn execute_virtual_orders(
+        &mut self,
+        current_block: U256,
+        current_reserve_0: U256,
+        current_reserve_1: U256,
+    ) -> Result<VirtualExecutionResult, OrderError> {
+        let last_block = self.virtual_order_state.last_virtual_order_block;
+        let result = self.execute_virtual_orders_inner(
+            current_block,
+            current_reserve_0,
+            current_reserve_1,
+            None,
+        )?;
+        Self::emit_execution(last_block, &result);
+
+        Ok(result)
+    }
+
+    /// Execute virtual orders up to current block, also returning each order's fill
+    /// Fills are (order_id, amount received this round) for every order that sold in the window
+    pub fn execute_virtual_orders_detailed(
+        &mut self,
+        current_block: U256,
+        current_reserve_0: U256,
+        current_reserve_1: U256,
+    ) -> Result<(VirtualExecutionResult, OrderFills), OrderError> {
+        let last_block = self.virtual_order_state.last_virtual_order_block;
+        let mut fills = Vec::new();
+        let result = self.execute_virtual_orders_inner(
+            current_block,
+            current_reserve_0,
+            current_reserve_1,
+            Some(&mut fills),
+        )?;
+        Self::emit_execution(last_block, &result);
+
+        Ok((result, fills))
+    }
+
+    /// Execute virtual orders only once the block interval has elapsed or the price has
+    /// drifted past max_price_deviation_bps
+    /// Returns None without touching state when execution is not yet due
+    pub fn execute_if_due(
+        &mut self,
+        current_block: U256,
+        current_reserve_0: U256,
+        current_reserve_1: U256,
+    ) -> Result<Option<VirtualExecutionResult>, OrderError> {
+        if !self.needs_virtual_order_execution(current_block, current_reserve_0, current_reserve_1)
+        {
+            return Ok(None);
+        }
+
+        self.execute_virtual_orders(current_block, current_reserve_0, current_reserve_1)
+            .map(Some)
+    }
+
+    /// Replay missed executions in interval-sized steps up to target_block, all or nothing
+    ///
+    /// Each step is a full execution against the reserves the previous one left, with its
+    /// own price reads, reserve floor and keeper reward, as regular executions every
+    /// interval blocks would have been. A single catch-up only re-reads prices every
+    /// order_block_interval. The last step ends at target_block even if it is shorter.
+    /// Returns each step's result in order.
+    pub fn backfill_executions(
+        &mut self,
+        target_block: U256,
+        interval: U256,
+        reserve_0: U256,
+        reserve_1: U256,
+    ) -> Result<Vec<VirtualExecutionResult>, OrderError> {
+        if interval == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid block interval"));
+        }
+        if target_block < self.virtual_order_state.last_virtual_order_block {
+            return Err(OrderError::InvalidState("block not advanced"));
+        }
+
+        let mut pool = self.fork();
+        let mut results = Vec::new();
+        let (mut reserve_0, mut reserve_1) = (reserve_0, reserve_1);
+        let mut step_block = pool.virtual_order_state.last_virtual_order_block;
+        while step_block < target_block {
+            step_block = step_block.saturating_add(interval).min(target_block);
+            let result = pool.execute_virtual_orders(step_block, reserve_0, reserve_1)?;
+            (reserve_0, reserve_1) = (result.new_reserve_0, result.new_reserve_1);
+            results.push(result);
+        }
+
+        self.join_fork(pool);
+        Ok(results)
+    }
+
+    /// Preview executing virtual orders up to current_block without changing any state
+    pub fn preview_virtual_execution(
+        &self,
+        current_block: U256,
+        current_reserve_0: U256,
+        current_reserve_1: U256,
+    ) -> Result<VirtualExecutionResult, OrderError> {
+        self.fork().execute_virtual_orders_inner(
+            current_block,
+            current_reserve_0,
+            current_reserve_1,
+            None,
+        )
+    }
+
+    /// Get an owner's claimable proceeds as (token0, token1) if execution ran to current_block
+    /// Covers every open order of the owner, including ones that would complete by then
+    pub fn total_claimable(
+        &self,
+        owner: Address,
+        current_block: U256,
+        current_reserve_0: U256,
+        current_reserve_1: U256,
+    ) -> Result<(U256, U256), OrderError> {
+        let mut preview = self.fork();
+        preview.execute_virtual_orders_inner(
+            current_block,
+            current_reserve_0,
+            current_reserve_1,
+            None,
+        )?;
+
+        let mut claimable_0 = U256::ZERO;
+        let mut claimable_1 = U256::ZERO;
+
+        for order in self.orders.iter().filter(|order| order.owner == owner) {
+            // Orders completing in the preview move to its history with their proceeds
+            let proceeds = preview
+                .get_order(order.id)
+                .map(|order| order.accumulated_out)
+                .unwrap_or(order.accumulated_out);
+
+            match order.direction {
+                OrderDirection::SellToken0 => {
+                    claimable_1 = claimable_1
+                        .checked_add(proceeds)
+                        .ok_or(OrderError::Overflow("Calculation overflow"))?;
+                }
+                OrderDirection::SellToken1 => {
+                    claimable_0 = claimable_0
+                        .checked_add(proceeds)
+                        .ok_or(OrderError::Overflow("Calculation overflow"))?;
+                }
+            }
+        }
+
+        Ok((claimable_0, claimable_1))
+    }
+
+    /// Mark an owner's open orders to market as (value_in_token0, value_in_token1)
+    ///
+    /// Each order holds its unsold remaining_amount of the sell token and its unclaimed
+    /// accumulated_out of the other; both are valued at the spot price of the given
+    /// reserves, so the two results are the same value in either token. Compare against
+    /// the initial amounts for the cost basis. Token1 holdings cannot be valued when the
+    /// spot price rounds to zero at the given precision; use a higher precision then.
+    pub fn owner_unrealized_value(
+        &self,
+        owner: Address,
+        reserve_0: U256,
+        reserve_1: U256,
+        precision: u32,
+    ) -> Result<(U256, U256), OrderError> {
+        let price = Self::spot_price(reserve_0, reserve_1, precision)?;
+        let one = U256::from(10u128.pow(precision));
+
+        let mut holding_0 = U256::ZERO;
+        let mut holding_1 = U256::ZERO;
+        for order in self.orders.iter().filter(|order| order.owner == owner) {
+            let (sold_token, bought_token) = match order.direction {
+                OrderDirection::SellToken0 => (&mut holding_0, &mut holding_1),
+                OrderDirection::SellToken1 => (&mut holding_1, &mut holding_0),
+            };
+            *sold_token = sold_token
+                .checked_add(order.remaining_amount)
+                .ok_or(OrderError::Overflow("Calculation overflow"))?;
+            *bought_token = bought_token
+                .checked_add(order.accumulated_out)
+                .ok_or(OrderError::Overflow("Calculation overflow"))?;
+        }
+
+        if price == U256::ZERO && holding_1 > U256::ZERO {
+            return Err(OrderError::MathFailed("Spot price rounds to zero"));
+        }
+
+        let holding_0_in_1 = TWAMMath::mul_div(holding_0, price, one)
+            .map_err(|_| OrderError::Overflow("Value overflow"))?;
+        let holding_1_in_0 = if holding_1 == U256::ZERO {
+            U256::ZERO
+        } else {
+            TWAMMath::mul_div(holding_1, one, price)
+                .map_err(|_| OrderError::Overflow("Value overflow"))?
+        };
+
+        let value_in_token0 = holding_0
+            .checked_add(holding_1_in_0)
+            .ok_or(OrderError::Overflow("Value overflow"))?;
+        let value_in_token1 = holding_1
+            .checked_add(holding_0_in_1)
+            .ok_or(OrderError::Overflow("Value overflow"))?;
+
+        Ok((value_in_token0, value_in_token1))
+    }
+
+    /// Get the constant-product invariant k = reserve_0 * reserve_1
+    /// Saturates at U256::MAX for reserves too large to multiply
+    pub fn pool_invariant(reserve_0: U256, reserve_1: U256) -> U256 {
+        TWAMMath::mul_div_wide(reserve_0, reserve_1, U256::from(1u32)).unwrap_or(U256::MAX)
+    }
+
+    /// Preview a liquidity add as (new_reserve_0, new_reserve_1, new_k)
+    /// Pending orders keep their sell rates, so deeper reserves mean less impact per block;
+    /// see preview_flow_impact_after_liquidity
+    pub fn preview_liquidity_change(
+        added_0: U256,
+        added_1: U256,
+        current_reserve_0: U256,
+        current_reserve_1: U256,
+    ) -> Result<(U256, U256, U256), OrderError> {
+        let new_reserve_0 = current_reserve_0
+            .checked_add(added_0)
+            .ok_or(OrderError::Overflow("Reserve overflow"))?;
+        let new_reserve_1 = current_reserve_1
+            .checked_add(added_1)
+            .ok_or(OrderError::Overflow("Reserve overflow"))?;
+        if new_reserve_0 == U256::ZERO || new_reserve_1 == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid reserves"));
+        }
+
+        let new_k = new_reserve_0
+            .checked_mul(new_reserve_1)
+            .ok_or(OrderError::Overflow("Invariant overflow"))?;
+        Ok((new_reserve_0, new_reserve_1, new_k))
+    }
+
+    /// Get the per-block price impact of the aggregate sell flow before and after a liquidity
+    /// add as (before, after)
+    /// Each direction's total sell rate is priced alone for one block against the
+    /// reserves, so opposing flow and ramping are ignored.
+    pub fn preview_flow_impact_after_liquidity(
+        &self,
+        added_0: U256,
+        added_1: U256,
+        current_reserve_0: U256,
+        current_reserve_1: U256,
+    ) -> Result<(FlowImpact, FlowImpact), OrderError> {
+        let (new_reserve_0, new_reserve_1, _) =
+            Self::preview_liquidity_change(added_0, added_1, current_reserve_0, current_reserve_1)?;
+
+        let flow_impact = |reserve_0: U256, reserve_1: U256| -> Result<FlowImpact, OrderError> {
+            let impact = |sell_rate: U256, direction: OrderDirection| {
+                TWAMMath::calculate_price_impact_directional(
+                    sell_rate, reserve_0, reserve_1, direction, PRECISION,
+                )
+                .and_then(|impact| TWAMMath::fixed_to_bps(impact, PRECISION))
+                .map_err(OrderError::MathFailed)
+            };
+            Ok((
+                impact(self.total_sell_rate_0, OrderDirection::SellToken0)?,
+                impact(self.total_sell_rate_1, OrderDirection::SellToken1)?,
+            ))
+        };
+
+        Ok((
+            flow_impact(current_reserve_0, current_reserve_1)?,
+            flow_impact(new_reserve_0, new_reserve_1)?,
+        ))
+    }
+
+    /// Get the spot price of token0 in token1 (reserve_1 / reserve_0) scaled to precision
+    pub fn spot_price(
+        reserve_0: U256,
+        reserve_1: U256,
+        precision: u32,
+    ) -> Result<U256, OrderError> {
+        if reserve_0 == U256::ZERO || reserve_1 == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid reserves"));
+        }
+
+        let one = U256::from(10u128.pow(precision));
+        TWAMMath::mul_div_wide(reserve_1, one, reserve_0)
+            .ok_or(OrderError::Overflow("Price overflow"))
+    }
+
+    /// Get the spot price of token0 in token1 as the unreduced fraction (reserve_1, reserve_0)
+    /// Unlike spot_price nothing is rounded, so extreme reserve ratios keep their precision
+    pub fn spot_price_fraction(
+        reserve_0: U256,
+        reserve_1: U256,
+    ) -> Result<(U256, U256), OrderError> {
+        if reserve_0 == U256::ZERO || reserve_1 == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid reserves"));
+        }
+
+        Ok((reserve_1, reserve_0))
+    }
+
+    /// Get the spot price the pool would reach after executing pending virtual orders
+    pub fn marginal_price_after_execution(
+        &self,
+        current_block: U256,
+        current_reserve_0: U256,
+        current_reserve_1: U256,
+        precision: u32,
+    ) -> Result<U256, OrderError> {
+        let result =
+            self.preview_virtual_execution(current_block, current_reserve_0, current_reserve_1)?;
+
+        Self::spot_price(result.new_reserve_0, result.new_reserve_1, precision)
+    }
+
+    /// Get the fair mid price for self-matching, token1 per token0 scaled by 10^precision
+    ///
+    /// The flows pending from the last executed block to current_block are netted at the
+    /// spot price and only the imbalance is swapped against the curve, without fees, so
+    /// heavier SellToken0 pressure moves the mid below spot and heavier SellToken1 pressure
+    /// above it. Balanced or no pending flow gives the spot price. Unlike
+    /// marginal_price_after_execution this does not simulate the execution itself.
+    pub fn fair_mid_price(
+        &self,
+        current_block: U256,
+        reserve_0: U256,
+        reserve_1: U256,
+        precision: u32,
+    ) -> Result<U256, OrderError> {
+        if reserve_0 == U256::ZERO || reserve_1 == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid reserves"));
+        }
+
+        let last_block = self.virtual_order_state.last_virtual_order_block;
+        let spot_price = if self.has_price_dependent_orders() {
+            Some(Self::spot_price(
+                reserve_0,
+                reserve_1,
+                LIMIT_PRICE_PRECISION,
+            )?)
+        } else {
+            None
+        };
+        let (pending_0, pending_1) =
+            self.get_active_sell_volumes(last_block, current_block.max(last_block), spot_price)?;
+
+        let (sells_token0, net_amount) =
+            TWAMMath::net_flow(reserve_0, reserve_1, pending_0, pending_1)
+                .map_err(|_| OrderError::MathFailed("Net flow calculation failed"))?;
+        if net_amount == U256::ZERO {
+            return Self::spot_price(reserve_0, reserve_1, precision);
+        }
+
+        // Swap the imbalance along x * y = k
+        let (new_reserve_0, new_reserve_1) = if sells_token0 {
+            let new_reserve_0 = reserve_0
+                .checked_add(net_amount)
+                .ok_or(OrderError::Overflow("Reserve overflow"))?;
+            let new_reserve_1 = TWAMMath::mul_div(reserve_0, reserve_1, new_reserve_0)
+                .map_err(|_| OrderError::Overflow("Reserve overflow"))?;
+            (new_reserve_0, new_reserve_1)
+        } else {
+            let new_reserve_1 = reserve_1
+                .checked_add(net_amount)
+                .ok_or(OrderError::Overflow("Reserve overflow"))?;
+            let new_reserve_0 = TWAMMath::mul_div(reserve_0, reserve_1, new_reserve_1)
+                .map_err(|_| OrderError::Overflow("Reserve overflow"))?;
+            (new_reserve_0, new_reserve_1)
+        };
+
+        Self::spot_price(new_reserve_0, new_reserve_1, precision)
+    }
+
+    /// Log an execution round, skipping calls that had nothing to execute
+    fn emit_execution(last_block: U256, result: &VirtualExecutionResult) {
+        if result.blocks_executed > U256::ZERO {
+            events::emit(events::virtual_orders_executed(
+                last_block,
+                last_block + result.blocks_executed,
+                result,
+            ));
+        }
+    }
+
+    fn execute_virtual_orders_inner(
+        &mut self,
+        current_block: U256,
+        current_reserve_0: U256,
+        current_reserve_1: U256,
+        mut fills: Option<&mut OrderFills>,
+    ) -> Result<VirtualExecutionResult, OrderError> {
+        if self.paused {
+            return Err(OrderError::Paused);
+        }
+
+        // Execute on a copy so a zero-output round can be rejected without advancing
+        if self.strict_execution {
+            let mut pool = self.fork();
+            pool.strict_execution = false;
+            let result = pool.execute_virtual_orders_inner(
+                current_block,
+                current_reserve_0,
+                current_reserve_1,
+                fills,
+            )?;
+
+            let sold = result.amount_0_sold > U256::ZERO || result.amount_1_sold > U256::ZERO;
+            if sold
+                && result.amount_0_received == U256::ZERO
+                && result.amount_1_received == U256::ZERO
+            {
+                return Err(OrderError::InvalidParams("zero output execution"));
+            }
+
+            pool.strict_execution = true;
+            self.join_fork(pool);
+            return Ok(result);
+        }
+
+        if self.reserves_look_swapped(current_reserve_0, current_reserve_1) {
+            return Err(OrderError::InvalidParams("Reserves appear swapped"));
+        }
+
+        // With tracking on the stored reserves are authoritative and the caller's must agree
+        let (current_reserve_0, current_reserve_1) = if self.reserve_tracking {
+            self.check_reserves(current_reserve_0, current_reserve_1)?;
+            (self.stored_reserve_0, self.stored_reserve_1)
+        } else {
+            (current_reserve_0, current_reserve_1)
+        };
+
+        let last_block = self.virtual_order_state.last_virtual_order_block;
+
+        if current_block < last_block {
+            return Err(OrderError::InvalidState("block not advanced"));
+        }
+
+        if current_block == last_block {
+            // No execution needed
+            return Ok(VirtualExecutionResult {
+                blocks_executed: U256::ZERO,
+                amount_0_sold: U256::ZERO,
+                amount_1_sold: U256::ZERO,
+                amount_0_received: U256::ZERO,
+                amount_1_received: U256::ZERO,
+                new_reserve_0: current_reserve_0,
+                new_reserve_1: current_reserve_1,
+                gas_used_estimate: U256::ZERO,
+                keeper_reward_0: U256::ZERO,
+                keeper_reward_1: U256::ZERO,
+                unfilled_0: U256::ZERO,
+                unfilled_1: U256::ZERO,
+                matched_0: U256::ZERO,
+                matched_1: U256::ZERO,
+                blocks_remaining: U256::ZERO,
+                k_before: Self::pool_invariant(current_reserve_0, current_reserve_1),
+                k_after: Self::pool_invariant(current_reserve_0, current_reserve_1),
+                expected_0: U256::ZERO,
+                expected_1: U256::ZERO,
+                realized_slippage_bps: U256::ZERO,
+                cancelled_payout_0: U256::ZERO,
+                cancelled_payout_1: U256::ZERO,
+            });
+        }
+
+        // Split the window at order start/expiry boundaries so sell rates are constant
+        // within each segment, chaining the closed-form reserves from one to the next
+        let mut new_reserve_0 = current_reserve_0;
+        let mut new_reserve_1 = current_reserve_1;
+        let mut amount_0_sold = U256::ZERO;
+        let mut amount_1_sold = U256::ZERO;
+        let mut amount_0_received = U256::ZERO;
+        let mut amount_1_received = U256::ZERO;
+        let mut unfilled_0 = U256::ZERO;
+        let mut unfilled_1 = U256::ZERO;
+        let mut matched_0 = U256::ZERO;
+        let mut matched_1 = U256::ZERO;
+        let mut segment_start = last_block;
+        let saturating = self.liquidity_policy != LiquidityPolicy::Halt;
+        let mut stranded_orders = Vec::new();
+
+        // Bound the work of a single call; the remaining gap is left for the next one
+        let execute_until = if self.max_blocks_per_execution > U256::ZERO {
+            current_block.min(last_block.saturating_add(self.max_blocks_per_execution))
+        } else {
+            current_block
+        };
+
+        for boundary in self.get_segment_boundaries(last_block, execute_until) {
+            // A drained reserve cannot price or absorb any flow; in saturating mode the
+            // segment's volume is reported unfilled and execution moves on
+            if saturating && (new_reserve_0 == U256::ZERO || new_reserve_1 == U256::ZERO) {
+                let (skipped_0, skipped_1) =
+                    self.get_active_sell_volumes(segment_start, boundary, None)?;
+                self.collect_stranded_orders(
+                    &mut stranded_orders,
+                    (segment_start, boundary),
+                    None,
+                )?;
+                unfilled_0 = unfilled_0
+                    .checked_add(skipped_0)
+                    .ok_or(OrderError::Overflow("Calculation overflow"))?;
+                unfilled_1 = unfilled_1
+                    .checked_add(skipped_1)
+                    .ok_or(OrderError::Overflow("Calculation overflow"))?;
+                segment_start = boundary;
+                continue;
+            }
+
+            // Price limits and target prices are read once per segment, at its starting price
+            let spot_price = if self.has_price_dependent_orders() {
+                Some(Self::spot_price(
+                    new_reserve_0,
+                    new_reserve_1,
+                    LIMIT_PRICE_PRECISION,
+                )?)
+            } else {
+                None
+            };
+
+            let (active_sell_rate_0, active_sell_rate_1) =
+                self.get_active_sell_rates(segment_start, boundary, spot_price)?;
+
+            // Only the post-fee rate trades against the curve
+            let effective_sell_rate_0 = self.apply_fee(active_sell_rate_0)?;
+            let effective_sell_rate_1 = self.apply_fee(active_sell_rate_1)?;
+
+            // Stop short of the boundary if the full segment would breach the reserve floor,
+            // leaving the rest of the window for later executions
+            let allowed_blocks = self.blocks_above_reserve_floor(
+                effective_sell_rate_0,
+                effective_sell_rate_1,
+                boundary - segment_start,
+                new_reserve_0,
+                new_reserve_1,
+            );
+            let throttled = allowed_blocks < boundary - segment_start;
+            if allowed_blocks == U256::ZERO {
+                break;
+            }
+
+            let segment_end = segment_start + allowed_blocks;
+            let segment_blocks = allowed_blocks;
+            let active_sell_volumes =
+                self.get_active_sell_volumes(segment_start, segment_end, spot_price)?;
+            let fee_0 = (active_sell_rate_0 - effective_sell_rate_0)
+                .checked_mul(segment_blocks)
+                .ok_or(OrderError::Overflow("Fee calculation overflow"))?;
+            let fee_1 = (active_sell_rate_1 - effective_sell_rate_1)
+                .checked_mul(segment_blocks)
+                .ok_or(OrderError::Overflow("Fee calculation overflow"))?;
+
+            // Use closed-form solution to calculate virtual order execution
+            let (segment_reserve_0, segment_reserve_1, segment_0_received, segment_1_received) =
+                match TWAMMath::execute_virtual_orders_closed_form(
+                    effective_sell_rate_0,
+                    effective_sell_rate_1,
+                    segment_blocks,
+                    new_reserve_0,
+                    new_reserve_1,
+                ) {
+                    Ok(state) => state,
+                    Err(_) if saturating => {
+                        self.collect_stranded_orders(
+                            &mut stranded_orders,
+                            (segment_start, segment_end),
+                            spot_price,
+                        )?;
+                        unfilled_0 = unfilled_0
+                            .checked_add(active_sell_volumes.0)
+                            .ok_or(OrderError::Overflow("Calculation overflow"))?;
+                        unfilled_1 = unfilled_1
+                            .checked_add(active_sell_volumes.1)
+                            .ok_or(OrderError::Overflow("Calculation overflow"))?;
+                        segment_start = segment_end;
+                        if throttled {
+                            break;
+                        }
+                        continue;
+                    }
+                    Err(e) => {
+                        return Err(match e.error {
+                            MathError::Overflow => {
+                                OrderError::Overflow("Math overflow in virtual execution")
+                            }
+                            MathError::DivisionByZero => {
+                                OrderError::MathFailed("Division by zero in virtual execution")
+                            }
+                            MathError::InvalidInput => {
+                                OrderError::InvalidParams("Invalid input for virtual execution")
+                            }
+                            MathError::ComputationFailed => {
+                                OrderError::MathFailed("Virtual execution computation failed")
+                            }
+                        })
+                    }
+                };
+
+            #[cfg(feature = "invariant-checks")]
+            check_curve_invariant(
+                new_reserve_0,
+                new_reserve_1,
+                effective_sell_rate_0 * segment_blocks,
+                effective_sell_rate_1 * segment_blocks,
+                segment_0_received,
+                segment_1_received,
+            )?;
+
+            let segment_flow_0 = effective_sell_rate_0 * segment_blocks;
+            let segment_flow_1 = effective_sell_rate_1 * segment_blocks;
+            let (segment_matched_0, segment_matched_1) = Self::matched_volumes(
+                new_reserve_0,
+                new_reserve_1,
+                segment_flow_0,
+                segment_flow_1,
+            )?;
+            // The same shares of the pre-fee volume snapshot, for splitting orders' sales
+            let matched_share = |matched: U256, flow: U256, volume: U256| {
+                if flow == U256::ZERO {
+                    return Ok(U256::ZERO);
+                }
+                TWAMMath::mul_div(volume, matched.min(flow), flow)
+                    .map_err(|_| OrderError::Overflow("Matching calculation overflow"))
+            };
+            let matched_sell_volumes = (
+                matched_share(segment_matched_0, segment_flow_0, active_sell_volumes.0)?,
+                matched_share(segment_matched_1, segment_flow_1, active_sell_volumes.1)?,
+            );
+            matched_0 = matched_0
+                .checked_add(segment_matched_0)
+                .ok_or(OrderError::Overflow("Calculation overflow"))?;
+            matched_1 = matched_1
+                .checked_add(segment_matched_1)
+                .ok_or(OrderError::Overflow("Calculation overflow"))?;
+
+            // Update order states against the segment's volume snapshot
+            self.update_orders_after_execution(
+                (new_reserve_0, new_reserve_1),
+                (segment_0_received, segment_1_received),
+                (active_sell_volumes, matched_sell_volumes),
+                (segment_start, segment_end),
+                spot_price,
+                fills.as_deref_mut(),
+            )?;
+
+            amount_0_sold = amount_0_sold
+                .checked_add(active_sell_volumes.0)
+                .ok_or(OrderError::Overflow("Calculation overflow"))?;
+            amount_1_sold = amount_1_sold
+                .checked_add(active_sell_volumes.1)
+                .ok_or(OrderError::Overflow("Calculation overflow"))?;
+            amount_0_received = amount_0_received
+                .checked_add(segment_0_received)
+                .ok_or(OrderError::Overflow("Calculation overflow"))?;
+            amount_1_received = amount_1_received
+                .checked_add(segment_1_received)
+                .ok_or(OrderError::Overflow("Calculation overflow"))?;
+
+            // Fees stay in the reserves until collected, growing k
+            new_reserve_0 = segment_reserve_0
+                .checked_add(fee_0)
+                .ok_or(OrderError::Overflow("Reserve overflow"))?;
+            new_reserve_1 = segment_reserve_1
+                .checked_add(fee_1)
+                .ok_or(OrderError::Overflow("Reserve overflow"))?;
+            self.collected_fees_0 = self
+                .collected_fees_0
+                .checked_add(fee_0)
+                .ok_or(OrderError::Overflow("Fee accumulation overflow"))?;
+            self.collected_fees_1 = self
+                .collected_fees_1
+                .checked_add(fee_1)
+                .ok_or(OrderError::Overflow("Fee accumulation overflow"))?;
+            segment_start = segment_end;
+
+            if throttled {
+                console!(
+                    "Throttled execution at block {} by reserve floor",
+                    segment_end
+                );
+                break;
+            }
+        }
+
+        // Blocks past a throttled segment stay pending
+        let executed_until = segment_start;
+        let blocks_elapsed = executed_until - last_block;
+
+        // Measure the keeper's work before finished orders leave and the block advances
+        let (mut keeper_reward_0, mut keeper_reward_1) = if blocks_elapsed > U256::ZERO {
+            self.keeper_rewards(executed_until, amount_0_sold, amount_1_sold)?
+        } else {
+            (U256::ZERO, U256::ZERO)
+        };
+        if saturating {
+            keeper_reward_0 = keeper_reward_0.min(new_reserve_0);
+            keeper_reward_1 = keeper_reward_1.min(new_reserve_1);
+        }
+        let new_reserve_0 = new_reserve_0
+            .checked_sub(keeper_reward_0)
+            .ok_or(OrderError::Overflow("Reserve underflow"))?;
+        let new_reserve_1 = new_reserve_1
+            .checked_sub(keeper_reward_1)
+            .ok_or(OrderError::Overflow("Reserve underflow"))?;
+
+        self.activate_scheduled_orders(executed_until)?;
+        let mut cancelled_payout_0 = U256::ZERO;
+        let mut cancelled_payout_1 = U256::ZERO;
+        for order_id in stranded_orders {
+            // Orders that sold out in a later segment keep their completion; ones that
+            // reached their end block still holding principal are cancelled all the same
+            let Some(order_index) = self
+                .order_index(order_id)
+                .filter(|&index| self.orders[index].remaining_amount > U256::ZERO)
+            else {
+                continue;
+            };
+            let order = self.remove_cancelled_order(order_index)?;
+            let (payout_0, payout_1) = match order.direction {
+                OrderDirection::SellToken0 => (order.remaining_amount, order.accumulated_out),
+                OrderDirection::SellToken1 => (order.accumulated_out, order.remaining_amount),
+            };
+            cancelled_payout_0 = cancelled_payout_0
+                .checked_add(payout_0)
+                .ok_or(OrderError::Overflow("Calculation overflow"))?;
+            cancelled_payout_1 = cancelled_payout_1
+                .checked_add(payout_1)
+                .ok_or(OrderError::Overflow("Calculation overflow"))?;
+            console!("Cancelled order {} stranded by drained reserves", order_id);
+        }
+        self.remove_finished_orders(executed_until);
+
+        self.cumulative_volume_0 = self
+            .cumulative_volume_0
+            .checked_add(amount_0_sold)
+            .ok_or(OrderError::Overflow("Volume accumulation overflow"))?;
+        self.cumulative_volume_1 = self
+            .cumulative_volume_1
+            .checked_add(amount_1_sold)
+            .ok_or(OrderError::Overflow("Volume accumulation overflow"))?;
+
+        // Update virtual order state
+        self.virtual_order_state.last_virtual_order_block = executed_until;
+        self.virtual_order_state.last_execution_price =
+            Self::spot_price(new_reserve_0, new_reserve_1, LIMIT_PRICE_PRECISION)
+                .unwrap_or(U256::ZERO);
+        if self.reserve_tracking {
+            self.stored_reserve_0 = new_reserve_0;
+            self.stored_reserve_1 = new_reserve_1;
+        }
+
+        let (expected_0, expected_1, realized_slippage_bps) = Self::execution_slippage(
+            (current_reserve_0, current_reserve_1),
+            (amount_0_sold, amount_1_sold),
+            (amount_0_received, amount_1_received),
+        )?;
+
+        // Estimate gas used (approximation based on blocks executed)
+        let gas_estimate = blocks_elapsed
+            .checked_mul(self.gas_model.gas_used_per_executed_block)
+            .ok_or(OrderError::Overflow("Gas calculation overflow"))?;
+
+        let result = VirtualExecutionResult {
+            blocks_executed: blocks_elapsed,
+            amount_0_sold,
+            amount_1_sold,
+            amount_0_received,
+            amount_1_received,
+            new_reserve_0,
+            new_reserve_1,
+            gas_used_estimate: gas_estimate,
+            keeper_reward_0,
+            keeper_reward_1,
+            unfilled_0,
+            unfilled_1,
+            matched_0,
+            matched_1,
+            blocks_remaining: current_block - executed_until,
+            k_before: Self::pool_invariant(current_reserve_0, current_reserve_1),
+            k_after: Self::pool_invariant(new_reserve_0, new_reserve_1),
+            expected_0,
+            expected_1,
+            realized_slippage_bps,
+            cancelled_payout_0,
+            cancelled_payout_1,
+        };
+
+        console!("Executed virtual orders for {} blocks", blocks_elapsed);
+
+        Ok(result)
+    }
+
+    /// Compare an execution's payouts with the spot price of the reserves it started from
+    /// Returns (expected_0, expected_1, realized_slippage_bps) for VirtualExecutionResult
+    fn execution_slippage(
+        reserves: (U256, U256),
+        sold: (U256, U256),
+        received: (U256, U256),
+    ) -> Result<(U256, U256, U256), OrderError> {
+        let (reserve_0, reserve_1) = reserves;
+        if reserve_0 == U256::ZERO || reserve_1 == U256::ZERO {
+            return Ok((U256::ZERO, U256::ZERO, U256::ZERO));
+        }
+
+        let overflow = |_| OrderError::Overflow("Slippage calculation overflow");
+        let expected_0 = TWAMMath::mul_div(sold.1, reserve_0, reserve_1).map_err(overflow)?;
+        let expected_1 = TWAMMath::mul_div(sold.0, reserve_1, reserve_0).map_err(overflow)?;
+
+        // Value both sides in token1 so the two directions combine into one figure
+        let expected_value = TWAMMath::mul_div(expected_0, reserve_1, reserve_0)
+            .map_err(overflow)?
+            .checked_add(expected_1)
+            .ok_or(OrderError::Overflow("Slippage calculation overflow"))?;
+        let received_value = TWAMMath::mul_div(received.0, reserve_1, reserve_0)
+            .map_err(overflow)?
+            .checked_add(received.1)
+            .ok_or(OrderError::Overflow("Slippage calculation overflow"))?;
+        if expected_value == U256::ZERO {
+            return Ok((expected_0, expected_1, U256::ZERO));
+        }
+
+        let slippage_bps = TWAMMath::mul_div(
+            expected_value.saturating_sub(received_value),
+            U256::from(FEE_DENOMINATOR),
+            expected_value,
+        )
+        .map_err(overflow)?;
+        Ok((expected_0, expected_1, slippage_bps))
+    }
+
+    /// Reject caller reserves further from the stored reserves than reserve_tolerance_bps
+    fn check_reserves(&self, reserve_0: U256, reserve_1: U256) -> Result<(), OrderError> {
+        for (reserve, stored) in [
+            (reserve_0, self.stored_reserve_0),
+            (reserve_1, self.stored_reserve_1),
+        ] {
+            let tolerance = TWAMMath::mul_div(
+                stored,
+                self.reserve_tolerance_bps,
+                U256::from(FEE_DENOMINATOR),
+            )
+            .map_err(|_| OrderError::Overflow("Tolerance calculation overflow"))?;
+
+            if reserve.abs_diff(stored) > tolerance {
+                return Err(OrderError::InvalidParams(
+                    "Reserves disagree with stored reserves",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Estimate the opposing volume crossed internally in a segment as (matched_0, matched_1)
+    /// Both flows are netted at the starting price, so the smaller side counts as matched in
+    /// full against its value in the other token. The closed form itself trades both flows
+    /// against the evolving reserves, so this is a reporting estimate. One-sided segments
+    /// match nothing.
+    fn matched_volumes(
+        reserve_0: U256,
+        reserve_1: U256,
+        total_sell_0: U256,
+        total_sell_1: U256,
+    ) -> Result<(U256, U256), OrderError> {
+        if total_sell_0 == U256::ZERO || total_sell_1 == U256::ZERO {
+            return Ok((U256::ZERO, U256::ZERO));
+        }
+
+        let (token_0_net_sold, net_amount) =
+            TWAMMath::net_flow(reserve_0, reserve_1, total_sell_0, total_sell_1)
+                .map_err(|_| OrderError::MathFailed("Matching calculation failed"))?;
+
+        if token_0_net_sold {
+            Ok((total_sell_0 - net_amount, total_sell_1))
+        } else {
+            Ok((total_sell_0, total_sell_1 - net_amount))
+        }
+    }
+
+    /// Get the keeper reward for executing up to executed_until as (reward_0, reward_1)
+    /// Each token's reward is keeper_reward_bps of its sold volume, capped at the estimated
+    /// execution gas priced at keeper_gas_price
+    fn keeper_rewards(
+        &self,
+        executed_until: U256,
+        amount_0_sold: U256,
+        amount_1_sold: U256,
+    ) -> Result<(U256, U256), OrderError> {
+        if self.keeper_reward_bps == U256::ZERO {
+            return Ok((U256::ZERO, U256::ZERO));
+        }
+
+        let cap = self
+            .estimate_virtual_execution_gas(executed_until)
+            .saturating_mul(self.keeper_gas_price);
+        let reward = |amount_sold: U256| -> Result<U256, OrderError> {
+            let reward = TWAMMath::mul_div(
+                amount_sold,
+                self.keeper_reward_bps,
+                U256::from(FEE_DENOMINATOR),
+            )
+            .map_err(|_| OrderError::Overflow("Reward calculation overflow"))?;
+            Ok(reward.min(cap))
+        };
+
+        Ok((reward(amount_0_sold)?, reward(amount_1_sold)?))
+    }
+
+    /// Get how many of a segment's blocks can execute without pushing a falling reserve
+    /// below min_reserve, searching the closed form over the block count
+    fn blocks_above_reserve_floor(
+        &self,
+        sell_rate_0: U256,
+        sell_rate_1: U256,
+        segment_blocks: U256,
+        reserve_0: U256,
+        reserve_1: U256,
+    ) -> U256 {
+        if self.min_reserve == U256::ZERO {
+            return segment_blocks;
+        }
+
+        let above_floor = |blocks: U256| {
+            match TWAMMath::execute_virtual_orders_closed_form(
+                sell_rate_0,
+                sell_rate_1,
+                blocks,
+                reserve_0,
+                reserve_1,
+            ) {
+                Ok((new_reserve_0, new_reserve_1, _, _)) => {
+                    (new_reserve_0 >= self.min_reserve || new_reserve_0 >= reserve_0)
+                        && (new_reserve_1 >= self.min_reserve || new_reserve_1 >= reserve_1)
+                }
+                // Let the real execution surface math errors
+                Err(_) => blocks == segment_blocks,
+            }
+        };
+
+        if above_floor(segment_blocks) {
+            return segment_blocks;
+        }
+
+        // Largest block count that stays above the floor; zero always does
+        let mut low = U256::ZERO;
+        let mut high = segment_blocks;
+        while high - low > U256::from(1u32) {
+            let mid = low + (high - low) / U256::from(2u32);
+            if above_floor(mid) {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+
+        low
+    }
+
+    /// Get the ends of the execution segments between two blocks
+    /// Segments break wherever an order starts or expires inside the window, and at each
+    /// order_block_interval multiple while any order has a price limit
+    fn get_segment_boundaries(&self, start_block: U256, end_block: U256) -> Vec<U256> {
+        let mut boundaries: Vec<U256> = self
+            .orders
+            .iter()
+            .filter(|order| {
+                order.order_type == OrderType::LongTerm
+                    && order.status != OrderStatus::Underfilled
+                    && order.active
+            })
+            .flat_map(|order| [order.start_block, order.end_block])
+            .filter(|block| *block > start_block && *block < end_block)
+            .collect();
+
+        // Limit and target-priced orders are re-checked at least every order_block_interval blocks
+        let interval = self.virtual_order_state.order_block_interval;
+        if interval > U256::ZERO && self.has_price_dependent_orders() {
+            let mut block = start_block - start_block % interval + interval;
+            while block < end_block {
+                boundaries.push(block);
+                block += interval;
+            }
+        }
+
+        boundaries.push(end_block);
+        boundaries.sort();
+        boundaries.dedup();
+        boundaries
+    }
+
+    /// Whether any order streams differently depending on the pool price
+    fn has_price_dependent_orders(&self) -> bool {
+        self.orders
+            .iter()
+            .any(|order| order.price_limit.is_some() || order.target_price.is_some())
+    }
+
+    /// Get active sell rates for a given time period
+    /// Orders only partially inside the window count by the fraction they overlap
+    fn get_active_sell_rates(
+        &self,
+        start_block: U256,
+        end_block: U256,
+        spot_price: Option<U256>,
+    ) -> Result<(U256, U256), OrderError> {
+        let window = end_block.saturating_sub(start_block);
+        if window == U256::ZERO {
+            return Ok((U256::ZERO, U256::ZERO));
+        }
+
+        let (volume_0, volume_1) =
+            self.get_active_sell_volumes(start_block, end_block, spot_price)?;
+
+        Ok((volume_0 / window, volume_1 / window))
+    }
+
+    /// Get the total amounts each direction streams during a time period
+    fn get_active_sell_volumes(
+        &self,
+        start_block: U256,
+        end_block: U256,
+        spot_price: Option<U256>,
+    ) -> Result<(U256, U256), OrderError> {
+        let mut total_volume_0 = U256::ZERO;
+        let mut total_volume_1 = U256::ZERO;
+
+        for order in &self.orders {
+            if order.order_type != OrderType::LongTerm || order.status == OrderStatus::Underfilled {
+                continue;
+            }
+
+            let volume = Self::order_window_volume(
+                order,
+                start_block,
+                end_block,
+                spot_price,
+                self.ramp_blocks,
+            )?;
+
+            match order.direction {
+                OrderDirection::SellToken0 => {
+                    total_volume_0 = total_volume_0
+                        .checked_add(volume)
+                        .ok_or(OrderError::Overflow("Rate calculation overflow"))?;
+                }
+                OrderDirection::SellToken1 => {
+                    total_volume_1 = total_volume_1
+                        .checked_add(volume)
+                        .ok_or(OrderError::Overflow("Rate calculation overflow"))?;
+                }
+            }
+        }
+
+        Ok((total_volume_0, total_volume_1))
+    }
+
+    /// Under LiquidityPolicy::Cancel, add the orders streaming in a segment the reserves
+    /// could not absorb to stranded_orders
+    fn collect_stranded_orders(
+        &self,
+        stranded_orders: &mut Vec<U256>,
+        segment: (U256, U256),
+        spot_price: Option<U256>,
+    ) -> Result<(), OrderError> {
+        if self.liquidity_policy != LiquidityPolicy::Cancel {
+            return Ok(());
+        }
+
+        for order in &self.orders {
+            if order.order_type != OrderType::LongTerm
+                || order.status == OrderStatus::Underfilled
+                || stranded_orders.contains(&order.id)
+            {
+                continue;
+            }
+            let volume = Self::order_window_volume(
+                order,
+                segment.0,
+                segment.1,
+                spot_price,
+                self.ramp_blocks,
+            )?;
+            if volume > U256::ZERO {
+                stranded_orders.push(order.id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Amount an order streams during the part of a time period it is active for
+    fn order_window_volume(
+        order: &Order,
+        start_block: U256,
+        end_block: U256,
+        spot_price: Option<U256>,
+        ramp_blocks: U256,
+    ) -> Result<U256, OrderError> {
+        // Paused orders and limit orders on the wrong side of the price keep their amount
+        if !order.active || !order.within_price_limit(spot_price) {
+            return Ok(U256::ZERO);
+        }
+
+        let effective_start = order.start_block.max(start_block);
+        let effective_end = order.end_block.min(end_block);
+
+        if effective_end <= effective_start {
+            return Ok(U256::ZERO); // Order not active in this period
+        }
+
+        // Orders with a rounded-up rate run out part way through their last block
+        let blocks = effective_end - effective_start;
+        if order.target_price.is_none() && ramp_blocks == U256::ZERO {
+            return Ok(order
+                .sell_rate
+                .checked_mul(blocks)
+                .ok_or(OrderError::Overflow("Rate calculation overflow"))?
+                .min(order.remaining_amount));
+        }
+
+        // Ramped orders fall behind while ramping in and make it up in the window reaching
+        // end_block, so they still finish on time
+        if order.target_price.is_none() {
+            if effective_end == order.end_block {
+                return Ok(order.remaining_amount);
+            }
+            return Ok(
+                Self::ramped_volume(order, effective_start, effective_end, ramp_blocks)
+                    .ok_or(OrderError::Overflow("Rate calculation overflow"))?
+                    .min(order.remaining_amount),
+            );
+        }
+
+        // Target-priced orders spread what is left over the blocks left, scaled by price,
+        // and release the rest in the window reaching end_block so they still finish on time
+        if effective_end == order.end_block {
+            return Ok(order.remaining_amount);
+        }
+        let scheduled = TWAMMath::mul_div(
+            order.remaining_amount,
+            blocks,
+            order.end_block - effective_start,
+        )
+        .map_err(|_| OrderError::Overflow("Rate calculation overflow"))?;
+        let scaled = TWAMMath::mul_div(
+            scheduled,
+            order.rate_scale_bps(spot_price),
+            U256::from(FEE_DENOMINATOR),
+        )
+        .map_err(|_| OrderError::Overflow("Rate calculation overflow"))?;
+
+        Ok(scaled.min(order.remaining_amount))
+    }
+
+    /// Amount a fixed-rate order sells over [from_block, to_block) while ramping in
+    /// Block b sells Order::effective_sell_rate(b), so the ramped blocks sum the arithmetic
+    /// series sell_rate * (b - start_block) / ramp_blocks
+    fn ramped_volume(
+        order: &Order,
+        from_block: U256,
+        to_block: U256,
+        ramp_blocks: U256,
+    ) -> Option<U256> {
+        let ramp_end = order.start_block.saturating_add(ramp_blocks);
+        // Sum of (b - start_block) for b in [start_block, start_block + k)
+        let series = |k: U256| -> Option<U256> {
+            Some(k.checked_mul(k.saturating_sub(U256::from(1u32)))? / U256::from(2u32))
+        };
+
+        let mut volume = U256::ZERO;
+        let ramp_to = to_block.min(ramp_end);
+        if ramp_to > from_block {
+            let elapsed =
+                series(ramp_to - order.start_block)? - series(from_block - order.start_block)?;
+            volume = TWAMMath::mul_div(order.sell_rate, elapsed, ramp_blocks).ok()?;
+        }
+
+        let full_from = from_block.max(ramp_end);
+        if to_block > full_from {
+            volume = volume.checked_add(order.sell_rate.checked_mul(to_block - full_from)?)?;
+        }
+        Some(volume)
+    }
+
+    /// Credit orders with their share of one execution segment
+    /// reserves are the segment's starting reserves, the baseline for execution quality
+    /// sell_volumes holds the volume snapshot and the part of it crossed against opposing
+    /// orders, which splits each order's sale into matched and curve volume
+    fn update_orders_after_execution(
+        &mut self,
+        reserves: (U256, U256),
+        amounts_received: (U256, U256),
+        sell_volumes: ((U256, U256), (U256, U256)),
+        segment: (U256, U256),
+        spot_price: Option<U256>,
+        mut fills: Option<&mut OrderFills>,
+    ) -> Result<(), OrderError> {
+        let (amount_0_received, amount_1_received) = amounts_received;
+        let ((active_sell_volume_0, active_sell_volume_1), matched_sell_volumes) = sell_volumes;
+        let (window_start, current_block) = segment;
+
+        let ramp_blocks = self.ramp_blocks;
+        let mut orders_to_halt = Vec::new();
+        for (index, order) in self.orders.iter_mut().enumerate() {
+            if order.order_type != OrderType::LongTerm || order.status == OrderStatus::Underfilled {
+                continue;
+            }
+
+            // Only orders counted in the volume snapshot share this window's proceeds, and
+            // blocks already credited by settle_order are not credited again
+            let credit_start = window_start.max(order.last_virtual_order_block);
+            let amount_sold = Self::order_window_volume(
+                order,
+                credit_start,
+                current_block,
+                spot_price,
+                ramp_blocks,
+            )?;
+
+            if amount_sold > U256::ZERO {
+                // Update order state
+
+                order.remaining_amount = order
+                    .remaining_amount
+                    .checked_sub(amount_sold.min(order.remaining_amount))
+                    .unwrap_or(U256::ZERO);
+
+                // Distribute received amounts proportionally to the volume snapshot, rounding
+                // down so the shares never sum to more than the segment released
+                let received_amount = match order.direction {
+                    OrderDirection::SellToken0 => {
+                        // This order sold token0, received token1
+                        if active_sell_volume_0 > U256::ZERO {
+                            TWAMMath::mul_div(amount_1_received, amount_sold, active_sell_volume_0)
+                                .map_err(|_| {
+                                    OrderError::Overflow("Distribution calculation overflow")
+                                })?
+                        } else {
+                            U256::ZERO
+                        }
+                    }
+                    OrderDirection::SellToken1 => {
+                        // This order sold token1, received token0
+                        if active_sell_volume_1 > U256::ZERO {
+                            TWAMMath::mul_div(amount_0_received, amount_sold, active_sell_volume_1)
+                                .map_err(|_| {
+                                    OrderError::Overflow("Distribution calculation overflow")
+                                })?
+                        } else {
+                            U256::ZERO
+                        }
+                    }
+                };
+
+                order.accumulated_out = order
+                    .accumulated_out
+                    .checked_add(received_amount)
+                    .ok_or(OrderError::Overflow("Accumulated amount overflow"))?;
+                Self::record_fill_quality(order, reserves, amount_sold, received_amount)?;
+
+                let (matched_volume, sell_volume) = match order.direction {
+                    OrderDirection::SellToken0 => (matched_sell_volumes.0, active_sell_volume_0),
+                    OrderDirection::SellToken1 => (matched_sell_volumes.1, active_sell_volume_1),
+                };
+                let order_matched = if sell_volume > U256::ZERO {
+                    TWAMMath::mul_div(amount_sold, matched_volume, sell_volume)
+                        .map_err(|_| OrderError::Overflow("Matching calculation overflow"))?
+                        .min(amount_sold)
+                } else {
+                    U256::ZERO
+                };
+                order.matched_volume = order
+                    .matched_volume
+                    .checked_add(order_matched)
+                    .ok_or(OrderError::Overflow("Matching calculation overflow"))?;
+                order.curve_volume = order
+                    .curve_volume
+                    .checked_add(amount_sold - order_matched)
+                    .ok_or(OrderError::Overflow("Matching calculation overflow"))?;
+
+                // Record the fill, merging with earlier segments of this round
+                if let Some(fills) = fills.as_deref_mut() {
+                    match fills.iter_mut().find(|(id, _)| *id == order.id) {
+                        Some((_, filled)) => {
+                            *filled = filled
+                                .checked_add(received_amount)
+                                .ok_or(OrderError::Overflow("Accumulated amount overflow"))?;
+                        }
+                        None => fills.push((order.id, received_amount)),
+                    }
+                }
+
+                order.last_virtual_order_block = current_block;
+
+                // Halt as soon as the remaining principal, valued at this segment's price,
+                // can no longer lift the output to the minimum
+                if order.remaining_amount > U256::ZERO && order.min_output_total > U256::ZERO {
+                    let projected_out =
+                        TWAMMath::mul_div(order.remaining_amount, received_amount, amount_sold)
+                            .ok()
+                            .and_then(|remaining_out| {
+                                remaining_out.checked_add(order.accumulated_out)
+                            })
+                            .ok_or(OrderError::Overflow("Projected output overflow"))?;
+                    if projected_out < order.min_output_total {
+                        orders_to_halt.push(index);
+                    }
+                }
+            }
+        }
+
+        for index in orders_to_halt {
+            self.halt_underfilled_order(index);
+        }
+
+        Ok(())
+    }
+
+    /// Add one segment's fill to an order's execution quality, weighted by its blocks, and
+    /// its no-fee output at the segment's starting spot price to benchmark_out
+    /// The fill is graded against that benchmark; fills too small to price are skipped
+    fn record_fill_quality(
+        order: &mut Order,
+        reserves: (U256, U256),
+        amount_sold: U256,
+        received_amount: U256,
+    ) -> Result<(), OrderError> {
+        let (reserve_in, reserve_out) = match order.direction {
+            OrderDirection::SellToken0 => reserves,
+            OrderDirection::SellToken1 => (reserves.1, reserves.0),
+        };
+        if reserve_in == U256::ZERO {
+            return Ok(());
+        }
+
+        let expected = TWAMMath::mul_div(amount_sold, reserve_out, reserve_in)
+            .map_err(|_| OrderError::Overflow("Quality calculation overflow"))?;
+        order.benchmark_out = order
+            .benchmark_out
+            .checked_add(expected)
+            .ok_or(OrderError::Overflow("Quality calculation overflow"))?;
+        if expected == U256::ZERO || order.sell_rate == U256::ZERO {
+            return Ok(());
+        }
+
+        let impact_bps = TWAMMath::mul_div(
+            expected.saturating_sub(received_amount),
+            U256::from(10000u32),
+            expected,
+        )
+        .map_err(|_| OrderError::Overflow("Quality calculation overflow"))?;
+        let quality =
+            TWAMMath::calculate_execution_quality(expected, received_amount, impact_bps, PRECISION)
+                .map_err(|_| OrderError::MathFailed("Quality calculation failed"))?;
+
+        let blocks = amount_sold / order.sell_rate;
+        order.quality_weighted_sum = order
+            .quality_weighted_sum
+            .checked_add(
+                quality
+                    .checked_mul(blocks)
+                    .ok_or(OrderError::Overflow("Quality calculation overflow"))?,
+            )
+            .ok_or(OrderError::Overflow("Quality calculation overflow"))?;
+        order.quality_weight = order
+            .quality_weight
+            .checked_add(blocks)
+            .ok_or(OrderError::Overflow("Quality calculation overflow"))?;
+
+        Ok(())
+    }
+
+    /// Count scheduled orders that started by current_block in the rate totals
+    fn activate_scheduled_orders(&mut self, current_block: U256) -> Result<(), OrderError> {
+        for order in self.orders.iter_mut() {
+            if order.status != OrderStatus::Scheduled || order.start_block > current_block {
+                continue;
+            }
+
+            order.status = OrderStatus::Active;
+            let total_sell_rate = match order.direction {
+                OrderDirection::SellToken0 => &mut self.total_sell_rate_0,
+                OrderDirection::SellToken1 => &mut self.total_sell_rate_1,
+            };
+            *total_sell_rate = total_sell_rate
+                .checked_add(order.sell_rate)
+                .ok_or(OrderError::Overflow("Rate overflow"))?;
+
+            console!("Activated scheduled order {}", order.id);
+        }
+        Ok(())
+    }
+
+    /// Halt an underfilled order: it stops streaming but keeps its unsold principal and
+    /// proceeds for the owner to settle
+    fn halt_underfilled_order(&mut self, index: usize) {
+        let order = &mut self.orders[index];
+        order.status = OrderStatus::Underfilled;
+
+        match order.direction {
+            OrderDirection::SellToken0 => {
+                self.total_sell_rate_0 = self
+                    .total_sell_rate_0
+                    .checked_sub(order.sell_rate)
+                    .unwrap_or(U256::ZERO);
+            }
+            OrderDirection::SellToken1 => {
+                self.total_sell_rate_1 = self
+                    .total_sell_rate_1
+                    .checked_sub(order.sell_rate)
+                    .unwrap_or(U256::ZERO);
+            }
+        }
+
+        console!("Halted underfilled order {}", order.id);
+    }
+
+    /// Remove expired or completed orders, halting those below their minimum output
+    fn remove_finished_orders(&mut self, current_block: U256) {
+        let mut orders_to_remove = Vec::new();
+        let mut orders_to_halt = Vec::new();
+
+        for (index, order) in self.orders.iter().enumerate() {
+            if order.order_type != OrderType::LongTerm
+                || order.status == OrderStatus::Underfilled
+                || !order.active
+            {
+                continue;
+            }
+
+            if current_block >= order.end_block || order.remaining_amount == U256::ZERO {
+                if order.accumulated_out < order.min_output_total {
+                    orders_to_halt.push(index);
+                } else {
+                    orders_to_remove.push(index);
+                }
+            }
+        }
+
+        for &index in &orders_to_halt {
+            self.halt_underfilled_order(index);
+        }
+
+        // Remove completed orders in one pass, keeping the rest in their relative order and
+        // recording completions in pool order so indexers see a deterministic sequence
+        let mut completed_orders = Vec::with_capacity(orders_to_remove.len());
+        let mut open_orders = Vec::with_capacity(self.orders.len() - orders_to_remove.len());
+        for (index, order) in core::mem::take(&mut self.orders).into_iter().enumerate() {
+            if orders_to_remove.binary_search(&index).is_ok() {
+                completed_orders.push(order);
+            } else {
+                open_orders.push(order);
+            }
+        }
+        self.orders = open_orders;
+
+        for completed_order in completed_orders {
+            self.record_completion(completed_order);
+
+            // Update total sell rates
+            match completed_order.direction {
+                OrderDirection::SellToken0 => {
+                    self.total_sell_rate_0 = self
+                        .total_sell_rate_0
+                        .checked_sub(completed_order.sell_rate)
+                        .unwrap_or(U256::ZERO);
+                }
+                OrderDirection::SellToken1 => {
+                    self.total_sell_rate_1 = self
+                        .total_sell_rate_1
+                        .checked_sub(completed_order.sell_rate)
+                        .unwrap_or(U256::ZERO);
+                }
+            }
+
+            console!("Completed order {}", completed_order.id);
+        }
+    }
+
+    /// Get order details by ID, including recently finished orders
+    pub fn get_order(&self, order_id: U256) -> Option<Order> {
+        self.orders
+            .iter()
+            .chain(self.history.iter())
+            .find(|order| order.id == order_id)
+            .copied()
+    }
+
+    /// Get the position of an open order in orders
+    pub fn order_index(&self, order_id: U256) -> Option<usize> {
+        self.orders.iter().position(|order| order.id == order_id)
+    }
+
+    /// Get a map from each open order's id to its position in orders
+    /// The map stays valid until the next call that adds or removes an order
+    pub fn order_positions(&self) -> BTreeMap<U256, usize> {
+        self.orders
+            .iter()
+            .enumerate()
+            .map(|(index, order)| (order.id, index))
+            .collect()
+    }
+
+    /// Get an order's progress as (filled_bps, blocks_remaining)
+    /// filled_bps is the share of the streamed amount (initial amount less dust) already sold,
+    /// in basis points
+    pub fn order_progress(&self, order_id: U256, current_block: U256) -> Option<(U256, U256)> {
+        let order = self.get_order(order_id)?;
+
+        let sold = order.amount_sold();
+        let filled_bps = sold
+            .checked_mul(U256::from(10000u32))?
+            .checked_div(order.initial_amount.saturating_sub(order.dust_remainder))
+            .unwrap_or(U256::ZERO);
+        let blocks_remaining = order.end_block.saturating_sub(current_block);
+
+        Some((filled_bps, blocks_remaining))
+    }
+
+    /// Get the sell rate an open order would stream at if it had new_remaining left to sell
+    /// by new_end_block, without changing anything
+    /// The rate is floored over the blocks from current_block (or the order's start, if
+    /// later) to new_end_block, as merge_orders recomputes it. Returns None if the order is
+    /// not open, new_end_block is not ahead of that start, or the rate would round to zero
+    pub fn preview_rate_after_change(
+        &self,
+        order_id: U256,
+        new_remaining: U256,
+        new_end_block: U256,
+        current_block: U256,
+    ) -> Option<U256> {
+        let order = self.orders.iter().find(|order| order.id == order_id)?;
+
+        let window_start = current_block.max(order.start_block);
+        let blocks = new_end_block.checked_sub(window_start)?;
+        let sell_rate = new_remaining.checked_div(blocks)?;
+        (sell_rate > U256::ZERO).then_some(sell_rate)
+    }
+
+    /// Check that pool bookkeeping is coherent, returning the first violation found
+    ///
+    /// Verifies that each total sell rate equals the sum of its active orders' rates, that
+    /// next_order_id is above every known id (sequential ids only), and that no order has
+    /// more remaining than it started with.
+    pub fn check_invariants(&self, _current_block: U256) -> Result<(), OrderError> {
+        let mut sell_rate_0 = U256::ZERO;
+        let mut sell_rate_1 = U256::ZERO;
+
+        for order in &self.orders {
+            if order.status != OrderStatus::Active || !order.active {
+                continue;
+            }
+            match order.direction {
+                OrderDirection::SellToken0 => {
+                    sell_rate_0 = sell_rate_0
+                        .checked_add(order.sell_rate)
+                        .ok_or(OrderError::Overflow("Rate overflow"))?;
+                }
+                OrderDirection::SellToken1 => {
+                    sell_rate_1 = sell_rate_1
+                        .checked_add(order.sell_rate)
+                        .ok_or(OrderError::Overflow("Rate overflow"))?;
+                }
+            }
+        }
+
+        if sell_rate_0 != self.total_sell_rate_0 {
+            return Err(OrderError::InvalidState(
+                "total_sell_rate_0 does not match active orders",
+            ));
+        }
+        if sell_rate_1 != self.total_sell_rate_1 {
+            return Err(OrderError::InvalidState(
+                "total_sell_rate_1 does not match active orders",
+            ));
+        }
+
+        for order in self.orders.iter().chain(self.history.iter()) {
+            if self.order_id_scheme == OrderIdScheme::Sequential && order.id >= self.next_order_id {
+                return Err(OrderError::InvalidState("Order id not below next_order_id"));
+            }
+            if order.remaining_amount.saturating_add(order.dust_remainder) > order.initial_amount {
+                return Err(OrderError::InvalidState(
+                    "Order remaining exceeds initial amount",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get an order's time-weighted average execution quality (0 to 100) over every segment
+    /// it has been credited for; None if the order is unknown or has not filled yet
+    pub fn order_lifetime_quality(&self, order_id: U256) -> Option<U256> {
+        let order = self.get_order(order_id)?;
+        if order.quality_weight == U256::ZERO {
+            return None;
+        }
+
+        Some(order.quality_weighted_sum / order.quality_weight)
+    }
+
+    /// Get an order's all-in cost in basis points: how far its proceeds fall short of selling
+    /// each segment at its starting spot price with no fee, so protocol fees and slippage
+    /// show up as one figure. The proceeds-to-benchmark ratio is taken at precision decimals
+    /// Based on unclaimed proceeds; returns None if the order is unknown or has no priced
+    /// fills since its last claim. Fills better than the benchmark cost zero
+    pub fn order_effective_cost_bps(&self, order_id: U256, precision: u32) -> Option<U256> {
+        let order = self.get_order(order_id)?;
+        if order.benchmark_out == U256::ZERO {
+            return None;
+        }
+
+        let one = U256::from(10u128.pow(precision));
+        let realized = TWAMMath::mul_div_wide(order.accumulated_out, one, order.benchmark_out)?;
+        TWAMMath::mul_div_wide(one.saturating_sub(realized), U256::from(10000u32), one)
+    }
+
+    /// Get the share of an order's sold volume crossed against opposing orders rather than
+    /// traded through the curve, in basis points; None if the order is unknown or has not
+    /// sold anything
+    pub fn order_match_ratio(&self, order_id: U256) -> Option<U256> {
+        let order = self.get_order(order_id)?;
+        let total = order.matched_volume.checked_add(order.curve_volume)?;
+        if total == U256::ZERO {
+            return None;
+        }
+
+        TWAMMath::mul_div_wide(order.matched_volume, U256::from(10000u32), total)
+    }
+
+    /// Get an order's average execution price (output per unit sold) scaled to precision
+    /// Based on unclaimed proceeds; returns None if nothing has been sold yet
+    pub fn order_avg_price(
+        &self,
+        order_id: U256,
+        _current_block: U256,
+        precision: u32,
+    ) -> Option<U256> {
+        let order = self.get_order(order_id)?;
+
+        let sold = order.amount_sold();
+        if sold == U256::ZERO {
+            return None;
+        }
+
+        let one = U256::from(10u128.pow(precision));
+        TWAMMath::mul_div_wide(order.accumulated_out, one, sold)
+    }
+
+    /// Get an order's status, or None if it is unknown or has left the history
+    pub fn get_order_status(&self, order_id: U256) -> Option<OrderStatus> {
+        self.get_order(order_id).map(|order| order.status)
+    }
+
+    /// Get whether an order would contribute sell rate in current_block at the given reserves
+    /// An order is paused if it is not yet started, expired or halted, if its price limit is
+    /// violated, or if the reserve floor throttles execution. Returns None for unknown orders.
+    pub fn is_order_executing(
+        &self,
+        order_id: U256,
+        current_block: U256,
+        reserve_0: U256,
+        reserve_1: U256,
+    ) -> Option<bool> {
+        let order = self.get_order(order_id)?;
+
+        if !matches!(order.status, OrderStatus::Active | OrderStatus::Scheduled)
+            || order.order_type != OrderType::LongTerm
+            || current_block < order.start_block
+            || current_block >= order.end_block
+        {
+            return Some(false);
+        }
+
+        let Ok(spot_price) = Self::spot_price(reserve_0, reserve_1, LIMIT_PRICE_PRECISION) else {
+            return Some(false);
+        };
+        if !order.within_price_limit(Some(spot_price)) {
+            return Some(false);
+        }
+
+        // The floor applies to the combined flow of every order streaming this block
+        let next_block = current_block.checked_add(U256::from(1u32))?;
+        let (sell_rate_0, sell_rate_1) = self
+            .get_active_sell_rates(current_block, next_block, Some(spot_price))
+            .ok()?;
+        let allowed_blocks = self.blocks_above_reserve_floor(
+            self.apply_fee(sell_rate_0).ok()?,
+            self.apply_fee(sell_rate_1).ok()?,
+            U256::from(1u32),
+            reserve_0,
+            reserve_1,
+        );
+
+        Some(allowed_blocks > U256::ZERO)
+    }
+
+    /// Project the block an open order's remaining amount runs out at the current pace
+    ///
+    /// The pace is the order's volume for its next uncredited block at the spot price of the
+    /// given reserves, so an unconstrained order finishes at end_block and a target-priced
+    /// one at or before it. A paused order is projected as if resumed at current_block,
+    /// which pushes it past its nominal end_block. Returns None for unknown or halted orders
+    /// and while the price limit or the reserve floor keeps the order from streaming.
+    pub fn estimate_completion_block(
+        &self,
+        order_id: U256,
+        current_block: U256,
+        reserve_0: U256,
+        reserve_1: U256,
+    ) -> Option<U256> {
+        let order = self.orders.iter().find(|order| order.id == order_id)?;
+        if order.order_type != OrderType::LongTerm || order.status == OrderStatus::Underfilled {
+            return None;
+        }
+
+        if !order.active {
+            // Resuming moves end_block out by the blocks spent paused
+            return order
+                .end_block
+                .checked_add(current_block.saturating_sub(order.paused_at));
+        }
+
+        let spot_price = Self::spot_price(reserve_0, reserve_1, LIMIT_PRICE_PRECISION).ok()?;
+        if !order.within_price_limit(Some(spot_price)) {
+            return None;
+        }
+        if current_block >= order.start_block
+            && current_block < order.end_block
+            && self.is_order_executing(order_id, current_block, reserve_0, reserve_1) != Some(true)
+        {
+            return None;
+        }
+
+        let from = order.last_virtual_order_block.max(order.start_block);
+        if from >= order.end_block || order.remaining_amount == U256::ZERO {
+            return Some(order.end_block);
+        }
+        let next_block = from.checked_add(U256::from(1u32))?;
+        let per_block =
+            Self::order_window_volume(order, from, next_block, Some(spot_price), self.ramp_blocks)
+                .ok()?;
+        if per_block == U256::ZERO {
+            // Target-priced orders release what is left in their last window
+            return Some(order.end_block);
+        }
+
+        Some(
+            from.saturating_add(order.remaining_amount.div_ceil(per_block))
+                .min(order.end_block),
+        )
+    }
+
+    /// Get how many blocks after current_block it takes the order's cumulative sold amount to
+    /// reach target_sold at its sell rate, ignoring ramp-in and price-driven pacing
+    /// Blocks since the last execution count as streamed. Zero once the target is reached;
+    /// None if the order is not open and streaming or target_sold is beyond what it sells
+    pub fn blocks_to_fill_amount(
+        &self,
+        order_id: U256,
+        target_sold: U256,
+        current_block: U256,
+    ) -> Option<U256> {
+        let order = self.orders.iter().find(|order| order.id == order_id)?;
+        if order.order_type != OrderType::LongTerm
+            || order.status == OrderStatus::Underfilled
+            || !order.active
+            || order.sell_rate == U256::ZERO
+        {
+            return None;
+        }
+
+        let sold = order.amount_sold();
+        if target_sold > sold.checked_add(order.remaining_amount)? {
+            return None;
+        }
+
+        let from = order.last_virtual_order_block.max(order.start_block);
+        let fill_block =
+            from.checked_add(target_sold.saturating_sub(sold).div_ceil(order.sell_rate))?;
+        Some(fill_block.saturating_sub(current_block))
+    }
+
+    /// Copy the pool to run an execution that may be discarded
+    ///
+    /// Execution only appends to the history and unclaimed proceeds and only drops proceeds
+    /// splits, so the copy starts without them rather than duplicating them; join_fork
+    /// merges them back. Orders finishing in the fork are still found by its get_order.
+    fn fork(&self) -> OrderPool {
+        OrderPool {
+            orders: self.orders.clone(),
+            order_beneficiaries: Vec::new(),
+            history: Vec::new(),
+            unclaimed_proceeds: Vec::new(),
+            ..*self
+        }
+    }
+
+    /// Take over the state of a fork, merging back the bookkeeping it started without
+    fn join_fork(&mut self, mut fork: OrderPool) {
+        let finished = core::mem::replace(&mut fork.history, core::mem::take(&mut self.history));
+        for order in finished {
+            fork.record_history(order);
+        }
+
+        let mut unclaimed_proceeds = core::mem::take(&mut self.unclaimed_proceeds);
+        unclaimed_proceeds.append(&mut fork.unclaimed_proceeds);
+        fork.unclaimed_proceeds = unclaimed_proceeds;
+
+        // Splits only outlive their order while its proceeds are unclaimed
+        fork.order_beneficiaries = core::mem::take(&mut self.order_beneficiaries);
+        fork.order_beneficiaries.retain(|(order_id, _)| {
+            fork.orders.iter().any(|order| order.id == *order_id)
+                || fork
+                    .unclaimed_proceeds
+                    .iter()
+                    .any(|(id, _, _)| id == order_id)
+        });
+
+        *self = fork;
+    }
+
+    /// Keep a finished order for status queries, dropping the oldest beyond the cap
+    fn record_history(&mut self, order: Order) {
+        if self.history.len() >= MAX_ORDER_HISTORY {
+            self.history.remove(0);
+        }
+        self.history.push(order);
+    }
+
+    /// Record a long-term order as completed, keeping its proceeds claimable
+    /// Beneficiaries stay until the proceeds are claimed
+    fn record_completion(&mut self, mut order: Order) {
+        order.status = OrderStatus::Completed;
+        if order.accumulated_out > U256::ZERO {
+            self.unclaimed_proceeds
+                .push((order.id, order.owner, order.accumulated_out));
+        } else {
+            self.remove_beneficiaries(order.id);
+        }
+        self.record_history(order);
+    }
+
+    /// Get all orders for a specific owner
+    pub fn get_orders_by_owner(&self, owner: Address) -> Vec<Order> {
+        self.orders
+            .iter()
+            .filter(|order| order.owner == owner)
+            .copied()
+            .collect()
+    }
+
+    /// Get all orders for a specific owner as ABI-friendly views
+    pub fn get_order_views_by_owner(&self, owner: Address) -> Vec<OrderView> {
+        self.orders
+            .iter()
+            .filter(|order| order.owner == owner)
+            .map(|&order| OrderView::from(order))
+            .collect()
+    }
+
+    /// Get a bounded page of an owner's orders
+    pub fn get_orders_by_owner_paginated(
+        &self,
+        owner: Address,
+        offset: usize,
+        limit: usize,
+    ) -> Vec<Order> {
+        self.orders
+            .iter()
+            .filter(|order| order.owner == owner)
+            .skip(offset)
+            .take(limit)
+            .copied()
+            .collect()
+    }
+
+    /// Get a bounded page of an owner's finished orders with their final stats, oldest first
+    ///
+    /// Retention is best effort: the history keeps only the last MAX_ORDER_HISTORY finished
+    /// orders across all owners, so older ones are gone once evicted.
+    pub fn get_history_by_owner(&self, owner: Address, offset: usize, limit: usize) -> Vec<Order> {
+        self.history
+            .iter()
+            .filter(|order| order.owner == owner)
+            .skip(offset)
+            .take(limit)
+            .copied()
+            .collect()
+    }
+
+    /// Get ids of open orders whose end_block is in [from_block, to_block], soonest first
+    pub fn get_orders_expiring_between(&self, from_block: U256, to_block: U256) -> Vec<U256> {
+        let mut expiring: Vec<&Order> = self
+            .orders
+            .iter()
+            .filter(|order| order.end_block >= from_block && order.end_block <= to_block)
+            .collect();
+        expiring.sort_by_key(|order| order.end_block);
+
+        expiring.iter().map(|order| order.id).collect()
+    }
+
+    /// Count orders for a specific owner
+    pub fn count_orders_by_owner(&self, owner: Address) -> usize {
+        self.orders
+            .iter()
+            .filter(|order| order.owner == owner)
+            .count()
+    }
+
+    /// Get the distinct owners of open orders, sorted by address
+    /// O(n log n) in the number of orders; meant for off-chain views, not transactions
+    pub fn get_active_owners(&self) -> Vec<Address> {
+        self.orders
+            .iter()
+            .map(|order| order.owner)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Count the distinct owners of open orders; see get_active_owners
+    pub fn active_owner_count(&self) -> usize {
+        self.get_active_owners().len()
+    }
+
+    /// Get active orders count
+    pub fn get_active_orders_count(&self) -> usize {
+        self.orders.len()
+    }
+
+    /// Get current total sell rates
+    pub fn get_current_sell_rates(&self) -> (U256, U256) {
+        (self.total_sell_rate_0, self.total_sell_rate_1)
+    }
+
+    /// Get the lifetime sell volume virtual execution has executed per token
+    pub fn get_cumulative_volumes(&self) -> (U256, U256) {
+        (self.cumulative_volume_0, self.cumulative_volume_1)
+    }
+
+    /// Get the principal active orders have yet to sell as (token0, token1)
+    /// Scheduled orders have not started selling and are not counted
+    pub fn total_remaining_principal(&self) -> Result<(U256, U256), OrderError> {
+        let mut remaining_0 = U256::ZERO;
+        let mut remaining_1 = U256::ZERO;
+        for order in self
+            .orders
+            .iter()
+            .filter(|order| order.status == OrderStatus::Active)
+        {
+            let total = match order.direction {
+                OrderDirection::SellToken0 => &mut remaining_0,
+                OrderDirection::SellToken1 => &mut remaining_1,
+            };
+            *total = total
+                .checked_add(order.remaining_amount)
+                .ok_or(OrderError::Overflow("Principal overflow"))?;
+        }
+        Ok((remaining_0, remaining_1))
+    }
+
+    /// Get the sell rates orders stream at during block, with ramping orders at their
+    /// effective rate; price limits and target prices are ignored
+    pub fn get_effective_sell_rates(&self, block: U256) -> Result<(U256, U256), OrderError> {
+        let next_block = block
+            .checked_add(U256::from(1u32))
+            .ok_or(OrderError::Overflow("Block overflow"))?;
+        self.get_active_sell_rates(block, next_block, None)
+    }
+
+    /// Get the time-weighted average sell rates over [from_block, to_block) as (rate_0, rate_1)
+    /// Each long-term order contributes its sell rate for the blocks of the window it spans,
+    /// so the result integrates the piecewise-constant totals between order boundaries.
+    /// Open orders and completed orders still in the history count; cancelled orders and
+    /// those evicted from the history are not known and price limits are ignored.
+    pub fn average_sell_rate(
+        &self,
+        from_block: U256,
+        to_block: U256,
+    ) -> Result<(U256, U256), OrderError> {
+        if to_block <= from_block {
+            return Err(OrderError::InvalidParams("Invalid window"));
+        }
+
+        let mut volume_0 = U256::ZERO;
+        let mut volume_1 = U256::ZERO;
+        let orders = self.orders.iter().chain(
+            self.history
+                .iter()
+                .filter(|order| order.status == OrderStatus::Completed),
+        );
+        for order in orders.filter(|order| order.order_type == OrderType::LongTerm) {
+            let overlap = order
+                .end_block
+                .min(to_block)
+                .saturating_sub(order.start_block.max(from_block));
+            let volume = order
+                .sell_rate
+                .checked_mul(overlap)
+                .ok_or(OrderError::Overflow("Rate calculation overflow"))?;
+
+            let total = match order.direction {
+                OrderDirection::SellToken0 => &mut volume_0,
+                OrderDirection::SellToken1 => &mut volume_1,
+            };
+            *total = total
+                .checked_add(volume)
+                .ok_or(OrderError::Overflow("Rate calculation overflow"))?;
+        }
+
+        let window = to_block - from_block;
+        Ok((volume_0 / window, volume_1 / window))
+    }
+
+    /// Calculate the instant swap that moves the pool price (token1 per token0)
+    /// back to an external spot price, returning the direction and sell amount
+    pub fn realignment_trade(
+        &self,
+        external_spot_price: U256,
+        reserve_0: U256,
+        reserve_1: U256,
+        precision: u32,
+    ) -> Result<(OrderDirection, U256), OrderError> {
+        if reserve_0 == U256::ZERO || reserve_1 == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid reserves"));
+        }
+
+        if external_spot_price == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid spot price"));
+        }
+
+        let one = U256::from(10u128.pow(precision));
+        let k = reserve_0
+            .checked_mul(reserve_1)
+            .ok_or(OrderError::Overflow("Invariant overflow"))?;
+
+        // On the curve x * y = k the price y / x equals p at x = sqrt(k / p)
+        let target_reserve_0 = TWAMMath::sqrt(
+            k.checked_mul(one)
+                .ok_or(OrderError::Overflow("Target reserve overflow"))?
+                / external_spot_price,
+        );
+
+        if target_reserve_0 >= reserve_0 {
+            return Ok((OrderDirection::SellToken0, target_reserve_0 - reserve_0));
+        }
+
+        // Price too low: buy token0 back by selling token1 up to y = sqrt(k * p)
+        let target_reserve_1 = TWAMMath::sqrt(
+            k.checked_mul(external_spot_price)
+                .ok_or(OrderError::Overflow("Target reserve overflow"))?
+                / one,
+        );
+
+        Ok((
+            OrderDirection::SellToken1,
+            target_reserve_1.saturating_sub(reserve_1),
+        ))
+    }
+
+    /// Whether no order has anything left to stream at current_block
+    /// Unlike an empty order list this ignores expired orders awaiting a sweep, paused and
+    /// halted orders; scheduled orders still count as work to come
+    pub fn is_idle(&self, current_block: U256) -> bool {
+        !self.orders.iter().any(|order| {
+            matches!(order.status, OrderStatus::Active | OrderStatus::Scheduled)
+                && order.active
+                && order.end_block > current_block
+                && order.remaining_amount > U256::ZERO
+        })
+    }
+
+    /// Check if virtual order execution is needed
+    /// It is once order_block_interval blocks have passed since the last execution, or
+    /// earlier when the spot price of the given reserves has moved more than
+    /// max_price_deviation_bps from the price the last execution left
+    pub fn needs_virtual_order_execution(
+        &self,
+        current_block: U256,
+        reserve_0: U256,
+        reserve_1: U256,
+    ) -> bool {
+        if self.orders.is_empty() {
+            return false;
+        }
+
+        let blocks_since_last = current_block
+            .checked_sub(self.virtual_order_state.last_virtual_order_block)
+            .unwrap_or(U256::ZERO);
+
+        blocks_since_last >= self.virtual_order_state.order_block_interval
+            || self.price_deviation_exceeded(reserve_0, reserve_1)
+    }
+
+    /// Whether the spot price has drifted past max_price_deviation_bps since the last
+    /// execution; false while disabled or before any execution recorded a price
+    fn price_deviation_exceeded(&self, reserve_0: U256, reserve_1: U256) -> bool {
+        let state = &self.virtual_order_state;
+        if state.max_price_deviation_bps == U256::ZERO || state.last_execution_price == U256::ZERO {
+            return false;
+        }
+        let Ok(spot_price) = Self::spot_price(reserve_0, reserve_1, LIMIT_PRICE_PRECISION) else {
+            return false;
+        };
+
+        TWAMMath::mul_div_wide(
+            spot_price.abs_diff(state.last_execution_price),
+            U256::from(FEE_DENOMINATOR),
+            state.last_execution_price,
+        )
+        .is_none_or(|deviation_bps| deviation_bps > state.max_price_deviation_bps)
+    }
+
+    /// Whether the caller's reserves look passed as (reserve_1, reserve_0)
+    /// True when their price is outside reserve_swap_tolerance_bps of the last execution
+    /// price but the inverted price is within it. A genuine move leaves the inverted price
+    /// far away too, and near-equal reserves are let through since swapping them changes
+    /// little. False while disabled or before any execution recorded a price.
+    fn reserves_look_swapped(&self, reserve_0: U256, reserve_1: U256) -> bool {
+        let last_price = self.virtual_order_state.last_execution_price;
+        if self.reserve_swap_tolerance_bps == U256::ZERO || last_price == U256::ZERO {
+            return false;
+        }
+        let (Ok(price), Ok(inverted)) = (
+            Self::spot_price(reserve_0, reserve_1, LIMIT_PRICE_PRECISION),
+            Self::spot_price(reserve_1, reserve_0, LIMIT_PRICE_PRECISION),
+        ) else {
+            return false;
+        };
+
+        let within_tolerance = |candidate: U256| {
+            TWAMMath::mul_div_wide(
+                candidate.abs_diff(last_price),
+                U256::from(FEE_DENOMINATOR),
+                last_price,
+            )
+            .is_some_and(|deviation_bps| deviation_bps <= self.reserve_swap_tolerance_bps)
+        };
+        !within_tolerance(price) && within_tolerance(inverted)
+    }
+
+    /// Get the block at which needs_virtual_order_execution next turns true by elapsed blocks
+    /// alone; a price deviation can make execution due sooner
+    /// With no orders there is nothing to schedule, so this is last_virtual_order_block
+    pub fn next_execution_block(&self) -> U256 {
+        let last_block = self.virtual_order_state.last_virtual_order_block;
+        if self.orders.is_empty() {
+            return last_block;
+        }
+
+        last_block.saturating_add(self.virtual_order_state.order_block_interval)
+    }
+
+    /// Set the price drift, in basis points, that makes execution due before the block
+    /// interval has elapsed (zero disables)
+    pub fn set_max_price_deviation(&mut self, max_deviation_bps: U256) {
+        self.virtual_order_state.max_price_deviation_bps = max_deviation_bps;
+        console!(
+            "Updated maximum price deviation to {} bps",
+            max_deviation_bps
+        );
+    }
+
+    /// Update virtual order execution interval
+    pub fn set_order_block_interval(&mut self, interval: U256) -> Result<(), OrderError> {
+        if interval == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid block interval"));
+        }
+        // A huge interval would stop virtual execution for good
+        if interval > self.max_order_block_interval {
+            return Err(OrderError::InvalidParams("interval too large"));
+        }
+
+        self.virtual_order_state.order_block_interval = interval;
+        console!("Updated order block interval to {}", interval);
+        Ok(())
+    }
+
+    /// Update the largest interval set_order_block_interval accepts
+    pub fn set_max_order_block_interval(&mut self, max_interval: U256) -> Result<(), OrderError> {
+        if max_interval == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid block interval"));
+        }
+
+        self.max_order_block_interval = max_interval;
+        console!("Updated maximum order block interval to {}", max_interval);
+        Ok(())
+    }
+
+    /// Get a copy of the virtual order execution state
+    pub fn get_virtual_order_state(&self) -> VirtualOrderState {
+        self.virtual_order_state
+    }
+
+    /// Start virtual execution from the given block, for pools bootstrapped after genesis
+    /// Only allowed while no orders are open, and the block can never move backwards
+    pub fn initialize_last_virtual_order_block(&mut self, block: U256) -> Result<(), OrderError> {
+        if !self.orders.is_empty() {
+            return Err(OrderError::InvalidState("Orders already open"));
+        }
+
+        if block < self.virtual_order_state.last_virtual_order_block {
+            return Err(OrderError::InvalidState("Block before last execution"));
+        }
+
+        self.virtual_order_state.last_virtual_order_block = block;
+        console!("Initialized last virtual order block to {}", block);
+        Ok(())
+    }
+
+    /// Halt order submission and virtual execution
+    /// Cancelling orders and claiming proceeds stay available so users can exit
+    pub fn pause(&mut self) {
+        self.paused = true;
+        console!("Order pool paused");
+    }
+
+    /// Resume order submission and virtual execution
+    pub fn unpause(&mut self) {
+        self.paused = false;
+        console!("Order pool unpaused");
+    }
+
+    /// Choose what execution does with segments the reserves cannot absorb; see
+    /// LiquidityPolicy. Halt is the default
+    pub fn set_liquidity_policy(&mut self, policy: LiquidityPolicy) {
+        self.liquidity_policy = policy;
+        console!("Updated liquidity policy to {}", policy as u8);
+    }
+
+    /// Choose whether execution skips segments the reserves cannot absorb, reporting their
+    /// volume as unfilled, or fails as a whole (the default)
+    /// Shorthand for set_liquidity_policy with Throttle or Halt
+    pub fn set_saturating_execution(&mut self, enabled: bool) {
+        self.set_liquidity_policy(if enabled {
+            LiquidityPolicy::Throttle
+        } else {
+            LiquidityPolicy::Halt
+        });
+    }
+
+    /// Choose whether execution fails with "zero output execution" instead of advancing when
+    /// orders sold volume but the curve paid nothing out, so the window is not wasted
+    pub fn set_strict_execution(&mut self, enabled: bool) {
+        self.strict_execution = enabled;
+        console!("Updated strict execution to {}", enabled);
+    }
+
+    /// Choose how new orders round their sell rate
+    /// Floor (the default) runs every order its full duration but leaves dust to refund;
+    /// Ceil sells the whole amount but can finish a few blocks before the requested end
+    pub fn set_rate_rounding(&mut self, rounding: RateRounding) {
+        self.rate_rounding = rounding;
+        console!("Updated rate rounding to {}", rounding as u8);
+    }
+
+    /// Set the blocks over which new and open orders ramp their rate in (zero disables)
+    /// A ramping order streams less at first and catches up in its last window, so it
+    /// still finishes at end_block; target-priced orders pace themselves and do not ramp
+    pub fn set_ramp_blocks(&mut self, ramp_blocks: U256) {
+        self.ramp_blocks = ramp_blocks;
+        console!("Updated ramp blocks to {}", ramp_blocks);
+    }
+
+    /// Update the seconds per block used to convert second-denominated order durations
+    pub fn set_block_time(&mut self, block_time_secs: U256) -> Result<(), OrderError> {
+        if block_time_secs == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid block time"));
+        }
+
+        self.block_time_secs = block_time_secs;
+        console!("Updated block time to {} seconds", block_time_secs);
+        Ok(())
+    }
+
+    /// Choose how new order ids are derived
+    /// Only possible while no orders are open, so ids of both schemes never mix
+    pub fn set_order_id_scheme(&mut self, scheme: OrderIdScheme) -> Result<(), OrderError> {
+        if !self.orders.is_empty() {
+            return Err(OrderError::InvalidState("Orders open"));
+        }
+
+        self.order_id_scheme = scheme;
+        console!("Updated order id scheme to {}", scheme as u8);
+        Ok(())
+    }
+
+    /// Update the most blocks a single execution may advance (zero removes the cap)
+    pub fn set_max_blocks_per_execution(&mut self, max_blocks: U256) {
+        self.max_blocks_per_execution = max_blocks;
+        console!("Updated max blocks per execution to {}", max_blocks);
+    }
+
+    /// Update the bounds on new order durations; existing orders are unaffected
+    pub fn set_duration_bounds(
+        &mut self,
+        min_duration_blocks: U256,
+        max_duration_blocks: U256,
+    ) -> Result<(), OrderError> {
+        if min_duration_blocks == U256::ZERO || min_duration_blocks > max_duration_blocks {
+            return Err(OrderError::InvalidParams("Invalid duration bounds"));
+        }
+
+        self.min_duration_blocks = min_duration_blocks;
+        self.max_duration_blocks = max_duration_blocks;
+        console!(
+            "Updated duration bounds to {} - {}",
+            min_duration_blocks,
+            max_duration_blocks
+        );
+        Ok(())
+    }
+
+    /// Update the maximum number of open orders per owner
+    pub fn set_max_orders_per_owner(&mut self, max_orders: usize) -> Result<(), OrderError> {
+        if max_orders == 0 {
+            return Err(OrderError::InvalidParams("Invalid order limit"));
+        }
+
+        self.max_orders_per_owner = max_orders;
+        console!("Updated max orders per owner to {}", max_orders);
+        Ok(())
+    }
+
+    /// Update the maximum number of open orders across the pool
+    /// Lowering it below the current count only blocks new orders; none are evicted
+    pub fn set_max_orders(&mut self, max_orders: usize) -> Result<(), OrderError> {
+        if max_orders == 0 {
+            return Err(OrderError::InvalidParams("Invalid order limit"));
         }
 
-        Ok((total_rate_0, total_rate_1))
+        self.max_orders = max_orders;
+        console!("Updated max pool orders to {}", max_orders);
+        Ok(())
     }
 
-    /// Update orders after virtual execution
-    fn update_orders_after_execution(
-        &mut self,
-        blocks_elapsed: U256,
-        amount_0_received: U256,
-        amount_1_received: U256,
-        current_block: U256,
-    ) -> Result<(), Vec<u8>> {
-        let mut orders_to_remove = Vec::new();
+    /// Get how many more orders the pool accepts before max_orders is reached
+    pub fn capacity_remaining(&self) -> usize {
+        self.max_orders.saturating_sub(self.orders.len())
+    }
 
-        for (index, order) in self.orders.iter_mut().enumerate() {
-            if order.order_type != OrderType::LongTerm {
+    /// Remove every expired order without running virtual execution, returning the removed
+    /// orders. All or nothing, like cancel_all_orders
+    ///
+    /// Orders execution has carried through their end block are recorded Completed and
+    /// their proceeds stay claimable from the history. Orders whose window was never fully
+    /// executed still hold principal, so they are cancelled and returned for the caller to
+    /// refund remaining_amount and accumulated_out. Executed orders short of
+    /// min_output_total are halted instead of completed, and halted orders are kept.
+    pub fn sweep_expired(&mut self, current_block: U256) -> Result<Vec<Order>, OrderError> {
+        let swept: Vec<Order> = self
+            .orders
+            .iter()
+            .filter(|order| Self::sweepable(order, current_block))
+            .copied()
+            .collect();
+        self.check_rates_cover(&swept)?;
+
+        let mut removed = Vec::new();
+        let mut index = 0;
+
+        while index < self.orders.len() {
+            let order = self.orders[index];
+            if !Self::sweepable(&order, current_block) {
+                index += 1;
                 continue;
             }
 
-            // Check if order has expired
-            if current_block >= order.end_block {
-                orders_to_remove.push(index);
+            if !self.fully_executed(&order) {
+                removed.push(self.remove_cancelled_order(index)?);
                 continue;
             }
 
-            // Update order state
-            let amount_sold = order
-                .sell_rate
-                .checked_mul(blocks_elapsed)
-                .ok_or(b"Amount calculation overflow".to_vec())?;
-
-            order.remaining_amount = order
-                .remaining_amount
-                .checked_sub(amount_sold.min(order.remaining_amount))
-                .unwrap_or(U256::ZERO);
-
-            // Distribute received amounts proportionally
-            let received_amount = match order.direction {
-                OrderDirection::SellToken0 => {
-                    // This order sold token0, received token1
-                    if self.total_sell_rate_0 > U256::ZERO {
-                        amount_1_received
-                            .checked_mul(order.sell_rate)
-                            .ok_or(b"Distribution calculation overflow".to_vec())?
-                            .checked_div(self.total_sell_rate_0)
-                            .ok_or(b"Distribution division error".to_vec())?
-                    } else {
-                        U256::ZERO
-                    }
-                }
-                OrderDirection::SellToken1 => {
-                    // This order sold token1, received token0
-                    if self.total_sell_rate_1 > U256::ZERO {
-                        amount_0_received
-                            .checked_mul(order.sell_rate)
-                            .ok_or(b"Distribution calculation overflow".to_vec())?
-                            .checked_div(self.total_sell_rate_1)
-                            .ok_or(b"Distribution division error".to_vec())?
-                    } else {
-                        U256::ZERO
-                    }
-                }
-            };
-
-            order.accumulated_out = order
-                .accumulated_out
-                .checked_add(received_amount)
-                .ok_or(b"Accumulated amount overflow".to_vec())?;
-
-            order.last_virtual_order_block = current_block;
-
-            // Mark completed orders for removal
-            if order.remaining_amount == U256::ZERO {
-                orders_to_remove.push(index);
+            if order.accumulated_out < order.min_output_total {
+                self.halt_underfilled_order(index);
+                index += 1;
+                continue;
             }
-        }
 
-        // Remove completed orders (in reverse order to maintain indices)
-        for &index in orders_to_remove.iter().rev() {
-            let completed_order = self.orders.remove(index);
+            self.orders.remove(index);
+            let order = Order {
+                status: OrderStatus::Completed,
+                ..order
+            };
+            self.record_completion(order);
 
-            // Update total sell rates
-            match completed_order.direction {
+            match order.direction {
                 OrderDirection::SellToken0 => {
                     self.total_sell_rate_0 = self
                         .total_sell_rate_0
-                        .checked_sub(completed_order.sell_rate)
+                        .checked_sub(order.sell_rate)
                         .unwrap_or(U256::ZERO);
                 }
                 OrderDirection::SellToken1 => {
                     self.total_sell_rate_1 = self
                         .total_sell_rate_1
-                        .checked_sub(completed_order.sell_rate)
+                        .checked_sub(order.sell_rate)
                         .unwrap_or(U256::ZERO);
                 }
             }
 
-            console!("Completed order {}", completed_order.id);
+            removed.push(order);
+        }
+
+        console!("Swept {} expired orders", removed.len());
+        Ok(removed)
+    }
+
+    /// Whether execution, or settle_order, has carried a streaming order through its end block
+    fn fully_executed(&self, order: &Order) -> bool {
+        order.status == OrderStatus::Active
+            && order
+                .last_virtual_order_block
+                .max(self.virtual_order_state.last_virtual_order_block)
+                >= order.end_block
+    }
+
+    /// Update the protocol fee applied to virtual execution
+    pub fn set_fee_bps(&mut self, fee_bps: U256) -> Result<(), OrderError> {
+        if fee_bps >= U256::from(FEE_DENOMINATOR) {
+            return Err(OrderError::InvalidParams("Invalid fee"));
         }
 
+        self.fee_bps = fee_bps;
+        console!("Updated fee to {} bps", fee_bps);
         Ok(())
     }
 
-    /// Get order details by ID
-    pub fn get_order(&self, order_id: U256) -> Option<Order> {
-        self.orders
-            .iter()
-            .find(|order| order.id == order_id)
-            .copied()
+    /// Update the keeper reward paid on each execution
+    /// gas_price is in token units per unit of gas and caps each token's reward
+    pub fn set_keeper_reward(
+        &mut self,
+        reward_bps: U256,
+        gas_price: U256,
+    ) -> Result<(), OrderError> {
+        if reward_bps >= U256::from(FEE_DENOMINATOR) {
+            return Err(OrderError::InvalidParams("Invalid keeper reward"));
+        }
+
+        self.keeper_reward_bps = reward_bps;
+        self.keeper_gas_price = gas_price;
+        console!("Updated keeper reward to {} bps", reward_bps);
+        Ok(())
     }
 
-    /// Get all orders for a specific owner
-    pub fn get_orders_by_owner(&self, owner: Address) -> Vec<Order> {
-        self.orders
-            .iter()
-            .filter(|order| order.owner == owner)
-            .copied()
-            .collect()
+    /// Update the cap on one direction's aggregate sell rate (zero disables)
+    /// Open orders are kept even if they already exceed a lowered cap
+    pub fn set_max_total_sell_rate(&mut self, direction: OrderDirection, max_rate: U256) {
+        match direction {
+            OrderDirection::SellToken0 => self.max_total_sell_rate_0 = max_rate,
+            OrderDirection::SellToken1 => self.max_total_sell_rate_1 = max_rate,
+        }
+        console!("Updated max total sell rate to {}", max_rate);
     }
 
-    /// Get active orders count
-    pub fn get_active_orders_count(&self) -> usize {
-        self.orders.len()
+    /// Update the reserve floor enforced during virtual execution
+    pub fn set_min_reserve(&mut self, min_reserve: U256) {
+        self.min_reserve = min_reserve;
+        console!("Updated min reserve to {}", min_reserve);
     }
 
-    /// Get current total sell rates
-    pub fn get_current_sell_rates(&self) -> (U256, U256) {
-        (self.total_sell_rate_0, self.total_sell_rate_1)
+    /// Record the pool's actual reserves and turn on reserve tracking
+    /// Call again after anything outside virtual execution moves the reserves, like swaps
+    pub fn sync_reserves(&mut self, reserve_0: U256, reserve_1: U256) {
+        self.reserve_tracking = true;
+        self.stored_reserve_0 = reserve_0;
+        self.stored_reserve_1 = reserve_1;
+        console!("Synced reserves {} / {}", reserve_0, reserve_1);
     }
 
-    /// Check if virtual order execution is needed
-    pub fn needs_virtual_order_execution(&self, current_block: U256) -> bool {
-        if self.orders.is_empty() {
-            return false;
+    /// Turn off reserve tracking, going back to trusting the caller's reserves
+    pub fn disable_reserve_tracking(&mut self) {
+        self.reserve_tracking = false;
+        console!("Disabled reserve tracking");
+    }
+
+    /// Update how far caller reserves may drift from the stored ones
+    pub fn set_reserve_tolerance(&mut self, tolerance_bps: U256) -> Result<(), OrderError> {
+        if tolerance_bps > U256::from(FEE_DENOMINATOR) {
+            return Err(OrderError::InvalidParams("Invalid tolerance"));
         }
 
-        let blocks_since_last = current_block
-            .checked_sub(self.virtual_order_state.last_virtual_order_block)
-            .unwrap_or(U256::ZERO);
+        self.reserve_tolerance_bps = tolerance_bps;
+        console!("Updated reserve tolerance to {} bps", tolerance_bps);
+        Ok(())
+    }
 
-        blocks_since_last >= self.virtual_order_state.order_block_interval
+    /// Update how many blocks an order must stream before it can be cancelled (zero disables)
+    pub fn set_min_blocks_before_cancel(&mut self, blocks: U256) {
+        self.min_blocks_before_cancel = blocks;
+        console!("Updated minimum blocks before cancel to {}", blocks);
     }
 
-    /// Update virtual order execution interval
-    pub fn set_order_block_interval(&mut self, interval: U256) -> Result<(), Vec<u8>> {
-        if interval == U256::ZERO {
-            return Err(b"Invalid block interval".to_vec());
+    /// Update the tolerance execution uses to detect swapped reserves (zero disables)
+    pub fn set_reserve_swap_tolerance(&mut self, tolerance_bps: U256) -> Result<(), OrderError> {
+        if tolerance_bps > U256::from(FEE_DENOMINATOR) {
+            return Err(OrderError::InvalidParams("Invalid tolerance"));
         }
 
-        self.virtual_order_state.order_block_interval = interval;
-        console!("Updated order block interval to {}", interval);
+        self.reserve_swap_tolerance_bps = tolerance_bps;
+        console!("Updated reserve swap tolerance to {} bps", tolerance_bps);
+        Ok(())
+    }
+
+    /// Update the cap on a new order's cumulative price move (zero disables)
+    pub fn set_max_price_move(&mut self, max_move_bps: U256) -> Result<(), OrderError> {
+        if max_move_bps > U256::from(FEE_DENOMINATOR) {
+            return Err(OrderError::InvalidParams("Invalid price move"));
+        }
+
+        self.max_price_move_bps = max_move_bps;
+        console!("Updated maximum price move to {} bps", max_move_bps);
         Ok(())
     }
 
-    /// Estimate gas cost for virtual order execution
+    /// Collect accumulated fees for the recipient, returning (fees_0, fees_1)
+    /// The caller is responsible for moving these amounts out of the reserves
+    pub fn collect_fees(&mut self, recipient: Address, current_block: U256) -> (U256, U256) {
+        let fees = (self.collected_fees_0, self.collected_fees_1);
+        self.collected_fees_0 = U256::ZERO;
+        self.collected_fees_1 = U256::ZERO;
+
+        if let Some(event) = events::fees_collected(recipient, fees.0, fees.1, current_block) {
+            events::emit(event);
+        }
+        console!("Collected fees {} / {}", fees.0, fees.1);
+        fees
+    }
+
+    /// Reduce a sell rate by the protocol fee
+    /// The fee rounds up, in the pool's favour; see TWAMMath::mul_div_rounding_up
+    fn apply_fee(&self, sell_rate: U256) -> Result<U256, OrderError> {
+        if self.fee_bps > U256::from(FEE_DENOMINATOR) {
+            return Err(OrderError::InvalidParams("Invalid fee"));
+        }
+
+        let fee =
+            TWAMMath::mul_div_rounding_up(sell_rate, self.fee_bps, U256::from(FEE_DENOMINATOR))
+                .map_err(|_| OrderError::Overflow("Fee calculation overflow"))?;
+
+        Ok(sell_rate - fee)
+    }
+
+    /// Estimate the gas of sweep_expired at current_block under the pool's gas model
+    /// Counts the orders it would remove; the estimate grows linearly with them
+    pub fn estimate_sweep_gas(&self, current_block: U256) -> U256 {
+        let expired = self
+            .orders
+            .iter()
+            .filter(|order| Self::sweepable(order, current_block))
+            .count();
+
+        self.gas_model.sweep_gas(expired)
+    }
+
+    /// Whether sweep_expired takes up the order at current_block
+    /// Halted underfilled and paused orders are kept
+    fn sweepable(order: &Order, current_block: U256) -> bool {
+        order.order_type == OrderType::LongTerm
+            && order.status != OrderStatus::Underfilled
+            && order.active
+            && order.end_block <= current_block
+    }
+
+    /// Estimate gas cost for virtual order execution under the pool's gas model
     pub fn estimate_virtual_execution_gas(&self, current_block: U256) -> U256 {
         let blocks_since_last = current_block
             .checked_sub(self.virtual_order_state.last_virtual_order_block)
             .unwrap_or(U256::ZERO);
 
-        // Base gas cost for virtual execution
-        let base_gas = U256::from(50000u32);
+        self.gas_model
+            .execution_gas(blocks_since_last, self.orders.len())
+    }
 
-        // Additional gas per block of execution
-        let per_block_gas = U256::from(1000u32);
-        let blocks_gas = blocks_since_last
-            .checked_mul(per_block_gas)
-            .unwrap_or(U256::ZERO);
+    /// Replace the gas costs used for estimates, for chains with different opcode pricing
+    pub fn set_gas_model(&mut self, gas_model: GasModel) {
+        self.gas_model = gas_model;
+        console!("Updated gas model");
+    }
 
-        // Additional gas per active order
-        let per_order_gas = U256::from(5000u32);
-        let orders_gas = U256::from(self.orders.len() as u64)
-            .checked_mul(per_order_gas)
-            .unwrap_or(U256::ZERO);
+    /// Marginal gas cost of waiting one more block before executing virtual orders
+    pub fn marginal_staleness_cost(&self, current_block: U256) -> U256 {
+        let next_block = current_block.saturating_add(U256::from(1u32));
 
-        base_gas
-            .checked_add(blocks_gas)
-            .unwrap_or(U256::MAX)
-            .checked_add(orders_gas)
-            .unwrap_or(U256::MAX)
+        self.estimate_virtual_execution_gas(next_block)
+            .saturating_sub(self.estimate_virtual_execution_gas(current_block))
     }
 
     /// Get detailed execution statistics
-    pub fn get_execution_statistics(&self, current_block: U256) -> ExecutionStatistics {
+    /// Fails with "Statistics overflow" rather than reporting a truncated volume
+    pub fn get_execution_statistics(
+        &self,
+        current_block: U256,
+    ) -> Result<ExecutionStatistics, OrderError> {
         let mut total_volume_0 = U256::ZERO;
         let mut total_volume_1 = U256::ZERO;
         let mut active_orders = 0;
@@ -527,39 +4402,22 @@ impl OrderPool {
                 active_orders += 1;
             }
 
-            match order.direction {
-                OrderDirection::SellToken0 => {
-                    let executed_amount = order
-                        .sell_rate
-                        .checked_mul(
-                            current_block
-                                .checked_sub(order.start_block)
-                                .unwrap_or(U256::ZERO),
-                        )
-                        .unwrap_or(U256::ZERO)
-                        .min(order.remaining_amount);
-                    total_volume_0 = total_volume_0
-                        .checked_add(executed_amount)
-                        .unwrap_or(total_volume_0);
-                }
-                OrderDirection::SellToken1 => {
-                    let executed_amount = order
-                        .sell_rate
-                        .checked_mul(
-                            current_block
-                                .checked_sub(order.start_block)
-                                .unwrap_or(U256::ZERO),
-                        )
-                        .unwrap_or(U256::ZERO)
-                        .min(order.remaining_amount);
-                    total_volume_1 = total_volume_1
-                        .checked_add(executed_amount)
-                        .unwrap_or(total_volume_1);
-                }
-            }
+            // Orders that have not started yet have executed nothing
+            let executed_amount = order
+                .sell_rate
+                .checked_mul(current_block.saturating_sub(order.start_block))
+                .ok_or(OrderError::Overflow("Statistics overflow"))?
+                .min(order.remaining_amount);
+            let total_volume = match order.direction {
+                OrderDirection::SellToken0 => &mut total_volume_0,
+                OrderDirection::SellToken1 => &mut total_volume_1,
+            };
+            *total_volume = total_volume
+                .checked_add(executed_amount)
+                .ok_or(OrderError::Overflow("Statistics overflow"))?;
         }
 
-        ExecutionStatistics {
+        Ok(ExecutionStatistics {
             active_orders,
             completed_orders,
             total_volume_0,
@@ -567,7 +4425,7 @@ impl OrderPool {
             current_sell_rate_0: self.total_sell_rate_0,
             current_sell_rate_1: self.total_sell_rate_1,
             last_virtual_execution_block: self.virtual_order_state.last_virtual_order_block,
-        }
+        })
     }
 }
 
@@ -583,11 +4441,72 @@ pub struct ExecutionStatistics {
     pub last_virtual_execution_block: U256,
 }
 
+/// Check that execution payouts stay within what the constant product curve can release
+///
+/// Given the starting reserves and the amounts sold into the curve, the reserves implied by
+/// the payouts must keep k, allowing for the closed form rounding each reserve down. Checked
+/// on every execution segment when the `invariant-checks` feature is enabled.
+pub fn check_curve_invariant(
+    reserve_0: U256,
+    reserve_1: U256,
+    sold_0: U256,
+    sold_1: U256,
+    received_0: U256,
+    received_1: U256,
+) -> Result<(), OrderError> {
+    let implied_reserve_0 = reserve_0
+        .checked_add(sold_0)
+        .and_then(|total| total.checked_sub(received_0))
+        .filter(|reserve| *reserve > U256::ZERO)
+        .ok_or(OrderError::InvalidState("invariant violated"))?;
+    let implied_reserve_1 = reserve_1
+        .checked_add(sold_1)
+        .and_then(|total| total.checked_sub(received_1))
+        .ok_or(OrderError::InvalidState("invariant violated"))?;
+
+    // A one-sided token1 flow pays out token0, so reserve_0 is the one rounded down
+    if sold_0 == U256::ZERO && sold_1 > U256::ZERO {
+        let min_reserve_0 = TWAMMath::mul_div_wide(reserve_0, reserve_1, implied_reserve_1)
+            .ok_or(OrderError::InvalidState("invariant violated"))?;
+        if implied_reserve_0 < min_reserve_0 {
+            return Err(OrderError::InvalidState("invariant violated"));
+        }
+        return Ok(());
+    }
+
+    // One-sided flows leave the input reserve exact, but the bidirectional closed form
+    // rounds both reserves, so allow each of them one unit
+    let rounding = if sold_0 > U256::ZERO && sold_1 > U256::ZERO {
+        U256::from(1u32)
+    } else {
+        U256::ZERO
+    };
+
+    // Smallest reserve_1 the curve allows at implied_reserve_0, rounded down
+    let min_reserve_1 = implied_reserve_0
+        .checked_add(rounding)
+        .and_then(|rounded_reserve_0| {
+            TWAMMath::mul_div_wide(reserve_0, reserve_1, rounded_reserve_0)
+        })
+        .ok_or(OrderError::InvalidState("invariant violated"))?;
+
+    if implied_reserve_1.saturating_add(rounding) < min_reserve_1 {
+        return Err(OrderError::InvalidState("invariant violated"));
+    }
+
+    Ok(())
+}
+
 /// Order management utilities
 pub struct OrderManager;
 
 impl OrderManager {
     /// Calculate optimal order block interval based on gas costs and execution frequency
+    ///
+    /// The frequency target sets a base interval (100 blocks if the target is zero), which is
+    /// then scaled by sqrt(avg_gas_price / REFERENCE_GAS_PRICE): each execution has a fixed
+    /// gas cost while staleness grows with the interval, so pricier gas favors fewer, larger
+    /// executions. The result is clamped to [MIN_ORDER_BLOCK_INTERVAL, MAX_ORDER_BLOCK_INTERVAL].
     pub fn calculate_optimal_interval(
         avg_gas_price: U256,
         execution_frequency_target: U256, // Target executions per day
@@ -595,13 +4514,61 @@ impl OrderManager {
         // Assume ~6000 blocks per day (15 second block times)
         let blocks_per_day = U256::from(6000u32);
 
-        let optimal_interval = blocks_per_day
+        let base_interval = blocks_per_day
             .checked_div(execution_frequency_target)
-            .unwrap_or(U256::from(100u32)) // Default fallback
-            .max(U256::from(10u32)) // Minimum interval
-            .min(U256::from(1000u32)); // Maximum interval
+            .unwrap_or(U256::from(100u32)); // Default fallback
+
+        // interval = sqrt(base^2 * gas / reference), saturating to the maximum on overflow
+        let scaled_interval = base_interval
+            .checked_mul(base_interval)
+            .and_then(|squared| squared.checked_mul(avg_gas_price))
+            .map(|weighted| TWAMMath::sqrt(weighted / U256::from(REFERENCE_GAS_PRICE)))
+            .unwrap_or(U256::from(MAX_ORDER_BLOCK_INTERVAL));
+
+        scaled_interval
+            .max(U256::from(MIN_ORDER_BLOCK_INTERVAL))
+            .min(U256::from(MAX_ORDER_BLOCK_INTERVAL))
+    }
+
+    /// Convert a duration in seconds to blocks, rounding up and to at least MIN_ORDER_DURATION
+    pub fn blocks_for_seconds(seconds: U256, block_time_secs: U256) -> Result<U256, OrderError> {
+        if seconds == U256::ZERO {
+            return Err(OrderError::InvalidParams("Duration must be positive"));
+        }
+        if block_time_secs == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid block time"));
+        }
+
+        // Round up so the order never streams for less than the requested time
+        let blocks = seconds.div_ceil(block_time_secs);
+
+        Ok(blocks.max(U256::from(MIN_ORDER_DURATION)))
+    }
+
+    /// Offset base_interval by a pseudo-random jitter in [-max_jitter, max_jitter] derived
+    /// from a block hash, so executions do not land on predictable blocks
+    /// The same seed always gives the same interval. The seed is hashed first, so
+    /// consecutive or low-entropy seeds still spread across the range. max_jitter must be
+    /// below base_interval, keeping the interval positive.
+    pub fn jittered_interval(
+        base_interval: U256,
+        block_hash_seed: B256,
+        max_jitter: U256,
+    ) -> Result<U256, OrderError> {
+        if max_jitter >= base_interval {
+            return Err(OrderError::InvalidParams(
+                "Jitter must be below the interval",
+            ));
+        }
 
-        optimal_interval
+        let outcomes = max_jitter
+            .checked_mul(U256::from(2u32))
+            .and_then(|span| span.checked_add(U256::from(1u32)))
+            .ok_or(OrderError::Overflow("Jitter overflow"))?;
+        let offset = U256::from_be_bytes(crypto::keccak(block_hash_seed).0) % outcomes;
+        (base_interval - max_jitter)
+            .checked_add(offset)
+            .ok_or(OrderError::Overflow("Jitter overflow"))
     }
 
     /// Validate order parameters
@@ -609,21 +4576,25 @@ impl OrderManager {
         sell_amount: U256,
         duration_blocks: U256,
         current_reserve: U256,
-    ) -> Result<(), Vec<u8>> {
+    ) -> Result<(), OrderError> {
         if sell_amount == U256::ZERO {
-            return Err(b"Sell amount cannot be zero".to_vec());
+            return Err(OrderError::InvalidParams("Sell amount cannot be zero"));
         }
 
         if duration_blocks == U256::ZERO {
-            return Err(b"Duration must be positive".to_vec());
+            return Err(OrderError::InvalidParams("Duration must be positive"));
         }
 
-        if duration_blocks < U256::from(10u32) {
-            return Err(b"Duration too short (minimum 10 blocks)".to_vec());
+        if duration_blocks < U256::from(MIN_ORDER_DURATION) {
+            return Err(OrderError::InvalidParams(
+                "Duration too short (minimum 10 blocks)",
+            ));
         }
 
-        if duration_blocks > U256::from(1000000u32) {
-            return Err(b"Duration too long (maximum 1M blocks)".to_vec());
+        if duration_blocks > U256::from(MAX_ORDER_DURATION) {
+            return Err(OrderError::InvalidParams(
+                "Duration too long (maximum 1M blocks)",
+            ));
         }
 
         // Check if sell amount is reasonable relative to current reserves
@@ -632,26 +4603,64 @@ impl OrderManager {
             .unwrap_or(U256::ZERO); // Max 10% of reserves
 
         if sell_amount > max_reasonable_amount && current_reserve > U256::ZERO {
-            return Err(b"Sell amount too large relative to reserves".to_vec());
+            return Err(OrderError::InvalidParams(
+                "Sell amount too large relative to reserves",
+            ));
         }
 
         Ok(())
     }
 
-    /// Calculate time-weighted average price impact
+    /// Validate that new orders together with the existing sell rates stay within the
+    /// per-block rate cap of validate_twamm_constraints (DEFAULT_MAX_RATE_BPS_PER_BLOCK of
+    /// each sold token's reserve), even when every order passes validate_order_params alone
+    pub fn validate_batch_against_caps(
+        existing_rate_0: U256,
+        existing_rate_1: U256,
+        new_orders: Vec<(OrderDirection, U256)>,
+        reserve_0: U256,
+        reserve_1: U256,
+    ) -> Result<(), OrderError> {
+        if reserve_0 == U256::ZERO || reserve_1 == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid reserves"));
+        }
+
+        let (mut total_rate_0, mut total_rate_1) = (existing_rate_0, existing_rate_1);
+        for (direction, sell_rate) in new_orders {
+            let total_rate = match direction {
+                OrderDirection::SellToken0 => &mut total_rate_0,
+                OrderDirection::SellToken1 => &mut total_rate_1,
+            };
+            *total_rate = total_rate
+                .checked_add(sell_rate)
+                .ok_or(OrderError::Overflow("Rate overflow"))?;
+        }
+
+        TWAMMath::validate_twamm_constraints(
+            reserve_0,
+            reserve_1,
+            total_rate_0,
+            total_rate_1,
+            U256::from(1u32),
+            U256::from(DEFAULT_MAX_RATE_BPS_PER_BLOCK),
+        )
+        .map_err(|_| OrderError::LimitReached("Batch sell rate too high"))
+    }
+
+    /// Calculate time-weighted average price impact, in basis points
     pub fn calculate_twap_impact(
         sell_amount: U256,
         duration_blocks: U256,
         reserve_in: U256,
         reserve_out: U256,
-    ) -> Result<U256, Vec<u8>> {
+    ) -> Result<U256, OrderError> {
         if duration_blocks == U256::ZERO {
-            return Err(b"Invalid duration".to_vec());
+            return Err(OrderError::InvalidParams("Invalid duration"));
         }
 
         let sell_rate = sell_amount
             .checked_div(duration_blocks)
-            .ok_or(b"Rate calculation error".to_vec())?;
+            .ok_or(OrderError::MathFailed("Rate calculation error"))?;
 
         // Calculate impact using TWAMM math
         let impact = TWAMMath::calculate_unidirectional_twamm(
@@ -660,27 +4669,343 @@ impl OrderManager {
             reserve_in,
             reserve_out,
         )
-        .map_err(|_| b"Impact calculation failed".to_vec())?;
+        .map_err(|_| OrderError::MathFailed("Impact calculation failed"))?;
 
+        Self::impact_bps(sell_amount, impact, reserve_in, reserve_out)
+    }
+
+    /// Convert an output amount to its shortfall against the spot price, in basis points
+    /// Outputs at or above the spot amount have zero impact
+    fn impact_bps(
+        sell_amount: U256,
+        amount_out: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+    ) -> Result<U256, OrderError> {
         // Convert to basis points (impact relative to expected amount)
-        let expected_out = sell_amount
-            .checked_mul(reserve_out)
-            .ok_or(b"Expected calculation overflow".to_vec())?
-            .checked_div(reserve_in)
-            .ok_or(b"Expected calculation division error".to_vec())?;
+        let expected_out = TWAMMath::mul_div(sell_amount, reserve_out, reserve_in)
+            .map_err(|_| OrderError::Overflow("Expected calculation overflow"))?;
 
-        if expected_out <= impact {
+        if expected_out <= amount_out {
             return Ok(U256::ZERO);
         }
 
-        let price_impact = expected_out
-            .checked_sub(impact)
-            .ok_or(b"Price impact calculation error".to_vec())?
-            .checked_mul(U256::from(10000u32))
-            .ok_or(b"Basis points calculation overflow".to_vec())?
-            .checked_div(expected_out)
-            .ok_or(b"Basis points calculation division error".to_vec())?;
+        // mul_div keeps the full shortfall * 10000 product, which may not fit in 256 bits
+        TWAMMath::mul_div(
+            expected_out - amount_out,
+            U256::from(10000u32),
+            expected_out,
+        )
+        .map_err(|_| OrderError::Overflow("Basis points calculation overflow"))
+    }
+
+    /// Quote a prospective order as (expected_out, avg_impact_bps)
+    ///
+    /// Streams the order against the existing opposing flow with the virtual AMM model,
+    /// so opposing volume can offset impact. The order is assumed to be the only seller of
+    /// its token; reserve_in is the reserve of the token it sells.
+    pub fn quote_order(
+        direction: OrderDirection,
+        sell_amount: U256,
+        duration_blocks: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+        current_total_opposing_rate: U256,
+        precision: u32,
+    ) -> Result<(U256, U256), OrderError> {
+        if reserve_in == U256::ZERO || reserve_out == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid reserves"));
+        }
+        if duration_blocks == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid duration"));
+        }
+
+        let sell_rate = sell_amount / duration_blocks;
+        if sell_rate == U256::ZERO {
+            return Err(OrderError::InvalidParams(
+                "sell rate rounds to zero; increase amount or shorten duration",
+            ));
+        }
+
+        // Orient the flows as token0 (x) and token1 (y) like the pool does
+        let final_reserve_out = match direction {
+            OrderDirection::SellToken0 => {
+                TWAMMath::calculate_virtual_amm_state(
+                    Unscaled(reserve_in),
+                    Unscaled(reserve_out),
+                    Unscaled(sell_rate),
+                    Unscaled(current_total_opposing_rate),
+                    duration_blocks,
+                    precision,
+                )
+                .map_err(|_| OrderError::MathFailed("Quote calculation failed"))?
+                .1
+            }
+            OrderDirection::SellToken1 => {
+                TWAMMath::calculate_virtual_amm_state(
+                    Unscaled(reserve_out),
+                    Unscaled(reserve_in),
+                    Unscaled(current_total_opposing_rate),
+                    Unscaled(sell_rate),
+                    duration_blocks,
+                    precision,
+                )
+                .map_err(|_| OrderError::MathFailed("Quote calculation failed"))?
+                .0
+            }
+        };
+
+        // The order receives everything the pool and the opposing sellers release
+        let opposing_sold = current_total_opposing_rate
+            .checked_mul(duration_blocks)
+            .ok_or(OrderError::Overflow("Opposing volume overflow"))?;
+        let expected_out = reserve_out
+            .checked_add(opposing_sold)
+            .ok_or(OrderError::Overflow("Output calculation overflow"))?
+            .saturating_sub(final_reserve_out);
+
+        let streamed_amount = sell_rate * duration_blocks;
+        let avg_impact_bps =
+            Self::impact_bps(streamed_amount, expected_out, reserve_in, reserve_out)?;
+
+        Ok((expected_out, avg_impact_bps))
+    }
+
+    /// Summarize how a one-sided order moves the price as (start_price, end_price, avg_price)
+    ///
+    /// All prices are token1 per token0 scaled to precision, as in OrderPool::spot_price:
+    /// the spot price before the order, the spot price of the calculate_virtual_amm_state
+    /// endpoint, and the average price the streamed order trades at.
+    pub fn order_price_summary(
+        direction: OrderDirection,
+        sell_amount: U256,
+        duration_blocks: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+        precision: u32,
+    ) -> Result<(U256, U256, U256), OrderError> {
+        if reserve_in == U256::ZERO || reserve_out == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid reserves"));
+        }
+        if duration_blocks == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid duration"));
+        }
+
+        let sell_rate = sell_amount / duration_blocks;
+        if sell_rate == U256::ZERO {
+            return Err(OrderError::InvalidParams(
+                "sell rate rounds to zero; increase amount or shorten duration",
+            ));
+        }
+
+        let (reserve_0, reserve_1, rate_0, rate_1) = match direction {
+            OrderDirection::SellToken0 => (reserve_in, reserve_out, sell_rate, U256::ZERO),
+            OrderDirection::SellToken1 => (reserve_out, reserve_in, U256::ZERO, sell_rate),
+        };
+
+        let (final_reserve_0, final_reserve_1) = TWAMMath::calculate_virtual_amm_state(
+            Unscaled(reserve_0),
+            Unscaled(reserve_1),
+            Unscaled(rate_0),
+            Unscaled(rate_1),
+            duration_blocks,
+            precision,
+        )
+        .map_err(|_| OrderError::MathFailed("Price summary calculation failed"))?;
+
+        let start_price = OrderPool::spot_price(reserve_0, reserve_1, precision)?;
+        let end_price = OrderPool::spot_price(final_reserve_0, final_reserve_1, precision)?;
+
+        // Token1 paid or received per token0 over the whole stream
+        let amount_0 = final_reserve_0.abs_diff(reserve_0);
+        let amount_1 = final_reserve_1.abs_diff(reserve_1);
+        if amount_0 == U256::ZERO {
+            return Err(OrderError::InvalidParams("Order output rounds to zero"));
+        }
+        let one = U256::from(10u128.pow(precision));
+        let avg_price = TWAMMath::mul_div_wide(amount_1, one, amount_0)
+            .ok_or(OrderError::Overflow("Price overflow"))?;
+
+        Ok((start_price, end_price, avg_price))
+    }
+
+    /// Whether streaming sell_amount in full would move the price by more than max_move_bps
+    ///
+    /// A one-sided stream ends where a single swap would, so the price of the sold token
+    /// falls by 1 - (reserve_in / (reserve_in + sell_amount))^2. This bounds the cumulative
+    /// move, unlike the per-block rate caps. The move is rounded up before comparing.
+    pub fn would_invert_price(
+        sell_amount: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+        max_move_bps: U256,
+    ) -> Result<bool, OrderError> {
+        if reserve_in == U256::ZERO || reserve_out == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid reserves"));
+        }
+
+        let final_reserve_in = reserve_in
+            .checked_add(sell_amount)
+            .ok_or(OrderError::Overflow("Reserve overflow"))?;
+
+        // 1 - (r / (r + s))^2 = s * (2r + s) / (r + s)^2
+        let spread = reserve_in
+            .checked_mul(U256::from(2u32))
+            .and_then(|double_reserve| double_reserve.checked_add(sell_amount))
+            .ok_or(OrderError::Overflow("Reserve overflow"))?;
+        let move_bps = TWAMMath::mul_div_rounding_up(sell_amount, spread, final_reserve_in)
+            .and_then(|partial| {
+                TWAMMath::mul_div_rounding_up(
+                    partial,
+                    U256::from(FEE_DENOMINATOR),
+                    final_reserve_in,
+                )
+            })
+            .map_err(|_| OrderError::Overflow("Price move calculation overflow"))?;
+
+        Ok(move_bps > max_move_bps)
+    }
+
+    /// Largest output an order could plausibly receive, for sizing the counterparty's escrow
+    /// This is the no-slippage bound sell_amount * reserve_out / reserve_in; streaming
+    /// against the pool alone always pays out at most this much
+    pub fn max_counterparty_obligation(
+        sell_amount: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+        _precision: u32,
+    ) -> Result<U256, OrderError> {
+        if reserve_in == U256::ZERO || reserve_out == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid reserves"));
+        }
+
+        TWAMMath::mul_div(sell_amount, reserve_out, reserve_in)
+            .map_err(|_| OrderError::Overflow("Obligation calculation overflow"))
+    }
+
+    /// Find the first block at which streaming an order has paid out at least as much as
+    /// swapping the whole amount at once
+    ///
+    /// Each block's slice trades against the starting reserves, assuming arbitrage restores
+    /// the price between blocks. Returns duration_blocks if the order never breaks even.
+    /// Only PRECISION is supported, matching duration_for_target_impact.
+    pub fn breakeven_block(
+        sell_amount: U256,
+        duration_blocks: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+        precision: u32,
+    ) -> Result<U256, OrderError> {
+        if precision != PRECISION {
+            return Err(OrderError::InvalidParams("Unsupported precision"));
+        }
+        if reserve_in == U256::ZERO || reserve_out == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid reserves"));
+        }
+        if duration_blocks == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid duration"));
+        }
+
+        let sell_rate = sell_amount / duration_blocks;
+        if sell_rate == U256::ZERO {
+            return Err(OrderError::InvalidParams(
+                "sell rate rounds to zero; increase amount or shorten duration",
+            ));
+        }
+
+        let constant_product_out = |amount_in: U256| -> Result<U256, OrderError> {
+            let denominator = reserve_in
+                .checked_add(amount_in)
+                .ok_or(OrderError::Overflow("Reserve overflow"))?;
+            TWAMMath::mul_div(reserve_out, amount_in, denominator)
+                .map_err(|_| OrderError::Overflow("Output calculation overflow"))
+        };
+
+        let instant_out = constant_product_out(sell_amount)?;
+        let block_out = constant_product_out(sell_rate)?;
+        if block_out == U256::ZERO {
+            return Ok(duration_blocks);
+        }
+
+        // Smallest block count whose streamed output covers the instant swap
+        let blocks = instant_out.div_ceil(block_out).max(U256::from(1u32));
+        Ok(blocks.min(duration_blocks))
+    }
+
+    /// Predict which token is net sold over a window of opposing flows, and by how much
+    /// Flows are netted at the starting price; balanced flows report SellToken0 with zero
+    /// The netting uses the exact reserve ratio, so _precision no longer affects the result
+    pub fn predict_net_flow(
+        initial_x: U256,
+        initial_y: U256,
+        sell_rate_x: U256,
+        sell_rate_y: U256,
+        time_blocks: U256,
+        _precision: u32,
+    ) -> Result<(OrderDirection, U256), OrderError> {
+        if initial_x == U256::ZERO || initial_y == U256::ZERO {
+            return Err(OrderError::InvalidParams("Invalid reserves"));
+        }
+
+        let total_sell_x = sell_rate_x
+            .checked_mul(time_blocks)
+            .ok_or(OrderError::Overflow("Calculation overflow"))?;
+        let total_sell_y = sell_rate_y
+            .checked_mul(time_blocks)
+            .ok_or(OrderError::Overflow("Calculation overflow"))?;
+
+        let (x_to_y, net_amount) =
+            TWAMMath::net_flow(initial_x, initial_y, total_sell_x, total_sell_y)
+                .map_err(|_| OrderError::MathFailed("Net flow calculation failed"))?;
+
+        if x_to_y || net_amount == U256::ZERO {
+            Ok((OrderDirection::SellToken0, net_amount))
+        } else {
+            Ok((OrderDirection::SellToken1, net_amount))
+        }
+    }
+
+    /// Find the shortest duration whose calculate_twap_impact is at most target_impact_bps
+    ///
+    /// Searches [MIN_ORDER_DURATION, MAX_ORDER_DURATION]. Impact is evaluated by the
+    /// closed form at PRECISION, so other precisions are rejected.
+    pub fn duration_for_target_impact(
+        sell_amount: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+        target_impact_bps: U256,
+        precision: u32,
+    ) -> Result<U256, OrderError> {
+        if precision != PRECISION {
+            return Err(OrderError::InvalidParams("Unsupported precision"));
+        }
+
+        let within_target = |duration: U256| -> Result<bool, OrderError> {
+            let impact =
+                Self::calculate_twap_impact(sell_amount, duration, reserve_in, reserve_out)?;
+            Ok(impact <= target_impact_bps)
+        };
+
+        let mut low = U256::from(MIN_ORDER_DURATION);
+        let mut high = U256::from(MAX_ORDER_DURATION);
+
+        if within_target(low)? {
+            return Ok(low);
+        }
+        if !within_target(high)? {
+            return Err(OrderError::InvalidParams("Target impact unreachable"));
+        }
+
+        // low always misses the target and high always meets it
+        while high - low > U256::from(1u32) {
+            let mid = low + (high - low) / U256::from(2u32);
+            if within_target(mid)? {
+                high = mid;
+            } else {
+                low = mid;
+            }
+        }
 
-        Ok(price_impact)
+        Ok(high)
     }
 }
@@ -0,0 +1,93 @@
+use crate::order_execution::OrderPool;
+use alloc::vec::Vec;
+use stylus_sdk::{
+    alloy_primitives::{Address, B256},
+    console, crypto,
+};
+
+// Isolated token pairs
+// Each pair has its own order pool so one deployment can serve many markets
+
+/// Identify the pair of two tokens, independent of argument order
+/// The id is keccak(token_0, token_1) with token_0 the lower address
+pub fn pair_id(token_a: Address, token_b: Address) -> B256 {
+    let (token_0, token_1) = sort_tokens(token_a, token_b);
+
+    let mut preimage = Vec::with_capacity(40);
+    preimage.extend_from_slice(token_0.as_slice());
+    preimage.extend_from_slice(token_1.as_slice());
+    crypto::keccak(preimage)
+}
+
+/// Order two token addresses as (token_0, token_1), lower address first
+pub fn sort_tokens(token_a: Address, token_b: Address) -> (Address, Address) {
+    if token_a < token_b {
+        (token_a, token_b)
+    } else {
+        (token_b, token_a)
+    }
+}
+
+/// One market: a token pair and the order pool trading it
+/// SellToken0 orders sell token_0, the lower of the two addresses
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pool {
+    pub id: B256,
+    pub token_0: Address,
+    pub token_1: Address,
+    pub order_pool: OrderPool,
+}
+
+impl Pool {
+    pub fn new(token_a: Address, token_b: Address) -> Result<Self, Vec<u8>> {
+        if token_a == token_b {
+            return Err(b"Identical tokens".to_vec());
+        }
+        if token_a == Address::ZERO || token_b == Address::ZERO {
+            return Err(b"Invalid token".to_vec());
+        }
+
+        let (token_0, token_1) = sort_tokens(token_a, token_b);
+        Ok(Self {
+            id: pair_id(token_0, token_1),
+            token_0,
+            token_1,
+            order_pool: OrderPool::default(),
+        })
+    }
+}
+
+/// Pools keyed by pair id
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PoolRegistry {
+    pub pools: Vec<Pool>,
+}
+
+impl PoolRegistry {
+    /// Register a new pair, returning its id
+    pub fn create_pool(&mut self, token_a: Address, token_b: Address) -> Result<B256, Vec<u8>> {
+        let pool = Pool::new(token_a, token_b)?;
+        if self.get_pool(pool.id).is_some() {
+            return Err(b"Pool exists".to_vec());
+        }
+
+        let id = pool.id;
+        self.pools.push(pool);
+        console!("Created pool {}", id);
+        Ok(id)
+    }
+
+    /// Get a pool by pair id
+    pub fn get_pool(&self, id: B256) -> Option<&Pool> {
+        self.pools.iter().find(|pool| pool.id == id)
+    }
+
+    /// Get a pool's order pool for changes, failing if the pair is not registered
+    pub fn order_pool_mut(&mut self, id: B256) -> Result<&mut OrderPool, Vec<u8>> {
+        self.pools
+            .iter_mut()
+            .find(|pool| pool.id == id)
+            .map(|pool| &mut pool.order_pool)
+            .ok_or(b"Pool not found".to_vec())
+    }
+}
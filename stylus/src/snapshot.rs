@@ -0,0 +1,427 @@
+use crate::order_execution::{
+    LimitSide, LiquidityPolicy, Order, OrderDirection, OrderIdScheme, OrderPool, OrderStatus,
+    OrderType, RateRounding, VirtualOrderState,
+};
+use crate::twamm_math::GasModel;
+use alloc::vec::Vec;
+use stylus_sdk::alloy_primitives::{Address, U256};
+
+// Binary snapshots of the order pool
+// Used to dump and restore a pool off chain for testing and migration
+
+/// Layout version written as the first byte of every snapshot
+/// A layout change bumps it and keeps a decoder for the previous version, so snapshots
+/// already taken still restore
+pub const SNAPSHOT_VERSION: u8 = 2;
+
+/// Appends fixed-width big-endian fields to a snapshot
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u64(len as u64);
+    }
+
+    fn u256(&mut self, value: U256) {
+        self.bytes.extend_from_slice(&value.to_be_bytes::<32>());
+    }
+
+    fn address(&mut self, value: Address) {
+        self.bytes.extend_from_slice(value.as_slice());
+    }
+
+    fn order(&mut self, order: &Order) {
+        self.u256(order.id);
+        self.address(order.owner);
+        self.u8(order.order_type as u8);
+        self.u8(order.direction as u8);
+        self.u256(order.sell_rate);
+        self.u256(order.initial_amount);
+        self.u256(order.remaining_amount);
+        self.u256(order.dust_remainder);
+        self.u256(order.start_block);
+        self.u256(order.end_block);
+        self.u256(order.last_virtual_order_block);
+        self.u256(order.accumulated_out);
+        self.u256(order.min_output_total);
+        self.u8(order.status as u8);
+        self.bool(order.price_limit.is_some());
+        self.u256(order.price_limit.unwrap_or_default());
+        self.u8(order.limit_side as u8);
+        self.bool(order.active);
+        self.u256(order.paused_at);
+        self.u256(order.quality_weighted_sum);
+        self.u256(order.quality_weight);
+        self.bool(order.target_price.is_some());
+        self.u256(order.target_price.unwrap_or_default());
+        self.u256(order.benchmark_out);
+        self.u256(order.matched_volume);
+        self.u256(order.curve_volume);
+    }
+
+    fn orders(&mut self, orders: &[Order]) {
+        self.len(orders.len());
+        for order in orders {
+            self.order(order);
+        }
+    }
+}
+
+/// Reads fields written by Encoder, failing on truncated or malformed input
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], Vec<u8>> {
+        let end = self
+            .offset
+            .checked_add(count)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(b"Snapshot truncated".to_vec())?;
+        let slice = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, Vec<u8>> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, Vec<u8>> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(b"Invalid snapshot".to_vec()),
+        }
+    }
+
+    fn u64(&mut self) -> Result<u64, Vec<u8>> {
+        let mut buffer = [0u8; 8];
+        buffer.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(buffer))
+    }
+
+    fn len(&mut self) -> Result<usize, Vec<u8>> {
+        usize::try_from(self.u64()?).map_err(|_| b"Invalid snapshot".to_vec())
+    }
+
+    fn u256(&mut self) -> Result<U256, Vec<u8>> {
+        Ok(U256::from_be_slice(self.take(32)?))
+    }
+
+    fn address(&mut self) -> Result<Address, Vec<u8>> {
+        Ok(Address::from_slice(self.take(20)?))
+    }
+
+    fn order(&mut self) -> Result<Order, Vec<u8>> {
+        let id = self.u256()?;
+        let owner = self.address()?;
+        let order_type = match self.u8()? {
+            0 => OrderType::LongTerm,
+            1 => OrderType::Instant,
+            _ => return Err(b"Invalid snapshot".to_vec()),
+        };
+        let direction = match self.u8()? {
+            0 => OrderDirection::SellToken0,
+            1 => OrderDirection::SellToken1,
+            _ => return Err(b"Invalid snapshot".to_vec()),
+        };
+        let sell_rate = self.u256()?;
+        let initial_amount = self.u256()?;
+        let remaining_amount = self.u256()?;
+        let dust_remainder = self.u256()?;
+        let start_block = self.u256()?;
+        let end_block = self.u256()?;
+        let last_virtual_order_block = self.u256()?;
+        let accumulated_out = self.u256()?;
+        let min_output_total = self.u256()?;
+        let status = match self.u8()? {
+            0 => OrderStatus::Active,
+            1 => OrderStatus::Completed,
+            2 => OrderStatus::Cancelled,
+            3 => OrderStatus::Underfilled,
+            4 => OrderStatus::Scheduled,
+            _ => return Err(b"Invalid snapshot".to_vec()),
+        };
+        let has_price_limit = self.bool()?;
+        let price_limit = self.u256()?;
+        let limit_side = match self.u8()? {
+            0 => LimitSide::Above,
+            1 => LimitSide::Below,
+            _ => return Err(b"Invalid snapshot".to_vec()),
+        };
+        let active = self.bool()?;
+        let paused_at = self.u256()?;
+        let quality_weighted_sum = self.u256()?;
+        let quality_weight = self.u256()?;
+        let has_target_price = self.bool()?;
+        let target_price = self.u256()?;
+        let benchmark_out = self.u256()?;
+        let matched_volume = self.u256()?;
+        let curve_volume = self.u256()?;
+
+        Ok(Order {
+            id,
+            owner,
+            order_type,
+            direction,
+            sell_rate,
+            initial_amount,
+            remaining_amount,
+            dust_remainder,
+            start_block,
+            end_block,
+            last_virtual_order_block,
+            accumulated_out,
+            min_output_total,
+            status,
+            price_limit: has_price_limit.then_some(price_limit),
+            limit_side,
+            active,
+            paused_at,
+            quality_weighted_sum,
+            quality_weight,
+            target_price: has_target_price.then_some(target_price),
+            benchmark_out,
+            matched_volume,
+            curve_volume,
+        })
+    }
+
+    fn orders(&mut self) -> Result<Vec<Order>, Vec<u8>> {
+        let count = self.len()?;
+        // Do not trust the count for preallocation; truncation is caught per order
+        let mut orders = Vec::new();
+        for _ in 0..count {
+            orders.push(self.order()?);
+        }
+        Ok(orders)
+    }
+}
+
+impl OrderPool {
+    /// Serialize the full pool into a versioned, deterministic byte layout
+    ///
+    /// Layout (version 2): the version byte, then every field in declaration order.
+    /// Integers are big-endian (U256 as 32 bytes, lengths and usize as 8 bytes), addresses
+    /// are 20 bytes, bools and enums are one byte, and an Option is a presence byte followed
+    /// by the value (zero when absent). Lists are a length followed by their entries.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder { bytes: Vec::new() };
+
+        encoder.u8(SNAPSHOT_VERSION);
+        encoder.orders(&self.orders);
+        encoder.u256(self.next_order_id);
+        encoder.u256(self.virtual_order_state.last_virtual_order_block);
+        encoder.u256(self.virtual_order_state.order_block_interval);
+        encoder.u256(self.virtual_order_state.last_execution_price);
+        encoder.u256(self.virtual_order_state.max_price_deviation_bps);
+        encoder.u256(self.total_sell_rate_0);
+        encoder.u256(self.total_sell_rate_1);
+
+        encoder.len(self.order_beneficiaries.len());
+        for (order_id, beneficiaries) in &self.order_beneficiaries {
+            encoder.u256(*order_id);
+            encoder.len(beneficiaries.len());
+            for (beneficiary, weight) in beneficiaries {
+                encoder.address(*beneficiary);
+                encoder.u256(*weight);
+            }
+        }
+
+        encoder.len(self.max_orders_per_owner);
+        encoder.bool(self.paused);
+        encoder.u256(self.fee_bps);
+        encoder.u256(self.collected_fees_0);
+        encoder.u256(self.collected_fees_1);
+        encoder.orders(&self.history);
+        encoder.u256(self.min_reserve);
+        encoder.u8(self.order_id_scheme as u8);
+        encoder.u256(self.block_time_secs);
+        encoder.u256(self.keeper_reward_bps);
+        encoder.u256(self.keeper_gas_price);
+        encoder.u256(self.max_total_sell_rate_0);
+        encoder.u256(self.max_total_sell_rate_1);
+        encoder.u8(self.liquidity_policy as u8);
+        encoder.u256(self.max_blocks_per_execution);
+        encoder.u256(self.min_duration_blocks);
+        encoder.u256(self.max_duration_blocks);
+        encoder.bool(self.reserve_tracking);
+        encoder.u256(self.stored_reserve_0);
+        encoder.u256(self.stored_reserve_1);
+        encoder.u256(self.reserve_tolerance_bps);
+        encoder.u256(self.gas_model.execution_base_gas);
+        encoder.u256(self.gas_model.execution_per_block_gas);
+        encoder.u256(self.gas_model.execution_per_order_gas);
+        encoder.u256(self.gas_model.gas_used_per_executed_block);
+        encoder.u256(self.gas_model.submit_gas);
+        encoder.u256(self.gas_model.execute_gas);
+        encoder.u256(self.gas_model.cancel_gas);
+        encoder.u256(self.gas_model.other_operation_gas);
+        encoder.u256(self.gas_model.per_complexity_gas);
+        encoder.u256(self.gas_model.sweep_base_gas);
+        encoder.u256(self.gas_model.sweep_per_order_gas);
+        encoder.u256(self.max_order_block_interval);
+        encoder.u256(self.max_price_move_bps);
+        encoder.len(self.max_orders);
+        encoder.bool(self.strict_execution);
+        encoder.u8(self.rate_rounding as u8);
+        encoder.u256(self.ramp_blocks);
+        encoder.u256(self.cumulative_volume_0);
+        encoder.u256(self.cumulative_volume_1);
+        encoder.u256(self.reserve_swap_tolerance_bps);
+        encoder.u256(self.min_blocks_before_cancel);
+
+        encoder.len(self.unclaimed_proceeds.len());
+        for (order_id, owner, amount) in &self.unclaimed_proceeds {
+            encoder.u256(*order_id);
+            encoder.address(*owner);
+            encoder.u256(*amount);
+        }
+
+        encoder.bytes
+    }
+
+    /// Restore a pool written by to_bytes
+    /// Version 1 snapshots end before unclaimed_proceeds, which is rebuilt from the
+    /// completed long-term orders in their history that still hold proceeds
+    pub fn from_bytes(bytes: &[u8]) -> Result<OrderPool, Vec<u8>> {
+        let mut decoder = Decoder { bytes, offset: 0 };
+
+        let version = decoder.u8()?;
+        if version == 0 || version > SNAPSHOT_VERSION {
+            return Err(b"Unsupported snapshot version".to_vec());
+        }
+
+        let orders = decoder.orders()?;
+        let next_order_id = decoder.u256()?;
+        let virtual_order_state = VirtualOrderState {
+            last_virtual_order_block: decoder.u256()?,
+            order_block_interval: decoder.u256()?,
+            last_execution_price: decoder.u256()?,
+            max_price_deviation_bps: decoder.u256()?,
+        };
+        let total_sell_rate_0 = decoder.u256()?;
+        let total_sell_rate_1 = decoder.u256()?;
+
+        let mut order_beneficiaries = Vec::new();
+        for _ in 0..decoder.len()? {
+            let order_id = decoder.u256()?;
+            let mut beneficiaries = Vec::new();
+            for _ in 0..decoder.len()? {
+                beneficiaries.push((decoder.address()?, decoder.u256()?));
+            }
+            order_beneficiaries.push((order_id, beneficiaries));
+        }
+
+        let mut pool = OrderPool {
+            orders,
+            next_order_id,
+            virtual_order_state,
+            total_sell_rate_0,
+            total_sell_rate_1,
+            order_beneficiaries,
+            max_orders_per_owner: decoder.len()?,
+            paused: decoder.bool()?,
+            fee_bps: decoder.u256()?,
+            collected_fees_0: decoder.u256()?,
+            collected_fees_1: decoder.u256()?,
+            history: decoder.orders()?,
+            min_reserve: decoder.u256()?,
+            order_id_scheme: match decoder.u8()? {
+                0 => OrderIdScheme::Sequential,
+                1 => OrderIdScheme::Hashed,
+                _ => return Err(b"Invalid snapshot".to_vec()),
+            },
+            block_time_secs: decoder.u256()?,
+            keeper_reward_bps: decoder.u256()?,
+            keeper_gas_price: decoder.u256()?,
+            max_total_sell_rate_0: decoder.u256()?,
+            max_total_sell_rate_1: decoder.u256()?,
+            liquidity_policy: match decoder.u8()? {
+                0 => LiquidityPolicy::Halt,
+                1 => LiquidityPolicy::Throttle,
+                2 => LiquidityPolicy::Cancel,
+                _ => return Err(b"Invalid snapshot".to_vec()),
+            },
+            max_blocks_per_execution: decoder.u256()?,
+            min_duration_blocks: decoder.u256()?,
+            max_duration_blocks: decoder.u256()?,
+            reserve_tracking: decoder.bool()?,
+            stored_reserve_0: decoder.u256()?,
+            stored_reserve_1: decoder.u256()?,
+            reserve_tolerance_bps: decoder.u256()?,
+            gas_model: GasModel {
+                execution_base_gas: decoder.u256()?,
+                execution_per_block_gas: decoder.u256()?,
+                execution_per_order_gas: decoder.u256()?,
+                gas_used_per_executed_block: decoder.u256()?,
+                submit_gas: decoder.u256()?,
+                execute_gas: decoder.u256()?,
+                cancel_gas: decoder.u256()?,
+                other_operation_gas: decoder.u256()?,
+                per_complexity_gas: decoder.u256()?,
+                sweep_base_gas: decoder.u256()?,
+                sweep_per_order_gas: decoder.u256()?,
+            },
+            max_order_block_interval: decoder.u256()?,
+            max_price_move_bps: decoder.u256()?,
+            max_orders: decoder.len()?,
+            strict_execution: decoder.bool()?,
+            rate_rounding: match decoder.u8()? {
+                0 => RateRounding::Floor,
+                1 => RateRounding::Ceil,
+                _ => return Err(b"Invalid snapshot".to_vec()),
+            },
+            ramp_blocks: decoder.u256()?,
+            cumulative_volume_0: decoder.u256()?,
+            cumulative_volume_1: decoder.u256()?,
+            reserve_swap_tolerance_bps: decoder.u256()?,
+            min_blocks_before_cancel: decoder.u256()?,
+            unclaimed_proceeds: Vec::new(),
+        };
+
+        if version == 1 {
+            pool.unclaimed_proceeds = pool
+                .history
+                .iter()
+                .filter(|order| {
+                    order.order_type == OrderType::LongTerm
+                        && order.status == OrderStatus::Completed
+                        && order.accumulated_out > U256::ZERO
+                })
+                .map(|order| (order.id, order.owner, order.accumulated_out))
+                .collect();
+        } else {
+            for _ in 0..decoder.len()? {
+                pool.unclaimed_proceeds.push((
+                    decoder.u256()?,
+                    decoder.address()?,
+                    decoder.u256()?,
+                ));
+            }
+        }
+
+        if decoder.offset != bytes.len() {
+            return Err(b"Trailing snapshot bytes".to_vec());
+        }
+
+        Ok(pool)
+    }
+}
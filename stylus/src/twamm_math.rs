@@ -1,5 +1,6 @@
+use crate::order_execution::OrderDirection;
 use alloc::vec::Vec;
-use stylus_sdk::alloy_primitives::U256;
+use stylus_sdk::alloy_primitives::{U256, U512};
 
 /// Mathematical utilities for TWAMM calculations
 /// Implements the closed-form solutions from Paradigm's TWAMM research
@@ -12,9 +13,199 @@ pub enum MathError {
     ComputationFailed,
 }
 
+/// Math error tagged with the operation that raised it, so callers can branch on the failure site
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MathFailure {
+    pub error: MathError,
+    pub op_code: u8,
+}
+
+impl MathFailure {
+    pub fn new(error: MathError, op_code: u8) -> Self {
+        Self { error, op_code }
+    }
+}
+
+impl From<MathFailure> for MathError {
+    fn from(failure: MathFailure) -> Self {
+        failure.error
+    }
+}
+
+/// Operation codes reported by execute_virtual_orders_closed_form
+/// Token0 total sell amount (sell_rate_0 * blocks)
+pub const OP_TOTAL_SELL_0: u8 = 1;
+/// Token1 total sell amount (sell_rate_1 * blocks)
+pub const OP_TOTAL_SELL_1: u8 = 2;
+/// One-sided token0 -> token1 execution
+pub const OP_UNIDIRECTIONAL_0_TO_1: u8 = 3;
+/// One-sided token1 -> token0 execution
+pub const OP_UNIDIRECTIONAL_1_TO_0: u8 = 4;
+/// Netted execution with both sides selling
+pub const OP_BIDIRECTIONAL: u8 = 5;
+
 pub struct TWAMMath;
 
-#[derive(Debug, Clone)]
+/// Base gas cost for virtual execution
+pub const VIRTUAL_EXECUTION_BASE_GAS: u32 = 50000;
+/// Additional gas per block of virtual execution
+pub const VIRTUAL_EXECUTION_PER_BLOCK_GAS: u32 = 1000;
+/// Additional gas per active order during virtual execution
+pub const VIRTUAL_EXECUTION_PER_ORDER_GAS: u32 = 5000;
+/// Gas reported per executed block in VirtualExecutionResult::gas_used_estimate
+pub const GAS_USED_PER_EXECUTED_BLOCK: u32 = 21000;
+/// Base gas cost for sweeping expired orders
+pub const SWEEP_BASE_GAS: u32 = 30000;
+/// Additional gas per order a sweep removes
+pub const SWEEP_PER_ORDER_GAS: u32 = 15000;
+
+/// Gas costs behind the execution and operation estimates
+/// Opcode pricing differs between chains, so pools can swap in their own model
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GasModel {
+    pub execution_base_gas: U256,
+    pub execution_per_block_gas: U256,
+    pub execution_per_order_gas: U256,
+    pub gas_used_per_executed_block: U256,
+    /// Base costs of the estimate_gas_cost operation types
+    pub submit_gas: U256,
+    pub execute_gas: U256,
+    pub cancel_gas: U256,
+    pub other_operation_gas: U256,
+    /// Gas per unit of estimate_gas_cost's complexity factor
+    pub per_complexity_gas: U256,
+    /// Costs of a sweep of expired orders
+    pub sweep_base_gas: U256,
+    pub sweep_per_order_gas: U256,
+}
+
+impl Default for GasModel {
+    fn default() -> Self {
+        Self {
+            execution_base_gas: U256::from(VIRTUAL_EXECUTION_BASE_GAS),
+            execution_per_block_gas: U256::from(VIRTUAL_EXECUTION_PER_BLOCK_GAS),
+            execution_per_order_gas: U256::from(VIRTUAL_EXECUTION_PER_ORDER_GAS),
+            gas_used_per_executed_block: U256::from(GAS_USED_PER_EXECUTED_BLOCK),
+            submit_gas: U256::from(80000u32),
+            execute_gas: U256::from(150000u32),
+            cancel_gas: U256::from(50000u32),
+            other_operation_gas: U256::from(100000u32),
+            per_complexity_gas: U256::from(1000u32),
+            sweep_base_gas: U256::from(SWEEP_BASE_GAS),
+            sweep_per_order_gas: U256::from(SWEEP_PER_ORDER_GAS),
+        }
+    }
+}
+
+impl GasModel {
+    /// Estimate a virtual execution covering the given blocks and orders, saturating
+    pub fn execution_gas(&self, blocks: U256, orders: usize) -> U256 {
+        let blocks_gas = blocks.saturating_mul(self.execution_per_block_gas);
+        let orders_gas = U256::from(orders).saturating_mul(self.execution_per_order_gas);
+
+        self.execution_base_gas
+            .saturating_add(blocks_gas)
+            .saturating_add(orders_gas)
+    }
+
+    /// Estimate a sweep removing the given number of orders, saturating
+    pub fn sweep_gas(&self, orders: usize) -> U256 {
+        self.sweep_base_gas
+            .saturating_add(U256::from(orders).saturating_mul(self.sweep_per_order_gas))
+    }
+
+    /// Estimate an operation (0=submit, 1=execute, 2=cancel, others use a default cost)
+    pub fn operation_gas(&self, operation_type: u8, complexity_factor: U256) -> U256 {
+        let base_gas = match operation_type {
+            0 => self.submit_gas,
+            1 => self.execute_gas,
+            2 => self.cancel_gas,
+            _ => self.other_operation_gas,
+        };
+
+        base_gas.saturating_add(complexity_factor.saturating_mul(self.per_complexity_gas))
+    }
+}
+
+/// Execution profile for calculate_time_decay_factor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecayKind {
+    /// Constant pace
+    Linear,
+    /// Front-loaded: most of the order executes early
+    Exponential,
+    /// Back-loaded: most of the order executes late
+    Quadratic,
+}
+
+/// Rate k of the exponential decay profile
+pub const EXPONENTIAL_DECAY_RATE: u32 = 3;
+
+/// Fixed-point precision used by the closed form and pool-level math
+pub const PRECISION: u32 = 18;
+
+/// Exponent (as a whole number) beyond which the bidirectional closed form has settled at
+/// its equilibrium; e^40 exceeds 10^17, so the neglected correction is below 10^-16
+pub const BIDIRECTIONAL_EXPONENT_CUTOFF: u32 = 40;
+
+/// Units of integer rounding the closed form may lose on each reserve
+pub const CLOSED_FORM_ROUNDING_UNITS: u32 = 2;
+
+/// Exponent (as a whole number) past which calculate_ema drops an observation entirely;
+/// e^-50 is below 10^-21, under the resolution of an 18-decimal weight
+pub const EMA_EXPONENT_CUTOFF: u32 = 50;
+
+/// Guard digits for TWAMMath::power_with_guard_digits
+/// Every squaring doubles the relative error carried so far, so an exponent below 2^30
+/// amplifies the last truncation by at most about 10^9, which nine extra digits absorb
+pub const POWER_GUARD_DIGITS: u32 = 9;
+
+/// Safety valve on Newton iterations in TWAMMath::sqrt; convergence takes far fewer
+pub const SQRT_MAX_ITERATIONS: u32 = 256;
+
+/// Default term budget for TWAMMath::exp_taylor
+pub const EXP_TAYLOR_MAX_TERMS: u32 = 20;
+
+/// Default iteration budget for TWAMMath::ln_newton
+pub const LN_NEWTON_MAX_ITERATIONS: u32 = 50;
+
+/// Number of observations PriceOracle::new keeps for consult
+pub const DEFAULT_OBSERVATION_CAPACITY: usize = 64;
+
+/// Shortest window PriceOracle::consult accepts by default; shorter averages are cheap to
+/// move with a single block's trade
+pub const DEFAULT_MIN_TWAP_WINDOW_BLOCKS: u64 = 10;
+
+/// Most points TWAMMath::reserve_trajectory returns, bounding its gas and memory
+pub const MAX_TRAJECTORY_SAMPLES: u32 = 256;
+
+/// Default per-block sell rate cap for validate_twamm_constraints (0.1% of the reserve)
+pub const DEFAULT_MAX_RATE_BPS_PER_BLOCK: u32 = 10;
+
+/// Result of an iterative approximation with the work it took
+/// converged is false when the budget ran out first, leaving value as the last estimate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Approximation {
+    pub value: U256,
+    pub iterations: u32,
+    pub converged: bool,
+}
+
+/// Closed-form execution with an estimate of how far it may sit from executing block by
+/// block; see TWAMMath::execution_with_error_bound
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundedExecution {
+    pub new_reserve_0: U256,
+    pub new_reserve_1: U256,
+    pub amount_0_received: U256,
+    pub amount_1_received: U256,
+    /// Estimated absolute error on new_reserve_0, and so on amount_0_received, in token0
+    pub error_bound_0: U256,
+    /// Estimated absolute error on new_reserve_1, and so on amount_1_received, in token1
+    pub error_bound_1: U256,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct FixedPoint {
     pub value: U256,
     pub precision: u32,
@@ -25,21 +216,484 @@ impl FixedPoint {
         Self { value, precision }
     }
 
-    pub fn from_u256(value: U256) -> Self {
+    /// Scale an integer to 18-decimal fixed point, failing on overflow
+    pub fn try_from_u256(value: U256) -> Result<Self, MathError> {
+        let scaled = value
+            .checked_mul(U256::from(10u128.pow(PRECISION)))
+            .ok_or(MathError::Overflow)?;
+
+        Ok(Self {
+            value: scaled,
+            precision: PRECISION,
+        })
+    }
+
+    /// Scale an integer to 18-decimal fixed point, clamping to U256::MAX on overflow
+    pub fn saturating_from_u256(value: U256) -> Self {
         Self {
-            value: value * U256::from(10u128.pow(18)),
-            precision: 18,
+            value: value.saturating_mul(U256::from(10u128.pow(PRECISION))),
+            precision: PRECISION,
         }
     }
 
+    /// Drop the fixed-point scaling, truncating any fractional part
     pub fn to_u256(&self) -> U256 {
         self.value / U256::from(10u128.pow(self.precision))
     }
+
+    /// Drop the fixed-point scaling, or None if that would discard a fractional part
+    pub fn to_u256_checked(&self) -> Option<U256> {
+        let one = U256::from(10u128.pow(self.precision));
+        (self.value % one == U256::ZERO).then(|| self.value / one)
+    }
+
+    /// Drop the fixed-point scaling, rounding to the nearest integer with halves rounding up
+    pub fn round_to_u256(&self) -> U256 {
+        let one = U256::from(10u128.pow(self.precision));
+        let (whole, fraction) = self.value.div_rem(one);
+        if fraction >= one - fraction {
+            whole + U256::from(1u32)
+        } else {
+            whole
+        }
+    }
+
+    fn check_precision(&self, other: &Self) -> Result<(), MathError> {
+        if self.precision != other.precision {
+            return Err(MathError::InvalidInput);
+        }
+        Ok(())
+    }
+
+    pub fn add(&self, other: &Self) -> Result<Self, MathError> {
+        self.check_precision(other)?;
+
+        let value = self
+            .value
+            .checked_add(other.value)
+            .ok_or(MathError::Overflow)?;
+        Ok(Self::new(value, self.precision))
+    }
+
+    pub fn sub(&self, other: &Self) -> Result<Self, MathError> {
+        self.check_precision(other)?;
+
+        let value = self
+            .value
+            .checked_sub(other.value)
+            .ok_or(MathError::Overflow)?;
+        Ok(Self::new(value, self.precision))
+    }
+
+    /// Multiply two fixed-point values, rounding the result down
+    pub fn mul(&self, other: &Self) -> Result<Self, MathError> {
+        self.check_precision(other)?;
+
+        let one = U256::from(10u128.pow(self.precision));
+        let value = self
+            .value
+            .checked_mul(other.value)
+            .ok_or(MathError::Overflow)?
+            / one;
+        Ok(Self::new(value, self.precision))
+    }
+
+    /// Divide two fixed-point values, rounding the result down
+    pub fn div(&self, other: &Self) -> Result<Self, MathError> {
+        self.check_precision(other)?;
+
+        if other.value == U256::ZERO {
+            return Err(MathError::DivisionByZero);
+        }
+
+        let one = U256::from(10u128.pow(self.precision));
+        let value = self.value.checked_mul(one).ok_or(MathError::Overflow)? / other.value;
+        Ok(Self::new(value, self.precision))
+    }
+}
+
+/// Raw token amount with no fixed-point scaling, such as a reserve or a sell rate
+/// Functions taking Unscaled mix it with 10^precision internally themselves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unscaled(pub U256);
+
+/// Fixed-point value scaled by 10^precision, such as a price, tagged with its precision
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scaled(pub U256, pub u32);
+
+impl Scaled {
+    /// Get the raw value, checking it carries the precision the caller works in
+    pub fn value_at(&self, precision: u32) -> Result<U256, MathError> {
+        if self.1 != precision {
+            return Err(MathError::InvalidInput);
+        }
+        Ok(self.0)
+    }
+
+    /// Drop the fixed-point scaling, rounding down to a whole token amount
+    pub fn to_unscaled(&self) -> Unscaled {
+        Unscaled(self.0 / U256::from(10u128.pow(self.1)))
+    }
+}
+
+/// Signed fixed-point value stored as sign and magnitude
+/// Used for directional quantities such as ln(x) for x < 1 and price deltas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignedFixed {
+    pub magnitude: U256,
+    pub negative: bool,
+    pub precision: u32,
+}
+
+impl SignedFixed {
+    /// Create a signed value, normalizing negative zero to positive zero
+    pub fn new(magnitude: U256, negative: bool, precision: u32) -> Self {
+        Self {
+            magnitude,
+            negative: negative && magnitude != U256::ZERO,
+            precision,
+        }
+    }
+
+    pub fn from_fixed(value: &FixedPoint) -> Self {
+        Self::new(value.value, false, value.precision)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude == U256::ZERO
+    }
+
+    pub fn negate(&self) -> Self {
+        Self::new(self.magnitude, !self.negative, self.precision)
+    }
+
+    fn check_precision(&self, other: &Self) -> Result<(), MathError> {
+        if self.precision != other.precision {
+            return Err(MathError::InvalidInput);
+        }
+        Ok(())
+    }
+
+    pub fn add(&self, other: &Self) -> Result<Self, MathError> {
+        self.check_precision(other)?;
+
+        if self.negative == other.negative {
+            let magnitude = self
+                .magnitude
+                .checked_add(other.magnitude)
+                .ok_or(MathError::Overflow)?;
+            return Ok(Self::new(magnitude, self.negative, self.precision));
+        }
+
+        // Opposite signs: the larger magnitude determines the sign
+        if self.magnitude >= other.magnitude {
+            Ok(Self::new(
+                self.magnitude - other.magnitude,
+                self.negative,
+                self.precision,
+            ))
+        } else {
+            Ok(Self::new(
+                other.magnitude - self.magnitude,
+                other.negative,
+                self.precision,
+            ))
+        }
+    }
+
+    pub fn sub(&self, other: &Self) -> Result<Self, MathError> {
+        self.add(&other.negate())
+    }
+
+    pub fn mul(&self, other: &Self) -> Result<Self, MathError> {
+        self.check_precision(other)?;
+
+        let one = U256::from(10u128.pow(self.precision));
+        let magnitude = self
+            .magnitude
+            .checked_mul(other.magnitude)
+            .ok_or(MathError::Overflow)?
+            / one;
+
+        Ok(Self::new(
+            magnitude,
+            self.negative != other.negative,
+            self.precision,
+        ))
+    }
+
+    pub fn div(&self, other: &Self) -> Result<Self, MathError> {
+        self.check_precision(other)?;
+
+        if other.is_zero() {
+            return Err(MathError::DivisionByZero);
+        }
+
+        let one = U256::from(10u128.pow(self.precision));
+        let magnitude =
+            self.magnitude.checked_mul(one).ok_or(MathError::Overflow)? / other.magnitude;
+
+        Ok(Self::new(
+            magnitude,
+            self.negative != other.negative,
+            self.precision,
+        ))
+    }
+}
+
+/// Incremental time-weighted average price
+/// Keeps the price * blocks sum in 512 bits, so long series of 256-bit prices cannot
+/// overflow it, and divides once in average()
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TwapAccumulator {
+    pub weighted_sum: U512,
+    pub total_blocks: U256,
+}
+
+impl TwapAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a price that held for the given number of blocks
+    pub fn observe(&mut self, price: U256, blocks: U256) -> Result<(), &'static str> {
+        if blocks == U256::ZERO {
+            return Ok(());
+        }
+
+        let total_blocks = self
+            .total_blocks
+            .checked_add(blocks)
+            .ok_or("TWAP overflow")?;
+        let weighted_sum = self
+            .weighted_sum
+            .checked_add(U512::from(price) * U512::from(blocks))
+            .ok_or("TWAP overflow")?;
+
+        self.weighted_sum = weighted_sum;
+        self.total_blocks = total_blocks;
+
+        Ok(())
+    }
+
+    /// Get the exact weighted mean, rounded down
+    pub fn average(&self) -> Result<U256, &'static str> {
+        if self.total_blocks == U256::ZERO {
+            return Err("Zero total weight");
+        }
+
+        // A mean of 256-bit prices always fits back in 256 bits
+        Ok((self.weighted_sum / U512::from(self.total_blocks)).to::<U256>())
+    }
+}
+
+/// Cumulative-price oracle in the style of Uniswap V2
+/// Prices are 18-decimal fixed point: price_0 is token1 per token0, price_1 is token0 per token1
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceOracle {
+    pub cumulative_price_0: U256,
+    pub cumulative_price_1: U256,
+    pub last_block: U256,
+    /// Ring buffer of (block, cumulative_price_0) written by observe, oldest overwritten first
+    pub observations: Vec<(U256, U256)>,
+    pub observation_capacity: usize,
+    /// Slot the next observation is written to once the buffer is full
+    pub observation_index: usize,
+    /// Shortest window consult accepts; see set_min_window_blocks
+    pub min_window_blocks: U256,
+    /// Reserves reported by the last update, zero before the first; the next update
+    /// credits the blocks since then at their price
+    pub last_reserve_0: U256,
+    pub last_reserve_1: U256,
+}
+
+impl Default for PriceOracle {
+    fn default() -> Self {
+        Self::new(U256::ZERO)
+    }
+}
+
+impl PriceOracle {
+    pub fn new(current_block: U256) -> Self {
+        Self::with_observation_capacity(current_block, DEFAULT_OBSERVATION_CAPACITY)
+    }
+
+    /// Create an oracle keeping up to capacity observations (at least one)
+    /// The creation block is recorded as the first observation
+    pub fn with_observation_capacity(current_block: U256, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let mut observations = Vec::with_capacity(capacity);
+        observations.push((current_block, U256::ZERO));
+
+        Self {
+            cumulative_price_0: U256::ZERO,
+            cumulative_price_1: U256::ZERO,
+            last_block: current_block,
+            observations,
+            observation_capacity: capacity,
+            observation_index: 0,
+            min_window_blocks: U256::from(DEFAULT_MIN_TWAP_WINDOW_BLOCKS),
+            last_reserve_0: U256::ZERO,
+            last_reserve_1: U256::ZERO,
+        }
+    }
+
+    /// Set the shortest window consult accepts, so consumers cannot read an average a single
+    /// block's trade can move. Zero windows stay invalid whatever the minimum
+    pub fn set_min_window_blocks(&mut self, min_window_blocks: U256) {
+        self.min_window_blocks = min_window_blocks;
+    }
+
+    /// Credit the blocks since the last update at the reserves it stored, then store the
+    /// current ones
+    /// As in Uniswap V2, reserves reported now only count from this block on, so moving the
+    /// pool just before an update cannot set the price for the whole gap. Before the first
+    /// update nothing is known, so that interval is credited at the reserves it reports.
+    /// Updates within the same block only replace the stored reserves.
+    pub fn update(
+        &mut self,
+        reserve_0: U256,
+        reserve_1: U256,
+        current_block: U256,
+    ) -> Result<(), &'static str> {
+        if current_block < self.last_block {
+            return Ok(());
+        }
+
+        if reserve_0 == U256::ZERO || reserve_1 == U256::ZERO {
+            return Err("Invalid reserves");
+        }
+
+        if current_block > self.last_block {
+            let (held_0, held_1) = if self.last_reserve_0 == U256::ZERO {
+                (reserve_0, reserve_1)
+            } else {
+                (self.last_reserve_0, self.last_reserve_1)
+            };
+
+            let one = U256::from(10u128.pow(PRECISION));
+            let blocks_elapsed = current_block - self.last_block;
+            let price_0 = TWAMMath::mul_div_wide(held_1, one, held_0).ok_or("Price overflow")?;
+            let price_1 = TWAMMath::mul_div_wide(held_0, one, held_1).ok_or("Price overflow")?;
+
+            // Cumulatives wrap on overflow; only differences between snapshots are meaningful
+            self.cumulative_price_0 = self
+                .cumulative_price_0
+                .wrapping_add(price_0.wrapping_mul(blocks_elapsed));
+            self.cumulative_price_1 = self
+                .cumulative_price_1
+                .wrapping_add(price_1.wrapping_mul(blocks_elapsed));
+            self.last_block = current_block;
+        }
+
+        self.last_reserve_0 = reserve_0;
+        self.last_reserve_1 = reserve_1;
+
+        Ok(())
+    }
+
+    /// Update the cumulatives and record the result as an observation for consult
+    /// Observing the block of the newest observation again records nothing
+    pub fn observe(
+        &mut self,
+        reserve_0: U256,
+        reserve_1: U256,
+        current_block: U256,
+    ) -> Result<(), &'static str> {
+        self.update(reserve_0, reserve_1, current_block)?;
+
+        let observation = (self.last_block, self.cumulative_price_0);
+        if self.observations_in_order().last().map(|(block, _)| *block) == Some(observation.0) {
+            return Ok(());
+        }
+
+        if self.observations.len() < self.observation_capacity {
+            self.observations.push(observation);
+        } else {
+            self.observations[self.observation_index] = observation;
+            self.observation_index = (self.observation_index + 1) % self.observation_capacity;
+        }
+
+        Ok(())
+    }
+
+    /// Observations from oldest to newest
+    fn observations_in_order(&self) -> impl Iterator<Item = &(U256, U256)> {
+        self.observations[self.observation_index..]
+            .iter()
+            .chain(self.observations[..self.observation_index].iter())
+    }
+
+    /// Average price_0 over the window_blocks ending at the newest observation
+    /// The window start is interpolated between the two observations bracketing it
+    /// Windows shorter than min_window_blocks are rejected as too easy to manipulate
+    pub fn consult(&self, window_blocks: U256) -> Result<U256, Vec<u8>> {
+        if window_blocks == U256::ZERO {
+            return Err(b"Invalid TWAP window".to_vec());
+        }
+        if window_blocks < self.min_window_blocks {
+            return Err(b"window too short".to_vec());
+        }
+
+        let &(newest_block, newest_cumulative) = self
+            .observations_in_order()
+            .last()
+            .ok_or(b"No observations".to_vec())?;
+        let target_block = newest_block
+            .checked_sub(window_blocks)
+            .ok_or(b"Window predates oldest observation".to_vec())?;
+
+        let mut previous: Option<&(U256, U256)> = None;
+        let mut target_cumulative = None;
+        for observation in self.observations_in_order() {
+            let &(block, cumulative) = observation;
+            if block == target_block {
+                target_cumulative = Some(cumulative);
+                break;
+            }
+            if block > target_block {
+                // Cumulatives grow linearly between observations
+                if let Some(&(before_block, before_cumulative)) = previous {
+                    let elapsed = target_block - before_block;
+                    let growth = cumulative.wrapping_sub(before_cumulative);
+                    let interpolated =
+                        TWAMMath::mul_div_wide(growth, elapsed, block - before_block)
+                            .ok_or(b"Interpolation overflow".to_vec())?;
+                    target_cumulative = Some(before_cumulative.wrapping_add(interpolated));
+                }
+                break;
+            }
+            previous = Some(observation);
+        }
+
+        let target_cumulative =
+            target_cumulative.ok_or(b"Window predates oldest observation".to_vec())?;
+        Ok(newest_cumulative.wrapping_sub(target_cumulative) / window_blocks)
+    }
+
+    /// Average prices (price_0, price_1) between two snapshots of the oracle
+    pub fn twap(old_snapshot: &Self, new_snapshot: &Self) -> Result<(U256, U256), &'static str> {
+        if new_snapshot.last_block <= old_snapshot.last_block {
+            return Err("Invalid TWAP window");
+        }
+
+        let window = new_snapshot.last_block - old_snapshot.last_block;
+        let average_0 = new_snapshot
+            .cumulative_price_0
+            .wrapping_sub(old_snapshot.cumulative_price_0)
+            / window;
+        let average_1 = new_snapshot
+            .cumulative_price_1
+            .wrapping_sub(old_snapshot.cumulative_price_1)
+            / window;
+
+        Ok((average_0, average_1))
+    }
 }
 
 impl TWAMMath {
     /// Calculate square root using Newton's method with high precision
-    /// Used for constant product calculations
+    /// Used for constant product calculations; returns the floor of the true root
     pub fn sqrt(x: U256) -> U256 {
         if x == U256::ZERO {
             return U256::ZERO;
@@ -49,20 +703,111 @@ impl TWAMMath {
             return U256::from(1u32);
         }
 
-        let mut z = x;
-        let mut y = (x + U256::from(1u32)) / U256::from(2u32);
+        // Start from the power of two just above the root so the iterates decrease
+        // monotonically to the floor; this also avoids computing x + 1 for U256::MAX
+        let mut z = U256::from(1u32) << x.bit_len().div_ceil(2);
+        let mut y = (x / z + z) >> 1;
+        let mut iterations = 0;
 
-        while y < z {
+        while y < z && iterations < SQRT_MAX_ITERATIONS {
             z = y;
-            y = (x / y + y) / U256::from(2u32);
+            y = (x / z + z) >> 1;
+            iterations += 1;
         }
 
         z
     }
 
+    /// Calculate sqrt(a * b) without overflowing when a * b exceeds U256
+    /// Used for the constant product invariant of large reserves
+    pub fn sqrt_product(a: U256, b: U256) -> U256 {
+        if let Some(product) = a.checked_mul(b) {
+            return Self::sqrt(product);
+        }
+
+        // Widen to 512 bits and start Newton's method from a power of two above the root
+        let product = U512::from(a) * U512::from(b);
+        let mut z = U512::from(1u32) << product.bit_len().div_ceil(2);
+        let mut y = (product / z + z) >> 1;
+
+        while y < z {
+            z = y;
+            y = (product / z + z) >> 1;
+        }
+
+        z.to::<U256>()
+    }
+
+    /// Convert to u64, returning None instead of truncating values above u64::MAX
+    /// Use this for block counts and indices rather than narrowing the limbs directly
+    pub fn checked_to_u64(value: U256) -> Option<u64> {
+        u64::try_from(value).ok()
+    }
+
+    /// Calculate floor(a * b / denominator) with a 512-bit intermediate product
+    /// Succeeds whenever the result fits in U256, even if a * b does not
+    pub fn mul_div(a: U256, b: U256, denominator: U256) -> Result<U256, MathError> {
+        if denominator == U256::ZERO {
+            return Err(MathError::DivisionByZero);
+        }
+
+        if let Some(product) = a.checked_mul(b) {
+            return Ok(product / denominator);
+        }
+
+        let quotient = U512::from(a) * U512::from(b) / U512::from(denominator);
+        if quotient > U512::from(U256::MAX) {
+            return Err(MathError::Overflow);
+        }
+
+        Ok(quotient.to::<U256>())
+    }
+
+    /// Calculate ceil(a * b / denominator) with a 512-bit intermediate product
+    ///
+    /// Rounding policy: amounts the pool keeps (fees) round up with this, amounts it pays
+    /// out (proceeds, rewards) round down with mul_div, so rounding can only leave dust in
+    /// the pool and never owe more than it holds.
+    pub fn mul_div_rounding_up(a: U256, b: U256, denominator: U256) -> Result<U256, MathError> {
+        let quotient = Self::mul_div(a, b, denominator)?;
+
+        let has_remainder = match a.checked_mul(b) {
+            Some(product) => product % denominator > U256::ZERO,
+            None => U512::from(a) * U512::from(b) % U512::from(denominator) > U512::ZERO,
+        };
+
+        if has_remainder {
+            quotient
+                .checked_add(U256::from(1u32))
+                .ok_or(MathError::Overflow)
+        } else {
+            Ok(quotient)
+        }
+    }
+
+    /// mul_div for callers that only need to know whether it succeeded
+    pub(crate) fn mul_div_wide(a: U256, b: U256, denominator: U256) -> Option<U256> {
+        Self::mul_div(a, b, denominator).ok()
+    }
+
     /// Calculate exponential function approximation using Taylor series
     /// Used for time-decay calculations in TWAMM
     pub fn exp_taylor(x: U256, precision: u32) -> Result<U256, &'static str> {
+        Ok(Self::exp_taylor_with_budget(x, precision, EXP_TAYLOR_MAX_TERMS)?.value)
+    }
+
+    /// exp_taylor returning a FixedPoint tagged with the precision it was computed at
+    pub fn exp_taylor_fp(x: U256, precision: u32) -> Result<FixedPoint, &'static str> {
+        Ok(FixedPoint::new(Self::exp_taylor(x, precision)?, precision))
+    }
+
+    /// exp_taylor summing at most max_terms terms after the leading 1
+    /// Stops early once a term rounds to zero at the given precision
+    pub fn exp_taylor_with_budget(
+        x: U256,
+        precision: u32,
+        max_terms: u32,
+    ) -> Result<Approximation, &'static str> {
         if x >= U256::from(50u32) * U256::from(10u128.pow(precision)) {
             return Err("Exponential overflow");
         }
@@ -73,24 +818,47 @@ impl TWAMMath {
         let mut factorial = U256::from(1u32);
 
         // Taylor series: e^x = 1 + x + x²/2! + x³/3! + ...
-        for i in 1..=20 {
+        for i in 1..=max_terms {
             factorial *= U256::from(i);
-            term = term * x / U256::from(10u128.pow(precision));
+            term = Self::mul_div(term, x, one).map_err(|_| "Exponential overflow")?;
             let term_value = term / factorial;
 
             if term_value == U256::ZERO {
-                break;
+                return Ok(Approximation {
+                    value: result,
+                    iterations: i - 1,
+                    converged: true,
+                });
             }
 
             result += term_value;
         }
 
-        Ok(result)
+        Ok(Approximation {
+            value: result,
+            iterations: max_terms,
+            converged: false,
+        })
     }
 
     /// Calculate natural logarithm using Newton's method
     /// Used for TWAMM price impact calculations
     pub fn ln_newton(x: U256, precision: u32) -> Result<U256, &'static str> {
+        Ok(Self::ln_newton_with_budget(x, precision, LN_NEWTON_MAX_ITERATIONS)?.value)
+    }
+
+    /// ln_newton returning a FixedPoint tagged with the precision it was computed at
+    pub fn ln_newton_fp(x: U256, precision: u32) -> Result<FixedPoint, &'static str> {
+        Ok(FixedPoint::new(Self::ln_newton(x, precision)?, precision))
+    }
+
+    /// ln_newton taking at most max_iterations Newton steps
+    /// Stops early once a step moves the estimate by less than 10^-6
+    pub fn ln_newton_with_budget(
+        x: U256,
+        precision: u32,
+        max_iterations: u32,
+    ) -> Result<Approximation, &'static str> {
         if x == U256::ZERO {
             return Err("ln(0) undefined");
         }
@@ -98,7 +866,12 @@ impl TWAMMath {
         let one = U256::from(10u128.pow(precision));
 
         if x == one {
-            return Ok(U256::ZERO); // ln(1) = 0
+            // ln(1) = 0
+            return Ok(Approximation {
+                value: U256::ZERO,
+                iterations: 0,
+                converged: true,
+            });
         }
 
         // Use Newton's method: y_{n+1} = y_n + 2(x - e^{y_n})/(x + e^{y_n})
@@ -108,7 +881,7 @@ impl TWAMMath {
             (one - x) * one / x // Initial guess for x < 1, but negative
         };
 
-        for _ in 0..50 {
+        for iteration in 1..=max_iterations {
             let exp_y = Self::exp_taylor(y, precision)?;
             let numerator = (x - exp_y) * U256::from(2u32) * one;
             let denominator = x + exp_y;
@@ -121,33 +894,49 @@ impl TWAMMath {
             let new_y = y + delta;
 
             // Check for convergence
-            if y > new_y && y - new_y < one / U256::from(1000000u32) {
-                break;
-            } else if new_y > y && new_y - y < one / U256::from(1000000u32) {
-                break;
+            if y.abs_diff(new_y) < one / U256::from(1000000u32) {
+                return Ok(Approximation {
+                    value: y,
+                    iterations: iteration,
+                    converged: true,
+                });
             }
 
             y = new_y;
         }
 
-        Ok(y)
+        Ok(Approximation {
+            value: y,
+            iterations: max_iterations,
+            converged: false,
+        })
     }
 
     /// Calculate compound interest formula: A = P(1 + r)^t
-    /// Used for time-weighted calculations
+    /// Used for time-weighted calculations; a negative rate models decay and must not
+    /// fall below -100%
     pub fn compound_interest(
         principal: U256,
-        rate: U256,
+        rate: SignedFixed,
         time: U256,
         precision: u32,
     ) -> Result<U256, &'static str> {
+        if rate.precision != precision {
+            return Err("Precision mismatch");
+        }
+
         let one = U256::from(10u128.pow(precision));
-        let rate_plus_one = one + rate;
+        let rate_plus_one = if rate.negative {
+            one.checked_sub(rate.magnitude).ok_or("Rate below -100%")?
+        } else {
+            one.checked_add(rate.magnitude)
+                .ok_or("Compound interest overflow")?
+        };
 
         // Use exponentiation by squaring for efficiency
         let multiplier = Self::power(rate_plus_one, time, precision)?;
 
-        Ok(principal * multiplier / one)
+        Self::mul_div(principal, multiplier, one).map_err(|_| "Compound interest overflow")
     }
 
     /// Fast exponentiation using binary method
@@ -163,31 +952,70 @@ impl TWAMMath {
 
         while exponent > U256::ZERO {
             if exponent & U256::from(1u32) == U256::from(1u32) {
-                result = result * base_power / one;
+                result = Self::mul_div_wide(result, base_power, one)
+                    .ok_or("Power calculation overflow")?;
             }
 
-            base_power = base_power * base_power / one;
             exponent >>= 1;
 
-            // Prevent overflow
-            if result > U256::MAX / U256::from(2u32) {
-                return Err("Power calculation overflow");
+            // Only square when a higher bit still needs it, so the unused final square can't fail
+            if exponent > U256::ZERO {
+                base_power = Self::mul_div_wide(base_power, base_power, one)
+                    .ok_or("Power calculation overflow")?;
             }
         }
 
         Ok(result)
     }
 
-    /// Calculate the TWAMM virtual AMM state after time t
-    /// This implements the core TWAMM mathematical model
+    /// power returning a FixedPoint tagged with the precision it was computed at
+    pub fn power_fp(base: U256, exp: U256, precision: u32) -> Result<FixedPoint, &'static str> {
+        Ok(FixedPoint::new(
+            Self::power(base, exp, precision)?,
+            precision,
+        ))
+    }
+
+    /// power computed guard_digits decimal places beyond precision and rounded back to it
+    /// With POWER_GUARD_DIGITS, a base of at least one and an exponent below 2^30 the
+    /// relative error stays below 10^-precision; plain power loses about log10(exp) digits.
+    pub fn power_with_guard_digits(
+        base: U256,
+        exp: U256,
+        precision: u32,
+        guard_digits: u32,
+    ) -> Result<U256, &'static str> {
+        // The working scale must still fit the u128 power of ten used by power
+        if precision.saturating_add(guard_digits) > 38 {
+            return Err("Precision too high");
+        }
+
+        let guard = U256::from(10u128.pow(guard_digits));
+        let wide_base = base
+            .checked_mul(guard)
+            .ok_or("Power calculation overflow")?;
+        let wide = Self::power(wide_base, exp, precision + guard_digits)?;
+
+        // Round half up back to the caller's precision
+        Ok(wide
+            .checked_add(guard / U256::from(2u32))
+            .ok_or("Power calculation overflow")?
+            / guard)
+    }
+
+    /// Calculate the TWAMM virtual AMM state after time t, returning unscaled reserves
+    /// This implements the core TWAMM mathematical model. Reserves and rates are raw token
+    /// amounts; the Unscaled wrapper keeps fixed-point values from being passed by mistake.
     pub fn calculate_virtual_amm_state(
-        initial_x: U256,
-        initial_y: U256,
-        sell_rate_x: U256,
-        sell_rate_y: U256,
+        initial_x: Unscaled,
+        initial_y: Unscaled,
+        sell_rate_x: Unscaled,
+        sell_rate_y: Unscaled,
         time_blocks: U256,
         precision: u32,
     ) -> Result<(U256, U256), &'static str> {
+        let (Unscaled(initial_x), Unscaled(initial_y)) = (initial_x, initial_y);
+        let (Unscaled(sell_rate_x), Unscaled(sell_rate_y)) = (sell_rate_x, sell_rate_y);
         let one = U256::from(10u128.pow(precision));
 
         // Handle special cases
@@ -202,9 +1030,9 @@ impl TWAMMath {
                 initial_y,
                 sell_rate_x,
                 time_blocks,
-                precision,
                 true,
-            );
+            )
+            .map_err(Self::state_error);
         }
 
         if sell_rate_y > U256::ZERO && sell_rate_x == U256::ZERO {
@@ -214,12 +1042,12 @@ impl TWAMMath {
                 initial_x,
                 sell_rate_y,
                 time_blocks,
-                precision,
                 false,
-            );
+            )
+            .map_err(Self::state_error);
         }
 
-        // Bidirectional case - use net flow calculation
+        // Bidirectional case - both flows against the same evolving reserves
         Self::calculate_bidirectional_state(
             initial_x,
             initial_y,
@@ -228,44 +1056,86 @@ impl TWAMMath {
             time_blocks,
             precision,
         )
+        .map_err(Self::state_error)
+    }
+
+    /// Sample the reserves along a virtual execution as (reserve_x, reserve_y) points
+    ///
+    /// Point i of n is the calculate_virtual_amm_state result after total_blocks * i / n
+    /// blocks, so the last point is the full-duration endpoint. samples is capped at
+    /// MAX_TRAJECTORY_SAMPLES.
+    pub fn reserve_trajectory(
+        initial_x: U256,
+        initial_y: U256,
+        sell_rate_x: U256,
+        sell_rate_y: U256,
+        total_blocks: U256,
+        samples: u32,
+        precision: u32,
+    ) -> Result<Vec<(U256, U256)>, &'static str> {
+        if samples == 0 {
+            return Err("Invalid sample count");
+        }
+        if total_blocks == U256::ZERO {
+            return Err("Invalid duration");
+        }
+
+        let samples = samples.min(MAX_TRAJECTORY_SAMPLES);
+        let mut trajectory = Vec::with_capacity(samples as usize);
+        for sample in 1..=samples {
+            let blocks = Self::mul_div(total_blocks, U256::from(sample), U256::from(samples))
+                .map_err(|_| "Sample block overflow")?;
+            trajectory.push(Self::calculate_virtual_amm_state(
+                Unscaled(initial_x),
+                Unscaled(initial_y),
+                Unscaled(sell_rate_x),
+                Unscaled(sell_rate_y),
+                blocks,
+                precision,
+            )?);
+        }
+
+        Ok(trajectory)
+    }
+
+    /// Message for a virtual AMM state error, keeping the strings callers already match on
+    fn state_error(error: MathError) -> &'static str {
+        match error {
+            MathError::DivisionByZero => "Invalid reserves",
+            MathError::Overflow => "Reserve calculation overflow",
+            MathError::InvalidInput | MathError::ComputationFailed => "State calculation failed",
+        }
     }
 
     /// Calculate unidirectional TWAMM state using closed-form solution
+    ///
+    /// This is the limit of the coupled solution in calculate_bidirectional_state as the
+    /// opposing flow goes to zero, so a one-sided window prices the same as a two-sided one
+    /// with a vanishing counter flow.
     fn calculate_unidirectional_state(
         reserve_in: U256,
         reserve_out: U256,
         sell_rate: U256,
         time_blocks: U256,
-        precision: u32,
         is_x_to_y: bool,
-    ) -> Result<(U256, U256), &'static str> {
-        let k = reserve_in * reserve_out; // Constant product
-        let one = U256::from(10u128.pow(precision));
-
-        // Calculate the decay parameter
-        let total_sell_amount = sell_rate * time_blocks;
-
-        // Apply the TWAMM formula: new_reserve_in = sqrt(k * e^(2 * total_sell / sqrt(k)))
-        let sqrt_k = Self::sqrt(k);
-
-        if sqrt_k == U256::ZERO {
-            return Err("Invalid liquidity");
+    ) -> Result<(U256, U256), MathError> {
+        // Guard here too, since validation is not always run before the math
+        if reserve_in == U256::ZERO || reserve_out == U256::ZERO {
+            return Err(MathError::DivisionByZero);
         }
 
-        // Simplified calculation to avoid complex exponentials
-        // Use approximation: e^x ≈ 1 + x for small x
-        let decay_factor = total_sell_amount * one / sqrt_k;
+        let total_sell_amount = sell_rate
+            .checked_mul(time_blocks)
+            .ok_or(MathError::Overflow)?;
 
-        let new_reserve_in = if decay_factor < one {
-            // Small decay: use linear approximation
-            reserve_in + (total_sell_amount * reserve_in / (reserve_in + sqrt_k))
-        } else {
-            // Larger decay: use more accurate calculation
-            reserve_in + total_sell_amount * U256::from(8u32) / U256::from(10u32)
-            // 80% efficiency
-        };
+        // A one-sided flow into a fee-less constant product pool is path independent,
+        // so streaming the total over time ends at the same state as a single swap
+        let new_reserve_in = reserve_in
+            .checked_add(total_sell_amount)
+            .ok_or(MathError::Overflow)?;
 
-        let new_reserve_out = k / new_reserve_in;
+        let new_reserve_out = Self::mul_div_wide(reserve_in, reserve_out, new_reserve_in)
+            .ok_or(MathError::Overflow)?;
 
         if is_x_to_y {
             Ok((new_reserve_in, new_reserve_out))
@@ -275,6 +1145,13 @@ impl TWAMMath {
     }
 
     /// Calculate bidirectional TWAMM state
+    ///
+    /// Both flows trade against the same evolving reserves. With window totals X and Y,
+    /// a = sqrt(x * Y), b = sqrt(y * X) and E = e^(2 * sqrt(X * Y / (x * y))), the
+    /// continuous solution is
+    ///   x_end = x * (b / a) * (a (E + 1) + b (E - 1)) / (a (E - 1) + b (E + 1))
+    /// and y_end = x * y / x_end. Netting the flows at the starting price instead ignores
+    /// how each flow supports the price for the other and overstates the impact.
     fn calculate_bidirectional_state(
         initial_x: U256,
         initial_y: U256,
@@ -282,42 +1159,137 @@ impl TWAMMath {
         sell_rate_y: U256,
         time_blocks: U256,
         precision: u32,
-    ) -> Result<(U256, U256), &'static str> {
+    ) -> Result<(U256, U256), MathError> {
+        if initial_x == U256::ZERO || initial_y == U256::ZERO {
+            return Err(MathError::DivisionByZero);
+        }
+
+        let total_sell_x = sell_rate_x
+            .checked_mul(time_blocks)
+            .ok_or(MathError::Overflow)?;
+        let total_sell_y = sell_rate_y
+            .checked_mul(time_blocks)
+            .ok_or(MathError::Overflow)?;
+        if total_sell_x == U256::ZERO || total_sell_y == U256::ZERO {
+            return Ok((initial_x, initial_y));
+        }
+
         let one = U256::from(10u128.pow(precision));
 
-        // Calculate net selling rates
-        let total_sell_x = sell_rate_x * time_blocks;
-        let total_sell_y = sell_rate_y * time_blocks;
+        // Scale both roots by one where it fits, so small amounts keep their precision
+        let scale = if [initial_x, initial_y, total_sell_x, total_sell_y]
+            .iter()
+            .all(|value| value.checked_mul(one).is_some())
+        {
+            one
+        } else {
+            U256::from(1u32)
+        };
+        let a = Self::sqrt_product(initial_x * scale, total_sell_y * scale);
+        let b = Self::sqrt_product(initial_y * scale, total_sell_x * scale);
+        if a == U256::ZERO || b == U256::ZERO {
+            return Err(MathError::ComputationFailed);
+        }
+
+        // Exponent 2 * sqrt(X * Y / k), scaled to one; None once it is far past the cutoff
+        let exponent = Self::mul_div(total_sell_x, one, initial_x)
+            .and_then(|x_share| {
+                Self::mul_div(x_share, Self::mul_div(total_sell_y, one, initial_y)?, one)
+            })
+            .ok()
+            .and_then(|flow_product| flow_product.checked_mul(one))
+            .map(|scaled| Self::sqrt(scaled) * U256::from(2u32));
+
+        // Compute the side with the larger root directly and derive the other from the
+        // invariant, so mirrored inputs give exactly mirrored results
+        if a >= b {
+            let final_x = Self::bidirectional_endpoint(initial_x, a, b, exponent, precision)?;
+            let final_y = Self::mul_div(initial_x, initial_y, final_x)?;
+            Ok((final_x, final_y))
+        } else {
+            let final_y = Self::bidirectional_endpoint(initial_y, b, a, exponent, precision)?;
+            let final_x = Self::mul_div(initial_x, initial_y, final_y)?;
+            Ok((final_x, final_y))
+        }
+    }
 
-        // Get current price ratio
-        let price_x_in_y = initial_y * one / initial_x;
-        let sell_x_value_in_y = total_sell_x * price_x_in_y / one;
+    /// Evaluate reserve * (b / a) * (a (E + 1) + b (E - 1)) / (a (E - 1) + b (E + 1))
+    /// for calculate_bidirectional_state, with E = e^exponent
+    fn bidirectional_endpoint(
+        reserve: U256,
+        a: U256,
+        b: U256,
+        exponent: Option<U256>,
+        precision: u32,
+    ) -> Result<U256, MathError> {
+        let one = U256::from(10u128.pow(precision));
+
+        // Past the cutoff E is so large that the ratio is 1 to within rounding, leaving the
+        // equilibrium reserve * b / a where both flows trade at the same price
+        let equilibrium = Self::mul_div(reserve, b, a)?;
+        let endpoint = match exponent {
+            Some(exponent) if exponent < U256::from(BIDIRECTIONAL_EXPONENT_CUTOFF) * one => {
+                let e = Self::exp_fixed(exponent, precision)?;
+
+                // Weights a / (a + b) and b / (a + b) keep the products within range
+                let weight_a = Self::mul_div(a, one, a.checked_add(b).ok_or(MathError::Overflow)?)?;
+                let weight_b = one - weight_a;
+                let numerator =
+                    Self::mul_div(weight_a, e + one, one)? + Self::mul_div(weight_b, e - one, one)?;
+                let denominator =
+                    Self::mul_div(weight_a, e - one, one)? + Self::mul_div(weight_b, e + one, one)?;
+
+                Self::mul_div(equilibrium, numerator, denominator)?
+            }
+            _ => equilibrium,
+        };
+
+        if endpoint == U256::ZERO {
+            return Err(MathError::ComputationFailed);
+        }
+        Ok(endpoint)
+    }
+
+    /// Calculate e^x for fixed-point x by halving into exp_taylor's accurate range and
+    /// squaring back up
+    fn exp_fixed(x: U256, precision: u32) -> Result<U256, MathError> {
+        let one = U256::from(10u128.pow(precision));
+
+        let mut reduced = x;
+        let mut halvings = 0;
+        while reduced > one {
+            reduced >>= 1;
+            halvings += 1;
+        }
+
+        let mut result = Self::exp_taylor(reduced, precision).map_err(|_| MathError::Overflow)?;
+        for _ in 0..halvings {
+            result = Self::mul_div(result, result, one)?;
+        }
+
+        Ok(result)
+    }
+
+    /// Net the two flows of a window at the starting price
+    /// Returns (x_to_y, net_amount) with net_amount in the net-sold token, zero when the
+    /// flows balance. Zero reserves return DivisionByZero.
+    ///
+    /// Values are converted with the reserve ratio directly rather than through a price
+    /// rounded to a fixed precision, which would truncate to zero for pools where one token
+    /// is worth far less than the other.
+    pub(crate) fn net_flow(
+        initial_x: U256,
+        initial_y: U256,
+        total_sell_x: U256,
+        total_sell_y: U256,
+    ) -> Result<(bool, U256), MathError> {
+        let sell_x_value_in_y = Self::mul_div(total_sell_x, initial_y, initial_x)?;
 
         if sell_x_value_in_y > total_sell_y {
-            // Net flow X -> Y
-            let net_sell_x = total_sell_x - (total_sell_y * one / price_x_in_y);
-            Self::calculate_unidirectional_state(
-                initial_x,
-                initial_y,
-                net_sell_x / time_blocks,
-                time_blocks,
-                precision,
-                true,
-            )
-        } else if total_sell_y > sell_x_value_in_y {
-            // Net flow Y -> X
-            let net_sell_y = total_sell_y - sell_x_value_in_y;
-            Self::calculate_unidirectional_state(
-                initial_y,
-                initial_x,
-                net_sell_y / time_blocks,
-                time_blocks,
-                precision,
-                false,
-            )
+            let sell_y_value_in_x = Self::mul_div(total_sell_y, initial_x, initial_y)?;
+            Ok((true, total_sell_x.saturating_sub(sell_y_value_in_x)))
         } else {
-            // Balanced flows - no net change
-            Ok((initial_x, initial_y))
+            Ok((false, total_sell_y - sell_x_value_in_y))
         }
     }
 
@@ -335,8 +1307,11 @@ impl TWAMMath {
         let mut total_weight = U256::ZERO;
 
         for (price, weight) in prices.iter().zip(time_weights.iter()) {
-            weighted_sum += price * weight;
-            total_weight += weight;
+            let weighted_price = price.checked_mul(*weight).ok_or("TWAP overflow")?;
+            weighted_sum = weighted_sum
+                .checked_add(weighted_price)
+                .ok_or("TWAP overflow")?;
+            total_weight = total_weight.checked_add(*weight).ok_or("TWAP overflow")?;
         }
 
         if total_weight == U256::ZERO {
@@ -346,7 +1321,146 @@ impl TWAMMath {
         Ok(weighted_sum / total_weight)
     }
 
+    /// Calculate an exponentially decayed time-weighted average price
+    ///
+    /// Prices are ordered oldest first and time_weights are how long each one held. Each
+    /// observation's weight is discounted by e^(-decay * age), where decay is decay_bps / 10000
+    /// per unit of time and age is the time elapsed since the observation ended, so recent
+    /// prices dominate. Zero decay gives the same result as calculate_twap.
+    pub fn calculate_ema(
+        prices: Vec<U256>,
+        time_weights: Vec<U256>,
+        decay_bps: U256,
+        precision: u32,
+    ) -> Result<U256, &'static str> {
+        if prices.len() != time_weights.len() || prices.is_empty() {
+            return Err("Invalid price/weight arrays");
+        }
+
+        let one = U256::from(10u128.pow(precision));
+        let mut weighted_sum = U256::ZERO;
+        let mut total_weight = U256::ZERO;
+        let mut age = U256::ZERO;
+
+        for (price, time_weight) in prices.iter().zip(time_weights.iter()).rev() {
+            // e^(-x) = 1 / e^x; observations past the exponent cutoff contribute nothing
+            let exponent = age
+                .checked_mul(decay_bps)
+                .and_then(|scaled| Self::mul_div_wide(scaled, one, U256::from(10000u32)));
+            let discount = match exponent {
+                Some(exponent) if exponent < U256::from(EMA_EXPONENT_CUTOFF) * one => {
+                    let growth =
+                        Self::exp_fixed(exponent, precision).map_err(|_| "EMA overflow")?;
+                    Self::mul_div_wide(one, one, growth).ok_or("EMA overflow")?
+                }
+                _ => U256::ZERO,
+            };
+
+            let weight = time_weight.checked_mul(discount).ok_or("EMA overflow")?;
+            let weighted_price = Self::mul_div_wide(*price, weight, one).ok_or("EMA overflow")?;
+            weighted_sum = weighted_sum
+                .checked_add(weighted_price)
+                .ok_or("EMA overflow")?;
+            total_weight = total_weight.checked_add(weight).ok_or("EMA overflow")?;
+            age = age.saturating_add(*time_weight);
+        }
+
+        if total_weight == U256::ZERO {
+            return Err("Zero total weight");
+        }
+
+        Self::mul_div_wide(weighted_sum, one, total_weight).ok_or("EMA overflow")
+    }
+
+    /// Calculate realized volatility, the standard deviation of log-returns ln(p_i / p_i-1)
+    ///
+    /// Prices are TWAP observations ordered oldest first. The deviation is over the whole
+    /// series of returns (population, not sample) and is scaled by 10^precision, so a flat
+    /// series has zero volatility. It is per observation interval and is not annualized.
+    pub fn realized_volatility(prices: Vec<U256>, precision: u32) -> Result<U256, &'static str> {
+        if prices.len() < 2 {
+            return Err("Need at least two observations");
+        }
+
+        let one = U256::from(10u128.pow(precision));
+        let mut returns = Vec::with_capacity(prices.len() - 1);
+        let mut sum = SignedFixed::new(U256::ZERO, false, precision);
+        for pair in prices.windows(2) {
+            let (previous, current) = (pair[0], pair[1]);
+            if previous == U256::ZERO || current == U256::ZERO {
+                return Err("Invalid price");
+            }
+
+            // ln_newton needs a ratio of at least one, so a fall is the negated log of the rise
+            let (ratio, negative) = if current >= previous {
+                (Self::mul_div(current, one, previous), false)
+            } else {
+                (Self::mul_div(previous, one, current), true)
+            };
+            let ratio = ratio.map_err(|_| "Volatility overflow")?;
+            let log_return =
+                SignedFixed::new(Self::ln_newton(ratio, precision)?, negative, precision);
+
+            sum = sum.add(&log_return).map_err(|_| "Volatility overflow")?;
+            returns.push(log_return);
+        }
+
+        let count = U256::from(returns.len());
+        let mean = SignedFixed::new(sum.magnitude / count, sum.negative, precision);
+        let mut squared_deviations = U256::ZERO;
+        for log_return in &returns {
+            let deviation = log_return.sub(&mean).map_err(|_| "Volatility overflow")?;
+            let squared = Self::mul_div(deviation.magnitude, deviation.magnitude, one)
+                .map_err(|_| "Volatility overflow")?;
+            squared_deviations = squared_deviations
+                .checked_add(squared)
+                .ok_or("Volatility overflow")?;
+        }
+
+        // sqrt(variance * one) keeps the result at 10^precision
+        let variance = squared_deviations / count;
+        Ok(Self::sqrt(
+            variance.checked_mul(one).ok_or("Volatility overflow")?,
+        ))
+    }
+
+    /// Convert basis points to the fixed-point percent unit of calculate_price_impact
+    /// 100 bps (1%) is 10^precision
+    pub fn bps_to_fixed(bps: U256, precision: u32) -> Result<U256, &'static str> {
+        Self::mul_div(
+            bps,
+            U256::from(10u32).pow(U256::from(precision)),
+            U256::from(100u32),
+        )
+        .map_err(|_| "Impact conversion overflow")
+    }
+
+    /// Convert a fixed-point percent from calculate_price_impact to basis points, rounding down
+    pub fn fixed_to_bps(value: U256, precision: u32) -> Result<U256, &'static str> {
+        Self::mul_div(
+            value,
+            U256::from(100u32),
+            U256::from(10u32).pow(U256::from(precision)),
+        )
+        .map_err(|_| "Impact conversion overflow")
+    }
+
+    /// Get the price impact of the next unit of input at the current reserves
+    /// This is the derivative of calculate_price_impact at zero trade size, 100 / reserve_in
+    /// in the same percent scaled by 10^precision, so a small trade's impact is about
+    /// trade_size times it. Returns U256::MAX for an empty pool.
+    pub fn marginal_impact(reserve_in: U256, reserve_out: U256, precision: u32) -> U256 {
+        if reserve_in == U256::ZERO || reserve_out == U256::ZERO {
+            return U256::MAX;
+        }
+
+        let one = U256::from(10u128.pow(precision));
+        Self::mul_div_wide(U256::from(100u32), one, reserve_in).unwrap_or(U256::MAX)
+    }
+
     /// Calculate price impact given trade size and liquidity
+    /// Returned as a percent scaled by 10^precision (1% is 10^precision); fixed_to_bps
+    /// converts it to the basis points used by OrderManager
     pub fn calculate_price_impact(
         trade_size: U256,
         reserve_in: U256,
@@ -358,33 +1472,69 @@ impl TWAMMath {
         }
 
         let one = U256::from(10u128.pow(precision));
-        let k = reserve_in * reserve_out;
 
-        // Calculate new reserves after trade
+        // Calculate new reserves after trade, keeping k = reserve_in * reserve_out implicit
         let new_reserve_in = reserve_in + trade_size;
-        let new_reserve_out = k / new_reserve_in;
+        let new_reserve_out = Self::mul_div_wide(reserve_in, reserve_out, new_reserve_in)
+            .ok_or("Reserve calculation overflow")?;
         let amount_out = reserve_out - new_reserve_out;
 
         // Expected amount out without slippage
-        let expected_out = trade_size * reserve_out / reserve_in;
+        let expected_out = Self::mul_div_wide(trade_size, reserve_out, reserve_in)
+            .ok_or("Expected output overflow")?;
 
         if expected_out <= amount_out {
             return Ok(U256::ZERO);
         }
 
         // Price impact as percentage
-        let impact = (expected_out - amount_out) * one * U256::from(100u32) / expected_out;
+        Self::mul_div(
+            expected_out - amount_out,
+            one * U256::from(100u32),
+            expected_out,
+        )
+        .map_err(|_| "Impact calculation overflow")
+    }
+
+    /// calculate_price_impact for a pool given as (reserve_0, reserve_1), picking the input
+    /// reserve from the direction so callers cannot pass the reserves the wrong way round
+    pub fn calculate_price_impact_directional(
+        trade_size: U256,
+        reserve_0: U256,
+        reserve_1: U256,
+        direction: OrderDirection,
+        precision: u32,
+    ) -> Result<U256, &'static str> {
+        let (reserve_in, reserve_out) = match direction {
+            OrderDirection::SellToken0 => (reserve_0, reserve_1),
+            OrderDirection::SellToken1 => (reserve_1, reserve_0),
+        };
+        Self::calculate_price_impact(trade_size, reserve_in, reserve_out, precision)
+    }
 
-        Ok(impact)
+    /// calculate_price_impact returning a FixedPoint tagged with the precision of its percent
+    pub fn calculate_price_impact_fp(
+        trade_size: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+        precision: u32,
+    ) -> Result<FixedPoint, &'static str> {
+        let impact = Self::calculate_price_impact(trade_size, reserve_in, reserve_out, precision)?;
+        Ok(FixedPoint::new(impact, precision))
     }
 
     /// Validate mathematical constraints for TWAMM
+    ///
+    /// Each sell rate may be at most `max_rate_bps_per_block` basis points of its reserve per
+    /// block (see DEFAULT_MAX_RATE_BPS_PER_BLOCK). The total sold over the window may exceed
+    /// the reserve, since streaming orders move along the curve rather than draining it.
     pub fn validate_twamm_constraints(
         reserve_x: U256,
         reserve_y: U256,
         sell_rate_x: U256,
         sell_rate_y: U256,
         time_blocks: U256,
+        max_rate_bps_per_block: U256,
     ) -> Result<(), &'static str> {
         // Check for zero reserves
         if reserve_x == U256::ZERO || reserve_y == U256::ZERO {
@@ -396,26 +1546,23 @@ impl TWAMMath {
             return Err("Invalid time range");
         }
 
-        // Check sell rates don't exceed reasonable bounds
-        let max_sell_rate_x = reserve_x / U256::from(1000u32); // Max 0.1% per block
-        let max_sell_rate_y = reserve_y / U256::from(1000u32);
+        // Check sell rates don't exceed the per-block cap
+        let max_sell_rate_x =
+            Self::mul_div_wide(reserve_x, max_rate_bps_per_block, U256::from(10000u32))
+                .ok_or("Rate cap overflow")?;
+        let max_sell_rate_y =
+            Self::mul_div_wide(reserve_y, max_rate_bps_per_block, U256::from(10000u32))
+                .ok_or("Rate cap overflow")?;
 
         if sell_rate_x > max_sell_rate_x || sell_rate_y > max_sell_rate_y {
             return Err("Sell rate too high");
         }
 
-        // Check total sell amount doesn't exceed reserves
-        let total_sell_x = sell_rate_x * time_blocks;
-        let total_sell_y = sell_rate_y * time_blocks;
-
-        if total_sell_x >= reserve_x || total_sell_y >= reserve_y {
-            return Err("Total sell exceeds reserves");
-        }
-
         Ok(())
     }
 
     /// Calculate optimal execution rate to minimize price impact
+    /// target_impact_bps is in basis points, compared via bps_to_fixed
     pub fn calculate_optimal_rate(
         total_amount: U256,
         available_time: U256,
@@ -435,17 +1582,23 @@ impl TWAMMath {
         let impact =
             Self::calculate_price_impact(uniform_rate, reserve_in, reserve_out, precision)?;
 
-        let one_percent = U256::from(10u128.pow(precision - 2)); // 1% in precision units
-        let target_impact = target_impact_bps * one_percent / U256::from(100u32);
+        let target_impact = Self::bps_to_fixed(target_impact_bps, precision)?;
 
         if impact <= target_impact {
             return Ok(uniform_rate);
         }
 
+        // The smallest nonzero rate bounds the search; if it misses the target nothing will
+        let min_impact =
+            Self::calculate_price_impact(U256::from(1u32), reserve_in, reserve_out, precision)?;
+        if min_impact > target_impact {
+            return Err("target impact unreachable");
+        }
+
         // Binary search for optimal rate
         let mut low = U256::from(1u32);
         let mut high = uniform_rate;
-        let mut optimal_rate = uniform_rate;
+        let mut optimal_rate = U256::from(1u32);
 
         for _ in 0..50 {
             let mid = (low + high) / U256::from(2u32);
@@ -455,10 +1608,14 @@ impl TWAMMath {
                 optimal_rate = mid;
                 low = mid + U256::from(1u32);
             } else {
+                // mid == low means every candidate above optimal_rate failed (and guards mid == 1)
+                if mid == low {
+                    break;
+                }
                 high = mid - U256::from(1u32);
             }
 
-            if high <= low {
+            if high < low {
                 break;
             }
         }
@@ -466,21 +1623,93 @@ impl TWAMMath {
         Ok(optimal_rate)
     }
 
+    /// Split an order into equal-time segments that each add the same slippage cost
+    ///
+    /// Returns the per-block sell rate of each segment. Slippage is measured against the
+    /// starting spot price, so later segments, trading against a depleted reserve_out, are
+    /// given smaller rates. When duration_blocks does not divide evenly the last segments
+    /// are one block longer. Rates are rounded down, so up to one unit per block of
+    /// total_amount is left unsold, like dust_remainder at order creation.
+    pub fn calculate_rate_schedule(
+        total_amount: U256,
+        duration_blocks: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+        segments: u32,
+        _precision: u32,
+    ) -> Result<Vec<U256>, &'static str> {
+        if reserve_in == U256::ZERO || reserve_out == U256::ZERO {
+            return Err("Invalid reserves");
+        }
+        if segments == 0 || duration_blocks < U256::from(segments) {
+            return Err("Invalid segment count");
+        }
+
+        // Cumulative slippage after selling a is reserve_out / reserve_in * a^2 / (reserve_in + a);
+        // the constant factor cancels, so split g(a) = a^2 / (reserve_in + a) evenly
+        let overflow = "Schedule overflow";
+        let total_cost = Self::mul_div(
+            total_amount,
+            total_amount,
+            reserve_in.checked_add(total_amount).ok_or(overflow)?,
+        )
+        .map_err(|_| overflow)?;
+
+        let mut schedule = Vec::with_capacity(segments as usize);
+        let mut sold = U256::ZERO;
+        for segment in 1..segments {
+            // Invert g: a = (c + sqrt(c^2 + 4 c reserve_in)) / 2
+            let cost = Self::mul_div(total_cost, U256::from(segment), U256::from(segments))
+                .map_err(|_| overflow)?;
+            let discriminant = cost
+                .checked_mul(cost)
+                .and_then(|square| {
+                    cost.checked_mul(reserve_in)?
+                        .checked_mul(U256::from(4u32))?
+                        .checked_add(square)
+                })
+                .ok_or(overflow)?;
+            let cumulative =
+                ((cost + Self::sqrt(discriminant)) / U256::from(2u32)).clamp(sold, total_amount);
+
+            schedule.push(cumulative - sold);
+            sold = cumulative;
+        }
+        schedule.push(total_amount - sold);
+
+        // Turn each segment's amount into a rate over its blocks
+        let segments_u256 = U256::from(segments);
+        let base_blocks = duration_blocks / segments_u256;
+        let longer_from = segments_u256 - duration_blocks % segments_u256;
+        for (segment, amount) in schedule.iter_mut().enumerate() {
+            let blocks = if U256::from(segment) >= longer_from {
+                base_blocks + U256::from(1u32)
+            } else {
+                base_blocks
+            };
+            *amount /= blocks;
+        }
+
+        Ok(schedule)
+    }
+
     /// Calculate execution quality score based on expected vs actual results
+    /// Scores range from 0 to 100: up to 50 for the amount received (fills better than expected
+    /// earn no bonus) and up to 50 for price impact. Errors if nothing was expected, since there
+    /// is no baseline to grade the fill against.
     pub fn calculate_execution_quality(
         expected_amount: U256,
         actual_amount: U256,
         price_impact_bps: U256,
-        precision: u32,
-    ) -> U256 {
-        let one = U256::from(10u128.pow(precision));
+        _precision: u32,
+    ) -> Result<U256, &'static str> {
+        if expected_amount == U256::ZERO {
+            return Err("Zero expected amount");
+        }
 
-        // Base quality from amount ratio (0-50 points)
-        let amount_ratio = if expected_amount > U256::ZERO {
-            actual_amount * U256::from(50u32) / expected_amount
-        } else {
-            U256::from(50u32)
-        };
+        // Base quality from amount ratio (0-50 points), capped for favorable fills
+        let amount_ratio = (actual_amount.saturating_mul(U256::from(50u32)) / expected_amount)
+            .min(U256::from(50u32));
 
         // Quality penalty for high price impact (0-50 points)
         let impact_score = if price_impact_bps > U256::from(500u32) {
@@ -492,13 +1721,15 @@ impl TWAMMath {
         };
 
         // Total quality score (0-100)
-        amount_ratio + impact_score
+        Ok(amount_ratio + impact_score)
     }
 
     /// Calculate time decay factor for gradual order execution
+    /// Returns the fraction executed so far under the given profile, reaching one at total_time
     pub fn calculate_time_decay_factor(
         elapsed_time: U256,
         total_time: U256,
+        decay_kind: DecayKind,
         precision: u32,
     ) -> Result<U256, &'static str> {
         if total_time == U256::ZERO {
@@ -511,26 +1742,29 @@ impl TWAMMath {
             return Ok(one); // 100% executed
         }
 
-        // Linear decay for simplicity
-        Ok(elapsed_time * one / total_time)
+        let progress = elapsed_time * one / total_time;
+
+        match decay_kind {
+            DecayKind::Linear => Ok(progress),
+            DecayKind::Quadratic => Ok(progress * progress / one),
+            DecayKind::Exponential => {
+                // (e^k - e^(k(1 - t))) / (e^k - 1), i.e. (1 - e^(-kt)) / (1 - e^(-k))
+                let rate = U256::from(EXPONENTIAL_DECAY_RATE) * one;
+                let exp_full = Self::exp_taylor(rate, precision)?;
+                let exp_remaining = Self::exp_taylor(rate * (one - progress) / one, precision)?;
+
+                Ok((exp_full - exp_remaining) * one / (exp_full - one))
+            }
+        }
     }
 
-    /// Estimate gas cost for TWAMM operations
+    /// Estimate gas cost for TWAMM operations under the default GasModel
+    /// See GasModel::operation_gas for other chains
     pub fn estimate_gas_cost(
         operation_type: u8, // 0=submit, 1=execute, 2=cancel
         complexity_factor: U256,
     ) -> U256 {
-        let base_gas = match operation_type {
-            0 => U256::from(80000u32),  // Submit order
-            1 => U256::from(150000u32), // Execute orders
-            2 => U256::from(50000u32),  // Cancel order
-            _ => U256::from(100000u32), // Default
-        };
-
-        // Add complexity-based gas
-        let complexity_gas = complexity_factor * U256::from(1000u32);
-
-        base_gas + complexity_gas
+        GasModel::default().operation_gas(operation_type, complexity_factor)
     }
 
     /// Calculate MEV protection score
@@ -559,19 +1793,22 @@ impl TWAMMath {
     }
 
     /// Execute virtual orders using closed-form solution
+    /// Returns (new_reserve_0, new_reserve_1, amount_0_received, amount_1_received) where
+    /// amount_0_received is token0 paid to token1 sellers and amount_1_received is token1 paid
+    /// to token0 sellers
     pub fn execute_virtual_orders_closed_form(
         sell_rate_0: U256,
         sell_rate_1: U256,
         blocks_elapsed: U256,
         reserve_0: U256,
         reserve_1: U256,
-    ) -> Result<(U256, U256, U256, U256), MathError> {
+    ) -> Result<(U256, U256, U256, U256), MathFailure> {
         let total_sell_0 = sell_rate_0
             .checked_mul(blocks_elapsed)
-            .ok_or(MathError::Overflow)?;
+            .ok_or(MathFailure::new(MathError::Overflow, OP_TOTAL_SELL_0))?;
         let total_sell_1 = sell_rate_1
             .checked_mul(blocks_elapsed)
-            .ok_or(MathError::Overflow)?;
+            .ok_or(MathFailure::new(MathError::Overflow, OP_TOTAL_SELL_1))?;
 
         if total_sell_0 == U256::ZERO && total_sell_1 == U256::ZERO {
             return Ok((reserve_0, reserve_1, U256::ZERO, U256::ZERO));
@@ -584,17 +1821,19 @@ impl TWAMMath {
                 reserve_1,
                 sell_rate_0,
                 blocks_elapsed,
-                18,
                 true,
             )
-            .map_err(|_| MathError::ComputationFailed)?;
-
-            let amount_0_received = new_reserve_0
-                .checked_sub(reserve_0)
-                .ok_or(MathError::Overflow)?;
-            let amount_1_received = reserve_1
-                .checked_sub(new_reserve_1)
-                .ok_or(MathError::Overflow)?;
+            .map_err(|error| MathFailure::new(error, OP_UNIDIRECTIONAL_0_TO_1))?;
+
+            // Only token0 sellers are paid out
+            let amount_0_received = U256::ZERO;
+            let amount_1_received =
+                reserve_1
+                    .checked_sub(new_reserve_1)
+                    .ok_or(MathFailure::new(
+                        MathError::Overflow,
+                        OP_UNIDIRECTIONAL_0_TO_1,
+                    ))?;
 
             Ok((
                 new_reserve_0,
@@ -609,17 +1848,19 @@ impl TWAMMath {
                 reserve_0,
                 sell_rate_1,
                 blocks_elapsed,
-                18,
                 true,
             )
-            .map_err(|_| MathError::ComputationFailed)?;
-
-            let amount_1_received = new_reserve_1
-                .checked_sub(reserve_1)
-                .ok_or(MathError::Overflow)?;
-            let amount_0_received = reserve_0
-                .checked_sub(new_reserve_0)
-                .ok_or(MathError::Overflow)?;
+            .map_err(|error| MathFailure::new(error, OP_UNIDIRECTIONAL_1_TO_0))?;
+
+            // Only token1 sellers are paid out
+            let amount_1_received = U256::ZERO;
+            let amount_0_received =
+                reserve_0
+                    .checked_sub(new_reserve_0)
+                    .ok_or(MathFailure::new(
+                        MathError::Overflow,
+                        OP_UNIDIRECTIONAL_1_TO_0,
+                    ))?;
 
             Ok((
                 new_reserve_0,
@@ -635,21 +1876,27 @@ impl TWAMMath {
                 sell_rate_0,
                 sell_rate_1,
                 blocks_elapsed,
-                18,
+                PRECISION,
             )
-            .map_err(|_| MathError::ComputationFailed)?;
-
-            let amount_0_received = if new_reserve_0 > reserve_0 {
-                new_reserve_0.checked_sub(reserve_0).unwrap_or(U256::ZERO)
-            } else {
-                reserve_0.checked_sub(new_reserve_0).unwrap_or(U256::ZERO)
-            };
+            .map_err(|error| MathFailure::new(error, OP_BIDIRECTIONAL))?;
 
-            let amount_1_received = if new_reserve_1 > reserve_1 {
-                new_reserve_1.checked_sub(reserve_1).unwrap_or(U256::ZERO)
-            } else {
-                reserve_1.checked_sub(new_reserve_1).unwrap_or(U256::ZERO)
-            };
+            // Each side is paid whatever of its output token entered but did not stay in the pool
+            let amount_0_received = reserve_0
+                .checked_add(total_sell_0)
+                .ok_or(MathFailure::new(MathError::Overflow, OP_BIDIRECTIONAL))?
+                .checked_sub(new_reserve_0)
+                .ok_or(MathFailure::new(
+                    MathError::ComputationFailed,
+                    OP_BIDIRECTIONAL,
+                ))?;
+            let amount_1_received = reserve_1
+                .checked_add(total_sell_1)
+                .ok_or(MathFailure::new(MathError::Overflow, OP_BIDIRECTIONAL))?
+                .checked_sub(new_reserve_1)
+                .ok_or(MathFailure::new(
+                    MathError::ComputationFailed,
+                    OP_BIDIRECTIONAL,
+                ))?;
 
             Ok((
                 new_reserve_0,
@@ -660,6 +1907,66 @@ impl TWAMMath {
         }
     }
 
+    /// Run execute_virtual_orders_closed_form and estimate its error against executing the
+    /// same flows block by block, so callers can widen slippage on large orders
+    ///
+    /// One-sided flows are path independent, so only rounding separates them from block by
+    /// block execution. Two-sided flows follow a continuous solution whose gap to discrete
+    /// blocks stays within X * Y / (x * y) / blocks of each reserve, that is
+    /// (exponent / 2)^2 / blocks for the decay factor e^exponent of
+    /// calculate_bidirectional_state. Rounding adds CLOSED_FORM_ROUNDING_UNITS either way, and
+    /// a bound that cannot be computed saturates at U256::MAX.
+    pub fn execution_with_error_bound(
+        sell_rate_0: U256,
+        sell_rate_1: U256,
+        blocks_elapsed: U256,
+        reserve_0: U256,
+        reserve_1: U256,
+    ) -> Result<BoundedExecution, MathFailure> {
+        let (new_reserve_0, new_reserve_1, amount_0_received, amount_1_received) =
+            Self::execute_virtual_orders_closed_form(
+                sell_rate_0,
+                sell_rate_1,
+                blocks_elapsed,
+                reserve_0,
+                reserve_1,
+            )?;
+
+        let one = U256::from(10u128.pow(PRECISION));
+        let rounding = U256::from(CLOSED_FORM_ROUNDING_UNITS);
+        let no_flow = sell_rate_0 == U256::ZERO && sell_rate_1 == U256::ZERO;
+        let (error_bound_0, error_bound_1) = if no_flow || blocks_elapsed == U256::ZERO {
+            (U256::ZERO, U256::ZERO)
+        } else if sell_rate_0 == U256::ZERO || sell_rate_1 == U256::ZERO {
+            (rounding, rounding)
+        } else {
+            // Both totals fit, as the closed form computed them
+            let total_sell_0 = sell_rate_0 * blocks_elapsed;
+            let total_sell_1 = sell_rate_1 * blocks_elapsed;
+            let relative_error = Self::mul_div(total_sell_0, one, reserve_0)
+                .and_then(|share_0| {
+                    Self::mul_div(share_0, Self::mul_div(total_sell_1, one, reserve_1)?, one)
+                })
+                .map(|flow_product| flow_product / blocks_elapsed)
+                .ok();
+            let bound = |reserve: U256| {
+                relative_error
+                    .and_then(|relative_error| Self::mul_div_wide(reserve, relative_error, one))
+                    .map_or(U256::MAX, |error| error.saturating_add(rounding))
+            };
+            (bound(new_reserve_0), bound(new_reserve_1))
+        };
+
+        Ok(BoundedExecution {
+            new_reserve_0,
+            new_reserve_1,
+            amount_0_received,
+            amount_1_received,
+            error_bound_0,
+            error_bound_1,
+        })
+    }
+
     /// Calculate unidirectional TWAMM execution
     pub fn calculate_unidirectional_twamm(
         sell_amount: U256,
@@ -680,7 +1987,6 @@ impl TWAMMath {
             reserve_out,
             sell_rate,
             duration_blocks,
-            18,
             true,
         )
         .map_err(|_| MathError::ComputationFailed)?;
@@ -691,4 +1997,60 @@ impl TWAMMath {
 
         Ok(amount_out)
     }
+
+    /// Quote an instant constant-product swap, Uniswap V2 style
+    ///
+    /// amount_out = amount_in * (10000 - fee_bps) * reserve_out
+    ///              / (reserve_in * 10000 + amount_in * (10000 - fee_bps))
+    /// The fee stays in the pool along with the rest of amount_in.
+    pub fn get_amount_out(
+        amount_in: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+        fee_bps: U256,
+    ) -> Result<U256, MathError> {
+        if amount_in == U256::ZERO || fee_bps > U256::from(10000u32) {
+            return Err(MathError::InvalidInput);
+        }
+        if reserve_in == U256::ZERO || reserve_out == U256::ZERO {
+            return Err(MathError::DivisionByZero);
+        }
+
+        let amount_in_with_fee = amount_in
+            .checked_mul(U256::from(10000u32) - fee_bps)
+            .ok_or(MathError::Overflow)?;
+        let denominator = reserve_in
+            .checked_mul(U256::from(10000u32))
+            .and_then(|scaled_reserve| scaled_reserve.checked_add(amount_in_with_fee))
+            .ok_or(MathError::Overflow)?;
+
+        Self::mul_div(amount_in_with_fee, reserve_out, denominator)
+    }
+
+    /// Get how much can be sold into the pool before its price falls to target_price
+    ///
+    /// Prices are reserve_out per reserve_in scaled by 10^precision. Keeping k fixed, the
+    /// price reaches target_price once reserve_in is sqrt(k * 10^precision / target_price),
+    /// so the result is that reserve less the current one, ignoring any fee. Selling only
+    /// lowers the price, so a target above the current price is InvalidInput.
+    pub fn depth_to_price(
+        reserve_in: U256,
+        reserve_out: U256,
+        target_price: U256,
+        precision: u32,
+    ) -> Result<U256, MathError> {
+        if reserve_in == U256::ZERO || reserve_out == U256::ZERO || target_price == U256::ZERO {
+            return Err(MathError::DivisionByZero);
+        }
+
+        let one = U256::from(10u128.pow(precision));
+        let current_price = Self::mul_div(reserve_out, one, reserve_in)?;
+        if target_price > current_price {
+            return Err(MathError::InvalidInput);
+        }
+
+        let target_reserve_in =
+            Self::sqrt_product(reserve_in, Self::mul_div(reserve_out, one, target_price)?);
+        Ok(target_reserve_in.saturating_sub(reserve_in))
+    }
 }
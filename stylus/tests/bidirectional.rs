@@ -0,0 +1,193 @@
+// Bidirectional consistency tests
+// These tests cross-check the closed-form bidirectional state against a
+// fine-grained simulation that streams both flows through the constant product curve
+
+#[cfg(test)]
+mod bidirectional {
+    use stylus_sdk::alloy_primitives::U256;
+    use twamm_calculator::twamm_math::{TWAMMath, Unscaled};
+
+    const PRECISION: u32 = 18;
+    const SIMULATION_STEPS: u64 = 2000;
+    // Maximum deviation as a fraction of the gross flow value
+    const TOLERANCE: f64 = 0.02;
+
+    /// Reference: split the interval into small steps and alternate both
+    /// flows through the curve (half X, full Y, half X) so neither side is favored
+    fn simulate(x: f64, y: f64, rate_x: f64, rate_y: f64, blocks: f64) -> (f64, f64) {
+        let mut x = x;
+        let mut y = y;
+        let step_x = rate_x * blocks / SIMULATION_STEPS as f64;
+        let step_y = rate_y * blocks / SIMULATION_STEPS as f64;
+
+        for _ in 0..SIMULATION_STEPS {
+            for (sell_x, sell_y) in [(step_x / 2.0, 0.0), (0.0, step_y), (step_x / 2.0, 0.0)] {
+                if sell_x > 0.0 {
+                    let out = y * sell_x / (x + sell_x);
+                    x += sell_x;
+                    y -= out;
+                }
+                if sell_y > 0.0 {
+                    let out = x * sell_y / (y + sell_y);
+                    y += sell_y;
+                    x -= out;
+                }
+            }
+        }
+
+        (x, y)
+    }
+
+    fn closed_form(x: u128, y: u128, rate_x: u128, rate_y: u128, blocks: u128) -> (f64, f64) {
+        let (new_x, new_y) = TWAMMath::calculate_virtual_amm_state(
+            Unscaled(U256::from(x)),
+            Unscaled(U256::from(y)),
+            Unscaled(U256::from(rate_x)),
+            Unscaled(U256::from(rate_y)),
+            U256::from(blocks),
+            PRECISION,
+        )
+        .unwrap();
+
+        (new_x.to::<u128>() as f64, new_y.to::<u128>() as f64)
+    }
+
+    /// Run a schedule of (rate_x, rate_y, blocks) segments through both
+    /// models and assert the net reserve change agrees within tolerance
+    fn assert_matches_reference(x: u128, y: u128, schedule: &[(u128, u128, u128)]) {
+        let (mut closed_x, mut closed_y) = (x, y);
+        let (mut sim_x, mut sim_y) = (x as f64, y as f64);
+        let mut gross_x = 0.0;
+        let mut gross_y = 0.0;
+
+        for &(rate_x, rate_y, blocks) in schedule {
+            let (next_x, next_y) = closed_form(closed_x, closed_y, rate_x, rate_y, blocks);
+            closed_x = next_x as u128;
+            closed_y = next_y as u128;
+
+            (sim_x, sim_y) = simulate(sim_x, sim_y, rate_x as f64, rate_y as f64, blocks as f64);
+
+            // Gross flow valued at the starting price on each side
+            let sold_x = (rate_x * blocks) as f64;
+            let sold_y = (rate_y * blocks) as f64;
+            gross_x += sold_x + sold_y * x as f64 / y as f64;
+            gross_y += sold_y + sold_x * y as f64 / x as f64;
+        }
+
+        let error_x = (closed_x as f64 - sim_x).abs() / gross_x;
+        let error_y = (closed_y as f64 - sim_y).abs() / gross_y;
+
+        assert!(
+            error_x <= TOLERANCE && error_y <= TOLERANCE,
+            "reserves ({}, {}) schedule {:?}: closed ({}, {}) vs reference ({:.0}, {:.0})",
+            x,
+            y,
+            schedule,
+            closed_x,
+            closed_y,
+            sim_x,
+            sim_y
+        );
+    }
+
+    /// Rates that sell the given fractions (in percent) of each reserve over the duration
+    fn rates(x: u128, y: u128, percent_x: u128, percent_y: u128, blocks: u128) -> (u128, u128) {
+        (x * percent_x / 100 / blocks, y * percent_y / 100 / blocks)
+    }
+
+    const RESERVES: [(u128, u128); 3] = [
+        (1_000_000_000, 1_000_000_000),
+        (1_000_000_000, 2_000_000_000),
+        (5_000_000_000, 1_000_000_000),
+    ];
+    const DURATIONS: [u128; 3] = [10, 100, 1000];
+
+    #[test]
+    fn test_dominant_x_matches_reference() {
+        for (x, y) in RESERVES {
+            for blocks in DURATIONS {
+                for percent_y in [0, 1, 2, 3, 4] {
+                    let (rate_x, rate_y) = rates(x, y, 5, percent_y, blocks);
+                    assert_matches_reference(x, y, &[(rate_x, rate_y, blocks)]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_dominant_y_matches_reference() {
+        for (x, y) in RESERVES {
+            for blocks in DURATIONS {
+                for percent_x in [0, 1, 2, 3, 4] {
+                    let (rate_x, rate_y) = rates(x, y, percent_x, 5, blocks);
+                    assert_matches_reference(x, y, &[(rate_x, rate_y, blocks)]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_balanced_flows_match_reference() {
+        for (x, y) in RESERVES {
+            for blocks in DURATIONS {
+                let (rate_x, rate_y) = rates(x, y, 5, 5, blocks);
+                assert_matches_reference(x, y, &[(rate_x, rate_y, blocks)]);
+
+                let (new_x, new_y) = closed_form(x, y, rate_x, rate_y, blocks);
+                assert!((new_x - x as f64).abs() / (x as f64) < 0.001);
+                assert!((new_y - y as f64).abs() / (y as f64) < 0.001);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dominant_direction_flip_matches_reference() {
+        for (x, y) in RESERVES {
+            for blocks in DURATIONS {
+                // X dominates until its heavier orders expire, then Y dominates
+                let (early_x, early_y) = rates(x, y, 4, 1, blocks);
+                let (late_x, late_y) = rates(x, y, 1, 4, blocks);
+                assert_matches_reference(
+                    x,
+                    y,
+                    &[(early_x, early_y, blocks), (late_x, late_y, blocks)],
+                );
+
+                // And the reverse
+                assert_matches_reference(
+                    x,
+                    y,
+                    &[(late_x, late_y, blocks), (early_x, early_y, blocks)],
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_coupled_flows_beat_netting() {
+        let (x, y) = (1_000_000_000_000u128, 1_000_000_000_000u128);
+        let blocks = 100;
+        let (rate_x, rate_y) = rates(x, y, 50, 40, blocks);
+
+        // Naive: net the flows at the 1:1 starting price, then push the rest one way
+        let (netted_x, netted_y) = closed_form(x, y, rate_x - rate_y, 0, blocks);
+
+        let (coupled_x, coupled_y) = closed_form(x, y, rate_x, rate_y, blocks);
+        let (sim_x, sim_y) = simulate(
+            x as f64,
+            y as f64,
+            rate_x as f64,
+            rate_y as f64,
+            blocks as f64,
+        );
+
+        // Each flow supports the other's price, so the coupled move is smaller and
+        // agrees with the reference far more closely than netting
+        let start_price = y as f64 / x as f64;
+        let netted_impact = start_price - netted_y / netted_x;
+        let coupled_impact = start_price - coupled_y / coupled_x;
+        let reference_impact = start_price - sim_y / sim_x;
+        assert!(coupled_impact > 0.0 && coupled_impact < netted_impact);
+        assert!((coupled_impact - reference_impact).abs() < 0.001 * reference_impact);
+    }
+}
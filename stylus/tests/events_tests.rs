@@ -0,0 +1,177 @@
+// Event log tests
+// These tests verify the topics and data of the logs emitted by the order pool
+
+#[cfg(test)]
+mod events_tests {
+    use alloy_sol_types::SolEvent;
+    use stylus_sdk::alloy_primitives::{Address, B256, U256};
+    use twamm_calculator::events::{
+        self, FeesCollected, InstantSwap, KeeperRewardPaid, OrderCreated, VirtualOrdersExecuted,
+    };
+    use twamm_calculator::order_execution::{OrderDirection, OrderPool};
+
+    #[test]
+    fn test_order_created_log() {
+        let owner = Address::repeat_byte(0x11);
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner,
+                OrderDirection::SellToken1,
+                U256::from(50000u32),
+                U256::from(500u32),
+                U256::from(7u32),
+                U256::ZERO,
+            )
+            .unwrap();
+        let order = pool.get_order(order_id).unwrap();
+
+        let log = events::order_created(&order).encode_log_data();
+        let topics = log.topics();
+
+        assert_eq!(topics.len(), 3);
+        assert_eq!(topics[0], OrderCreated::SIGNATURE_HASH);
+        assert_eq!(topics[1], B256::from(order_id));
+        assert_eq!(topics[2], owner.into_word());
+
+        let decoded = OrderCreated::decode_log_data(&log, true).unwrap();
+        assert_eq!(log.data.len(), 4 * 32);
+        assert_eq!(decoded.direction, 1u8);
+        assert_eq!(decoded.sellRate, U256::from(100u32));
+        assert_eq!(decoded.startBlock, U256::from(7u32));
+        assert_eq!(decoded.endBlock, U256::from(507u32));
+    }
+
+    #[test]
+    fn test_virtual_orders_executed_log() {
+        let mut pool = OrderPool::default();
+        pool.create_long_term_order(
+            Address::repeat_byte(0x11),
+            OrderDirection::SellToken0,
+            U256::from(100000u32),
+            U256::from(1000u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+
+        let result = pool
+            .execute_virtual_orders(
+                U256::from(100u32),
+                U256::from(1000000u32),
+                U256::from(1000000u32),
+            )
+            .unwrap();
+
+        let log = events::virtual_orders_executed(U256::ZERO, U256::from(100u32), &result)
+            .encode_log_data();
+
+        assert_eq!(log.topics(), &[VirtualOrdersExecuted::SIGNATURE_HASH]);
+        let decoded = VirtualOrdersExecuted::decode_log_data(&log, true).unwrap();
+        assert_eq!(decoded.toBlock, U256::from(100u32));
+        assert_eq!(decoded.amount0Sold, U256::from(10000u32));
+        assert_eq!(decoded.newReserve0, result.new_reserve_0);
+    }
+
+    #[test]
+    fn test_fees_collected_log() {
+        let recipient = Address::repeat_byte(0x99);
+        let mut pool = OrderPool::default();
+        pool.set_fee_bps(U256::from(30u32)).unwrap();
+        pool.create_long_term_order(
+            Address::repeat_byte(0x11),
+            OrderDirection::SellToken0,
+            U256::from(1000000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+        pool.execute_virtual_orders(
+            U256::from(100u32),
+            U256::from(100000000u32),
+            U256::from(100000000u32),
+        )
+        .unwrap();
+
+        let (fees_0, fees_1) = pool.collect_fees(recipient, U256::from(120u32));
+        let log = events::fees_collected(recipient, fees_0, fees_1, U256::from(120u32))
+            .unwrap()
+            .encode_log_data();
+
+        assert_eq!(
+            log.topics(),
+            &[FeesCollected::SIGNATURE_HASH, recipient.into_word()]
+        );
+        let decoded = FeesCollected::decode_log_data(&log, true).unwrap();
+        assert_eq!(decoded.amount0, U256::from(3000u32));
+        assert_eq!(decoded.amount1, U256::ZERO);
+        assert_eq!(decoded.blockNumber, U256::from(120u32));
+
+        // A second collection has nothing to log
+        let (fees_0, fees_1) = pool.collect_fees(recipient, U256::from(121u32));
+        assert!(events::fees_collected(recipient, fees_0, fees_1, U256::from(121u32)).is_none());
+    }
+
+    #[test]
+    fn test_keeper_reward_paid_log() {
+        let keeper = Address::repeat_byte(0x22);
+        let reserve = U256::from(100000000u32);
+        let execute = |reward_bps: u32| {
+            let mut pool = OrderPool::default();
+            pool.set_keeper_reward(U256::from(reward_bps), U256::from(1u32))
+                .unwrap();
+            pool.create_long_term_order(
+                Address::repeat_byte(0x11),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+            pool.execute_virtual_orders(U256::from(40u32), reserve, reserve)
+                .unwrap()
+        };
+
+        let rewarded = execute(10);
+        let log = events::keeper_reward_paid(keeper, &rewarded, U256::from(40u32))
+            .unwrap()
+            .encode_log_data();
+
+        assert_eq!(
+            log.topics(),
+            &[KeeperRewardPaid::SIGNATURE_HASH, keeper.into_word()]
+        );
+        let decoded = KeeperRewardPaid::decode_log_data(&log, true).unwrap();
+        assert_eq!(decoded.amount0, U256::from(400u32));
+        assert_eq!(decoded.amount1, U256::ZERO);
+        assert_eq!(decoded.blockNumber, U256::from(40u32));
+
+        // Without a reward configured nothing is logged
+        assert!(events::keeper_reward_paid(keeper, &execute(0), U256::from(40u32)).is_none());
+    }
+
+    #[test]
+    fn test_instant_swap_log() {
+        let owner = Address::repeat_byte(0x11);
+        let log = events::instant_swap(
+            owner,
+            OrderDirection::SellToken1,
+            U256::from(1000u32),
+            U256::from(906u32),
+            U256::from(12u32),
+        )
+        .encode_log_data();
+
+        assert_eq!(
+            log.topics(),
+            &[InstantSwap::SIGNATURE_HASH, owner.into_word()]
+        );
+        let decoded = InstantSwap::decode_log_data(&log, true).unwrap();
+        assert_eq!(decoded.direction, 1u8);
+        assert_eq!(decoded.amountIn, U256::from(1000u32));
+        assert_eq!(decoded.amountOut, U256::from(906u32));
+        assert_eq!(decoded.blockNumber, U256::from(12u32));
+    }
+}
@@ -0,0 +1,213 @@
+// Fixed-point arithmetic tests
+// These tests verify signed and unsigned fixed-point helpers in twamm_math
+
+#[cfg(test)]
+mod fixed_point_tests {
+    use stylus_sdk::alloy_primitives::U256;
+    use twamm_calculator::twamm_math::{FixedPoint, MathError, SignedFixed, TWAMMath, PRECISION};
+
+    fn signed(value: u128, negative: bool) -> SignedFixed {
+        SignedFixed::new(U256::from(value * 10u128.pow(18)), negative, 18)
+    }
+
+    fn fixed(numerator: u128, denominator: u128) -> FixedPoint {
+        FixedPoint::new(U256::from(numerator * 10u128.pow(18) / denominator), 18)
+    }
+
+    #[test]
+    fn test_fixed_point_mul() {
+        let result = fixed(3, 2).mul(&fixed(2, 1)).unwrap();
+        assert_eq!(result, fixed(3, 1));
+        assert_eq!(result.to_u256(), U256::from(3u32));
+    }
+
+    #[test]
+    fn test_fixed_point_div_rounds_down() {
+        let result = fixed(1, 1).div(&fixed(3, 1)).unwrap();
+        assert_eq!(result.value, U256::from(333333333333333333u128));
+
+        let result = fixed(2, 1).div(&fixed(3, 1)).unwrap();
+        assert_eq!(result.value, U256::from(666666666666666666u128));
+
+        assert_eq!(
+            fixed(1, 1).div(&fixed(0, 1)),
+            Err(MathError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn test_fixed_point_add_sub() {
+        let sum = fixed(3, 2).add(&fixed(1, 2)).unwrap();
+        assert_eq!(sum, fixed(2, 1));
+
+        let difference = sum.sub(&fixed(1, 4)).unwrap();
+        assert_eq!(difference, fixed(7, 4));
+
+        assert_eq!(fixed(1, 2).sub(&fixed(1, 1)), Err(MathError::Overflow));
+    }
+
+    #[test]
+    fn test_fixed_point_precision_mismatch() {
+        let a = FixedPoint::new(U256::from(1000u32), 3);
+        let b = FixedPoint::try_from_u256(U256::from(1u32)).unwrap();
+        assert_eq!(a.add(&b), Err(MathError::InvalidInput));
+        assert_eq!(a.mul(&b), Err(MathError::InvalidInput));
+    }
+
+    #[test]
+    fn test_mixed_precision_rejected_by_every_operation() {
+        // 1.0 at six decimals next to 1.0 at the pool precision
+        let six = FixedPoint::new(U256::from(1000000u32), 6);
+        let pool = FixedPoint::try_from_u256(U256::from(1u32)).unwrap();
+        assert_eq!(pool.precision, PRECISION);
+
+        assert_eq!(six.add(&pool), Err(MathError::InvalidInput));
+        assert_eq!(pool.sub(&six), Err(MathError::InvalidInput));
+        assert_eq!(pool.mul(&six), Err(MathError::InvalidInput));
+        assert_eq!(six.div(&pool), Err(MathError::InvalidInput));
+
+        let signed_six = SignedFixed::from_fixed(&six);
+        let signed_pool = SignedFixed::from_fixed(&pool);
+        assert_eq!(signed_six.add(&signed_pool), Err(MathError::InvalidInput));
+        assert_eq!(signed_pool.sub(&signed_six), Err(MathError::InvalidInput));
+        assert_eq!(signed_pool.mul(&signed_six), Err(MathError::InvalidInput));
+        assert_eq!(signed_six.div(&signed_pool), Err(MathError::InvalidInput));
+    }
+
+    #[test]
+    fn test_from_u256_overflow() {
+        let near_max = U256::MAX - U256::from(1u32);
+        assert_eq!(
+            FixedPoint::try_from_u256(near_max),
+            Err(MathError::Overflow)
+        );
+
+        let clamped = FixedPoint::saturating_from_u256(near_max);
+        assert_eq!(clamped.value, U256::MAX);
+        assert_eq!(clamped.precision, 18);
+
+        let scaled = FixedPoint::try_from_u256(U256::from(42u32)).unwrap();
+        assert_eq!(scaled.to_u256(), U256::from(42u32));
+    }
+
+    #[test]
+    fn test_signed_mul_sign_propagation() {
+        let pos = signed(2, false);
+        let neg = signed(3, true);
+
+        let result = pos.mul(&neg).unwrap();
+        assert_eq!(result, signed(6, true));
+
+        let result = neg.mul(&neg).unwrap();
+        assert_eq!(result, signed(9, false));
+
+        let result = neg.div(&pos).unwrap();
+        assert!(result.negative);
+        assert_eq!(result.magnitude, U256::from(15u128 * 10u128.pow(17)));
+    }
+
+    #[test]
+    fn test_signed_sub_crossing_zero() {
+        let two = signed(2, false);
+        let five = signed(5, false);
+
+        let result = two.sub(&five).unwrap();
+        assert_eq!(result, signed(3, true));
+
+        let result = result.add(&five).unwrap();
+        assert_eq!(result, two);
+    }
+
+    #[test]
+    fn test_signed_negative_zero_normalized() {
+        let three = signed(3, true);
+        let zero = three.sub(&three).unwrap();
+        assert!(zero.is_zero());
+        assert!(!zero.negative);
+
+        let neg_zero = SignedFixed::new(U256::ZERO, true, 18);
+        assert_eq!(neg_zero, SignedFixed::new(U256::ZERO, false, 18));
+        assert_eq!(neg_zero.negate(), neg_zero);
+    }
+
+    #[test]
+    fn test_signed_errors() {
+        let a = signed(1, false);
+        let b = SignedFixed::new(U256::from(1u32), false, 6);
+        assert_eq!(a.add(&b), Err(MathError::InvalidInput));
+        assert_eq!(a.div(&signed(0, true)), Err(MathError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_to_u256_checked_and_rounding() {
+        let six_tenths = fixed(6, 10);
+        assert_eq!(six_tenths.to_u256(), U256::ZERO);
+        assert_eq!(six_tenths.to_u256_checked(), None);
+        assert_eq!(six_tenths.round_to_u256(), U256::from(1u32));
+
+        // Below a half rounds down, a half rounds up
+        assert_eq!(fixed(4, 10).round_to_u256(), U256::ZERO);
+        assert_eq!(fixed(5, 2).round_to_u256(), U256::from(3u32));
+
+        let whole = FixedPoint::try_from_u256(U256::from(7u32)).unwrap();
+        assert_eq!(whole.to_u256_checked(), Some(U256::from(7u32)));
+        assert_eq!(whole.round_to_u256(), U256::from(7u32));
+
+        let max = FixedPoint::new(U256::MAX, PRECISION);
+        assert_eq!(max.to_u256_checked(), None);
+        assert_eq!(
+            max.round_to_u256(),
+            U256::MAX / U256::from(10u128.pow(PRECISION)) + U256::from(1u32)
+        );
+    }
+
+    #[test]
+    fn test_fp_variants_carry_precision() {
+        for precision in [6u32, 18] {
+            let one = U256::from(10u128.pow(precision));
+
+            let impact = TWAMMath::calculate_price_impact_fp(
+                U256::from(1000u32),
+                U256::from(100000u32),
+                U256::from(100000u32),
+                precision,
+            )
+            .unwrap();
+            assert_eq!(impact.precision, precision);
+            assert_eq!(
+                impact.value,
+                TWAMMath::calculate_price_impact(
+                    U256::from(1000u32),
+                    U256::from(100000u32),
+                    U256::from(100000u32),
+                    precision
+                )
+                .unwrap()
+            );
+            // About a 1% impact, so the whole part round-trips to 0
+            assert_eq!(impact.to_u256(), U256::ZERO);
+
+            // 2^10 = 1024 as a whole number at either precision
+            let power =
+                TWAMMath::power_fp(U256::from(2u32) * one, U256::from(10u32), precision).unwrap();
+            assert_eq!(power.precision, precision);
+            assert_eq!(power.to_u256(), U256::from(1024u32));
+            assert_eq!(power.to_u256_checked(), Some(U256::from(1024u32)));
+
+            let e = TWAMMath::exp_taylor_fp(one, precision).unwrap();
+            assert_eq!(e.precision, precision);
+            assert_eq!(e.to_u256(), U256::from(2u32));
+
+            let ln = TWAMMath::ln_newton_fp(one, precision).unwrap();
+            assert_eq!(ln.precision, precision);
+            assert_eq!(ln.to_u256(), U256::ZERO);
+        }
+
+        // Results at one precision combine without guessing scales
+        let a = TWAMMath::exp_taylor_fp(U256::ZERO, 18).unwrap();
+        let b = TWAMMath::power_fp(U256::from(10u128.pow(18)), U256::from(3u32), 18).unwrap();
+        assert_eq!(a.add(&b).unwrap().to_u256(), U256::from(2u32));
+        let six = TWAMMath::exp_taylor_fp(U256::ZERO, 6).unwrap();
+        assert_eq!(a.add(&six), Err(MathError::InvalidInput));
+    }
+}
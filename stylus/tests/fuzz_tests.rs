@@ -0,0 +1,156 @@
+// Randomized invariant tests
+// These tests drive the order pool through seeded sequences of create, cancel and
+// execute operations, checking pool invariants and token conservation after each
+
+#[cfg(test)]
+mod fuzz_tests {
+    use stylus_sdk::alloy_primitives::{Address, U256};
+    use twamm_calculator::order_execution::{OrderDirection, OrderError, OrderPool};
+
+    const SEEDS: [u64; 5] = [1, 42, 1337, 0xdead_beef, 0x1234_5678_9abc_def0];
+    const OPERATIONS: usize = 1000;
+    const INITIAL_RESERVE: u64 = 1_000_000_000_000;
+
+    /// Deterministic linear congruential generator so failures are reproducible from the seed
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            self.0 >> 33
+        }
+
+        fn range(&mut self, low: u64, high: u64) -> u64 {
+            low + self.next() % (high - low + 1)
+        }
+    }
+
+    /// Sum of accumulated_out over the given order ids, split by the token paid out
+    /// Orders that left the pool during execution are found in the history
+    fn proceeds_for(pool: &OrderPool, ids: &[U256]) -> (U256, U256) {
+        let mut token_0 = U256::ZERO;
+        let mut token_1 = U256::ZERO;
+
+        for id in ids {
+            let order = pool.get_order(*id).expect("order vanished");
+            match order.direction {
+                OrderDirection::SellToken0 => token_1 += order.accumulated_out,
+                OrderDirection::SellToken1 => token_0 += order.accumulated_out,
+            }
+        }
+
+        (token_0, token_1)
+    }
+
+    fn run(seed: u64) {
+        let mut rng = Rng(seed);
+        let mut pool = OrderPool {
+            fee_bps: U256::from(rng.range(0, 1) * 30),
+            ..OrderPool::default()
+        };
+        let owners = [
+            Address::repeat_byte(0x11),
+            Address::repeat_byte(0x22),
+            Address::repeat_byte(0x33),
+        ];
+        let mut reserve_0 = U256::from(INITIAL_RESERVE);
+        let mut reserve_1 = U256::from(INITIAL_RESERVE);
+        let mut block = 0u64;
+
+        for step in 0..OPERATIONS {
+            match rng.range(0, 9) {
+                // Create: up to 0.1% of the reserve over 10 to 500 blocks
+                0..=3 => {
+                    let owner = owners[rng.range(0, 2) as usize];
+                    let direction = if rng.range(0, 1) == 0 {
+                        OrderDirection::SellToken0
+                    } else {
+                        OrderDirection::SellToken1
+                    };
+                    let amount = U256::from(rng.range(1_000, INITIAL_RESERVE / 1000));
+                    let duration = U256::from(rng.range(10, 500));
+
+                    // Hitting the per-owner limit is expected; anything else is a bug
+                    if let Err(error) = pool.create_long_term_order(
+                        owner,
+                        direction,
+                        amount,
+                        duration,
+                        U256::from(block),
+                        U256::ZERO,
+                    ) {
+                        assert_eq!(
+                            error,
+                            OrderError::LimitReached("Order limit reached"),
+                            "seed {seed}"
+                        );
+                    }
+                }
+                // Cancel a random open order as its owner
+                4..=5 => {
+                    if !pool.orders.is_empty() {
+                        let index = rng.range(0, pool.orders.len() as u64 - 1) as usize;
+                        let order = pool.orders[index];
+                        pool.cancel_order(order.id, order.owner, U256::from(block))
+                            .unwrap_or_else(|_| panic!("seed {seed} step {step}: cancel failed"));
+                    }
+                }
+                // Execute after advancing up to 200 blocks
+                _ => {
+                    block += rng.range(0, 200);
+                    let ids: Vec<U256> = pool.orders.iter().map(|order| order.id).collect();
+                    let (before_0, before_1) = proceeds_for(&pool, &ids);
+
+                    let result = pool
+                        .execute_virtual_orders(U256::from(block), reserve_0, reserve_1)
+                        .unwrap_or_else(|e| panic!("seed {seed} step {step}: {}", e.message()));
+                    let (after_0, after_1) = proceeds_for(&pool, &ids);
+
+                    // Orders are paid no more than the curve released, losing at most
+                    // one unit of rounding per order and segment
+                    let dust =
+                        U256::from(ids.len() as u64 * (result.blocks_executed.to::<u64>() + 1));
+                    for (paid, released) in [
+                        (after_0 - before_0, result.amount_0_received),
+                        (after_1 - before_1, result.amount_1_received),
+                    ] {
+                        assert!(paid <= released, "seed {seed} step {step}: overpaid");
+                        assert!(
+                            released - paid <= dust,
+                            "seed {seed} step {step}: underpaid"
+                        );
+                    }
+
+                    // The pool never holds more than it started with plus what was sold in
+                    assert!(
+                        result.new_reserve_0 + result.amount_0_received
+                            <= reserve_0 + result.amount_0_sold,
+                        "seed {seed} step {step}: token0 created"
+                    );
+                    assert!(
+                        result.new_reserve_1 + result.amount_1_received
+                            <= reserve_1 + result.amount_1_sold,
+                        "seed {seed} step {step}: token1 created"
+                    );
+
+                    reserve_0 = result.new_reserve_0;
+                    reserve_1 = result.new_reserve_1;
+                }
+            }
+
+            if let Err(error) = pool.check_invariants(U256::from(block)) {
+                panic!("seed {seed} step {step}: {}", error.message());
+            }
+        }
+    }
+
+    #[test]
+    fn test_random_operations_keep_invariants() {
+        for seed in SEEDS {
+            run(seed);
+        }
+    }
+}
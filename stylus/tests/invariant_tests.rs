@@ -0,0 +1,144 @@
+// Execution invariant tests
+// These tests verify check_curve_invariant and, with the invariant-checks
+// feature, that virtual execution satisfies it on every segment
+
+#[cfg(test)]
+mod invariant_tests {
+    use stylus_sdk::alloy_primitives::U256;
+    use twamm_calculator::order_execution::{check_curve_invariant, OrderError};
+
+    #[test]
+    fn test_exact_swap_passes() {
+        // Selling 100 into (1000, 1000) leaves floor(1,000,000 / 1100) = 909 of token1
+        let result = check_curve_invariant(
+            U256::from(1000u32),
+            U256::from(1000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+            U256::from(91u32),
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_overpaid_swap_trips_invariant() {
+        let result = check_curve_invariant(
+            U256::from(1000u32),
+            U256::from(1000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+            U256::from(92u32),
+        );
+        assert_eq!(result, Err(OrderError::InvalidState("invariant violated")));
+
+        // Paying out more than the pool plus inflow holds
+        let result = check_curve_invariant(
+            U256::from(1000u32),
+            U256::from(1000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+            U256::from(1001u32),
+        );
+        assert_eq!(result, Err(OrderError::InvalidState("invariant violated")));
+    }
+
+    #[test]
+    fn test_token1_sale_checks_token0_payout() {
+        // Selling 100 token1 into (1000, 1000) leaves floor(1,000,000 / 1100) = 909 of token0
+        let exact = check_curve_invariant(
+            U256::from(1000u32),
+            U256::from(1000u32),
+            U256::ZERO,
+            U256::from(100u32),
+            U256::from(91u32),
+            U256::ZERO,
+        );
+        assert!(exact.is_ok());
+
+        let overpaid = check_curve_invariant(
+            U256::from(1000u32),
+            U256::from(1000u32),
+            U256::ZERO,
+            U256::from(100u32),
+            U256::from(92u32),
+            U256::ZERO,
+        );
+        assert_eq!(
+            overpaid,
+            Err(OrderError::InvalidState("invariant violated"))
+        );
+    }
+
+    #[test]
+    fn test_bidirectional_rounding_tolerated() {
+        // Both closed form reserves land one unit below the exact curve
+        let result = check_curve_invariant(
+            U256::from(997100100319u64),
+            U256::from(1002908333381u64),
+            U256::from(117355704u32),
+            U256::from(196275072u32),
+            U256::from(195132296u32),
+            U256::from(118039320u32),
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_bidirectional_netting_passes() {
+        // 100 of each side nets out at a 1:1 price and leaves k unchanged
+        let result = check_curve_invariant(
+            U256::from(1000u32),
+            U256::from(1000u32),
+            U256::from(100u32),
+            U256::from(100u32),
+            U256::from(100u32),
+            U256::from(100u32),
+        );
+        assert!(result.is_ok());
+    }
+
+    #[cfg(feature = "invariant-checks")]
+    #[test]
+    fn test_virtual_execution_satisfies_invariant() {
+        use stylus_sdk::alloy_primitives::Address;
+        use twamm_calculator::order_execution::{OrderDirection, OrderPool};
+
+        for (amount_0, amount_1) in [
+            (1000000u32, 0u32),
+            (0, 1000000),
+            (1000000, 1000000),
+            (5000000, 1000000),
+            (1000000, 5000000),
+        ] {
+            let mut pool = OrderPool::default();
+            pool.set_fee_bps(U256::from(30u32)).unwrap();
+
+            for (direction, amount, duration) in [
+                (OrderDirection::SellToken0, amount_0, 100u32),
+                (OrderDirection::SellToken1, amount_1, 60),
+            ] {
+                if amount > 0 {
+                    pool.create_long_term_order(
+                        Address::repeat_byte(0x11),
+                        direction,
+                        U256::from(amount),
+                        U256::from(duration),
+                        U256::ZERO,
+                        U256::ZERO,
+                    )
+                    .unwrap();
+                }
+            }
+
+            let result = pool.execute_virtual_orders(
+                U256::from(100u32),
+                U256::from(100000000u32),
+                U256::from(50000000u32),
+            );
+            assert!(result.is_ok(), "amounts ({}, {})", amount_0, amount_1);
+        }
+    }
+}
@@ -2,4 +2,16 @@
 // This file organizes the essential tests
 
 mod basic_tests;
+mod bidirectional;
+mod entrypoint_tests;
+mod events_tests;
+mod fixed_point_tests;
+mod fuzz_tests;
+mod invariant_tests;
+mod order_manager_tests;
+mod order_pool_tests;
+mod pool_registry_tests;
+mod reference_simulation;
 mod simple_tests;
+mod snapshot_tests;
+mod twamm_math_tests;
@@ -0,0 +1,607 @@
+// Order manager tests
+// These tests verify the OrderManager utilities in order_execution
+
+#[cfg(test)]
+mod order_manager_tests {
+    use stylus_sdk::alloy_primitives::{Address, B256, U256};
+    use twamm_calculator::order_execution::{
+        OrderDirection, OrderError, OrderManager, OrderPool, MAX_ORDER_BLOCK_INTERVAL,
+        MIN_ORDER_BLOCK_INTERVAL, MIN_ORDER_DURATION, REFERENCE_GAS_PRICE,
+    };
+    use twamm_calculator::twamm_math::{TWAMMath, PRECISION};
+
+    #[test]
+    fn test_interval_matches_frequency_at_reference_gas() {
+        // 6000 blocks per day / 60 executions
+        let interval = OrderManager::calculate_optimal_interval(
+            U256::from(REFERENCE_GAS_PRICE),
+            U256::from(60u32),
+        );
+        assert_eq!(interval, U256::from(100u32));
+    }
+
+    #[test]
+    fn test_interval_increases_with_gas_price() {
+        let frequency = U256::from(60u32);
+        let mut previous = U256::ZERO;
+
+        for gas_price in [
+            1_000_000u64,
+            10_000_000,
+            50_000_000,
+            100_000_000,
+            400_000_000,
+            1_000_000_000,
+            10_000_000_000,
+        ] {
+            let interval =
+                OrderManager::calculate_optimal_interval(U256::from(gas_price), frequency);
+            assert!(interval >= previous);
+            previous = interval;
+        }
+
+        // Quadrupling gas doubles the interval
+        let interval =
+            OrderManager::calculate_optimal_interval(U256::from(400_000_000u64), frequency);
+        assert_eq!(interval, U256::from(200u32));
+    }
+
+    #[test]
+    fn test_interval_clamped() {
+        let frequency = U256::from(60u32);
+
+        let low = OrderManager::calculate_optimal_interval(U256::ZERO, frequency);
+        assert_eq!(low, U256::from(MIN_ORDER_BLOCK_INTERVAL));
+
+        let high = OrderManager::calculate_optimal_interval(U256::MAX, frequency);
+        assert_eq!(high, U256::from(MAX_ORDER_BLOCK_INTERVAL));
+    }
+
+    #[test]
+    fn test_zero_frequency_uses_fallback() {
+        let interval =
+            OrderManager::calculate_optimal_interval(U256::from(REFERENCE_GAS_PRICE), U256::ZERO);
+        assert_eq!(interval, U256::from(100u32));
+    }
+
+    #[test]
+    fn test_duration_for_target_impact_meets_target() {
+        let sell_amount = U256::from(1000000u32);
+        let reserve = U256::from(100000000u32);
+
+        for target in [100u32, 150, 500] {
+            let duration = OrderManager::duration_for_target_impact(
+                sell_amount,
+                reserve,
+                reserve,
+                U256::from(target),
+                18,
+            )
+            .unwrap();
+            assert!(duration >= U256::from(MIN_ORDER_DURATION));
+
+            let impact =
+                OrderManager::calculate_twap_impact(sell_amount, duration, reserve, reserve)
+                    .unwrap();
+            assert!(impact <= U256::from(target));
+        }
+    }
+
+    #[test]
+    fn test_duration_for_unreachable_impact_errors() {
+        // Selling 1% of the pool costs about 99 bps however slowly it streams
+        let result = OrderManager::duration_for_target_impact(
+            U256::from(1000000u32),
+            U256::from(100000000u32),
+            U256::from(100000000u32),
+            U256::from(10u32),
+            18,
+        );
+        assert_eq!(
+            result,
+            Err(OrderError::InvalidParams("Target impact unreachable"))
+        );
+    }
+
+    #[test]
+    fn test_duration_for_target_impact_rejects_other_precision() {
+        let result = OrderManager::duration_for_target_impact(
+            U256::from(1000000u32),
+            U256::from(100000000u32),
+            U256::from(100000000u32),
+            U256::from(100u32),
+            6,
+        );
+        assert_eq!(
+            result,
+            Err(OrderError::InvalidParams("Unsupported precision"))
+        );
+    }
+
+    #[test]
+    fn test_blocks_for_seconds_two_second_blocks() {
+        let block_time = U256::from(2u32);
+
+        // One hour is exactly 1800 blocks
+        assert_eq!(
+            OrderManager::blocks_for_seconds(U256::from(3600u32), block_time),
+            Ok(U256::from(1800u32))
+        );
+        // A partial block rounds up
+        assert_eq!(
+            OrderManager::blocks_for_seconds(U256::from(3601u32), block_time),
+            Ok(U256::from(1801u32))
+        );
+        // Short durations still get the minimum
+        assert_eq!(
+            OrderManager::blocks_for_seconds(U256::from(5u32), block_time),
+            Ok(U256::from(MIN_ORDER_DURATION))
+        );
+    }
+
+    #[test]
+    fn test_blocks_for_seconds_twelve_second_blocks() {
+        let block_time = U256::from(12u32);
+
+        assert_eq!(
+            OrderManager::blocks_for_seconds(U256::from(3600u32), block_time),
+            Ok(U256::from(300u32))
+        );
+        assert_eq!(
+            OrderManager::blocks_for_seconds(U256::from(3610u32), block_time),
+            Ok(U256::from(301u32))
+        );
+        // 100 seconds would be 9 blocks, below the minimum
+        assert_eq!(
+            OrderManager::blocks_for_seconds(U256::from(100u32), block_time),
+            Ok(U256::from(MIN_ORDER_DURATION))
+        );
+
+        assert!(OrderManager::blocks_for_seconds(U256::ZERO, block_time).is_err());
+        assert!(OrderManager::blocks_for_seconds(U256::from(3600u32), U256::ZERO).is_err());
+    }
+
+    #[test]
+    fn test_predict_net_flow_x_dominant() {
+        // At a 1:2 price, 1000 X is worth 2000 Y against 500 Y sold: 750 X net
+        let (direction, net_amount) = OrderManager::predict_net_flow(
+            U256::from(1000000u32),
+            U256::from(2000000u32),
+            U256::from(10u32),
+            U256::from(5u32),
+            U256::from(100u32),
+            18,
+        )
+        .unwrap();
+        assert_eq!(direction, OrderDirection::SellToken0);
+        assert_eq!(net_amount, U256::from(750u32));
+    }
+
+    #[test]
+    fn test_predict_net_flow_y_dominant() {
+        // 1000 X is worth 2000 Y against 3000 Y sold: 1000 Y net
+        let (direction, net_amount) = OrderManager::predict_net_flow(
+            U256::from(1000000u32),
+            U256::from(2000000u32),
+            U256::from(10u32),
+            U256::from(30u32),
+            U256::from(100u32),
+            18,
+        )
+        .unwrap();
+        assert_eq!(direction, OrderDirection::SellToken1);
+        assert_eq!(net_amount, U256::from(1000u32));
+    }
+
+    #[test]
+    fn test_predict_net_flow_balanced() {
+        let (_, net_amount) = OrderManager::predict_net_flow(
+            U256::from(1000000u32),
+            U256::from(2000000u32),
+            U256::from(10u32),
+            U256::from(20u32),
+            U256::from(100u32),
+            18,
+        )
+        .unwrap();
+        assert_eq!(net_amount, U256::ZERO);
+
+        assert!(OrderManager::predict_net_flow(
+            U256::ZERO,
+            U256::from(2000000u32),
+            U256::from(10u32),
+            U256::from(20u32),
+            U256::from(100u32),
+            18,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_quote_order_degrades_with_size() {
+        let reserve = U256::from(1_000_000_000_000u64);
+        let duration = U256::from(100u32);
+        let mut previous: Option<(U256, U256, U256)> = None;
+
+        for sell_amount in [1_000_000u64, 1_000_000_000, 100_000_000_000] {
+            let sell_amount = U256::from(sell_amount);
+            let (expected_out, impact_bps) = OrderManager::quote_order(
+                OrderDirection::SellToken0,
+                sell_amount,
+                duration,
+                reserve,
+                reserve,
+                U256::ZERO,
+                18,
+            )
+            .unwrap();
+
+            assert!(expected_out <= sell_amount);
+            if let Some((previous_amount, previous_out, previous_impact)) = previous {
+                // Output per unit sold falls and impact rises as the order grows
+                assert!(expected_out * previous_amount < previous_out * sell_amount);
+                assert!(impact_bps > previous_impact);
+            }
+            previous = Some((sell_amount, expected_out, impact_bps));
+        }
+
+        // A 10% order with no opposing flow matches the standalone impact estimate
+        let (_, _, impact_bps) = previous.unwrap();
+        let twap_impact = OrderManager::calculate_twap_impact(
+            U256::from(100_000_000_000u64),
+            duration,
+            reserve,
+            reserve,
+        )
+        .unwrap();
+        assert_eq!(impact_bps, twap_impact);
+    }
+
+    #[test]
+    fn test_quote_order_opposing_flow() {
+        let reserve = U256::from(1_000_000_000_000u64);
+        let sell_amount = U256::from(100_000_000_000u64);
+        let duration = U256::from(100u32);
+
+        let quote = |direction, opposing_rate: u64| {
+            OrderManager::quote_order(
+                direction,
+                sell_amount,
+                duration,
+                reserve,
+                reserve,
+                U256::from(opposing_rate),
+                18,
+            )
+            .unwrap()
+        };
+
+        // Opposing flow absorbs part of the order, improving the quote
+        let (alone_out, alone_impact) = quote(OrderDirection::SellToken0, 0);
+        let (offset_out, offset_impact) = quote(OrderDirection::SellToken0, 500_000_000);
+        assert!(offset_out > alone_out);
+        assert!(offset_impact < alone_impact);
+
+        // The quote is symmetric in direction for symmetric reserves
+        assert_eq!(
+            quote(OrderDirection::SellToken1, 500_000_000),
+            (offset_out, offset_impact)
+        );
+
+        assert_eq!(
+            OrderManager::quote_order(
+                OrderDirection::SellToken0,
+                sell_amount,
+                duration,
+                U256::ZERO,
+                reserve,
+                U256::ZERO,
+                18,
+            ),
+            Err(OrderError::InvalidParams("Invalid reserves"))
+        );
+    }
+
+    #[test]
+    fn test_breakeven_block_large_order() {
+        // Selling half the reserve at once loses a third of its value to slippage
+        let reserve = U256::from(1_000_000_000u64);
+        let duration = U256::from(100u32);
+
+        let breakeven = OrderManager::breakeven_block(
+            U256::from(500_000_000u64),
+            duration,
+            reserve,
+            reserve,
+            18,
+        )
+        .unwrap();
+        assert_eq!(breakeven, U256::from(68u32));
+
+        // A 10% order breaks even later, but still before it ends
+        let breakeven = OrderManager::breakeven_block(
+            U256::from(100_000_000u64),
+            duration,
+            reserve,
+            reserve,
+            18,
+        )
+        .unwrap();
+        assert_eq!(breakeven, U256::from(92u32));
+    }
+
+    #[test]
+    fn test_breakeven_block_tiny_order() {
+        // An instant swap of a tiny order has no slippage to recover
+        let duration = U256::from(100u32);
+        let breakeven = OrderManager::breakeven_block(
+            U256::from(1000u32),
+            duration,
+            U256::from(1_000_000_000u64),
+            U256::from(1_000_000_000u64),
+            18,
+        )
+        .unwrap();
+        assert_eq!(breakeven, duration);
+
+        assert_eq!(
+            OrderManager::breakeven_block(
+                U256::from(1000u32),
+                duration,
+                U256::ZERO,
+                U256::from(1_000_000_000u64),
+                18,
+            ),
+            Err(OrderError::InvalidParams("Invalid reserves"))
+        );
+    }
+
+    #[test]
+    fn test_impact_functions_agree_in_bps() {
+        // A one-sided stream ends where a single swap would, so both measures match
+        let reserve = U256::from(1_000_000_000u64);
+        let sell_amount = U256::from(100_000_000u64);
+
+        let twap_impact =
+            OrderManager::calculate_twap_impact(sell_amount, U256::from(100u32), reserve, reserve)
+                .unwrap();
+        let swap_impact = TWAMMath::fixed_to_bps(
+            TWAMMath::calculate_price_impact(sell_amount, reserve, reserve, 18).unwrap(),
+            18,
+        )
+        .unwrap();
+
+        // 10% of the reserve costs 1/11 of the spot output, about 909 bps
+        assert_eq!(twap_impact, U256::from(909u32));
+        assert_eq!(swap_impact, twap_impact);
+    }
+
+    #[test]
+    fn test_order_price_summary_average_between_endpoints() {
+        let reserve_in = U256::from(1000000000u64);
+        let reserve_out = U256::from(2000000000u64);
+        let sell_amount = U256::from(100000000u64);
+        let duration = U256::from(100u32);
+
+        // Selling token0 pushes its price down
+        let (start, end, avg) = OrderManager::order_price_summary(
+            OrderDirection::SellToken0,
+            sell_amount,
+            duration,
+            reserve_in,
+            reserve_out,
+            PRECISION,
+        )
+        .unwrap();
+        assert_eq!(
+            start,
+            OrderPool::spot_price(reserve_in, reserve_out, PRECISION).unwrap()
+        );
+        assert!(end < avg && avg < start);
+
+        // Selling token1 pushes the token0 price up
+        let (start, end, avg) = OrderManager::order_price_summary(
+            OrderDirection::SellToken1,
+            sell_amount,
+            duration,
+            reserve_in,
+            reserve_out,
+            PRECISION,
+        )
+        .unwrap();
+        assert_eq!(
+            start,
+            OrderPool::spot_price(reserve_out, reserve_in, PRECISION).unwrap()
+        );
+        assert!(start < avg && avg < end);
+    }
+
+    #[test]
+    fn test_max_counterparty_obligation_bounds_streamed_output() {
+        let reserve_in = U256::from(1000000000u64);
+        let reserve_out = U256::from(3000000000u64);
+        let duration = U256::from(100u32);
+
+        for sell_amount in [1000u64, 1000000, 500000000] {
+            let sell_amount = U256::from(sell_amount);
+            let bound = OrderManager::max_counterparty_obligation(
+                sell_amount,
+                reserve_in,
+                reserve_out,
+                PRECISION,
+            )
+            .unwrap();
+            assert_eq!(bound, sell_amount * U256::from(3u32));
+
+            let mut pool = OrderPool::default();
+            let order_id = pool
+                .create_long_term_order(
+                    Address::from([1u8; 20]),
+                    OrderDirection::SellToken0,
+                    sell_amount,
+                    duration,
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap();
+            pool.execute_virtual_orders(duration, reserve_in, reserve_out)
+                .unwrap();
+
+            let streamed_out = pool.get_order(order_id).unwrap().accumulated_out;
+            assert!(streamed_out > U256::ZERO);
+            assert!(streamed_out <= bound);
+        }
+
+        // mul_div keeps the intermediate product from overflowing
+        assert_eq!(
+            OrderManager::max_counterparty_obligation(
+                U256::MAX,
+                U256::from(2u32),
+                U256::from(1u32),
+                PRECISION
+            ),
+            Ok(U256::MAX / U256::from(2u32))
+        );
+        assert!(OrderManager::max_counterparty_obligation(
+            U256::MAX,
+            U256::from(1u32),
+            U256::from(2u32),
+            PRECISION
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_predict_net_flow_extreme_price() {
+        // token0 is worth 10^-24 token1, below what a precision-18 price can represent
+        let (direction, net_amount) = OrderManager::predict_net_flow(
+            U256::from(10u128.pow(30)),
+            U256::from(10u32.pow(6)),
+            U256::from(10u128.pow(24)),
+            U256::from(1u32),
+            U256::from(100u32),
+            18,
+        )
+        .unwrap();
+
+        // 10^26 token0 is worth 100 token1 against 100 token1 sold: balanced
+        assert_eq!(net_amount, U256::ZERO);
+        assert_eq!(direction, OrderDirection::SellToken0);
+
+        let (direction, net_amount) = OrderManager::predict_net_flow(
+            U256::from(10u128.pow(30)),
+            U256::from(10u32.pow(6)),
+            U256::from(10u128.pow(24)),
+            U256::from(3u32),
+            U256::from(100u32),
+            18,
+        )
+        .unwrap();
+        assert_eq!(direction, OrderDirection::SellToken1);
+        assert_eq!(net_amount, U256::from(200u32));
+    }
+
+    #[test]
+    fn test_validate_batch_against_caps_sums_orders() {
+        // The per-block cap is 10 bps of each reserve: 1000 of 1,000,000
+        let reserve = U256::from(1000000u32);
+        let existing = U256::from(200u32);
+        let order = (OrderDirection::SellToken0, U256::from(500u32));
+
+        // Each order fits on top of the existing rate by itself
+        assert_eq!(
+            OrderManager::validate_batch_against_caps(
+                existing,
+                U256::ZERO,
+                vec![order],
+                reserve,
+                reserve
+            ),
+            Ok(())
+        );
+
+        // Together they reach 1200 and the batch is rejected
+        assert_eq!(
+            OrderManager::validate_batch_against_caps(
+                existing,
+                U256::ZERO,
+                vec![order, order],
+                reserve,
+                reserve
+            ),
+            Err(OrderError::LimitReached("Batch sell rate too high"))
+        );
+
+        // Opposite directions count against separate reserves
+        assert_eq!(
+            OrderManager::validate_batch_against_caps(
+                existing,
+                U256::ZERO,
+                vec![order, (OrderDirection::SellToken1, U256::from(500u32))],
+                reserve,
+                reserve
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            OrderManager::validate_batch_against_caps(
+                U256::ZERO,
+                U256::ZERO,
+                vec![order],
+                U256::ZERO,
+                reserve
+            ),
+            Err(OrderError::InvalidParams("Invalid reserves"))
+        );
+    }
+
+    #[test]
+    fn test_twap_impact_with_huge_amounts() {
+        // The shortfall times 10000 no longer fits in 256 bits, but the result does
+        let reserve = U256::from(1u32) << 250;
+        let sell_amount = U256::from(1u32) << 249;
+        let impact =
+            OrderManager::calculate_twap_impact(sell_amount, U256::from(1024u32), reserve, reserve)
+                .unwrap();
+
+        // Selling half the reserve returns two thirds of the spot amount
+        assert_eq!(impact, U256::from(3333u32));
+    }
+
+    #[test]
+    fn test_jittered_interval_is_deterministic_and_spread() {
+        let base = U256::from(100u32);
+        let max_jitter = U256::from(10u32);
+        let seed = B256::repeat_byte(0xab);
+
+        let interval = OrderManager::jittered_interval(base, seed, max_jitter).unwrap();
+        assert_eq!(
+            OrderManager::jittered_interval(base, seed, max_jitter),
+            Ok(interval)
+        );
+
+        // Consecutive seeds land all over [90, 110]
+        let mut seen = std::collections::BTreeSet::new();
+        for n in 0u64..200 {
+            let seed = B256::from(U256::from(n));
+            let interval = OrderManager::jittered_interval(base, seed, max_jitter).unwrap();
+            assert!(interval >= U256::from(90u32) && interval <= U256::from(110u32));
+            seen.insert(interval);
+        }
+        assert!(seen.len() >= 18);
+        assert!(seen.iter().any(|interval| *interval < base));
+        assert!(seen.iter().any(|interval| *interval > base));
+
+        // No jitter leaves the interval as is; jitter must stay below it
+        assert_eq!(
+            OrderManager::jittered_interval(base, seed, U256::ZERO),
+            Ok(base)
+        );
+        assert_eq!(
+            OrderManager::jittered_interval(base, seed, base),
+            Err(OrderError::InvalidParams(
+                "Jitter must be below the interval"
+            ))
+        );
+    }
+}
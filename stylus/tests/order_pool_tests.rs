@@ -0,0 +1,5190 @@
+// Order pool tests
+// These tests verify long-term order management in order_execution
+
+#[cfg(test)]
+mod order_pool_tests {
+    use stylus_sdk::alloy_primitives::{Address, U256};
+    use twamm_calculator::order_execution::{
+        LimitSide, LiquidityPolicy, Order, OrderDirection, OrderError, OrderIdScheme, OrderManager,
+        OrderPool, OrderStatus, OrderType, OrderView, RateRounding, MAX_ORDER_BLOCK_INTERVAL,
+        MAX_ORDER_HISTORY, VIRTUAL_EXECUTION_PER_BLOCK_GAS,
+    };
+    use twamm_calculator::twamm_math::{GasModel, TWAMMath, Unscaled};
+
+    fn owner() -> Address {
+        Address::repeat_byte(0x11)
+    }
+
+    #[test]
+    fn test_claim_proceeds_split_between_beneficiaries() {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(1000u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        let treasury = Address::repeat_byte(0x22);
+        let partner = Address::repeat_byte(0x33);
+        pool.set_order_beneficiaries(
+            order_id,
+            owner(),
+            vec![
+                (treasury, U256::from(7000u32)),
+                (partner, U256::from(3000u32)),
+            ],
+        )
+        .unwrap();
+
+        pool.execute_virtual_orders(
+            U256::from(100u32),
+            U256::from(1000000u32),
+            U256::from(1000000u32),
+        )
+        .unwrap();
+
+        let proceeds = pool.get_order(order_id).unwrap().accumulated_out;
+        assert!(proceeds > U256::ZERO);
+
+        let payouts = pool.claim_proceeds(order_id, owner()).unwrap();
+        assert_eq!(payouts.len(), 2);
+        assert_eq!(payouts[0].0, treasury);
+        assert_eq!(payouts[1].0, partner);
+        assert_eq!(
+            payouts[0].1,
+            proceeds * U256::from(7000u32) / U256::from(10000u32)
+        );
+        assert_eq!(payouts[0].1 + payouts[1].1, proceeds);
+        assert_eq!(
+            pool.get_order(order_id).unwrap().accumulated_out,
+            U256::ZERO
+        );
+    }
+
+    #[test]
+    fn test_beneficiary_weights_must_sum_to_total() {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(1000u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        let result = pool.set_order_beneficiaries(
+            order_id,
+            owner(),
+            vec![
+                (Address::repeat_byte(0x22), U256::from(7000u32)),
+                (Address::repeat_byte(0x33), U256::from(2000u32)),
+            ],
+        );
+        assert!(result.is_err());
+
+        let payouts = pool.claim_proceeds(order_id, owner()).unwrap();
+        assert_eq!(payouts, vec![(owner(), U256::ZERO)]);
+    }
+
+    #[test]
+    fn test_marginal_staleness_cost_is_per_block_gas() {
+        let mut pool = OrderPool::default();
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken1,
+            U256::from(50000u32),
+            U256::from(500u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+
+        for block in [0u32, 1, 250, 10000] {
+            assert_eq!(
+                pool.marginal_staleness_cost(U256::from(block)),
+                U256::from(VIRTUAL_EXECUTION_PER_BLOCK_GAS)
+            );
+        }
+    }
+
+    #[test]
+    fn test_realignment_trade_lands_on_target_price() {
+        let pool = OrderPool::default();
+        let one = U256::from(10u128.pow(18));
+        let reserve_0 = U256::from(1_000_000_000_000u64);
+        let reserve_1 = U256::from(1_000_000_000_000u64);
+        let k = reserve_0 * reserve_1;
+
+        // 1.21 token1 per token0 pushes the price up, 0.25 pushes it down
+        let targets = [
+            (
+                U256::from(121u32) * one / U256::from(100u32),
+                OrderDirection::SellToken1,
+            ),
+            (one / U256::from(4u32), OrderDirection::SellToken0),
+        ];
+
+        for (target, expected_direction) in targets {
+            let (direction, amount) = pool
+                .realignment_trade(target, reserve_0, reserve_1, 18)
+                .unwrap();
+            assert_eq!(direction, expected_direction);
+
+            let (new_reserve_0, new_reserve_1) = match direction {
+                OrderDirection::SellToken0 => (reserve_0 + amount, k / (reserve_0 + amount)),
+                OrderDirection::SellToken1 => (k / (reserve_1 + amount), reserve_1 + amount),
+            };
+
+            let price = new_reserve_1 * one / new_reserve_0;
+            let deviation = if price > target {
+                price - target
+            } else {
+                target - price
+            };
+            assert!(deviation * U256::from(10000u32) <= target);
+        }
+
+        let (_, amount) = pool
+            .realignment_trade(one, reserve_0, reserve_1, 18)
+            .unwrap();
+        assert_eq!(amount, U256::ZERO);
+    }
+
+    #[test]
+    fn test_max_orders_per_owner() {
+        let mut pool = OrderPool::default();
+        pool.set_max_orders_per_owner(3).unwrap();
+        assert!(pool.set_max_orders_per_owner(0).is_err());
+
+        for _ in 0..3 {
+            pool.create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        }
+
+        let result = pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken1,
+            U256::from(1000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        );
+        assert_eq!(result, Err(OrderError::LimitReached("Order limit reached")));
+
+        // Other owners are unaffected
+        let other = Address::repeat_byte(0x44);
+        assert!(pool
+            .create_long_term_order(
+                other,
+                OrderDirection::SellToken0,
+                U256::from(1000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_paused_pool_blocks_submission_but_allows_exit() {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(1000u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        pool.pause();
+
+        let result = pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(1000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        );
+        assert_eq!(result, Err(OrderError::Paused));
+
+        let result = pool.execute_virtual_orders(
+            U256::from(100u32),
+            U256::from(1000000u32),
+            U256::from(1000000u32),
+        );
+        assert_eq!(result.unwrap_err(), OrderError::Paused);
+
+        assert!(pool.claim_proceeds(order_id, owner()).is_ok());
+        let cancelled = pool.cancel_order(order_id, owner(), U256::ZERO).unwrap();
+        assert_eq!(cancelled.id, order_id);
+        assert_eq!(pool.get_active_orders_count(), 0);
+
+        pool.unpause();
+        assert!(pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_min_output_guard_halts_underfilled_order() {
+        let mut pool = OrderPool::default();
+        let sell_amount = U256::from(100000u32);
+        let guarded_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                sell_amount,
+                U256::from(1000u32),
+                U256::ZERO,
+                U256::from(90000u32),
+            )
+            .unwrap();
+        let unguarded_id = pool
+            .create_long_term_order(
+                Address::repeat_byte(0x44),
+                OrderDirection::SellToken0,
+                sell_amount,
+                U256::from(1000u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        pool.execute_virtual_orders(
+            U256::from(500u32),
+            U256::from(1000000u32),
+            U256::from(1000000u32),
+        )
+        .unwrap();
+
+        // Token1 crashes to a tenth of its previous depth before the orders finish
+        pool.execute_virtual_orders(
+            U256::from(1000u32),
+            U256::from(1000000u32),
+            U256::from(100000u32),
+        )
+        .unwrap();
+
+        let guarded = pool.get_order(guarded_id).unwrap();
+        assert_eq!(guarded.status, OrderStatus::Underfilled);
+        assert!(guarded.accumulated_out < guarded.min_output_total);
+        assert_eq!(
+            pool.get_order_status(unguarded_id),
+            Some(OrderStatus::Completed)
+        );
+        assert_eq!(pool.get_current_sell_rates(), (U256::ZERO, U256::ZERO));
+
+        // Halted orders stop streaming and can still be cancelled by the owner
+        pool.execute_virtual_orders(
+            U256::from(1100u32),
+            U256::from(1000000u32),
+            U256::from(1000000u32),
+        )
+        .unwrap();
+        assert_eq!(
+            pool.get_order(guarded_id).unwrap().accumulated_out,
+            guarded.accumulated_out
+        );
+        assert!(pool.cancel_order(guarded_id, owner(), U256::ZERO).is_ok());
+    }
+
+    #[test]
+    fn test_min_output_guard_halts_before_principal_is_sold() {
+        let mut pool = OrderPool::default();
+        let sell_amount = U256::from(100000u32);
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                sell_amount,
+                U256::from(1000u32),
+                U256::ZERO,
+                U256::from(90000u32),
+            )
+            .unwrap();
+
+        pool.execute_virtual_orders(
+            U256::from(500u32),
+            U256::from(1000000u32),
+            U256::from(1000000u32),
+        )
+        .unwrap();
+        assert_eq!(pool.get_order_status(order_id), Some(OrderStatus::Active));
+
+        // At the crashed price the remaining principal can no longer reach the minimum
+        pool.execute_virtual_orders(
+            U256::from(600u32),
+            U256::from(1000000u32),
+            U256::from(100000u32),
+        )
+        .unwrap();
+
+        let halted = pool.get_order(order_id).unwrap();
+        assert_eq!(halted.status, OrderStatus::Underfilled);
+        assert_eq!(halted.remaining_amount, U256::from(40000u32));
+        assert_eq!(pool.get_current_sell_rates(), (U256::ZERO, U256::ZERO));
+
+        // The unsold principal is kept through later rounds and refunded on cancel
+        pool.execute_virtual_orders(
+            U256::from(1000u32),
+            U256::from(1000000u32),
+            U256::from(1000000u32),
+        )
+        .unwrap();
+        let kept = pool.get_order(order_id).unwrap();
+        assert_eq!(kept.remaining_amount, halted.remaining_amount);
+        assert_eq!(kept.accumulated_out, halted.accumulated_out);
+        let (refund, proceeds) = pool
+            .cancel_order_with_refund(order_id, owner(), U256::from(1000u32))
+            .unwrap();
+        assert_eq!(refund, U256::from(40000u32));
+        assert_eq!(proceeds, halted.accumulated_out);
+    }
+
+    #[test]
+    fn test_get_orders_by_owner_paginated() {
+        let mut pool = OrderPool::default();
+        let other = Address::repeat_byte(0x44);
+        let mut owner_ids = Vec::new();
+
+        for i in 0..5 {
+            // Interleave another owner's orders to check filtering
+            pool.create_long_term_order(
+                other,
+                OrderDirection::SellToken1,
+                U256::from(1000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+            let id = pool
+                .create_long_term_order(
+                    owner(),
+                    OrderDirection::SellToken0,
+                    U256::from(1000u32 + i),
+                    U256::from(100u32),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap();
+            owner_ids.push(id);
+        }
+
+        assert_eq!(pool.count_orders_by_owner(owner()), 5);
+        assert_eq!(pool.count_orders_by_owner(Address::ZERO), 0);
+
+        let ids =
+            |orders: Vec<Order>| -> Vec<U256> { orders.iter().map(|order| order.id).collect() };
+
+        assert_eq!(
+            ids(pool.get_orders_by_owner_paginated(owner(), 0, 2)),
+            owner_ids[0..2].to_vec()
+        );
+        assert_eq!(
+            ids(pool.get_orders_by_owner_paginated(owner(), 2, 2)),
+            owner_ids[2..4].to_vec()
+        );
+        // Final partial page
+        assert_eq!(
+            ids(pool.get_orders_by_owner_paginated(owner(), 4, 2)),
+            owner_ids[4..5].to_vec()
+        );
+        assert_eq!(
+            ids(pool.get_orders_by_owner_paginated(owner(), 0, 100)),
+            owner_ids
+        );
+        assert!(pool.get_orders_by_owner_paginated(owner(), 5, 2).is_empty());
+        assert!(pool
+            .get_orders_by_owner_paginated(owner(), 50, 2)
+            .is_empty());
+        assert!(pool.get_orders_by_owner_paginated(owner(), 0, 0).is_empty());
+    }
+
+    #[test]
+    fn test_proceeds_split_against_window_rate_snapshot() {
+        let mut pool = OrderPool::default();
+        let mut order_ids = Vec::new();
+
+        for byte in [0x11u8, 0x22] {
+            let id = pool
+                .create_long_term_order(
+                    Address::repeat_byte(byte),
+                    OrderDirection::SellToken0,
+                    U256::from(100000u32),
+                    U256::from(1000u32),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap();
+            order_ids.push(id);
+        }
+
+        // Joins at the end of the window, so it must not dilute the first two
+        let late_id = pool
+            .create_long_term_order(
+                Address::repeat_byte(0x33),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(1000u32),
+                U256::from(100u32),
+                U256::ZERO,
+            )
+            .unwrap();
+
+        let result = pool
+            .execute_virtual_orders(
+                U256::from(100u32),
+                U256::from(1000000u32),
+                U256::from(1000000u32),
+            )
+            .unwrap();
+        assert!(result.amount_1_received > U256::ZERO);
+
+        let half = result.amount_1_received / U256::from(2u32);
+        for id in order_ids {
+            assert_eq!(pool.get_order(id).unwrap().accumulated_out, half);
+        }
+        assert_eq!(pool.get_order(late_id).unwrap().accumulated_out, U256::ZERO);
+    }
+
+    #[test]
+    fn test_partially_active_order_weighted_by_overlap() {
+        let mut pool = OrderPool::default();
+
+        // Window is blocks 0..100, the order only streams during the last 10
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(1000u32),
+                U256::from(90u32),
+                U256::ZERO,
+            )
+            .unwrap();
+
+        let result = pool
+            .execute_virtual_orders(
+                U256::from(100u32),
+                U256::from(100000000u32),
+                U256::from(100000000u32),
+            )
+            .unwrap();
+
+        // 10% of the order's 100-block volume of 1000 * 100
+        assert_eq!(result.amount_0_sold, U256::from(10000u32));
+        let order = pool.get_order(order_id).unwrap();
+        assert_eq!(order.remaining_amount, U256::from(990000u32));
+        assert_eq!(order.accumulated_out, result.amount_1_received);
+    }
+
+    #[test]
+    fn test_execution_split_at_order_expiry() {
+        let mut pool = OrderPool::default();
+        let reserve = U256::from(100000000u32);
+
+        // Token0 order expires halfway through the window, token1 order runs the whole window
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(5000000u32),
+            U256::from(50u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken1,
+            U256::from(2000000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+
+        let result = pool
+            .execute_virtual_orders(U256::from(100u32), reserve, reserve)
+            .unwrap();
+
+        // Both flows for the first 50 blocks, then only token1
+        let (mid_0, mid_1) = TWAMMath::calculate_virtual_amm_state(
+            Unscaled(reserve),
+            Unscaled(reserve),
+            Unscaled(U256::from(100000u32)),
+            Unscaled(U256::from(20000u32)),
+            U256::from(50u32),
+            18,
+        )
+        .unwrap();
+        let (expected_0, expected_1) = TWAMMath::calculate_virtual_amm_state(
+            Unscaled(mid_0),
+            Unscaled(mid_1),
+            Unscaled(U256::ZERO),
+            Unscaled(U256::from(20000u32)),
+            U256::from(50u32),
+            18,
+        )
+        .unwrap();
+        assert_eq!(result.new_reserve_0, expected_0);
+        assert_eq!(result.new_reserve_1, expected_1);
+        assert_eq!(result.amount_0_sold, U256::from(5000000u32));
+        assert_eq!(result.amount_1_sold, U256::from(2000000u32));
+
+        // Averaging the token0 rate over the whole window gives different reserves
+        let (single_0, single_1) = TWAMMath::calculate_virtual_amm_state(
+            Unscaled(reserve),
+            Unscaled(reserve),
+            Unscaled(U256::from(50000u32)),
+            Unscaled(U256::from(20000u32)),
+            U256::from(100u32),
+            18,
+        )
+        .unwrap();
+        assert!(single_0 != expected_0 || single_1 != expected_1);
+    }
+
+    #[test]
+    fn test_detailed_fills_sum_to_aggregate() {
+        let mut pool = OrderPool::default();
+        let mut order_ids = Vec::new();
+
+        for (byte, direction, amount, duration) in [
+            (0x11u8, OrderDirection::SellToken0, 300000u32, 100u32),
+            (0x22, OrderDirection::SellToken0, 700000, 50),
+            (0x33, OrderDirection::SellToken1, 500000, 100),
+        ] {
+            let id = pool
+                .create_long_term_order(
+                    Address::repeat_byte(byte),
+                    direction,
+                    U256::from(amount),
+                    U256::from(duration),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap();
+            order_ids.push(id);
+        }
+
+        let (result, fills) = pool
+            .execute_virtual_orders_detailed(
+                U256::from(100u32),
+                U256::from(100000000u32),
+                U256::from(100000000u32),
+            )
+            .unwrap();
+
+        assert_eq!(fills.len(), order_ids.len());
+        for id in &order_ids {
+            assert!(fills
+                .iter()
+                .any(|(fill_id, amount)| fill_id == id && *amount > U256::ZERO));
+        }
+
+        // Token0 sellers receive token1 and vice versa, up to per-order rounding dust
+        let filled_1: U256 = fills[..2].iter().map(|(_, amount)| *amount).sum();
+        let filled_0 = fills[2].1;
+        assert!(filled_1 <= result.amount_1_received);
+        assert!(result.amount_1_received - filled_1 < U256::from(fills.len()));
+        assert!(filled_0 <= result.amount_0_received);
+        assert!(result.amount_0_received - filled_0 < U256::from(fills.len()));
+    }
+
+    #[test]
+    fn test_fee_grows_k_each_round() {
+        let mut pool = OrderPool::default();
+        pool.set_fee_bps(U256::from(30u32)).unwrap();
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(2000000u32),
+            U256::from(200u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+
+        let mut reserve_0 = U256::from(100000000u32);
+        let mut reserve_1 = U256::from(100000000u32);
+
+        for block in [100u32, 200] {
+            let k_before = reserve_0 * reserve_1;
+            let result = pool
+                .execute_virtual_orders(U256::from(block), reserve_0, reserve_1)
+                .unwrap();
+            reserve_0 = result.new_reserve_0;
+            reserve_1 = result.new_reserve_1;
+            let k_after = reserve_0 * reserve_1;
+
+            // 0.3% of the 1,000,000 sold stays in the pool, so k grows by ~3000 / reserve_0
+            let growth_ppm = (k_after - k_before) * U256::from(1000000u32) / k_before;
+            let fee_0 = U256::from(3000u32);
+            let expected_ppm = fee_0 * U256::from(1000000u32) / reserve_0;
+            assert!(growth_ppm.abs_diff(expected_ppm) <= U256::from(1u32));
+        }
+
+        assert_eq!(pool.collected_fees_0, U256::from(6000u32));
+        assert_eq!(pool.collected_fees_1, U256::ZERO);
+    }
+
+    #[test]
+    fn test_collect_fees_resets_balances() {
+        let mut pool = OrderPool::default();
+        pool.set_fee_bps(U256::from(30u32)).unwrap();
+        for direction in [OrderDirection::SellToken0, OrderDirection::SellToken1] {
+            pool.create_long_term_order(
+                owner(),
+                direction,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        }
+
+        let reserve = U256::from(100000000u32);
+        pool.execute_virtual_orders(U256::from(100u32), reserve, reserve)
+            .unwrap();
+
+        let fees = pool.collect_fees(Address::repeat_byte(0x99), U256::from(100u32));
+        assert_eq!(fees, (U256::from(3000u32), U256::from(3000u32)));
+        assert_eq!(
+            pool.collect_fees(Address::repeat_byte(0x99), U256::from(100u32)),
+            (U256::ZERO, U256::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_fee_cannot_reach_denominator() {
+        let mut pool = OrderPool::default();
+        assert!(pool.set_fee_bps(U256::from(10000u32)).is_err());
+        assert!(pool.set_fee_bps(U256::from(9999u32)).is_ok());
+    }
+
+    #[test]
+    fn test_sweep_expired_removes_only_expired_orders() {
+        let mut pool = OrderPool::default();
+        let mut ids = Vec::new();
+
+        // (direction, amount, duration): rates are 1000, 2000, 3000 and 4000
+        for (direction, amount, duration) in [
+            (OrderDirection::SellToken0, 100000u32, 100u32),
+            (OrderDirection::SellToken0, 400000, 200),
+            (OrderDirection::SellToken1, 150000, 50),
+            (OrderDirection::SellToken1, 800000, 200),
+        ] {
+            let id = pool
+                .create_long_term_order(
+                    owner(),
+                    direction,
+                    U256::from(amount),
+                    U256::from(duration),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap();
+            ids.push(id);
+        }
+
+        // Nothing was executed, so the expired orders are cancelled with their principal
+        let removed = pool.sweep_expired(U256::from(100u32)).unwrap();
+        let removed_ids: Vec<U256> = removed.iter().map(|order| order.id).collect();
+        assert_eq!(removed_ids, vec![ids[0], ids[2]]);
+        assert_eq!(removed[0].remaining_amount, U256::from(100000u32));
+        assert_eq!(removed[1].remaining_amount, U256::from(150000u32));
+
+        assert_eq!(pool.get_order_status(ids[0]), Some(OrderStatus::Cancelled));
+        assert_eq!(pool.get_order_status(ids[1]), Some(OrderStatus::Active));
+        assert_eq!(pool.get_order_status(ids[2]), Some(OrderStatus::Cancelled));
+        assert_eq!(pool.get_order_status(ids[3]), Some(OrderStatus::Active));
+        assert_eq!(pool.total_sell_rate_0, U256::from(2000u32));
+        assert_eq!(pool.total_sell_rate_1, U256::from(4000u32));
+
+        // Nothing else has expired yet
+        assert!(pool.sweep_expired(U256::from(150u32)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sweep_expired_completes_only_settled_orders() {
+        let mut pool = OrderPool::default();
+        let reserve = U256::from(1000000u32);
+        let mut ids = Vec::new();
+
+        // The second order demands far more than the curve can pay
+        for min_output_total in [U256::ZERO, U256::from(1000000u32)] {
+            let id = pool
+                .create_long_term_order(
+                    owner(),
+                    OrderDirection::SellToken0,
+                    U256::from(100000u32),
+                    U256::from(100u32),
+                    U256::ZERO,
+                    min_output_total,
+                )
+                .unwrap();
+            ids.push(id);
+        }
+        for &id in &ids {
+            pool.settle_order(id, U256::from(100u32), reserve, reserve)
+                .unwrap();
+        }
+        let settled = pool.get_order(ids[0]).unwrap();
+        assert_eq!(settled.remaining_amount, U256::ZERO);
+
+        let removed = pool.sweep_expired(U256::from(100u32)).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].id, ids[0]);
+        assert_eq!(removed[0].status, OrderStatus::Completed);
+        assert_eq!(removed[0].accumulated_out, settled.accumulated_out);
+
+        // The underfilled order is halted with its proceeds rather than completed
+        let halted = pool.get_order(ids[1]).unwrap();
+        assert_eq!(halted.status, OrderStatus::Underfilled);
+        assert!(halted.accumulated_out > U256::ZERO);
+        assert_eq!(pool.get_current_sell_rates(), (U256::ZERO, U256::ZERO));
+
+        // The completed order's proceeds are still claimable
+        let payouts = pool.claim_proceeds(ids[0], owner()).unwrap();
+        assert_eq!(payouts, vec![(owner(), settled.accumulated_out)]);
+    }
+
+    #[test]
+    fn test_spot_price_moves_with_one_sided_stream() {
+        let mut pool = OrderPool::default();
+        let one = U256::from(10u128.pow(18));
+        let reserve = U256::from(100000000u32);
+
+        assert_eq!(OrderPool::spot_price(reserve, reserve, 18).unwrap(), one);
+        assert!(OrderPool::spot_price(U256::ZERO, reserve, 18).is_err());
+
+        // Sell 25% of reserve_0 over 100 blocks
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(25000000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+
+        let marginal = pool
+            .marginal_price_after_execution(U256::from(100u32), reserve, reserve, 18)
+            .unwrap();
+        assert_eq!(
+            pool.virtual_order_state.last_virtual_order_block,
+            U256::ZERO
+        );
+
+        let result = pool
+            .execute_virtual_orders(U256::from(100u32), reserve, reserve)
+            .unwrap();
+        let after = OrderPool::spot_price(result.new_reserve_0, result.new_reserve_1, 18).unwrap();
+        assert_eq!(marginal, after);
+
+        // 100M * 80M / 125M^2 = 0.64
+        assert_eq!(after, U256::from(64u32) * one / U256::from(100u32));
+    }
+
+    #[test]
+    fn test_cancelled_order_reports_cancelled() {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        assert_eq!(pool.get_order_status(order_id), Some(OrderStatus::Active));
+
+        pool.cancel_order(order_id, owner(), U256::ZERO).unwrap();
+
+        assert_eq!(
+            pool.get_order_status(order_id),
+            Some(OrderStatus::Cancelled)
+        );
+        assert!(pool.orders.is_empty());
+        assert!(pool.cancel_order(order_id, owner(), U256::ZERO).is_err());
+        assert_eq!(pool.get_order_status(U256::from(999u32)), None);
+    }
+
+    #[test]
+    fn test_fully_streamed_order_reports_completed() {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken1,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        pool.execute_virtual_orders(
+            U256::from(100u32),
+            U256::from(10000000u32),
+            U256::from(10000000u32),
+        )
+        .unwrap();
+
+        let order = pool.get_order(order_id).unwrap();
+        assert_eq!(order.status, OrderStatus::Completed);
+        assert_eq!(order.remaining_amount, U256::ZERO);
+        assert!(order.accumulated_out > U256::ZERO);
+    }
+
+    #[test]
+    fn test_order_history_is_capped() {
+        let mut pool = OrderPool::default();
+        pool.set_max_orders_per_owner(MAX_ORDER_HISTORY + 1)
+            .unwrap();
+        let mut ids = Vec::new();
+
+        for _ in 0..=MAX_ORDER_HISTORY {
+            let id = pool
+                .create_long_term_order(
+                    owner(),
+                    OrderDirection::SellToken0,
+                    U256::from(1000u32),
+                    U256::from(10u32),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap();
+            pool.cancel_order(id, owner(), U256::ZERO).unwrap();
+            ids.push(id);
+        }
+
+        assert_eq!(pool.history.len(), MAX_ORDER_HISTORY);
+        assert_eq!(pool.get_order_status(ids[0]), None);
+        assert_eq!(
+            pool.get_order_status(ids[MAX_ORDER_HISTORY]),
+            Some(OrderStatus::Cancelled)
+        );
+    }
+
+    #[test]
+    fn test_preview_matches_execution_without_mutating() {
+        let mut pool = OrderPool::default();
+        pool.set_fee_bps(U256::from(30u32)).unwrap();
+        for (direction, amount, duration) in [
+            (OrderDirection::SellToken0, 3000000u32, 100u32),
+            (OrderDirection::SellToken1, 1000000, 40),
+        ] {
+            pool.create_long_term_order(
+                owner(),
+                direction,
+                U256::from(amount),
+                U256::from(duration),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        }
+
+        let reserve_0 = U256::from(100000000u32);
+        let reserve_1 = U256::from(50000000u32);
+        let preview = pool
+            .preview_virtual_execution(U256::from(60u32), reserve_0, reserve_1)
+            .unwrap();
+
+        // Nothing moved
+        assert_eq!(
+            pool.virtual_order_state.last_virtual_order_block,
+            U256::ZERO
+        );
+        assert_eq!(pool.orders.len(), 2);
+        for order in &pool.orders {
+            assert_eq!(order.accumulated_out, U256::ZERO);
+            assert_eq!(order.last_virtual_order_block, U256::ZERO);
+        }
+        assert_eq!(pool.collected_fees_0, U256::ZERO);
+        assert_eq!(pool.collected_fees_1, U256::ZERO);
+
+        let result = pool
+            .execute_virtual_orders(U256::from(60u32), reserve_0, reserve_1)
+            .unwrap();
+        assert_eq!(preview, result);
+    }
+
+    #[test]
+    fn test_execution_at_same_block_is_noop() {
+        let mut pool = OrderPool::default();
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(100000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+
+        let reserve = U256::from(10000000u32);
+        pool.execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+
+        let result = pool
+            .execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(result.blocks_executed, U256::ZERO);
+        assert_eq!(result.amount_0_sold, U256::ZERO);
+        assert_eq!(result.new_reserve_0, reserve);
+        assert_eq!(result.new_reserve_1, reserve);
+    }
+
+    #[test]
+    fn test_execution_at_stale_block_errors() {
+        let mut pool = OrderPool::default();
+        let reserve = U256::from(10000000u32);
+        pool.execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+
+        assert_eq!(
+            pool.execute_virtual_orders(U256::from(49u32), reserve, reserve)
+                .unwrap_err(),
+            OrderError::InvalidState("block not advanced")
+        );
+        assert_eq!(
+            pool.virtual_order_state.last_virtual_order_block,
+            U256::from(50u32)
+        );
+    }
+
+    #[test]
+    fn test_batch_creates_orders_in_order() {
+        let mut pool = OrderPool::default();
+        let ids = pool
+            .create_long_term_orders_batch(
+                owner(),
+                vec![
+                    (
+                        OrderDirection::SellToken0,
+                        U256::from(1000u32),
+                        U256::from(10u32),
+                    ),
+                    (
+                        OrderDirection::SellToken0,
+                        U256::from(2000u32),
+                        U256::from(10u32),
+                    ),
+                    (
+                        OrderDirection::SellToken1,
+                        U256::from(3000u32),
+                        U256::from(10u32),
+                    ),
+                ],
+                U256::from(5u32),
+            )
+            .unwrap();
+
+        assert_eq!(
+            ids,
+            vec![U256::from(1u32), U256::from(2u32), U256::from(3u32)]
+        );
+        assert_eq!(pool.next_order_id, U256::from(4u32));
+        assert_eq!(pool.total_sell_rate_0, U256::from(300u32));
+        assert_eq!(pool.total_sell_rate_1, U256::from(300u32));
+        assert_eq!(
+            pool.get_order(ids[2]).unwrap().start_block,
+            U256::from(5u32)
+        );
+    }
+
+    #[test]
+    fn test_batch_with_bad_entry_inserts_nothing() {
+        let mut pool = OrderPool::default();
+        let result = pool.create_long_term_orders_batch(
+            owner(),
+            vec![
+                (
+                    OrderDirection::SellToken0,
+                    U256::from(1000u32),
+                    U256::from(10u32),
+                ),
+                (OrderDirection::SellToken1, U256::ZERO, U256::from(10u32)),
+                (
+                    OrderDirection::SellToken0,
+                    U256::from(2000u32),
+                    U256::from(10u32),
+                ),
+            ],
+            U256::ZERO,
+        );
+
+        assert_eq!(
+            result,
+            Err(OrderError::InvalidParams("Invalid order parameters"))
+        );
+        assert!(pool.orders.is_empty());
+        assert_eq!(pool.next_order_id, U256::from(1u32));
+        assert_eq!(pool.get_current_sell_rates(), (U256::ZERO, U256::ZERO));
+
+        // A batch that would push the owner past the order limit is rejected whole
+        pool.set_max_orders_per_owner(2).unwrap();
+        let result = pool.create_long_term_orders_batch(
+            owner(),
+            vec![
+                (
+                    OrderDirection::SellToken0,
+                    U256::from(1000u32),
+                    U256::from(10u32)
+                );
+                3
+            ],
+            U256::ZERO,
+        );
+        assert_eq!(result, Err(OrderError::LimitReached("Order limit reached")));
+        assert!(pool.orders.is_empty());
+    }
+
+    #[test]
+    fn test_order_progress() {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        let reserve = U256::from(100000000u32);
+
+        assert_eq!(
+            pool.order_progress(order_id, U256::ZERO),
+            Some((U256::ZERO, U256::from(100u32)))
+        );
+
+        pool.execute_virtual_orders(U256::from(25u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(
+            pool.order_progress(order_id, U256::from(25u32)),
+            Some((U256::from(2500u32), U256::from(75u32)))
+        );
+
+        pool.execute_virtual_orders(U256::from(100u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(
+            pool.order_progress(order_id, U256::from(120u32)),
+            Some((U256::from(10000u32), U256::ZERO))
+        );
+
+        assert_eq!(pool.order_progress(U256::from(999u32), U256::ZERO), None);
+    }
+
+    #[test]
+    fn test_initial_amount_fixed_across_executions() {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken1,
+                U256::from(500000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        let reserve = U256::from(100000000u32);
+        let mut previous_remaining = U256::from(500000u32);
+
+        for block in [10u32, 40, 70] {
+            pool.execute_virtual_orders(U256::from(block), reserve, reserve)
+                .unwrap();
+
+            let order = pool.get_order(order_id).unwrap();
+            assert_eq!(order.initial_amount, U256::from(500000u32));
+            assert!(order.remaining_amount < previous_remaining);
+            previous_remaining = order.remaining_amount;
+        }
+    }
+
+    #[test]
+    fn test_order_avg_price_two_rounds() {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        assert_eq!(pool.order_avg_price(order_id, U256::ZERO, 18), None);
+
+        let first = pool
+            .execute_virtual_orders(
+                U256::from(50u32),
+                U256::from(100000000u32),
+                U256::from(100000000u32),
+            )
+            .unwrap();
+        pool.execute_virtual_orders(U256::from(100u32), first.new_reserve_0, first.new_reserve_1)
+            .unwrap();
+
+        // Round 1: 1e8 - 1e16 / 100.5e6 = 497,513 out
+        // Round 2: 99,502,487 - 100.5e6 * 99,502,487 / 101e6 = 492,587 out
+        // Average: 990,100 / 1,000,000 sold
+        assert_eq!(
+            pool.order_avg_price(order_id, U256::from(100u32), 18),
+            Some(U256::from(990100000000000000u64))
+        );
+    }
+
+    #[test]
+    fn test_reserve_floor_throttles_large_order() {
+        let mut pool = OrderPool::default();
+        let reserve = U256::from(100000000u32);
+        let min_reserve = U256::from(80000000u32);
+        pool.set_min_reserve(min_reserve);
+
+        // Unthrottled, this would halve reserve_1 by block 100
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        // Liquidity is topped back up between executions; each can sell 25 blocks'
+        // worth before 1e16 / (1e8 + 1e6 * blocks) reaches the floor
+        for round in 1..=4u32 {
+            let result = pool
+                .execute_virtual_orders(U256::from(100u32), reserve, reserve)
+                .unwrap();
+
+            assert!(result.new_reserve_0 >= min_reserve);
+            assert!(result.new_reserve_1 >= min_reserve);
+            assert_eq!(result.blocks_executed, U256::from(25u32));
+            assert_eq!(
+                pool.virtual_order_state.last_virtual_order_block,
+                U256::from(25 * round)
+            );
+        }
+
+        let order = pool.get_order(order_id).unwrap();
+        assert_eq!(order.status, OrderStatus::Completed);
+        assert_eq!(order.remaining_amount, U256::ZERO);
+
+        // Without a top-up the floor holds execution in place
+        let mut pool = OrderPool::default();
+        pool.set_min_reserve(min_reserve);
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(100000000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+        let first = pool
+            .execute_virtual_orders(U256::from(100u32), reserve, reserve)
+            .unwrap();
+        let second = pool
+            .execute_virtual_orders(U256::from(100u32), first.new_reserve_0, first.new_reserve_1)
+            .unwrap();
+        assert_eq!(second.blocks_executed, U256::ZERO);
+        assert_eq!(second.new_reserve_1, first.new_reserve_1);
+    }
+
+    #[test]
+    fn test_price_limit_pauses_and_resumes_streaming() {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        pool.set_order_price_limit(
+            order_id,
+            owner(),
+            Some(U256::from(900000000000000000u64)),
+            LimitSide::Above,
+        )
+        .unwrap();
+        let reserve = U256::from(100000000u32);
+
+        // At 1:1 the limit holds and ten blocks stream
+        pool.execute_virtual_orders(U256::from(10u32), reserve, reserve)
+            .unwrap();
+        let before = pool.get_order(order_id).unwrap();
+        assert_eq!(before.remaining_amount, U256::from(900000u32));
+
+        // Price excursion to 0.8: nothing is sold and the reserves are untouched
+        let paused = pool
+            .execute_virtual_orders(U256::from(20u32), reserve, U256::from(80000000u32))
+            .unwrap();
+        assert_eq!(paused.amount_0_sold, U256::ZERO);
+        assert_eq!(paused.new_reserve_0, reserve);
+        assert_eq!(paused.new_reserve_1, U256::from(80000000u32));
+        let during = pool.get_order(order_id).unwrap();
+        assert_eq!(during.remaining_amount, before.remaining_amount);
+        assert_eq!(during.accumulated_out, before.accumulated_out);
+
+        // Recovery resumes streaming at the same rate
+        pool.execute_virtual_orders(U256::from(30u32), reserve, reserve)
+            .unwrap();
+        let after = pool.get_order(order_id).unwrap();
+        assert_eq!(after.remaining_amount, U256::from(800000u32));
+        assert!(after.accumulated_out > before.accumulated_out);
+    }
+
+    #[test]
+    fn test_price_limit_only_settable_by_owner() {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        let result = pool.set_order_price_limit(
+            order_id,
+            Address::repeat_byte(0x22),
+            Some(U256::from(1u32)),
+            LimitSide::Below,
+        );
+        assert_eq!(result, Err(OrderError::NotOwner));
+        assert_eq!(pool.get_order(order_id).unwrap().price_limit, None);
+    }
+
+    #[test]
+    fn test_is_order_executing_reports_each_pause_reason() {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        let reserve = U256::from(100000000u32);
+        let block = U256::from(50u32);
+
+        assert_eq!(
+            pool.is_order_executing(order_id, block, reserve, reserve),
+            Some(true)
+        );
+        assert_eq!(
+            pool.is_order_executing(U256::from(999u32), block, reserve, reserve),
+            None
+        );
+
+        // Expired
+        assert_eq!(
+            pool.is_order_executing(order_id, U256::from(100u32), reserve, reserve),
+            Some(false)
+        );
+
+        // Limit-blocked below 0.9, executing again once the price recovers
+        pool.set_order_price_limit(
+            order_id,
+            owner(),
+            Some(U256::from(900000000000000000u64)),
+            LimitSide::Above,
+        )
+        .unwrap();
+        assert_eq!(
+            pool.is_order_executing(order_id, block, reserve, U256::from(80000000u32)),
+            Some(false)
+        );
+        assert_eq!(
+            pool.is_order_executing(order_id, block, reserve, reserve),
+            Some(true)
+        );
+
+        // Floor-throttled: any sale would take reserve_1 below the floor
+        pool.set_min_reserve(reserve);
+        assert_eq!(
+            pool.is_order_executing(order_id, block, reserve, reserve),
+            Some(false)
+        );
+        pool.set_min_reserve(U256::ZERO);
+
+        // Cancelled orders stay known but never execute
+        pool.cancel_order(order_id, owner(), U256::ZERO).unwrap();
+        assert_eq!(
+            pool.is_order_executing(order_id, block, reserve, reserve),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn test_hashed_order_ids_distinct_across_pools() {
+        let create = |pool: &mut OrderPool, current_block: u32| {
+            pool.create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::from(current_block),
+                U256::ZERO,
+            )
+            .unwrap()
+        };
+
+        // Sequential ids collide when a fresh pool restarts the counter
+        let mut original = OrderPool::default();
+        let mut redeployed = OrderPool::default();
+        assert_eq!(create(&mut original, 10), create(&mut redeployed, 500));
+
+        // Hashed ids with the same counter differ by start block
+        let mut original = OrderPool {
+            order_id_scheme: OrderIdScheme::Hashed,
+            ..OrderPool::default()
+        };
+        let mut redeployed = original.clone();
+        let first = create(&mut original, 10);
+        let second = create(&mut redeployed, 500);
+        assert_eq!(original.next_order_id, redeployed.next_order_id);
+        assert_ne!(first, second);
+        assert_ne!(first, U256::from(1u32));
+        assert_eq!(
+            first,
+            original.derive_order_id(U256::from(1u32), owner(), U256::from(10u32))
+        );
+
+        // Ids within one pool stay unique and resolvable
+        let third = create(&mut original, 10);
+        assert_ne!(first, third);
+        assert_eq!(original.get_order(third).unwrap().id, third);
+        let cancelled = original.cancel_order(first, owner(), U256::ZERO).unwrap();
+        assert_eq!(cancelled.id, first);
+    }
+
+    #[test]
+    fn test_timed_order_uses_pool_block_time() {
+        let mut pool = OrderPool {
+            block_time_secs: U256::from(2u32),
+            ..OrderPool::default()
+        };
+
+        // One hour at 2 second blocks
+        let order_id = pool
+            .create_long_term_order_timed(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1800000u32),
+                U256::from(3600u32),
+                U256::from(100u32),
+                U256::ZERO,
+            )
+            .unwrap();
+        let order = pool.get_order(order_id).unwrap();
+        assert_eq!(order.end_block, U256::from(1900u32));
+        assert_eq!(order.sell_rate, U256::from(1000u32));
+    }
+
+    #[test]
+    fn test_check_invariants_catches_corruption() {
+        let mut pool = OrderPool::default();
+        for direction in [OrderDirection::SellToken0, OrderDirection::SellToken1] {
+            pool.create_long_term_order(
+                owner(),
+                direction,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        }
+        let reserve = U256::from(100000000u32);
+        pool.execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(pool.check_invariants(U256::from(50u32)), Ok(()));
+
+        let mut corrupted = pool.clone();
+        corrupted.total_sell_rate_0 += U256::from(1u32);
+        assert_eq!(
+            corrupted.check_invariants(U256::from(50u32)),
+            Err(OrderError::InvalidState(
+                "total_sell_rate_0 does not match active orders"
+            ))
+        );
+
+        let mut corrupted = pool.clone();
+        corrupted.total_sell_rate_1 = U256::ZERO;
+        assert_eq!(
+            corrupted.check_invariants(U256::from(50u32)),
+            Err(OrderError::InvalidState(
+                "total_sell_rate_1 does not match active orders"
+            ))
+        );
+
+        let mut corrupted = pool.clone();
+        corrupted.next_order_id = U256::from(2u32);
+        assert_eq!(
+            corrupted.check_invariants(U256::from(50u32)),
+            Err(OrderError::InvalidState("Order id not below next_order_id"))
+        );
+
+        let mut corrupted = pool.clone();
+        corrupted.orders[1].remaining_amount = U256::from(1000001u32);
+        assert_eq!(
+            corrupted.check_invariants(U256::from(50u32)),
+            Err(OrderError::InvalidState(
+                "Order remaining exceeds initial amount"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_bootstrap_at_nonzero_block() {
+        let mut pool = OrderPool::default();
+        let deploy_block = U256::from(1000000u32);
+        pool.initialize_last_virtual_order_block(deploy_block)
+            .unwrap();
+        assert_eq!(
+            pool.get_virtual_order_state().last_virtual_order_block,
+            deploy_block
+        );
+
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                deploy_block,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        // Staleness counts from the bootstrap block, not from genesis
+        let ten_blocks_later = deploy_block + U256::from(10u32);
+        assert!(!pool.needs_virtual_order_execution(
+            ten_blocks_later,
+            U256::from(1000000u32),
+            U256::from(1000000u32)
+        ));
+        assert_eq!(
+            pool.estimate_virtual_execution_gas(ten_blocks_later),
+            pool.estimate_virtual_execution_gas(deploy_block)
+                + U256::from(10 * VIRTUAL_EXECUTION_PER_BLOCK_GAS)
+        );
+
+        let reserve = U256::from(100000000u32);
+        let result = pool
+            .execute_virtual_orders(ten_blocks_later, reserve, reserve)
+            .unwrap();
+        assert_eq!(result.blocks_executed, U256::from(10u32));
+        assert_eq!(
+            pool.get_order(order_id).unwrap().remaining_amount,
+            U256::from(900000u32)
+        );
+
+        // Open orders and rewinds are rejected
+        assert_eq!(
+            pool.initialize_last_virtual_order_block(U256::from(2000000u32)),
+            Err(OrderError::InvalidState("Orders already open"))
+        );
+        pool.cancel_order(order_id, owner(), U256::ZERO).unwrap();
+        assert_eq!(
+            pool.initialize_last_virtual_order_block(deploy_block),
+            Err(OrderError::InvalidState("Block before last execution"))
+        );
+    }
+
+    #[test]
+    fn test_total_sell_rates_track_create_cancel_execute() {
+        let mut pool = OrderPool::default();
+        let reserve = U256::from(100000000u32);
+        let assert_rates = |pool: &OrderPool| {
+            let sum = |direction| {
+                pool.orders
+                    .iter()
+                    .filter(|order| order.direction == direction)
+                    .fold(U256::ZERO, |total, order| total + order.sell_rate)
+            };
+            assert_eq!(pool.total_sell_rate_0, sum(OrderDirection::SellToken0));
+            assert_eq!(pool.total_sell_rate_1, sum(OrderDirection::SellToken1));
+        };
+
+        let short = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(20u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        let cancelled = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken1,
+                U256::from(300000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken1,
+            U256::from(200000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+        assert_rates(&pool);
+        assert_eq!(pool.total_sell_rate_0, U256::from(5000u32));
+        assert_eq!(pool.total_sell_rate_1, U256::from(5000u32));
+
+        pool.cancel_order(cancelled, owner(), U256::ZERO).unwrap();
+        assert_rates(&pool);
+        assert_eq!(pool.total_sell_rate_1, U256::from(2000u32));
+
+        // Execution past the short order's expiry drops its rate
+        pool.execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(
+            pool.get_order(short).unwrap().status,
+            OrderStatus::Completed
+        );
+        assert_rates(&pool);
+        assert_eq!(pool.total_sell_rate_0, U256::ZERO);
+    }
+
+    #[test]
+    fn test_total_claimable_reports_both_tokens() {
+        let mut pool = OrderPool::default();
+        let other = Address::repeat_byte(0x22);
+        for (owner, direction) in [
+            (owner(), OrderDirection::SellToken0),
+            (owner(), OrderDirection::SellToken1),
+            (other, OrderDirection::SellToken0),
+        ] {
+            pool.create_long_term_order(
+                owner,
+                direction,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        }
+        let reserve = U256::from(100000000u32);
+        assert_eq!(
+            pool.total_claimable(owner(), U256::ZERO, reserve, reserve),
+            Ok((U256::ZERO, U256::ZERO))
+        );
+
+        // Previewing leaves the pool untouched
+        let (claimable_0, claimable_1) = pool
+            .total_claimable(owner(), U256::from(50u32), reserve, reserve)
+            .unwrap();
+        assert!(claimable_0 > U256::ZERO);
+        assert!(claimable_1 > U256::ZERO);
+        assert_eq!(
+            pool.virtual_order_state.last_virtual_order_block,
+            U256::ZERO
+        );
+        assert!(pool
+            .orders
+            .iter()
+            .all(|order| order.accumulated_out == U256::ZERO));
+
+        // Executing for real pays out the previewed amounts
+        pool.execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        let settled = pool
+            .orders
+            .iter()
+            .filter(|order| order.owner == owner())
+            .fold(
+                (U256::ZERO, U256::ZERO),
+                |(token_0, token_1), order| match order.direction {
+                    OrderDirection::SellToken0 => (token_0, token_1 + order.accumulated_out),
+                    OrderDirection::SellToken1 => (token_0 + order.accumulated_out, token_1),
+                },
+            );
+        assert_eq!(settled, (claimable_0, claimable_1));
+    }
+
+    #[test]
+    fn test_keeper_reward_scales_with_blocks() {
+        let reserve = U256::from(100000000u32);
+        let reward_after = |blocks: u32| {
+            let mut pool = OrderPool::default();
+            pool.set_keeper_reward(U256::from(10u32), U256::from(1u32))
+                .unwrap();
+            pool.create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+            pool.execute_virtual_orders(U256::from(blocks), reserve, reserve)
+                .unwrap()
+        };
+
+        // 0.1% of the 10,000 sold per block, well under the gas cap
+        let short = reward_after(10);
+        let long = reward_after(40);
+        assert_eq!(short.keeper_reward_0, U256::from(100u32));
+        assert_eq!(long.keeper_reward_0, U256::from(400u32));
+        assert_eq!(long.keeper_reward_1, U256::ZERO);
+
+        // The reward comes out of the reserves
+        let mut unrewarded = OrderPool::default();
+        unrewarded
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        let baseline = unrewarded
+            .execute_virtual_orders(U256::from(40u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(
+            long.new_reserve_0 + long.keeper_reward_0,
+            baseline.new_reserve_0
+        );
+    }
+
+    #[test]
+    fn test_keeper_reward_capped_at_gas_cost() {
+        let mut pool = OrderPool::default();
+        pool.set_keeper_reward(U256::from(5000u32), U256::from(2u32))
+            .unwrap();
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(100000000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+        let gas = pool.estimate_virtual_execution_gas(U256::from(50u32));
+
+        let reserve = U256::from(1000000000u32);
+        let result = pool
+            .execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(result.keeper_reward_0, gas * U256::from(2u32));
+        assert!(result.keeper_reward_0 < result.amount_0_sold / U256::from(2u32));
+
+        assert_eq!(
+            pool.set_keeper_reward(U256::from(10000u32), U256::ZERO),
+            Err(OrderError::InvalidParams("Invalid keeper reward"))
+        );
+    }
+
+    #[test]
+    fn test_settle_order_matches_full_execution() {
+        let reserve = U256::from(100000000u32);
+        let new_pool = || {
+            let mut pool = OrderPool::default();
+            for (direction, amount) in [
+                (OrderDirection::SellToken0, 1000000u32),
+                (OrderDirection::SellToken0, 3000000u32),
+                (OrderDirection::SellToken1, 2000000u32),
+            ] {
+                pool.create_long_term_order(
+                    owner(),
+                    direction,
+                    U256::from(amount),
+                    U256::from(100u32),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap();
+            }
+            pool
+        };
+
+        let mut full = new_pool();
+        full.execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        let expected = full.get_order(U256::from(1u32)).unwrap();
+
+        // Settling one order credits what full execution would, leaving the pool in place
+        let mut settled = new_pool();
+        let credited = settled
+            .settle_order(U256::from(1u32), U256::from(50u32), reserve, reserve)
+            .unwrap();
+        let order = settled.get_order(U256::from(1u32)).unwrap();
+        assert_eq!(credited, expected.accumulated_out);
+        assert_eq!(order.remaining_amount, expected.remaining_amount);
+        assert_eq!(
+            settled.virtual_order_state.last_virtual_order_block,
+            U256::ZERO
+        );
+        assert_eq!(
+            settled.get_order(U256::from(2u32)).unwrap().accumulated_out,
+            U256::ZERO
+        );
+
+        // A later full execution over the same blocks does not credit the order twice
+        settled
+            .execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        for id in 1..=3u32 {
+            let id = U256::from(id);
+            let order = settled.get_order(id).unwrap();
+            let expected = full.get_order(id).unwrap();
+            assert_eq!(order.accumulated_out, expected.accumulated_out);
+            assert_eq!(order.remaining_amount, expected.remaining_amount);
+        }
+    }
+
+    #[test]
+    fn test_zero_sell_rate_rejected() {
+        let mut pool = OrderPool::default();
+        let result = pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(5u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        );
+        assert_eq!(
+            result,
+            Err(OrderError::InvalidParams(
+                "sell rate rounds to zero; increase amount or shorten duration"
+            ))
+        );
+        assert!(pool.orders.is_empty());
+        assert_eq!(pool.next_order_id, U256::from(1u32));
+    }
+
+    #[test]
+    fn test_dust_remainder_accounts_for_full_amount() {
+        let mut pool = OrderPool::default();
+        let initial_amount = U256::from(1000003u32);
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                initial_amount,
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        let order = pool.get_order(order_id).unwrap();
+        assert_eq!(order.sell_rate, U256::from(10000u32));
+        assert_eq!(order.dust_remainder, U256::from(3u32));
+        assert_eq!(order.remaining_amount, U256::from(1000000u32));
+
+        let reserve = U256::from(1000000000u64);
+        pool.execute_virtual_orders(U256::from(100u32), reserve, reserve)
+            .unwrap();
+
+        let order = pool.get_order(order_id).unwrap();
+        assert_eq!(order.status, OrderStatus::Completed);
+        assert_eq!(order.remaining_amount, U256::ZERO);
+        assert_eq!(order.amount_sold() + order.dust_remainder, initial_amount);
+        assert_eq!(
+            pool.order_progress(order_id, U256::from(100u32)),
+            Some((U256::from(10000u32), U256::ZERO))
+        );
+    }
+
+    #[test]
+    fn test_aggregate_rate_cap() {
+        let mut pool = OrderPool::default();
+        pool.set_max_total_sell_rate(OrderDirection::SellToken0, U256::from(3000u32));
+        let duration = U256::from(100u32);
+
+        // Fill the cap exactly with three orders of rate 1000
+        let mut order_ids = Vec::new();
+        for _ in 0..3 {
+            order_ids.push(
+                pool.create_long_term_order(
+                    owner(),
+                    OrderDirection::SellToken0,
+                    U256::from(100000u32),
+                    duration,
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap(),
+            );
+        }
+        assert_eq!(pool.total_sell_rate_0, U256::from(3000u32));
+
+        let over_cap = pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(100u32),
+            duration,
+            U256::ZERO,
+            U256::ZERO,
+        );
+        assert_eq!(
+            over_cap,
+            Err(OrderError::LimitReached("aggregate rate cap exceeded"))
+        );
+        assert_eq!(pool.orders.len(), 3);
+        assert_eq!(pool.total_sell_rate_0, U256::from(3000u32));
+
+        // The other direction is uncapped
+        assert!(pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken1,
+                U256::from(1000000u32),
+                duration,
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .is_ok());
+
+        // Batches are rejected as a whole
+        let batch = pool.create_long_term_orders_batch(
+            owner(),
+            vec![(OrderDirection::SellToken0, U256::from(100u32), duration)],
+            U256::ZERO,
+        );
+        assert_eq!(
+            batch,
+            Err(OrderError::LimitReached("aggregate rate cap exceeded"))
+        );
+
+        // Cancelling frees capacity for a new order
+        pool.cancel_order(order_ids[0], owner(), U256::ZERO)
+            .unwrap();
+        assert!(pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                duration,
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .is_ok());
+        assert_eq!(pool.total_sell_rate_0, U256::from(3000u32));
+        assert!(pool.check_invariants(U256::ZERO).is_ok());
+    }
+
+    #[test]
+    fn test_saturating_execution_reports_unfilled() {
+        let mut pool = OrderPool::default();
+        let short_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(50u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        let long_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        // The first segment drains the single unit of token1, leaving nothing for the second
+        let reserve_0 = U256::from(1000u32);
+        let reserve_1 = U256::from(1u32);
+        let mut strict = pool.clone();
+        assert!(strict
+            .execute_virtual_orders(U256::from(100u32), reserve_0, reserve_1)
+            .is_err());
+        assert_eq!(
+            strict.virtual_order_state.last_virtual_order_block,
+            U256::ZERO
+        );
+
+        pool.set_saturating_execution(true);
+        let result = pool
+            .execute_virtual_orders(U256::from(100u32), reserve_0, reserve_1)
+            .unwrap();
+
+        assert_eq!(result.blocks_executed, U256::from(100u32));
+        assert_eq!(result.amount_0_sold, U256::from(1500000u32));
+        assert_eq!(result.amount_1_received, U256::from(1u32));
+        assert_eq!(result.new_reserve_1, U256::ZERO);
+        assert_eq!(result.unfilled_0, U256::from(500000u32));
+        assert_eq!(result.unfilled_1, U256::ZERO);
+        assert_eq!(
+            pool.virtual_order_state.last_virtual_order_block,
+            U256::from(100u32)
+        );
+
+        // The skipped volume stays with the order that could not sell it
+        assert_eq!(
+            pool.get_order(short_id).unwrap().remaining_amount,
+            U256::ZERO
+        );
+        assert_eq!(
+            pool.get_order(long_id).unwrap().remaining_amount,
+            U256::from(500000u32)
+        );
+    }
+
+    #[test]
+    fn test_distribution_never_exceeds_received() {
+        let mut pool = OrderPool {
+            fee_bps: U256::from(30u32),
+            ..OrderPool::default()
+        };
+
+        // Odd, mutually prime amounts and durations so every share has a remainder
+        let sizes = [
+            (1000003u32, 97u32),
+            (999983, 89),
+            (7919, 13),
+            (104729, 61),
+            (15485863, 101),
+            (32452843, 53),
+        ];
+        let mut order_ids = Vec::new();
+        for (index, (amount, duration)) in sizes.iter().enumerate() {
+            let direction = if index % 2 == 0 {
+                OrderDirection::SellToken0
+            } else {
+                OrderDirection::SellToken1
+            };
+            order_ids.push(
+                pool.create_long_term_order(
+                    owner(),
+                    direction,
+                    U256::from(*amount),
+                    U256::from(*duration),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap(),
+            );
+        }
+
+        let mut reserve_0 = U256::from(1000000007u64);
+        let mut reserve_1 = U256::from(999999937u64);
+        let mut received = (U256::ZERO, U256::ZERO);
+        for block in [7u32, 13, 29, 31, 53, 61, 89, 97, 101] {
+            let result = pool
+                .execute_virtual_orders(U256::from(block), reserve_0, reserve_1)
+                .unwrap();
+            received.0 += result.amount_0_received;
+            received.1 += result.amount_1_received;
+            reserve_0 = result.new_reserve_0;
+            reserve_1 = result.new_reserve_1;
+        }
+
+        let mut distributed = (U256::ZERO, U256::ZERO);
+        for order_id in order_ids {
+            let order = pool.get_order(order_id).unwrap();
+            match order.direction {
+                OrderDirection::SellToken0 => distributed.1 += order.accumulated_out,
+                OrderDirection::SellToken1 => distributed.0 += order.accumulated_out,
+            }
+        }
+
+        assert!(received.0 > U256::ZERO && received.1 > U256::ZERO);
+        assert!(distributed.0 <= received.0);
+        assert!(distributed.1 <= received.1);
+    }
+
+    #[test]
+    fn test_get_orders_expiring_between() {
+        let mut pool = OrderPool::default();
+        let mut order_ids = Vec::new();
+        for duration in [300u32, 100, 200] {
+            order_ids.push(
+                pool.create_long_term_order(
+                    owner(),
+                    OrderDirection::SellToken0,
+                    U256::from(100000u32),
+                    U256::from(duration),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap(),
+            );
+        }
+
+        // Both bounds are inclusive and results come soonest first
+        assert_eq!(
+            pool.get_orders_expiring_between(U256::from(100u32), U256::from(200u32)),
+            vec![order_ids[1], order_ids[2]]
+        );
+        assert_eq!(
+            pool.get_orders_expiring_between(U256::ZERO, U256::from(1000u32)),
+            vec![order_ids[1], order_ids[2], order_ids[0]]
+        );
+        assert_eq!(
+            pool.get_orders_expiring_between(U256::from(300u32), U256::from(300u32)),
+            vec![order_ids[0]]
+        );
+
+        // Ranges with no expiries, or reversed bounds, are empty
+        assert!(pool
+            .get_orders_expiring_between(U256::from(101u32), U256::from(199u32))
+            .is_empty());
+        assert!(pool
+            .get_orders_expiring_between(U256::from(300u32), U256::from(100u32))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_cancel_order_with_refund() {
+        let mut pool = OrderPool::default();
+        let sell_amount = U256::from(1000000u32);
+        let create = |pool: &mut OrderPool| {
+            pool.create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                sell_amount,
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap()
+        };
+
+        // A fresh order refunds its full principal and no proceeds
+        let fresh_id = create(&mut pool);
+        assert_eq!(
+            pool.cancel_order_with_refund(fresh_id, owner(), U256::ZERO),
+            Ok((sell_amount, U256::ZERO))
+        );
+
+        // Mid-life, the unstreamed principal and accrued proceeds come back together
+        let order_id = create(&mut pool);
+        let reserve = U256::from(1000000000u64);
+        pool.execute_virtual_orders(U256::from(40u32), reserve, reserve)
+            .unwrap();
+        let accrued = pool.get_order(order_id).unwrap().accumulated_out;
+        assert!(accrued > U256::ZERO);
+
+        assert_eq!(
+            pool.cancel_order_with_refund(order_id, Address::repeat_byte(0x99), U256::ZERO),
+            Err(OrderError::NotOwner)
+        );
+        assert_eq!(
+            pool.cancel_order_with_refund(order_id, owner(), U256::ZERO),
+            Ok((U256::from(600000u32), accrued))
+        );
+        assert_eq!(pool.total_sell_rate_0, U256::ZERO);
+        assert_eq!(
+            pool.cancel_order_with_refund(order_id, owner(), U256::ZERO),
+            Err(OrderError::InvalidState("Order already cancelled"))
+        );
+    }
+
+    #[test]
+    fn test_offsetting_flows_match_at_mid() {
+        let mut pool = OrderPool::default();
+        let sell_amount = U256::from(100000u32);
+        let sell_0 = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                sell_amount,
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        let sell_1 = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken1,
+                sell_amount * U256::from(2u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        // Price 2 token1 per token0, so the flows offset exactly
+        let reserve_0 = U256::from(1000000000u64);
+        let reserve_1 = U256::from(2000000000u64);
+        let result = pool
+            .execute_virtual_orders(U256::from(100u32), reserve_0, reserve_1)
+            .unwrap();
+
+        // Everything crosses internally and the curve does not move
+        assert_eq!(result.matched_0, sell_amount);
+        assert_eq!(result.matched_1, sell_amount * U256::from(2u32));
+        assert_eq!(result.new_reserve_0, reserve_0);
+        assert_eq!(result.new_reserve_1, reserve_1);
+
+        // Both sides are filled at the mid price
+        assert_eq!(
+            pool.get_order(sell_0).unwrap().accumulated_out,
+            sell_amount * U256::from(2u32)
+        );
+        assert_eq!(pool.get_order(sell_1).unwrap().accumulated_out, sell_amount);
+
+        // One-sided flow matches nothing
+        let mut one_sided = OrderPool::default();
+        one_sided
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                sell_amount,
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        let result = one_sided
+            .execute_virtual_orders(U256::from(100u32), reserve_0, reserve_1)
+            .unwrap();
+        assert_eq!(
+            (result.matched_0, result.matched_1),
+            (U256::ZERO, U256::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_max_blocks_per_execution() {
+        let mut unbounded = OrderPool::default();
+        for (direction, amount, duration) in [
+            (OrderDirection::SellToken0, 1000000u32, 250u32),
+            (OrderDirection::SellToken1, 3000000, 700),
+            (OrderDirection::SellToken0, 500000, 1000),
+        ] {
+            unbounded
+                .create_long_term_order(
+                    owner(),
+                    direction,
+                    U256::from(amount),
+                    U256::from(duration),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap();
+        }
+        let mut bounded = unbounded.clone();
+        bounded.set_max_blocks_per_execution(U256::from(300u32));
+
+        let reserve = U256::from(1000000000u64);
+        let target = U256::from(1000u32);
+        let expected = unbounded
+            .execute_virtual_orders(target, reserve, reserve)
+            .unwrap();
+        assert_eq!(expected.blocks_remaining, U256::ZERO);
+
+        // Four bounded calls cover the 1000 block gap
+        let mut reserves = (reserve, reserve);
+        let mut calls = 0;
+        loop {
+            let result = bounded
+                .execute_virtual_orders(target, reserves.0, reserves.1)
+                .unwrap();
+            calls += 1;
+            assert!(result.blocks_executed <= U256::from(300u32));
+            reserves = (result.new_reserve_0, result.new_reserve_1);
+            if result.blocks_remaining == U256::ZERO {
+                break;
+            }
+            assert_eq!(
+                result.blocks_remaining,
+                target - bounded.virtual_order_state.last_virtual_order_block
+            );
+        }
+        assert_eq!(calls, 4);
+
+        // Splitting at block boundaries only re-prices segments, so the end state matches
+        // the single call up to rounding
+        assert_eq!(bounded.virtual_order_state.last_virtual_order_block, target);
+        assert!(bounded.orders.is_empty() && unbounded.orders.is_empty());
+        for (actual, expected) in [
+            (reserves.0, expected.new_reserve_0),
+            (reserves.1, expected.new_reserve_1),
+        ] {
+            let difference = if actual > expected {
+                actual - expected
+            } else {
+                expected - actual
+            };
+            assert!(difference * U256::from(1000u32) <= expected);
+        }
+    }
+
+    #[test]
+    fn test_execute_if_due() {
+        let mut pool = OrderPool::default();
+        pool.set_order_block_interval(U256::from(10u32)).unwrap();
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(1000000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+        let reserve = U256::from(100000000u32);
+
+        // Before the interval elapses nothing runs
+        let before = pool.clone();
+        assert_eq!(
+            pool.execute_if_due(U256::from(9u32), reserve, reserve),
+            Ok(None)
+        );
+        assert_eq!(pool, before);
+
+        // Once due it matches a direct execution
+        let expected = before
+            .clone()
+            .execute_virtual_orders(U256::from(10u32), reserve, reserve)
+            .unwrap();
+        let result = pool
+            .execute_if_due(U256::from(10u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(result, Some(expected));
+        assert_eq!(
+            pool.get_virtual_order_state().last_virtual_order_block,
+            U256::from(10u32)
+        );
+
+        // The interval restarts from the execution
+        assert_eq!(
+            pool.execute_if_due(U256::from(15u32), reserve, reserve),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_configurable_duration_bounds() {
+        let mut pool = OrderPool::default();
+        let create = |pool: &mut OrderPool, duration: u32| {
+            pool.create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(duration),
+                U256::ZERO,
+                U256::ZERO,
+            )
+        };
+
+        // The defaults keep the previous bounds
+        assert_eq!(
+            create(&mut pool, 9),
+            Err(OrderError::InvalidParams("Duration below minimum"))
+        );
+        assert!(create(&mut pool, 10).is_ok());
+        assert_eq!(
+            create(&mut pool, 1000001),
+            Err(OrderError::InvalidParams("Duration above maximum"))
+        );
+
+        // Raising the minimum moves the boundary
+        pool.set_duration_bounds(U256::from(100u32), U256::from(1000u32))
+            .unwrap();
+        assert_eq!(
+            create(&mut pool, 99),
+            Err(OrderError::InvalidParams("Duration below minimum"))
+        );
+        assert!(create(&mut pool, 100).is_ok());
+        assert!(create(&mut pool, 1000).is_ok());
+        assert_eq!(
+            create(&mut pool, 1001),
+            Err(OrderError::InvalidParams("Duration above maximum"))
+        );
+
+        // Batches respect the bounds too
+        assert_eq!(
+            pool.create_long_term_orders_batch(
+                owner(),
+                vec![(
+                    OrderDirection::SellToken1,
+                    U256::from(1000000u32),
+                    U256::from(50u32)
+                )],
+                U256::ZERO,
+            ),
+            Err(OrderError::InvalidParams("Duration below minimum"))
+        );
+
+        assert_eq!(
+            pool.set_duration_bounds(U256::from(1000u32), U256::from(100u32)),
+            Err(OrderError::InvalidParams("Invalid duration bounds"))
+        );
+        assert!(pool
+            .set_duration_bounds(U256::ZERO, U256::from(100u32))
+            .is_err());
+    }
+
+    #[test]
+    fn test_execution_statistics_overflow_is_an_error() {
+        let mut pool = OrderPool::default();
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(1000000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+
+        let stats = pool.get_execution_statistics(U256::from(40u32)).unwrap();
+        assert_eq!(stats.active_orders, 1);
+        assert_eq!(stats.total_volume_0, U256::from(400000u32));
+
+        // A rate near U256::MAX / 10 overflows once more than ten blocks have elapsed
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken1,
+            U256::MAX,
+            U256::from(10u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+        assert!(pool.get_execution_statistics(U256::from(10u32)).is_ok());
+        assert_eq!(
+            pool.get_execution_statistics(U256::from(11u32))
+                .unwrap_err(),
+            OrderError::Overflow("Statistics overflow")
+        );
+    }
+
+    #[test]
+    fn test_pause_and_resume_order() {
+        let mut pool = OrderPool::default();
+        let reserve = U256::from(100000000u32);
+        let paused_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        pool.create_long_term_order(
+            Address::repeat_byte(0x22),
+            OrderDirection::SellToken0,
+            U256::from(500000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+        assert_eq!(pool.total_sell_rate_0, U256::from(15000u32));
+
+        pool.execute_virtual_orders(U256::from(20u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(
+            pool.pause_order(paused_id, Address::repeat_byte(0x22)),
+            Err(OrderError::NotOwner)
+        );
+        pool.pause_order(paused_id, owner()).unwrap();
+        assert_eq!(pool.total_sell_rate_0, U256::from(5000u32));
+        pool.check_invariants(U256::from(20u32)).unwrap();
+        assert_eq!(
+            pool.pause_order(paused_id, owner()),
+            Err(OrderError::InvalidState("Order already paused"))
+        );
+
+        // While paused the order keeps its amount, even past its original end
+        let result = pool
+            .execute_virtual_orders(U256::from(150u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(result.amount_0_sold, U256::from(400000u32));
+        let order = pool.get_order(paused_id).unwrap();
+        assert_eq!(order.remaining_amount, U256::from(800000u32));
+        assert_eq!(order.status, OrderStatus::Active);
+
+        // Resuming restores the rate and extends the order by the 130 paused blocks
+        pool.resume_order(paused_id, owner()).unwrap();
+        assert_eq!(pool.total_sell_rate_0, U256::from(10000u32));
+        pool.check_invariants(U256::from(150u32)).unwrap();
+        assert_eq!(
+            pool.get_order(paused_id).unwrap().end_block,
+            U256::from(230u32)
+        );
+        assert_eq!(
+            pool.resume_order(paused_id, owner()),
+            Err(OrderError::InvalidState("Order not paused"))
+        );
+
+        // The rest streams out by the extended end
+        let result = pool
+            .execute_virtual_orders(U256::from(230u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(result.amount_0_sold, U256::from(800000u32));
+        assert_eq!(
+            pool.get_order_status(paused_id),
+            Some(OrderStatus::Completed)
+        );
+        assert_eq!(pool.total_sell_rate_0, U256::ZERO);
+    }
+
+    #[test]
+    fn test_cancel_paused_order_keeps_rates_consistent() {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken1,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        pool.pause_order(order_id, owner()).unwrap();
+        assert_eq!(pool.total_sell_rate_1, U256::ZERO);
+
+        let (refund, _) = pool
+            .cancel_order_with_refund(order_id, owner(), U256::ZERO)
+            .unwrap();
+        assert_eq!(refund, U256::from(1000000u32));
+        assert_eq!(pool.total_sell_rate_1, U256::ZERO);
+        pool.check_invariants(U256::ZERO).unwrap();
+    }
+
+    #[test]
+    fn test_reserve_tracking_rejects_stale_reserves() {
+        let mut pool = OrderPool::default();
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(1000000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+        let reserve = U256::from(100000000u32);
+        pool.sync_reserves(reserve, reserve);
+
+        let first = pool
+            .execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(
+            (pool.stored_reserve_0, pool.stored_reserve_1),
+            (first.new_reserve_0, first.new_reserve_1)
+        );
+
+        // A caller replaying the pre-execution reserves is caught
+        let before = pool.clone();
+        assert_eq!(
+            pool.execute_virtual_orders(U256::from(60u32), reserve, reserve),
+            Err(OrderError::InvalidParams(
+                "Reserves disagree with stored reserves"
+            ))
+        );
+        assert_eq!(pool, before);
+
+        // Within the tolerance, execution chains from the stored reserves
+        pool.set_reserve_tolerance(U256::from(100u32)).unwrap();
+        let second = pool
+            .execute_virtual_orders(U256::from(60u32), reserve, reserve)
+            .unwrap();
+        let mut untracked = before.clone();
+        untracked.disable_reserve_tracking();
+        let expected = untracked
+            .execute_virtual_orders(U256::from(60u32), first.new_reserve_0, first.new_reserve_1)
+            .unwrap();
+        assert_eq!(second, expected);
+
+        assert!(pool.set_reserve_tolerance(U256::from(10001u32)).is_err());
+    }
+
+    #[test]
+    fn test_cancel_all_orders() {
+        let mut pool = OrderPool::default();
+        let other = Address::repeat_byte(0x22);
+        let mut owned_ids = Vec::new();
+        for (direction, amount) in [
+            (OrderDirection::SellToken0, 1000000u32),
+            (OrderDirection::SellToken1, 2000000),
+            (OrderDirection::SellToken0, 3000000),
+        ] {
+            owned_ids.push(
+                pool.create_long_term_order(
+                    owner(),
+                    direction,
+                    U256::from(amount),
+                    U256::from(100u32),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap(),
+            );
+        }
+        let other_id = pool
+            .create_long_term_order(
+                other,
+                OrderDirection::SellToken1,
+                U256::from(500000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        let reserve = U256::from(100000000u32);
+        pool.execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        let expected: Vec<(U256, U256, U256)> = owned_ids
+            .iter()
+            .map(|id| {
+                let order = pool.get_order(*id).unwrap();
+                (*id, order.remaining_amount, order.accumulated_out)
+            })
+            .collect();
+
+        let refunds = pool.cancel_all_orders(owner(), U256::ZERO).unwrap();
+        assert_eq!(refunds, expected);
+        assert_eq!(refunds[1].1, U256::from(1000000u32));
+
+        // Only the other owner's order is left, and the totals match it
+        assert_eq!(pool.get_active_orders_count(), 1);
+        assert!(pool.get_order(other_id).is_some());
+        assert_eq!(pool.total_sell_rate_0, U256::ZERO);
+        assert_eq!(pool.total_sell_rate_1, U256::from(5000u32));
+        pool.check_invariants(U256::from(50u32)).unwrap();
+
+        assert_eq!(pool.cancel_all_orders(owner(), U256::ZERO), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn test_cancel_all_orders_is_all_or_nothing() {
+        let mut pool = OrderPool::default();
+        pool.set_min_blocks_before_cancel(U256::from(10u32));
+        let reserve = U256::from(100000000u32);
+        let create = |pool: &mut OrderPool, direction, current_block: u32| {
+            pool.create_long_term_order(
+                owner(),
+                direction,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::from(current_block),
+                U256::ZERO,
+            )
+            .unwrap()
+        };
+        create(&mut pool, OrderDirection::SellToken0, 0);
+        pool.execute_virtual_orders(U256::from(20u32), reserve, reserve)
+            .unwrap();
+        create(&mut pool, OrderDirection::SellToken1, 20);
+
+        // The newer order is still inside its cancel window, so neither is cancelled
+        let before = pool.clone();
+        assert_eq!(
+            pool.cancel_all_orders(owner(), U256::from(25u32)),
+            Err(OrderError::InvalidState("cancel too early"))
+        );
+        assert_eq!(pool, before);
+
+        assert_eq!(
+            pool.cancel_all_orders(owner(), U256::from(30u32))
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(pool.get_active_orders_count(), 0);
+        assert_eq!(pool.get_current_sell_rates(), (U256::ZERO, U256::ZERO));
+    }
+
+    #[test]
+    fn test_order_lifetime_quality_blends_fills() {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        assert_eq!(pool.order_lifetime_quality(order_id), None);
+
+        // The first half fills against deep reserves with negligible impact
+        let deep = U256::from(1_000_000_000_000u64);
+        pool.execute_virtual_orders(U256::from(50u32), deep, deep)
+            .unwrap();
+        let early = pool.order_lifetime_quality(order_id).unwrap();
+        assert!(early >= U256::from(99u32));
+
+        // The second half sells half the pool's token0 reserve, a poor fill
+        let shallow = U256::from(1000000u32);
+        let mut late_only = OrderPool::default();
+        let late_id = late_only
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(500000u32),
+                U256::from(50u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        late_only
+            .execute_virtual_orders(U256::from(50u32), shallow, shallow)
+            .unwrap();
+        let late = late_only.order_lifetime_quality(late_id).unwrap();
+        assert!(late < U256::from(60u32));
+
+        pool.execute_virtual_orders(U256::from(100u32), shallow, shallow)
+            .unwrap();
+
+        // Equal block counts weigh both halves equally, and the score survives completion
+        assert_eq!(
+            pool.get_order_status(order_id),
+            Some(OrderStatus::Completed)
+        );
+        assert_eq!(
+            pool.order_lifetime_quality(order_id),
+            Some((early + late) / U256::from(2u32))
+        );
+    }
+
+    #[test]
+    fn test_gas_estimate_saturates_for_huge_gaps() {
+        let mut pool = OrderPool::default();
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(1000000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+
+        // The per-block cost overflows; the estimate must not collapse to the base cost
+        assert_eq!(pool.estimate_virtual_execution_gas(U256::MAX), U256::MAX);
+    }
+
+    #[test]
+    fn test_gas_estimate_scales_with_per_order_cost() {
+        let mut pool = OrderPool::default();
+        for _ in 0..3 {
+            pool.create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        }
+        let current_block = U256::from(10u32);
+        let default_estimate = pool.estimate_virtual_execution_gas(current_block);
+        assert_eq!(pool.gas_model, GasModel::default());
+
+        // Each extra unit of per-order cost adds one unit per active order
+        for extra in [1u32, 100, 5000] {
+            pool.set_gas_model(GasModel {
+                execution_per_order_gas: GasModel::default().execution_per_order_gas
+                    + U256::from(extra),
+                ..GasModel::default()
+            });
+            assert_eq!(
+                pool.estimate_virtual_execution_gas(current_block),
+                default_estimate + U256::from(3 * extra)
+            );
+        }
+    }
+
+    #[test]
+    fn test_completed_order_appears_in_owner_history() {
+        let mut pool = OrderPool::default();
+        let other = Address::from([2u8; 20]);
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken1,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        let result = pool
+            .execute_virtual_orders(
+                U256::from(100u32),
+                U256::from(10000000u32),
+                U256::from(10000000u32),
+            )
+            .unwrap();
+
+        let history = pool.get_history_by_owner(owner(), 0, 10);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].id, order_id);
+        assert_eq!(history[0].status, OrderStatus::Completed);
+        assert_eq!(history[0].remaining_amount, U256::ZERO);
+        assert_eq!(history[0].accumulated_out, result.amount_0_received);
+
+        assert!(pool.get_history_by_owner(owner(), 1, 10).is_empty());
+        assert!(pool.get_history_by_owner(other, 0, 10).is_empty());
+    }
+
+    #[test]
+    fn test_order_block_interval_upper_bound() {
+        let mut pool = OrderPool::default();
+        let max_interval = U256::from(MAX_ORDER_BLOCK_INTERVAL);
+
+        assert!(pool.set_order_block_interval(U256::ZERO).is_err());
+        pool.set_order_block_interval(max_interval).unwrap();
+        assert_eq!(
+            pool.set_order_block_interval(max_interval + U256::from(1u32)),
+            Err(OrderError::InvalidParams("interval too large"))
+        );
+        assert_eq!(
+            pool.get_virtual_order_state().order_block_interval,
+            max_interval
+        );
+
+        // The bound is configurable
+        pool.set_max_order_block_interval(U256::from(10u32))
+            .unwrap();
+        pool.set_order_block_interval(U256::from(10u32)).unwrap();
+        assert!(pool.set_order_block_interval(U256::from(11u32)).is_err());
+        assert!(pool.set_max_order_block_interval(U256::ZERO).is_err());
+    }
+
+    #[test]
+    fn test_instant_order_swaps_at_current_price() {
+        let mut pool = OrderPool::default();
+        pool.set_fee_bps(U256::from(30u32)).unwrap();
+        let reserve = U256::from(10000000u32);
+        let sell_amount = U256::from(100000u32);
+
+        // A long-term order is pending, so the swap must see the reserves after it
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(1000000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+
+        let result = pool
+            .execute_instant_order(
+                owner(),
+                OrderDirection::SellToken1,
+                sell_amount,
+                U256::ZERO,
+                U256::from(50u32),
+                (reserve, reserve),
+            )
+            .unwrap();
+
+        let expected_out = TWAMMath::get_amount_out(
+            sell_amount,
+            result.virtual_execution.new_reserve_1,
+            result.virtual_execution.new_reserve_0,
+            U256::from(30u32),
+        )
+        .unwrap();
+        assert_eq!(result.virtual_execution.blocks_executed, U256::from(50u32));
+        assert_eq!(result.amount_out, expected_out);
+        assert_eq!(
+            result.new_reserve_1,
+            result.virtual_execution.new_reserve_1 + sell_amount
+        );
+        assert_eq!(
+            result.new_reserve_0,
+            result.virtual_execution.new_reserve_0 - expected_out
+        );
+
+        let order = pool.get_order(result.order_id).unwrap();
+        assert_eq!(order.order_type, OrderType::Instant);
+        assert_eq!(order.status, OrderStatus::Completed);
+        assert_eq!(order.accumulated_out, expected_out);
+        assert_eq!(order.remaining_amount, U256::ZERO);
+    }
+
+    #[test]
+    fn test_instant_order_output_cannot_be_claimed_again() {
+        let mut pool = OrderPool::default();
+        let reserve = U256::from(10000000u32);
+        let result = pool
+            .execute_instant_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::ZERO,
+                U256::from(10u32),
+                (reserve, reserve),
+            )
+            .unwrap();
+
+        // The swap paid out at once; its history entry only reports the output
+        assert_eq!(
+            pool.claim_proceeds(result.order_id, owner()),
+            Err(OrderError::NotFound)
+        );
+        assert_eq!(
+            pool.get_order(result.order_id).unwrap().accumulated_out,
+            result.amount_out
+        );
+    }
+
+    #[test]
+    fn test_instant_order_slippage_leaves_pool_untouched() {
+        let mut pool = OrderPool::default();
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(1000000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+        let before = pool.clone();
+
+        let reserve = U256::from(10000000u32);
+        assert_eq!(
+            pool.execute_instant_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(100000u32),
+                U256::from(50u32),
+                (reserve, reserve),
+            ),
+            Err(OrderError::InvalidParams("Insufficient output"))
+        );
+        assert_eq!(pool, before);
+    }
+
+    #[test]
+    fn test_instant_swap_without_streaming_order() {
+        let mut pool = OrderPool::default();
+        let reserve_0 = U256::from(10000000u32);
+        let reserve_1 = U256::from(20000000u32);
+        let amount_in = U256::from(100000u32);
+        let expected_out =
+            TWAMMath::get_amount_out(amount_in, reserve_0, reserve_1, U256::ZERO).unwrap();
+
+        let amount_out = pool
+            .execute_instant_swap(
+                owner(),
+                OrderDirection::SellToken0,
+                amount_in,
+                expected_out,
+                reserve_0,
+                reserve_1,
+            )
+            .unwrap();
+        assert_eq!(amount_out, expected_out);
+        assert!(pool.orders.is_empty());
+        assert_eq!(pool.total_sell_rate_0, U256::ZERO);
+
+        // Asking for one unit more than the quote reverts
+        assert_eq!(
+            pool.execute_instant_swap(
+                owner(),
+                OrderDirection::SellToken0,
+                amount_in,
+                expected_out + U256::from(1u32),
+                reserve_0,
+                reserve_1,
+            ),
+            Err(OrderError::InvalidParams("Insufficient output"))
+        );
+    }
+
+    #[test]
+    fn test_next_execution_block() {
+        let mut pool = OrderPool::default();
+        pool.initialize_last_virtual_order_block(U256::from(100u32))
+            .unwrap();
+        assert_eq!(pool.next_execution_block(), U256::from(100u32));
+
+        pool.set_order_block_interval(U256::from(10u32)).unwrap();
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(1000000u32),
+            U256::from(100u32),
+            U256::from(100u32),
+            U256::ZERO,
+        )
+        .unwrap();
+
+        let next_block = pool.next_execution_block();
+        assert_eq!(next_block, U256::from(110u32));
+        assert!(!pool.needs_virtual_order_execution(
+            next_block - U256::from(1u32),
+            U256::from(1000000u32),
+            U256::from(1000000u32)
+        ));
+        assert!(pool.needs_virtual_order_execution(
+            next_block,
+            U256::from(1000000u32),
+            U256::from(1000000u32)
+        ));
+    }
+
+    #[test]
+    fn test_cumulative_price_move_cap() {
+        let reserve = U256::from(1000000u32);
+        let max_move = U256::from(100u32);
+
+        // 5037 moves the price 99.98 bps, 5038 moves it 100.004 bps
+        assert_eq!(
+            OrderManager::would_invert_price(U256::from(5037u32), reserve, reserve, max_move),
+            Ok(false)
+        );
+        assert_eq!(
+            OrderManager::would_invert_price(U256::from(5038u32), reserve, reserve, max_move),
+            Ok(true)
+        );
+
+        let mut pool = OrderPool::default();
+        pool.set_max_price_move(max_move).unwrap();
+        let create = |pool: &mut OrderPool, sell_amount: u32| {
+            pool.create_long_term_order(
+                owner(),
+                OrderDirection::SellToken1,
+                U256::from(sell_amount),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+        };
+
+        // The cap is judged against stored reserves
+        assert_eq!(
+            create(&mut pool, 5037),
+            Err(OrderError::InvalidParams(
+                "Price move cap needs reserve tracking"
+            ))
+        );
+        pool.sync_reserves(reserve, reserve);
+        assert!(create(&mut pool, 5037).is_ok());
+        assert_eq!(
+            create(&mut pool, 5038),
+            Err(OrderError::InvalidParams("Price move too large"))
+        );
+        assert!(pool.set_max_price_move(U256::from(10001u32)).is_err());
+    }
+
+    #[test]
+    fn test_repeat_cancel_reports_already_cancelled() {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        pool.cancel_order(order_id, owner(), U256::ZERO).unwrap();
+        assert_eq!(
+            pool.cancel_order(order_id, owner(), U256::ZERO),
+            Err(OrderError::InvalidState("Order already cancelled"))
+        );
+        assert_eq!(
+            pool.cancel_order(U256::from(999u32), owner(), U256::ZERO),
+            Err(OrderError::NotFound)
+        );
+    }
+
+    #[test]
+    fn test_owner_unrealized_value_mid_stream() {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        let result = pool
+            .execute_virtual_orders(
+                U256::from(50u32),
+                U256::from(1000000000u32),
+                U256::from(2000000000u32),
+            )
+            .unwrap();
+        let (reserve_0, reserve_1) = (result.new_reserve_0, result.new_reserve_1);
+
+        let (value_0, value_1) = pool
+            .owner_unrealized_value(owner(), reserve_0, reserve_1, 18)
+            .unwrap();
+
+        // Half the principal is left, the other half has become token1 proceeds
+        let order = pool.get_order(order_id).unwrap();
+        assert_eq!(order.remaining_amount, U256::from(500000u32));
+        let remaining_in_1 = order.remaining_amount * reserve_1 / reserve_0;
+        assert_eq!(value_1, order.accumulated_out + remaining_in_1);
+
+        // Both values describe the same holdings; at the starting price of 2 the principal
+        // was worth 2000000 token1, and selling into the pool lost a little to slippage
+        assert!(value_0.abs_diff(value_1 * reserve_0 / reserve_1) <= U256::from(1u32));
+        assert!(value_1 < U256::from(2000000u32));
+        assert!(value_1 > U256::from(1980000u32));
+
+        assert_eq!(
+            pool.owner_unrealized_value(Address::repeat_byte(0x99), reserve_0, reserve_1, 18),
+            Ok((U256::ZERO, U256::ZERO))
+        );
+    }
+
+    #[test]
+    fn test_owner_unrealized_value_price_rounds_to_zero() {
+        let mut pool = OrderPool::default();
+        let create = |pool: &mut OrderPool, direction| {
+            pool.create_long_term_order(
+                owner(),
+                direction,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap()
+        };
+        create(&mut pool, OrderDirection::SellToken0);
+
+        // One token1 against 10^30 token0 is a price of 10^-30, zero at 18 decimals
+        let reserve_0 = U256::from(10u32).pow(U256::from(30u32));
+        let reserve_1 = U256::from(1u32);
+
+        // Token0 holdings alone can still be valued
+        assert_eq!(
+            pool.owner_unrealized_value(owner(), reserve_0, reserve_1, 18),
+            Ok((U256::from(1000000u32), U256::ZERO))
+        );
+
+        // Token1 holdings cannot, and this is not reported as an overflow
+        create(&mut pool, OrderDirection::SellToken1);
+        assert_eq!(
+            pool.owner_unrealized_value(owner(), reserve_0, reserve_1, 18),
+            Err(OrderError::MathFailed("Spot price rounds to zero"))
+        );
+        assert!(pool
+            .owner_unrealized_value(owner(), reserve_0, reserve_1, 36)
+            .is_ok());
+    }
+
+    /// A pool holding one target-priced order selling 1000000 token0 over 100 blocks at 1:1
+    fn target_priced_pool() -> (OrderPool, U256) {
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        pool.set_order_target_price(order_id, owner(), Some(U256::from(10u128.pow(18))))
+            .unwrap();
+        (pool, order_id)
+    }
+
+    #[test]
+    fn test_target_price_accelerates_on_favorable_price() {
+        let (mut pool, order_id) = target_priced_pool();
+        let reserve_0 = U256::from(100000000u32);
+
+        // Spot 1.5 token1 per token0 beats the target by half, so ten blocks sell 1.5x schedule
+        let result = pool
+            .execute_virtual_orders(U256::from(10u32), reserve_0, U256::from(150000000u32))
+            .unwrap();
+        assert_eq!(result.amount_0_sold, U256::from(150000u32));
+        assert_eq!(
+            pool.get_order(order_id).unwrap().remaining_amount,
+            U256::from(850000u32)
+        );
+
+        // A far better price is held to the cap
+        let result = pool
+            .execute_virtual_orders(U256::from(20u32), reserve_0, U256::from(500000000u32))
+            .unwrap();
+        assert_eq!(result.amount_0_sold, U256::from(850000u32 * 10 / 90 * 2));
+    }
+
+    #[test]
+    fn test_target_price_decelerates_on_unfavorable_price_and_still_finishes() {
+        let (mut pool, order_id) = target_priced_pool();
+        let reserve = U256::from(100000000u32);
+
+        // Spot 0.8 is worse than target, so ten blocks sell only 0.8x schedule
+        let slowed = pool
+            .execute_virtual_orders(U256::from(10u32), reserve, U256::from(80000000u32))
+            .unwrap();
+        assert_eq!(slowed.amount_0_sold, U256::from(80000u32));
+
+        // A far worse price is held to the floor
+        let floored = pool
+            .execute_virtual_orders(U256::from(20u32), reserve, U256::from(10000000u32))
+            .unwrap();
+        assert_eq!(floored.amount_0_sold, U256::from(920000u32 * 10 / 90 / 2));
+        let remaining = pool.get_order(order_id).unwrap().remaining_amount;
+
+        // The shortfall is caught up and the order is fully sold by its end block
+        let rest = pool
+            .execute_virtual_orders(U256::from(100u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(rest.amount_0_sold, remaining);
+        let order = pool.get_order(order_id).unwrap();
+        assert_eq!(order.status, OrderStatus::Completed);
+        assert_eq!(order.remaining_amount, U256::ZERO);
+    }
+
+    #[test]
+    fn test_target_price_setter_checks_owner_and_value() {
+        let (mut pool, order_id) = target_priced_pool();
+        assert_eq!(
+            pool.set_order_target_price(order_id, Address::repeat_byte(0x99), None),
+            Err(OrderError::NotOwner)
+        );
+        assert_eq!(
+            pool.set_order_target_price(order_id, owner(), Some(U256::ZERO)),
+            Err(OrderError::InvalidParams("Invalid target price"))
+        );
+
+        // Clearing the target restores the fixed rate
+        pool.set_order_target_price(order_id, owner(), None)
+            .unwrap();
+        let reserve = U256::from(100000000u32);
+        let result = pool
+            .execute_virtual_orders(U256::from(10u32), reserve, U256::from(150000000u32))
+            .unwrap();
+        assert_eq!(result.amount_0_sold, U256::from(100000u32));
+    }
+
+    #[test]
+    fn test_merge_orders_combines_amounts_and_rates() {
+        let mut pool = OrderPool::default();
+        let reserve = U256::from(100000000u32);
+        let create = |pool: &mut OrderPool, amount: u32, duration: u32| {
+            pool.create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(amount),
+                U256::from(duration),
+                U256::ZERO,
+                U256::from(1u32),
+            )
+            .unwrap()
+        };
+        let id_a = create(&mut pool, 100000, 100);
+        let id_b = create(&mut pool, 60000, 200);
+        pool.execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        let (a, b) = (pool.get_order(id_a).unwrap(), pool.get_order(id_b).unwrap());
+
+        let merged_id = pool
+            .merge_orders(id_a, id_b, owner(), U256::from(50u32))
+            .unwrap();
+
+        // 50000 + 45000 left, streamed over the 150 blocks to the later end block
+        let merged = pool.get_order(merged_id).unwrap();
+        assert_eq!(merged.remaining_amount, U256::from(94950u32));
+        assert_eq!(merged.sell_rate, U256::from(633u32));
+        assert_eq!(merged.dust_remainder, U256::from(50u32));
+        assert_eq!(merged.end_block, U256::from(200u32));
+        assert_eq!(
+            merged.accumulated_out,
+            a.accumulated_out + b.accumulated_out
+        );
+        assert_eq!(merged.min_output_total, U256::from(2u32));
+        assert_eq!(merged.amount_sold(), a.amount_sold() + b.amount_sold());
+
+        // The originals are closed as cancelled and only the merged rate remains
+        for order_id in [id_a, id_b] {
+            assert_eq!(
+                pool.get_order(order_id).unwrap().status,
+                OrderStatus::Cancelled
+            );
+            assert_eq!(pool.order_index(order_id), None);
+            assert_eq!(
+                pool.cancel_order(order_id, owner(), U256::from(50u32)),
+                Err(OrderError::InvalidState("Order already cancelled"))
+            );
+        }
+        assert_eq!(pool.get_active_orders_count(), 1);
+        assert_eq!(
+            pool.get_current_sell_rates(),
+            (U256::from(633u32), U256::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_merge_orders_rejects_mismatched_orders() {
+        let mut pool = OrderPool::default();
+        let create = |pool: &mut OrderPool, owner: Address, direction| {
+            pool.create_long_term_order(
+                owner,
+                direction,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap()
+        };
+        let sell_0 = create(&mut pool, owner(), OrderDirection::SellToken0);
+        let sell_1 = create(&mut pool, owner(), OrderDirection::SellToken1);
+        let other = create(
+            &mut pool,
+            Address::repeat_byte(0x99),
+            OrderDirection::SellToken0,
+        );
+
+        assert_eq!(
+            pool.merge_orders(sell_0, sell_1, owner(), U256::ZERO),
+            Err(OrderError::InvalidParams("Orders differ in direction"))
+        );
+        assert_eq!(
+            pool.merge_orders(sell_0, other, owner(), U256::ZERO),
+            Err(OrderError::NotOwner)
+        );
+        assert_eq!(
+            pool.merge_orders(sell_0, sell_0, owner(), U256::ZERO),
+            Err(OrderError::InvalidParams("Cannot merge order with itself"))
+        );
+        assert_eq!(
+            pool.merge_orders(sell_0, sell_1, owner(), U256::from(10u32)),
+            Err(OrderError::InvalidState("Virtual orders pending"))
+        );
+        pool.pause();
+        assert_eq!(
+            pool.merge_orders(sell_0, sell_1, owner(), U256::ZERO),
+            Err(OrderError::Paused)
+        );
+        pool.unpause();
+
+        // Nothing changed
+        assert_eq!(pool.get_active_orders_count(), 3);
+        assert_eq!(
+            pool.get_current_sell_rates(),
+            (U256::from(2000u32), U256::from(1000u32))
+        );
+    }
+
+    #[test]
+    fn test_execution_reports_invariant_before_and_after() {
+        let run = |fee_bps: u32| {
+            let mut pool = OrderPool::default();
+            pool.set_fee_bps(U256::from(fee_bps)).unwrap();
+            for direction in [OrderDirection::SellToken0, OrderDirection::SellToken1] {
+                pool.create_long_term_order(
+                    owner(),
+                    direction,
+                    U256::from(1000000u32),
+                    U256::from(100u32),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap();
+            }
+            pool.execute_virtual_orders(
+                U256::from(100u32),
+                U256::from(100000000u32),
+                U256::from(200000000u32),
+            )
+            .unwrap()
+        };
+
+        let with_fee = run(30);
+        assert_eq!(with_fee.k_before, U256::from(20000000000000000u64));
+        assert_eq!(
+            with_fee.k_after,
+            OrderPool::pool_invariant(with_fee.new_reserve_0, with_fee.new_reserve_1)
+        );
+        assert!(with_fee.k_after > with_fee.k_before);
+
+        // Without fees k only moves by the closed form's rounding
+        let no_fee = run(0);
+        let drift = no_fee.k_after.abs_diff(no_fee.k_before);
+        assert!(drift * U256::from(1000000u32) <= no_fee.k_before);
+    }
+
+    #[test]
+    fn test_pool_invariant_saturates() {
+        assert_eq!(
+            OrderPool::pool_invariant(U256::from(3u32), U256::from(7u32)),
+            U256::from(21u32)
+        );
+        assert_eq!(
+            OrderPool::pool_invariant(U256::MAX, U256::from(2u32)),
+            U256::MAX
+        );
+    }
+
+    #[test]
+    fn test_scheduled_order_has_no_rate_until_start() {
+        let mut pool = OrderPool::default();
+        let reserve = U256::from(100000000u32);
+        let order_id = pool
+            .create_scheduled_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(100u32),
+                (U256::ZERO, U256::from(50u32)),
+                U256::ZERO,
+            )
+            .unwrap();
+
+        let order = pool.get_order(order_id).unwrap();
+        assert_eq!(order.status, OrderStatus::Scheduled);
+        assert_eq!(order.end_block, U256::from(150u32));
+        assert_eq!(pool.get_current_sell_rates(), (U256::ZERO, U256::ZERO));
+
+        // Nothing streams before the start block
+        let before = pool
+            .execute_virtual_orders(U256::from(40u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(before.amount_0_sold, U256::ZERO);
+        assert_eq!(pool.get_current_sell_rates(), (U256::ZERO, U256::ZERO));
+
+        // Crossing the start streams only the blocks after it and activates the rate
+        let after = pool
+            .execute_virtual_orders(U256::from(60u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(after.amount_0_sold, U256::from(10000u32));
+        assert_eq!(
+            pool.get_order(order_id).unwrap().status,
+            OrderStatus::Active
+        );
+        assert_eq!(
+            pool.get_current_sell_rates(),
+            (U256::from(1000u32), U256::ZERO)
+        );
+        assert!(pool.check_invariants(U256::from(60u32)).is_ok());
+    }
+
+    #[test]
+    fn test_scheduled_order_bookkeeping() {
+        let mut pool = OrderPool::default();
+        let schedule = |pool: &mut OrderPool, start: u32| {
+            pool.create_scheduled_order(
+                owner(),
+                OrderDirection::SellToken1,
+                U256::from(10000u32),
+                U256::from(10u32),
+                (U256::from(5u32), U256::from(start)),
+                U256::ZERO,
+            )
+        };
+        assert_eq!(
+            schedule(&mut pool, 4),
+            Err(OrderError::InvalidParams("Start block in the past"))
+        );
+
+        // Cancelling before the start leaves the totals untouched
+        let cancelled = schedule(&mut pool, 50).unwrap();
+        pool.cancel_order(cancelled, owner(), U256::ZERO).unwrap();
+        assert_eq!(pool.get_current_sell_rates(), (U256::ZERO, U256::ZERO));
+
+        // An order starting and ending within one execution joins and leaves the totals
+        let order_id = schedule(&mut pool, 10).unwrap();
+        let reserve = U256::from(100000000u32);
+        let result = pool
+            .execute_virtual_orders(U256::from(30u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(result.amount_1_sold, U256::from(10000u32));
+        assert_eq!(
+            pool.get_order(order_id).unwrap().status,
+            OrderStatus::Completed
+        );
+        assert_eq!(pool.get_current_sell_rates(), (U256::ZERO, U256::ZERO));
+        assert!(pool.check_invariants(U256::from(30u32)).is_ok());
+    }
+
+    #[test]
+    fn test_pool_order_cap_rejects_without_evicting() {
+        let mut pool = OrderPool::default();
+        pool.set_max_orders(3).unwrap();
+        assert_eq!(pool.capacity_remaining(), 3);
+
+        let owners = [0x11u8, 0x22, 0x33, 0x44].map(Address::repeat_byte);
+        let create = |pool: &mut OrderPool, owner: Address| {
+            pool.create_long_term_order(
+                owner,
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+        };
+        let ids: Vec<U256> = owners[..3]
+            .iter()
+            .map(|owner| create(&mut pool, *owner).unwrap())
+            .collect();
+        assert_eq!(pool.capacity_remaining(), 0);
+
+        // The cap is pool-wide, so a fresh owner is turned away too
+        assert_eq!(
+            create(&mut pool, owners[3]),
+            Err(OrderError::LimitReached("pool order limit reached"))
+        );
+        assert_eq!(
+            pool.create_long_term_orders_batch(
+                owners[3],
+                vec![(
+                    OrderDirection::SellToken1,
+                    U256::from(1000u32),
+                    U256::from(10u32)
+                )],
+                U256::ZERO,
+            ),
+            Err(OrderError::LimitReached("pool order limit reached"))
+        );
+        assert_eq!(pool.get_active_orders_count(), 3);
+
+        // Cancelling frees a slot
+        pool.cancel_order(ids[0], owners[0], U256::ZERO).unwrap();
+        assert_eq!(pool.capacity_remaining(), 1);
+        assert!(create(&mut pool, owners[3]).is_ok());
+        assert_eq!(
+            pool.set_max_orders(0),
+            Err(OrderError::InvalidParams("Invalid order limit"))
+        );
+    }
+
+    #[test]
+    fn test_average_sell_rate_weights_overlapping_orders() {
+        let mut pool = OrderPool::default();
+        // Rate 1000 over [0, 100) and rate 3000 over [40, 80)
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(100000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(120000u32),
+            U256::from(40u32),
+            U256::from(40u32),
+            U256::ZERO,
+        )
+        .unwrap();
+
+        // [20, 100): 40 blocks at 1000 and 40 blocks at 4000 average to 2500
+        assert_eq!(
+            pool.average_sell_rate(U256::from(20u32), U256::from(100u32)),
+            Ok((U256::from(2500u32), U256::ZERO))
+        );
+
+        // The second order still counts once it completes and leaves the open orders
+        let reserve = U256::from(100000000u32);
+        pool.execute_virtual_orders(U256::from(90u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(pool.get_active_orders_count(), 1);
+        assert_eq!(
+            pool.average_sell_rate(U256::from(20u32), U256::from(100u32)),
+            Ok((U256::from(2500u32), U256::ZERO))
+        );
+        assert_eq!(
+            pool.average_sell_rate(U256::from(50u32), U256::from(50u32)),
+            Err(OrderError::InvalidParams("Invalid window"))
+        );
+    }
+
+    #[test]
+    fn test_strict_execution_rejects_zero_output() {
+        let mut pool = OrderPool::default();
+        pool.set_fee_bps(U256::from(30u32)).unwrap();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        // A sell rate of 1 is taken whole by the fee, so nothing reaches the curve
+        let reserve = U256::from(100000000u32);
+
+        let mut lenient = pool.clone();
+        let result = lenient
+            .execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(result.amount_0_sold, U256::from(50u32));
+        assert_eq!(result.amount_1_received, U256::ZERO);
+        assert_eq!(
+            lenient.get_virtual_order_state().last_virtual_order_block,
+            U256::from(50u32)
+        );
+
+        // Strict mode refuses the round and leaves the window and order untouched
+        pool.set_strict_execution(true);
+        assert_eq!(
+            pool.execute_virtual_orders(U256::from(50u32), reserve, reserve),
+            Err(OrderError::InvalidParams("zero output execution"))
+        );
+        assert_eq!(
+            pool.get_virtual_order_state().last_virtual_order_block,
+            U256::ZERO
+        );
+        assert_eq!(
+            pool.get_order(order_id).unwrap().remaining_amount,
+            U256::from(100u32)
+        );
+
+        // Rounds that pay out still go through
+        pool.set_fee_bps(U256::ZERO).unwrap();
+        let result = pool
+            .execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        assert!(result.amount_1_received > U256::ZERO);
+        assert!(pool.strict_execution);
+    }
+
+    #[test]
+    fn test_strict_execution_keeps_history_and_splits() {
+        let mut pool = OrderPool::default();
+        pool.set_strict_execution(true);
+        let create = |pool: &mut OrderPool| {
+            pool.create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap()
+        };
+        let cancelled_id = create(&mut pool);
+        pool.cancel_order(cancelled_id, owner(), U256::ZERO)
+            .unwrap();
+        let order_id = create(&mut pool);
+        let treasury = Address::repeat_byte(0x22);
+        pool.set_order_beneficiaries(order_id, owner(), vec![(treasury, U256::from(10000u32))])
+            .unwrap();
+
+        // Strict execution runs on a fork; completing the order there must merge back
+        // into the existing history, unclaimed proceeds and proceeds splits
+        let reserve = U256::from(1000000u32);
+        pool.execute_virtual_orders(U256::from(100u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(
+            pool.get_order(cancelled_id).unwrap().status,
+            OrderStatus::Cancelled
+        );
+        let completed = pool.get_order(order_id).unwrap();
+        assert_eq!(completed.status, OrderStatus::Completed);
+        assert!(pool.get_order_beneficiaries(order_id).is_some());
+
+        assert_eq!(
+            pool.claim_proceeds(order_id, owner()),
+            Ok(vec![(treasury, completed.accumulated_out)])
+        );
+        assert_eq!(pool.get_order_beneficiaries(order_id), None);
+    }
+
+    #[test]
+    fn test_ceil_rate_rounding_sells_full_amount_early() {
+        let create = |rounding: RateRounding| {
+            let mut pool = OrderPool::default();
+            pool.set_rate_rounding(rounding);
+            let order_id = pool
+                .create_long_term_order(
+                    owner(),
+                    OrderDirection::SellToken0,
+                    U256::from(100u32),
+                    U256::from(40u32),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap();
+            (pool, order_id)
+        };
+
+        // Floor streams 2 a block for all 40 blocks and refunds 20 as dust
+        let (floor_pool, floor_id) = create(RateRounding::Floor);
+        let floor = floor_pool.get_order(floor_id).unwrap();
+        assert_eq!(floor.sell_rate, U256::from(2u32));
+        assert_eq!(floor.dust_remainder, U256::from(20u32));
+
+        // Ceil streams 3 a block and is done by block 34 with nothing left over
+        let (mut pool, order_id) = create(RateRounding::Ceil);
+        let order = pool.get_order(order_id).unwrap();
+        assert_eq!(order.sell_rate, U256::from(3u32));
+        assert_eq!(order.remaining_amount, U256::from(100u32));
+        assert_eq!(order.dust_remainder, U256::ZERO);
+        assert_eq!(order.end_block, U256::from(34u32));
+
+        let reserve = U256::from(100000000u32);
+        let first = pool
+            .execute_virtual_orders(U256::from(33u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(first.amount_0_sold, U256::from(99u32));
+
+        // The last block only sells what is left
+        let last = pool
+            .execute_virtual_orders(U256::from(40u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(last.amount_0_sold, U256::from(1u32));
+        let order = pool.get_order(order_id).unwrap();
+        assert_eq!(order.status, OrderStatus::Completed);
+        assert_eq!(order.amount_sold(), U256::from(100u32));
+        assert_eq!(pool.get_current_sell_rates(), (U256::ZERO, U256::ZERO));
+    }
+
+    #[test]
+    fn test_sweep_gas_scales_with_expired_orders() {
+        let mut pool = OrderPool::default();
+        for duration in [10u32, 20, 30, 40] {
+            pool.create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(duration),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        }
+        let model = pool.gas_model;
+
+        // Each block boundary crossed adds one more expired order at the per-order cost
+        for (block, expired) in [(5u32, 0u32), (10, 1), (25, 2), (35, 3), (40, 4)] {
+            assert_eq!(
+                pool.estimate_sweep_gas(U256::from(block)),
+                model.sweep_base_gas + model.sweep_per_order_gas * U256::from(expired)
+            );
+        }
+
+        // After the sweep only the base cost is left
+        assert_eq!(pool.sweep_expired(U256::from(25u32)).unwrap().len(), 2);
+        assert_eq!(
+            pool.estimate_sweep_gas(U256::from(25u32)),
+            model.sweep_base_gas
+        );
+    }
+
+    #[test]
+    fn test_is_idle_ignores_expired_and_paused_orders() {
+        let mut pool = OrderPool::default();
+        assert!(pool.is_idle(U256::ZERO));
+
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        assert!(!pool.is_idle(U256::from(50u32)));
+
+        // Expired but not yet swept: still listed, nothing left to do
+        assert_eq!(pool.get_active_orders_count(), 1);
+        assert!(pool.is_idle(U256::from(100u32)));
+
+        // A paused order does not stream either
+        pool.pause_order(order_id, owner()).unwrap();
+        assert!(pool.is_idle(U256::from(50u32)));
+        pool.resume_order(order_id, owner()).unwrap();
+        assert!(!pool.is_idle(U256::from(50u32)));
+    }
+
+    #[test]
+    fn test_estimate_completion_block_follows_pace() {
+        let mut pool = OrderPool::default();
+        let reserve = U256::from(100000000u32);
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        let estimate = |pool: &OrderPool, block: u32, reserve_1: U256| {
+            pool.estimate_completion_block(order_id, U256::from(block), reserve, reserve_1)
+        };
+
+        // Unconstrained, the order finishes on schedule
+        assert_eq!(estimate(&pool, 0, reserve), Some(U256::from(100u32)));
+        pool.execute_virtual_orders(U256::from(30u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(estimate(&pool, 30, reserve), Some(U256::from(100u32)));
+
+        // Paused at block 30, it finishes 20 blocks late if resumed at block 50
+        pool.pause_order(order_id, owner()).unwrap();
+        assert_eq!(estimate(&pool, 50, reserve), Some(U256::from(120u32)));
+        pool.resume_order(order_id, owner()).unwrap();
+
+        // A favorable target price pulls completion in
+        pool.set_order_target_price(order_id, owner(), Some(U256::from(10u128.pow(18))))
+            .unwrap();
+        let early = estimate(&pool, 30, U256::from(200000000u32)).unwrap();
+        assert!(early < U256::from(100u32));
+
+        // While the price limit is not met there is no pace to project
+        pool.set_order_price_limit(
+            order_id,
+            owner(),
+            Some(U256::from(2u128 * 10u128.pow(18))),
+            LimitSide::Above,
+        )
+        .unwrap();
+        assert_eq!(estimate(&pool, 30, reserve), None);
+        assert_eq!(
+            pool.estimate_completion_block(U256::from(99u32), U256::ZERO, reserve, reserve),
+            None
+        );
+    }
+
+    #[test]
+    fn test_order_error_variants_and_legacy_messages() {
+        let mut pool = OrderPool::default();
+        let id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        assert_eq!(
+            pool.cancel_order(id, Address::from([9u8; 20]), U256::ZERO),
+            Err(OrderError::NotOwner)
+        );
+        assert_eq!(
+            pool.cancel_order(U256::from(999u32), owner(), U256::ZERO),
+            Err(OrderError::NotFound)
+        );
+        assert!(matches!(
+            pool.set_max_orders(0),
+            Err(OrderError::InvalidParams(_))
+        ));
+
+        pool.pause();
+        let paused = pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(1000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        );
+        assert_eq!(paused, Err(OrderError::Paused));
+
+        // The contract boundary still reverts with the original byte messages
+        assert_eq!(
+            Vec::<u8>::from(OrderError::NotOwner),
+            b"Not order owner".to_vec()
+        );
+        assert_eq!(Vec::<u8>::from(OrderError::Paused), b"Pool paused".to_vec());
+        assert_eq!(
+            Vec::<u8>::from(OrderError::Overflow("Rate overflow")),
+            b"Rate overflow".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_ramp_in_scales_effective_rate() {
+        let mut pool = OrderPool::default();
+        pool.set_ramp_blocks(U256::from(100u32));
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(1000u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        let order = pool.get_order(order_id).unwrap();
+
+        // Half the full rate at the ramp midpoint, the full rate once ramped in
+        assert_eq!(
+            order.effective_sell_rate(U256::from(50u32), pool.ramp_blocks),
+            U256::from(50u32)
+        );
+        assert_eq!(
+            pool.get_effective_sell_rates(U256::from(50u32)).unwrap(),
+            (U256::from(50u32), U256::ZERO)
+        );
+        assert_eq!(
+            pool.get_effective_sell_rates(U256::ZERO).unwrap(),
+            (U256::ZERO, U256::ZERO)
+        );
+        assert_eq!(
+            pool.get_effective_sell_rates(U256::from(100u32)).unwrap(),
+            (U256::from(100u32), U256::ZERO)
+        );
+        assert_eq!(pool.get_current_sell_rates().0, U256::from(100u32));
+
+        // The ramp sells 100 * (0 + 1 + ... + 99) / 100 over its blocks, then the full rate
+        let reserve = U256::from(10u64.pow(12));
+        let first = pool
+            .execute_virtual_orders(U256::from(500u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(first.amount_0_sold, U256::from(4950u32 + 40000));
+
+        // The shortfall is made up in the window reaching end_block
+        let second = pool
+            .execute_virtual_orders(
+                U256::from(1000u32),
+                first.new_reserve_0,
+                first.new_reserve_1,
+            )
+            .unwrap();
+        assert_eq!(
+            first.amount_0_sold + second.amount_0_sold,
+            U256::from(100000u32)
+        );
+        assert_eq!(
+            pool.get_order(order_id).unwrap().status,
+            OrderStatus::Completed
+        );
+    }
+
+    #[test]
+    fn test_rate_cap_counts_ramped_rates() {
+        let create = |pool: &mut OrderPool, block: u32| {
+            pool.create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(1000u32),
+                U256::from(block),
+                U256::ZERO,
+            )
+        };
+
+        // Without a ramp two rate-100 orders exceed a cap of 150
+        let mut flat = OrderPool::default();
+        flat.set_max_total_sell_rate(OrderDirection::SellToken0, U256::from(150u32));
+        create(&mut flat, 0).unwrap();
+        assert_eq!(
+            create(&mut flat, 10),
+            Err(OrderError::LimitReached("aggregate rate cap exceeded"))
+        );
+
+        // With a ramp the first order only counts 10 of its 100 at block 10
+        let mut ramped = OrderPool::default();
+        ramped.set_max_total_sell_rate(OrderDirection::SellToken0, U256::from(150u32));
+        ramped.set_ramp_blocks(U256::from(100u32));
+        create(&mut ramped, 0).unwrap();
+        create(&mut ramped, 10).unwrap();
+
+        // By block 90 the two count 90 + 80, so a third order no longer fits
+        assert_eq!(
+            create(&mut ramped, 90),
+            Err(OrderError::LimitReached("aggregate rate cap exceeded"))
+        );
+        assert_eq!(ramped.orders.len(), 2);
+    }
+
+    #[test]
+    fn test_order_lookup_survives_interleaved_removals() {
+        let mut pool = OrderPool::default();
+        let mut order_ids = Vec::new();
+        for duration in [100u32, 400, 200, 400, 300, 400] {
+            order_ids.push(
+                pool.create_long_term_order(
+                    owner(),
+                    OrderDirection::SellToken0,
+                    U256::from(100000u32),
+                    U256::from(duration),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap(),
+            );
+        }
+
+        let reserve = U256::from(10u64.pow(12));
+        pool.execute_virtual_orders(U256::from(150u32), reserve, reserve)
+            .unwrap();
+        pool.cancel_order(order_ids[3], owner(), U256::ZERO)
+            .unwrap();
+        pool.execute_virtual_orders(U256::from(350u32), reserve, reserve)
+            .unwrap();
+
+        // Orders 0, 2 and 4 completed and 3 was cancelled; 1 and 5 keep their relative order
+        let open: Vec<U256> = pool.orders.iter().map(|order| order.id).collect();
+        assert_eq!(open, vec![order_ids[1], order_ids[5]]);
+        for (index, &order_id) in order_ids.iter().enumerate() {
+            assert_eq!(pool.get_order(order_id).unwrap().id, order_id);
+            let expected = match index {
+                3 => OrderStatus::Cancelled,
+                1 | 5 => OrderStatus::Active,
+                _ => OrderStatus::Completed,
+            };
+            assert_eq!(pool.get_order(order_id).unwrap().status, expected);
+        }
+
+        let positions = pool.order_positions();
+        assert_eq!(positions.len(), 2);
+        for (order_id, index) in positions {
+            assert_eq!(pool.orders[index].id, order_id);
+            assert_eq!(pool.order_index(order_id), Some(index));
+        }
+        assert_eq!(pool.order_index(order_ids[0]), None);
+
+        // Completions are recorded in pool order
+        let history: Vec<U256> = pool.history.iter().map(|order| order.id).collect();
+        assert_eq!(
+            history,
+            vec![order_ids[0], order_ids[3], order_ids[2], order_ids[4]]
+        );
+    }
+
+    #[test]
+    fn test_fair_mid_price_reflects_net_pending_flow() {
+        let mut pool = OrderPool::default();
+        let reserve = U256::from(10u64.pow(12));
+        let one = U256::from(10u128.pow(18));
+        for (direction, amount) in [
+            (OrderDirection::SellToken0, 3000000u32),
+            (OrderDirection::SellToken1, 1000000u32),
+        ] {
+            pool.create_long_term_order(
+                owner(),
+                direction,
+                U256::from(amount),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        }
+
+        // Nothing pending yet: the fair mid is the spot price
+        assert_eq!(
+            pool.fair_mid_price(U256::ZERO, reserve, reserve, 18)
+                .unwrap(),
+            one
+        );
+
+        // Heavier SellToken0 pressure pushes the mid below spot, more so with more pending
+        let early = pool
+            .fair_mid_price(U256::from(10u32), reserve, reserve, 18)
+            .unwrap();
+        let late = pool
+            .fair_mid_price(U256::from(50u32), reserve, reserve, 18)
+            .unwrap();
+        assert!(early < one);
+        assert!(late < early);
+
+        // After 10 blocks 300000 token0 nets against 100000 token1, leaving 200000 to sell
+        let net = U256::from(200000u32);
+        let expected = OrderPool::spot_price(
+            reserve + net,
+            TWAMMath::mul_div(reserve, reserve, reserve + net).unwrap(),
+            18,
+        )
+        .unwrap();
+        assert_eq!(early, expected);
+
+        // Flipping the pressure moves the mid above spot
+        let mut opposite = OrderPool::default();
+        opposite
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken1,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        assert!(
+            opposite
+                .fair_mid_price(U256::from(10u32), reserve, reserve, 18)
+                .unwrap()
+                > one
+        );
+        assert_eq!(
+            pool.fair_mid_price(U256::from(10u32), U256::ZERO, reserve, 18),
+            Err(OrderError::InvalidParams("Invalid reserves"))
+        );
+    }
+
+    #[test]
+    fn test_execution_due_on_price_deviation() {
+        let mut pool = OrderPool::default();
+        pool.set_max_price_deviation(U256::from(100u32));
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(100000u32),
+            U256::from(1000u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+
+        // Before any execution there is no reference price, so only time counts
+        let reserve = U256::from(10u64.pow(12));
+        let moved = reserve + reserve / U256::from(20u32);
+        assert!(!pool.needs_virtual_order_execution(U256::from(10u32), reserve, moved));
+
+        let result = pool
+            .execute_virtual_orders(U256::from(100u32), reserve, reserve)
+            .unwrap();
+        let (reserve_0, reserve_1) = (result.new_reserve_0, result.new_reserve_1);
+        assert_eq!(
+            pool.virtual_order_state.last_execution_price,
+            OrderPool::spot_price(reserve_0, reserve_1, 18).unwrap()
+        );
+
+        // Low activity: the price holds, so execution waits for the block interval
+        assert!(!pool.needs_virtual_order_execution(U256::from(150u32), reserve_0, reserve_1));
+        assert!(pool.needs_virtual_order_execution(U256::from(200u32), reserve_0, reserve_1));
+
+        // Volatile: a 5% move makes execution due right away, in either direction
+        let up = reserve_1 + reserve_1 / U256::from(20u32);
+        let down = reserve_1 - reserve_1 / U256::from(20u32);
+        assert!(pool.needs_virtual_order_execution(U256::from(120u32), reserve_0, up));
+        assert!(pool.needs_virtual_order_execution(U256::from(120u32), reserve_0, down));
+
+        // A move inside the threshold is not enough
+        let small = reserve_1 + reserve_1 / U256::from(200u32);
+        assert!(!pool.needs_virtual_order_execution(U256::from(120u32), reserve_0, small));
+        assert!(pool
+            .execute_if_due(U256::from(120u32), reserve_0, small)
+            .unwrap()
+            .is_none());
+        assert!(pool
+            .execute_if_due(U256::from(120u32), reserve_0, up)
+            .unwrap()
+            .is_some());
+
+        // Disabled, only the interval triggers
+        pool.set_max_price_deviation(U256::ZERO);
+        assert!(!pool.needs_virtual_order_execution(
+            U256::from(130u32),
+            reserve_0,
+            reserve_1 * U256::from(2u32)
+        ));
+    }
+
+    #[test]
+    fn test_active_owners_are_deduplicated() {
+        let mut pool = OrderPool::default();
+        assert!(pool.get_active_owners().is_empty());
+        assert_eq!(pool.active_owner_count(), 0);
+
+        let alice = Address::from([1u8; 20]);
+        let bob = Address::from([2u8; 20]);
+        let carol = Address::from([3u8; 20]);
+        let mut order_ids = Vec::new();
+        for account in [bob, alice, bob, carol, alice, bob] {
+            order_ids.push(
+                pool.create_long_term_order(
+                    account,
+                    OrderDirection::SellToken0,
+                    U256::from(100000u32),
+                    U256::from(100u32),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap(),
+            );
+        }
+
+        assert_eq!(pool.get_active_owners(), vec![alice, bob, carol]);
+        assert_eq!(pool.active_owner_count(), 3);
+
+        // An owner drops out only once their last order is gone
+        pool.cancel_order(order_ids[3], carol, U256::ZERO).unwrap();
+        pool.cancel_order(order_ids[1], alice, U256::ZERO).unwrap();
+        assert_eq!(pool.get_active_owners(), vec![alice, bob]);
+        assert_eq!(pool.active_owner_count(), 2);
+    }
+
+    #[test]
+    fn test_realized_slippage_grows_with_order_size() {
+        let reserve = U256::from(10u128.pow(24));
+        let slippage = |amount: u128| {
+            let mut pool = OrderPool::default();
+            pool.create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(amount),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+            let result = pool
+                .execute_virtual_orders(U256::from(100u32), reserve, reserve * U256::from(2u32))
+                .unwrap();
+
+            // At the 1:2 spot price every token0 sold should have bought two token1
+            assert_eq!(result.expected_1, result.amount_0_sold * U256::from(2u32));
+            assert_eq!(result.expected_0, U256::ZERO);
+            assert!(result.amount_1_received <= result.expected_1);
+            result.realized_slippage_bps
+        };
+
+        // An order this small against the reserves trades at the spot price
+        assert_eq!(slippage(10u128.pow(9)), U256::ZERO);
+
+        let medium = slippage(10u128.pow(22));
+        let large = slippage(10u128.pow(23));
+        assert!(medium > U256::ZERO);
+        assert!(large > medium);
+        // Selling 10% of the reserve returns 1 / 1.1 of the spot amount, about 909 bps short
+        assert!(large.abs_diff(U256::from(909u32)) <= U256::from(1u32));
+    }
+
+    #[test]
+    fn test_backfill_matches_regular_execution() {
+        // A token0 sale that stops streaming once the price falls below 0.95
+        let setup = || {
+            let mut pool = OrderPool::default();
+            let order_id = pool
+                .create_long_term_order(
+                    owner(),
+                    OrderDirection::SellToken0,
+                    U256::from(200000u32),
+                    U256::from(1000u32),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap();
+            pool.set_order_price_limit(
+                order_id,
+                owner(),
+                Some(U256::from(95u128 * 10u128.pow(16))),
+                LimitSide::Above,
+            )
+            .unwrap();
+            // A single execution only re-reads the price every 500 blocks
+            pool.set_order_block_interval(U256::from(500u32)).unwrap();
+            pool
+        };
+        let reserve = U256::from(2000000u32);
+
+        // Regular executions every 100 blocks
+        let mut regular = setup();
+        let (mut reserve_0, mut reserve_1) = (reserve, reserve);
+        let mut regular_results = Vec::new();
+        for step in 1..=5u32 {
+            let result = regular
+                .execute_virtual_orders(U256::from(step * 100), reserve_0, reserve_1)
+                .unwrap();
+            (reserve_0, reserve_1) = (result.new_reserve_0, result.new_reserve_1);
+            regular_results.push(result);
+        }
+
+        let mut backfilled = setup();
+        let results = backfilled
+            .backfill_executions(U256::from(500u32), U256::from(100u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(results, regular_results);
+        assert_eq!(backfilled, regular);
+
+        // One giant catch-up reads the price once and sells straight through the limit
+        let mut caught_up = setup();
+        let giant = caught_up
+            .execute_virtual_orders(U256::from(500u32), reserve, reserve)
+            .unwrap();
+        let backfilled_sold: U256 = results.iter().map(|result| result.amount_0_sold).sum();
+        assert_eq!(giant.amount_0_sold, U256::from(100000u32));
+        assert!(backfilled_sold < giant.amount_0_sold);
+        assert_ne!(giant.new_reserve_0, reserve_0);
+
+        // A failing step leaves the pool untouched
+        let mut failing = setup();
+        let before = failing.clone();
+        assert_eq!(
+            failing.backfill_executions(U256::from(500u32), U256::ZERO, reserve, reserve),
+            Err(OrderError::InvalidParams("Invalid block interval"))
+        );
+        failing.pause();
+        let paused = failing.clone();
+        assert_eq!(
+            failing.backfill_executions(U256::from(500u32), U256::from(100u32), reserve, reserve),
+            Err(OrderError::Paused)
+        );
+        assert_eq!(failing, paused);
+        assert_ne!(before, paused);
+    }
+
+    #[test]
+    fn test_liquidity_policies_on_drained_pool() {
+        let mut pool = OrderPool::default();
+        let short_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(50u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        let long_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        assert_eq!(pool.liquidity_policy, LiquidityPolicy::Halt);
+
+        // A single unit of token1 is drained by the first segment
+        let reserve_0 = U256::from(1000u32);
+        let reserve_1 = U256::from(1u32);
+
+        // Halt fails without advancing the pool
+        let mut halted = pool.clone();
+        assert!(halted
+            .execute_virtual_orders(U256::from(100u32), reserve_0, reserve_1)
+            .is_err());
+        assert_eq!(
+            halted.virtual_order_state.last_virtual_order_block,
+            U256::ZERO
+        );
+
+        // Throttle lets the stranded order finish holding its unsold amount
+        let mut throttled = pool.clone();
+        throttled.set_liquidity_policy(LiquidityPolicy::Throttle);
+        let result = throttled
+            .execute_virtual_orders(U256::from(100u32), reserve_0, reserve_1)
+            .unwrap();
+        assert_eq!(result.unfilled_0, U256::from(500000u32));
+        assert_eq!(result.cancelled_payout_0, U256::ZERO);
+        let stranded = throttled.get_order(long_id).unwrap();
+        assert_eq!(stranded.status, OrderStatus::Completed);
+        assert_eq!(stranded.remaining_amount, U256::from(500000u32));
+
+        // Cancel refunds the stranded order and drops its rate
+        pool.set_liquidity_policy(LiquidityPolicy::Cancel);
+        let result = pool
+            .execute_virtual_orders(U256::from(100u32), reserve_0, reserve_1)
+            .unwrap();
+        assert_eq!(result.unfilled_0, U256::from(500000u32));
+        assert_eq!(result.cancelled_payout_0, U256::from(500000u32));
+        assert_eq!(result.cancelled_payout_1, stranded.accumulated_out);
+        let cancelled = pool.get_order(long_id).unwrap();
+        assert_eq!(cancelled.status, OrderStatus::Cancelled);
+        assert_eq!(pool.order_index(long_id), None);
+        assert_eq!(
+            pool.get_order(short_id).unwrap().status,
+            OrderStatus::Completed
+        );
+        assert_eq!(pool.total_sell_rate_0, U256::ZERO);
+        assert!(pool.check_invariants(U256::from(100u32)).is_ok());
+    }
+
+    #[test]
+    fn test_order_effective_cost_grows_with_size() {
+        let mut template = OrderPool::default();
+        template.set_fee_bps(U256::from(30u32)).unwrap();
+        let reserve = U256::from(100000000u32);
+
+        let cost_of = |amount: u32| {
+            let mut pool = template.clone();
+            let order_id = pool
+                .create_long_term_order(
+                    owner(),
+                    OrderDirection::SellToken0,
+                    U256::from(amount),
+                    U256::from(100u32),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap();
+            assert_eq!(pool.order_effective_cost_bps(order_id, 18), None);
+            pool.execute_virtual_orders(U256::from(90u32), reserve, reserve)
+                .unwrap();
+            let cost = pool.order_effective_cost_bps(order_id, 18).unwrap();
+
+            // Claiming clears the proceeds the cost is measured on
+            pool.claim_proceeds(order_id, owner()).unwrap();
+            assert_eq!(pool.order_effective_cost_bps(order_id, 18), None);
+            cost
+        };
+
+        // Both pay the 30 bps fee; the larger order also moves the price further
+        let small = cost_of(1000000);
+        let large = cost_of(20000000);
+        assert!(small >= U256::from(30u32));
+        assert!(large > small);
+    }
+
+    #[test]
+    fn test_cumulative_volumes_survive_finished_orders() {
+        let mut pool = OrderPool::default();
+        let seller_0 = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken1,
+            U256::from(50000u32),
+            U256::from(50u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+        assert_eq!(pool.get_cumulative_volumes(), (U256::ZERO, U256::ZERO));
+
+        let mut reserves = (U256::from(100000000u32), U256::from(100000000u32));
+        let mut previous = pool.get_cumulative_volumes();
+        for block in [20u32, 50, 70] {
+            let result = pool
+                .execute_virtual_orders(U256::from(block), reserves.0, reserves.1)
+                .unwrap();
+            reserves = (result.new_reserve_0, result.new_reserve_1);
+
+            let volumes = pool.get_cumulative_volumes();
+            assert_eq!(volumes.0, previous.0 + result.amount_0_sold);
+            assert_eq!(volumes.1, previous.1 + result.amount_1_sold);
+            previous = volumes;
+        }
+
+        // The token1 order completed at block 50 and the token0 order is cancelled; both
+        // keep their executed volume in the counters
+        pool.cancel_order(seller_0, owner(), U256::ZERO).unwrap();
+        assert_eq!(pool.orders.len(), 0);
+        assert_eq!(
+            pool.get_cumulative_volumes(),
+            (U256::from(70000u32), U256::from(50000u32))
+        );
+    }
+
+    #[test]
+    fn test_preview_rate_after_change_matches_merge() {
+        let mut pool = OrderPool::default();
+        let reserve = U256::from(100000000u32);
+        let id_a = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        let id_b = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(60000u32),
+                U256::from(200u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        pool.execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        let (a, b) = (pool.get_order(id_a).unwrap(), pool.get_order(id_b).unwrap());
+
+        // Folding a's remainder into b over b's window is what merging does
+        let current_block = U256::from(50u32);
+        let preview = pool.preview_rate_after_change(
+            id_b,
+            a.remaining_amount + b.remaining_amount,
+            b.end_block,
+            current_block,
+        );
+        let before = pool.clone();
+        let merged_id = pool
+            .merge_orders(id_a, id_b, owner(), current_block)
+            .unwrap();
+        assert_eq!(preview, Some(pool.get_order(merged_id).unwrap().sell_rate));
+
+        // The preview left the pool as it was
+        assert_eq!(before.get_order(id_b), Some(b));
+
+        // Unknown orders, windows already over and zero rates have nothing to preview
+        assert_eq!(
+            before.preview_rate_after_change(
+                U256::from(99u32),
+                U256::from(1000u32),
+                b.end_block,
+                current_block
+            ),
+            None
+        );
+        assert_eq!(
+            before.preview_rate_after_change(
+                id_b,
+                b.remaining_amount,
+                current_block,
+                current_block
+            ),
+            None
+        );
+        assert_eq!(
+            before.preview_rate_after_change(id_b, U256::from(100u32), b.end_block, current_block),
+            None
+        );
+    }
+
+    #[test]
+    fn test_order_match_ratio_offsetting_and_one_sided() {
+        let reserve = U256::from(100000000u32);
+        let create = |pool: &mut OrderPool, direction: OrderDirection| {
+            pool.create_long_term_order(
+                owner(),
+                direction,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap()
+        };
+
+        // Equal opposing flows at a 1:1 price cross almost entirely
+        let mut pool = OrderPool::default();
+        let seller_0 = create(&mut pool, OrderDirection::SellToken0);
+        let seller_1 = create(&mut pool, OrderDirection::SellToken1);
+        assert_eq!(pool.order_match_ratio(seller_0), None);
+        pool.execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        for order_id in [seller_0, seller_1] {
+            let order = pool.get_order(order_id).unwrap();
+            assert_eq!(
+                order.matched_volume + order.curve_volume,
+                order.amount_sold()
+            );
+            assert!(pool.order_match_ratio(order_id).unwrap() >= U256::from(9900u32));
+        }
+
+        // A lone seller has nothing to cross against
+        let mut pool = OrderPool::default();
+        let lone = create(&mut pool, OrderDirection::SellToken0);
+        pool.execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(pool.order_match_ratio(lone), Some(U256::ZERO));
+        assert_eq!(
+            pool.get_order(lone).unwrap().curve_volume,
+            U256::from(500000u32)
+        );
+    }
+
+    #[test]
+    fn test_spot_price_fraction_keeps_extreme_ratios() {
+        // One unit of token1 against 10^30 of token0 is below 10^-18 per token0
+        let reserve_0 = U256::from(10u128.pow(30));
+        let reserve_1 = U256::from(1u32);
+        assert_eq!(
+            OrderPool::spot_price(reserve_0, reserve_1, 18),
+            Ok(U256::ZERO)
+        );
+        assert_eq!(
+            OrderPool::spot_price_fraction(reserve_0, reserve_1),
+            Ok((reserve_1, reserve_0))
+        );
+
+        // Scaling the fraction reproduces the rounded price
+        let (numerator, denominator) =
+            OrderPool::spot_price_fraction(U256::from(3u32), U256::from(7u32)).unwrap();
+        assert_eq!(
+            numerator * U256::from(10u128.pow(18)) / denominator,
+            OrderPool::spot_price(U256::from(3u32), U256::from(7u32), 18).unwrap()
+        );
+
+        assert_eq!(
+            OrderPool::spot_price_fraction(U256::ZERO, reserve_1),
+            Err(OrderError::InvalidParams("Invalid reserves"))
+        );
+    }
+
+    #[test]
+    fn test_cancel_expired_for_owner_keeps_live_orders() {
+        let mut pool = OrderPool::default();
+        let other = Address::from([0x22; 20]);
+        let create = |pool: &mut OrderPool, owner: Address, duration: u32| {
+            pool.create_long_term_order(
+                owner,
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(duration),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap()
+        };
+
+        // A ladder ending at blocks 50, 100, 200 and 400, plus another owner's expired order
+        let ladder: Vec<U256> = [50u32, 100, 200, 400]
+            .iter()
+            .map(|&duration| create(&mut pool, owner(), duration))
+            .collect();
+        let foreign = create(&mut pool, other, 50);
+        let (rate_0, _) = pool.get_current_sell_rates();
+
+        let cancelled = pool
+            .cancel_expired_for_owner(owner(), U256::from(100u32))
+            .unwrap();
+        assert_eq!(cancelled, vec![ladder[0], ladder[1]]);
+
+        for &order_id in &ladder[..2] {
+            assert_eq!(pool.order_index(order_id), None);
+            assert_eq!(
+                pool.get_order_status(order_id),
+                Some(OrderStatus::Cancelled)
+            );
+        }
+        for &order_id in &ladder[2..] {
+            assert!(pool.order_index(order_id).is_some());
+        }
+        assert!(pool.order_index(foreign).is_some());
+
+        // Only the two cancelled rates (2000 and 1000) leave the total
+        assert_eq!(
+            pool.get_current_sell_rates().0,
+            rate_0 - U256::from(3000u32)
+        );
+        assert!(pool.check_invariants(U256::from(100u32)).is_ok());
+
+        // Nothing else has expired yet
+        assert_eq!(
+            pool.cancel_expired_for_owner(owner(), U256::from(100u32)),
+            Ok(Vec::new())
+        );
+    }
+
+    #[test]
+    fn test_blocks_to_fill_amount() {
+        let mut pool = OrderPool::default();
+        let reserve = U256::from(100000000u32);
+        // 1000 per block over blocks 0-100
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        pool.execute_virtual_orders(U256::from(20u32), reserve, reserve)
+            .unwrap();
+
+        // 20000 sold by block 20 and 5000 more streamed by block 25
+        let blocks = |target: u32| {
+            pool.blocks_to_fill_amount(order_id, U256::from(target), U256::from(25u32))
+        };
+        assert_eq!(blocks(50000), Some(U256::from(25u32)));
+        assert_eq!(blocks(50500), Some(U256::from(26u32)));
+        assert_eq!(blocks(25000), Some(U256::ZERO));
+        assert_eq!(blocks(10000), Some(U256::ZERO));
+        assert_eq!(blocks(100000), Some(U256::from(75u32)));
+
+        // More than the order ever sells
+        assert_eq!(blocks(100001), None);
+        assert_eq!(
+            pool.blocks_to_fill_amount(U256::from(99u32), U256::from(1u32), U256::ZERO),
+            None
+        );
+    }
+
+    #[test]
+    fn test_order_finishing_mid_block_shares_by_amount_sold() {
+        let mut pool = OrderPool::default();
+        pool.set_rate_rounding(RateRounding::Ceil);
+        let create = |pool: &mut OrderPool, amount: u32, duration: u32| {
+            pool.create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(amount),
+                U256::from(duration),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap()
+        };
+        // 3334 a block, running out with 3134 in its last block (299-300)
+        let short_id = create(&mut pool, 1000000, 300);
+        let long_id = create(&mut pool, 10000000, 1000);
+
+        let reserve = U256::from(1_000_000_000_000u64);
+        let before = pool
+            .execute_virtual_orders(U256::from(299u32), reserve, reserve)
+            .unwrap();
+        let short_before = pool.get_order(short_id).unwrap().accumulated_out;
+        let long_before = pool.get_order(long_id).unwrap().accumulated_out;
+        assert_eq!(
+            pool.get_order(short_id).unwrap().remaining_amount,
+            U256::from(3134u32)
+        );
+
+        let last = pool
+            .execute_virtual_orders(
+                U256::from(300u32),
+                before.new_reserve_0,
+                before.new_reserve_1,
+            )
+            .unwrap();
+        assert_eq!(last.amount_0_sold, U256::from(13134u32));
+        let short_share = pool.get_order(short_id).unwrap().accumulated_out - short_before;
+        let long_share = pool.get_order(long_id).unwrap().accumulated_out - long_before;
+
+        // The finishing order is paid for the 3134 it sold, not its nominal 3334 rate
+        let received = last.amount_1_received;
+        assert_eq!(
+            short_share,
+            received * U256::from(3134u32) / U256::from(13134u32)
+        );
+        assert!(short_share < received * U256::from(3334u32) / U256::from(13334u32));
+        assert!(short_share + long_share <= received);
+    }
+
+    #[test]
+    fn test_swapped_reserves_rejected() {
+        let mut pool = OrderPool::default();
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(100000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+        pool.set_reserve_swap_tolerance(U256::from(500u32)).unwrap();
+
+        // Token1 is worth a quarter of token0
+        let reserve_0 = U256::from(10000000u32);
+        let reserve_1 = U256::from(40000000u32);
+        let first = pool
+            .execute_virtual_orders(U256::from(10u32), reserve_0, reserve_1)
+            .unwrap();
+
+        // Passing the same pool the wrong way round inverts its price and is caught
+        let before = pool.clone();
+        assert_eq!(
+            pool.execute_virtual_orders(
+                U256::from(20u32),
+                first.new_reserve_1,
+                first.new_reserve_0
+            ),
+            Err(OrderError::InvalidParams("Reserves appear swapped"))
+        );
+        assert_eq!(pool, before);
+
+        // A genuine move that does not land near the inverse price still executes
+        pool.execute_virtual_orders(
+            U256::from(20u32),
+            first.new_reserve_0,
+            first.new_reserve_1 / U256::from(2u32),
+        )
+        .unwrap();
+
+        // Disabled, the swapped call goes through
+        let mut unchecked = before.clone();
+        unchecked.set_reserve_swap_tolerance(U256::ZERO).unwrap();
+        assert!(unchecked
+            .execute_virtual_orders(U256::from(20u32), first.new_reserve_1, first.new_reserve_0)
+            .is_ok());
+
+        assert!(pool
+            .set_reserve_swap_tolerance(U256::from(10001u32))
+            .is_err());
+    }
+
+    #[test]
+    fn test_total_remaining_principal() {
+        let mut pool = OrderPool::default();
+        for (direction, amount) in [
+            (OrderDirection::SellToken0, 100000u32),
+            (OrderDirection::SellToken0, 50000u32),
+            (OrderDirection::SellToken1, 80000u32),
+        ] {
+            pool.create_long_term_order(
+                owner(),
+                direction,
+                U256::from(amount),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            pool.total_remaining_principal(),
+            Ok((U256::from(150000u32), U256::from(80000u32)))
+        );
+
+        let reserve = U256::from(100000000u32);
+        let result = pool
+            .execute_virtual_orders(U256::from(40u32), reserve, reserve)
+            .unwrap();
+        let (remaining_0, remaining_1) = pool.total_remaining_principal().unwrap();
+        assert_eq!(remaining_0, U256::from(150000u32) - result.amount_0_sold);
+        assert_eq!(remaining_1, U256::from(80000u32) - result.amount_1_sold);
+
+        pool.execute_virtual_orders(
+            U256::from(100u32),
+            result.new_reserve_0,
+            result.new_reserve_1,
+        )
+        .unwrap();
+        assert_eq!(
+            pool.total_remaining_principal(),
+            Ok((U256::ZERO, U256::ZERO))
+        );
+    }
+
+    #[test]
+    fn test_min_blocks_before_cancel() {
+        let mut pool = OrderPool::default();
+        pool.set_min_blocks_before_cancel(U256::from(20u32));
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::from(10u32),
+                U256::ZERO,
+            )
+            .unwrap();
+        let start_block = pool.get_order(order_id).unwrap().start_block;
+
+        let before = pool.clone();
+        assert_eq!(
+            pool.cancel_order(order_id, owner(), start_block + U256::from(19u32)),
+            Err(OrderError::InvalidState("cancel too early"))
+        );
+        assert_eq!(
+            pool.cancel_order_with_refund(order_id, owner(), start_block),
+            Err(OrderError::InvalidState("cancel too early"))
+        );
+        assert_eq!(pool, before);
+
+        // Ownership is still checked first
+        assert_eq!(
+            pool.cancel_order(order_id, Address::repeat_byte(0x99), start_block),
+            Err(OrderError::NotOwner)
+        );
+
+        let cancelled = pool
+            .cancel_order(order_id, owner(), start_block + U256::from(20u32))
+            .unwrap();
+        assert_eq!(cancelled.status, OrderStatus::Cancelled);
+    }
+
+    #[test]
+    fn test_min_blocks_before_cancel_exempts_scheduled_orders_and_paused_pool() {
+        let mut pool = OrderPool::default();
+        pool.set_min_blocks_before_cancel(U256::from(20u32));
+        let scheduled_id = pool
+            .create_scheduled_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(100u32),
+                (U256::from(10u32), U256::from(50u32)),
+                U256::ZERO,
+            )
+            .unwrap();
+        let streaming_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken1,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::from(10u32),
+                U256::ZERO,
+            )
+            .unwrap();
+
+        // An order that has not started can be withdrawn before the window
+        assert_eq!(
+            pool.get_order_status(scheduled_id),
+            Some(OrderStatus::Scheduled)
+        );
+        let (refund, _) = pool
+            .cancel_order_with_refund(scheduled_id, owner(), U256::from(11u32))
+            .unwrap();
+        assert_eq!(refund, U256::from(100000u32));
+
+        // Streaming orders wait out the window until the pool is paused
+        assert_eq!(
+            pool.cancel_order(streaming_id, owner(), U256::from(11u32)),
+            Err(OrderError::InvalidState("cancel too early"))
+        );
+        pool.pause();
+        let cancelled = pool
+            .cancel_order(streaming_id, owner(), U256::from(11u32))
+            .unwrap();
+        assert_eq!(cancelled.status, OrderStatus::Cancelled);
+        assert_eq!(pool.get_current_sell_rates(), (U256::ZERO, U256::ZERO));
+    }
+
+    #[test]
+    fn test_preview_liquidity_change() {
+        let reserve_0 = U256::from(1000000u32);
+        let reserve_1 = U256::from(4000000u32);
+
+        // Adding at the pool's ratio grows every reserve, and k by the square of the factor
+        let (new_reserve_0, new_reserve_1, new_k) =
+            OrderPool::preview_liquidity_change(reserve_0, reserve_1, reserve_0, reserve_1)
+                .unwrap();
+        assert_eq!(
+            (new_reserve_0, new_reserve_1),
+            (U256::from(2000000u32), U256::from(8000000u32))
+        );
+        assert_eq!(
+            new_k,
+            OrderPool::pool_invariant(reserve_0, reserve_1) * U256::from(4u32)
+        );
+
+        assert_eq!(
+            OrderPool::preview_liquidity_change(U256::ZERO, U256::ZERO, U256::ZERO, reserve_1),
+            Err(OrderError::InvalidParams("Invalid reserves"))
+        );
+        assert_eq!(
+            OrderPool::preview_liquidity_change(U256::from(1u32), U256::ZERO, U256::MAX, reserve_1),
+            Err(OrderError::Overflow("Reserve overflow"))
+        );
+
+        let mut pool = OrderPool::default();
+        for direction in [OrderDirection::SellToken0, OrderDirection::SellToken1] {
+            pool.create_long_term_order(
+                owner(),
+                direction,
+                U256::from(1000000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        }
+
+        let (before, after) = pool
+            .preview_flow_impact_after_liquidity(reserve_0, reserve_1, reserve_0, reserve_1)
+            .unwrap();
+        assert!(before.0 > U256::ZERO && before.1 > U256::ZERO);
+        assert!(after.0 < before.0);
+        assert!(after.1 < before.1);
+
+        // Adding nothing leaves the impact as it was
+        let (before, unchanged) = pool
+            .preview_flow_impact_after_liquidity(U256::ZERO, U256::ZERO, reserve_0, reserve_1)
+            .unwrap();
+        assert_eq!(before, unchanged);
+    }
+
+    #[test]
+    fn test_order_view_encodings_round_trip() {
+        let statuses = [
+            OrderStatus::Active,
+            OrderStatus::Completed,
+            OrderStatus::Cancelled,
+            OrderStatus::Underfilled,
+            OrderStatus::Scheduled,
+        ];
+        for (index, status) in statuses.into_iter().enumerate() {
+            let order = Order {
+                id: U256::from(index),
+                owner: owner(),
+                order_type: [OrderType::LongTerm, OrderType::Instant][index % 2],
+                direction: [OrderDirection::SellToken0, OrderDirection::SellToken1][index % 2],
+                status,
+                price_limit: Some(U256::from(5u32)),
+                limit_side: [LimitSide::Above, LimitSide::Below][index % 2],
+                ..Order::default()
+            };
+            let view = OrderView::from(order);
+
+            assert_eq!(OrderType::try_from(view.order_type), Ok(order.order_type));
+            assert_eq!(
+                OrderDirection::try_from(view.direction),
+                Ok(order.direction)
+            );
+            assert_eq!(OrderStatus::try_from(view.status), Ok(status));
+            assert_eq!(LimitSide::try_from(view.limit_side), Ok(order.limit_side));
+            assert_eq!(view.price_limit, U256::from(5u32));
+        }
+
+        assert!(OrderType::try_from(2u8).is_err());
+        assert!(OrderDirection::try_from(2u8).is_err());
+        assert!(OrderStatus::try_from(5u8).is_err());
+        assert!(LimitSide::try_from(2u8).is_err());
+
+        let mut pool = OrderPool::default();
+        let order_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken1,
+                U256::from(100000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        pool.pause_order(order_id, owner()).unwrap();
+
+        let views = pool.get_order_views_by_owner(owner());
+        assert_eq!(views.len(), 1);
+        let order = pool.get_order(order_id).unwrap();
+        assert_eq!(views[0], OrderView::from(order));
+        assert_eq!(views[0].direction, OrderDirection::SellToken1 as u8);
+        assert!(views[0].paused);
+        assert_eq!(views[0].price_limit, U256::ZERO);
+        assert!(pool
+            .get_order_views_by_owner(Address::repeat_byte(0x99))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_claim_proceeds_after_completion() {
+        let treasury = Address::repeat_byte(0x22);
+        let partner = Address::repeat_byte(0x33);
+
+        for split in [false, true] {
+            let mut pool = OrderPool::default();
+            let order_id = pool
+                .create_long_term_order(
+                    owner(),
+                    OrderDirection::SellToken0,
+                    U256::from(100000u32),
+                    U256::from(100u32),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap();
+            if split {
+                pool.set_order_beneficiaries(
+                    order_id,
+                    owner(),
+                    vec![
+                        (treasury, U256::from(7000u32)),
+                        (partner, U256::from(3000u32)),
+                    ],
+                )
+                .unwrap();
+            }
+
+            // Streaming to the end moves the order into the history
+            pool.execute_virtual_orders(
+                U256::from(100u32),
+                U256::from(1000000u32),
+                U256::from(1000000u32),
+            )
+            .unwrap();
+            assert!(pool.orders.is_empty());
+            let order = pool.get_order(order_id).unwrap();
+            assert_eq!(order.status, OrderStatus::Completed);
+            let proceeds = order.accumulated_out;
+            assert!(proceeds > U256::ZERO);
+
+            assert_eq!(
+                pool.claim_proceeds(order_id, Address::repeat_byte(0x99)),
+                Err(OrderError::NotOwner)
+            );
+            let payouts = pool.claim_proceeds(order_id, owner()).unwrap();
+            if split {
+                assert_eq!(payouts.len(), 2);
+                assert_eq!((payouts[0].0, payouts[1].0), (treasury, partner));
+                assert_eq!(
+                    payouts[0].1,
+                    proceeds * U256::from(7000u32) / U256::from(10000u32)
+                );
+                assert_eq!(payouts[0].1 + payouts[1].1, proceeds);
+            } else {
+                assert_eq!(payouts, vec![(owner(), proceeds)]);
+            }
+            assert_eq!(pool.get_order_beneficiaries(order_id), None);
+
+            // Nothing is paid twice
+            assert_eq!(
+                pool.claim_proceeds(order_id, owner()),
+                Err(OrderError::NotFound)
+            );
+            assert_eq!(
+                pool.get_order(order_id).unwrap().accumulated_out,
+                U256::ZERO
+            );
+        }
+    }
+
+    #[test]
+    fn test_unclaimed_proceeds_survive_history_cap() {
+        let mut pool = OrderPool::default();
+        let completed_id = pool
+            .create_long_term_order(
+                owner(),
+                OrderDirection::SellToken0,
+                U256::from(100000u32),
+                U256::from(10u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        pool.execute_virtual_orders(
+            U256::from(10u32),
+            U256::from(1000000u32),
+            U256::from(1000000u32),
+        )
+        .unwrap();
+        let proceeds = pool.get_order(completed_id).unwrap().accumulated_out;
+        assert!(proceeds > U256::ZERO);
+
+        // The history stays capped, dropping the completed order with the oldest entries
+        for _ in 0..MAX_ORDER_HISTORY {
+            let id = pool
+                .create_long_term_order(
+                    owner(),
+                    OrderDirection::SellToken0,
+                    U256::from(1000u32),
+                    U256::from(10u32),
+                    U256::from(10u32),
+                    U256::ZERO,
+                )
+                .unwrap();
+            pool.cancel_order(id, owner(), U256::from(10u32)).unwrap();
+            assert_eq!(pool.claim_proceeds(id, owner()), Err(OrderError::NotFound));
+        }
+
+        assert_eq!(pool.history.len(), MAX_ORDER_HISTORY);
+        assert_eq!(pool.get_order(completed_id), None);
+        assert_eq!(
+            pool.claim_proceeds(completed_id, owner()).unwrap(),
+            vec![(owner(), proceeds)]
+        );
+    }
+
+    #[test]
+    fn test_set_order_id_scheme_requires_no_open_orders() {
+        let mut pool = OrderPool::default();
+        assert!(pool.set_block_time(U256::ZERO).is_err());
+        pool.set_block_time(U256::from(2u32)).unwrap();
+        assert_eq!(pool.block_time_secs, U256::from(2u32));
+
+        pool.create_long_term_order(
+            owner(),
+            OrderDirection::SellToken0,
+            U256::from(1000u32),
+            U256::from(100u32),
+            U256::ZERO,
+            U256::ZERO,
+        )
+        .unwrap();
+        assert_eq!(
+            pool.set_order_id_scheme(OrderIdScheme::Hashed),
+            Err(OrderError::InvalidState("Orders open"))
+        );
+
+        let mut empty = OrderPool::default();
+        empty.set_order_id_scheme(OrderIdScheme::Hashed).unwrap();
+        assert_eq!(empty.order_id_scheme, OrderIdScheme::Hashed);
+    }
+}
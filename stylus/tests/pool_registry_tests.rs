@@ -0,0 +1,114 @@
+// Pool registry tests
+// These tests verify that token pairs keep isolated order pools
+
+#[cfg(test)]
+mod pool_registry_tests {
+    use stylus_sdk::alloy_primitives::{Address, U256};
+    use twamm_calculator::order_execution::OrderDirection;
+    use twamm_calculator::pool_registry::{pair_id, sort_tokens, PoolRegistry};
+
+    fn token(byte: u8) -> Address {
+        Address::repeat_byte(byte)
+    }
+
+    #[test]
+    fn test_pair_id_ignores_argument_order() {
+        assert_eq!(pair_id(token(1), token(2)), pair_id(token(2), token(1)));
+        assert_ne!(pair_id(token(1), token(2)), pair_id(token(1), token(3)));
+        assert_eq!(sort_tokens(token(2), token(1)), (token(1), token(2)));
+    }
+
+    #[test]
+    fn test_create_pool_errors() {
+        let mut registry = PoolRegistry::default();
+
+        assert_eq!(
+            registry.create_pool(token(1), token(1)),
+            Err(b"Identical tokens".to_vec())
+        );
+        assert_eq!(
+            registry.create_pool(Address::ZERO, token(1)),
+            Err(b"Invalid token".to_vec())
+        );
+
+        let id = registry.create_pool(token(2), token(1)).unwrap();
+        let pool = registry.get_pool(id).unwrap();
+        assert_eq!((pool.token_0, pool.token_1), (token(1), token(2)));
+
+        assert_eq!(
+            registry.create_pool(token(1), token(2)),
+            Err(b"Pool exists".to_vec())
+        );
+        assert!(registry
+            .order_pool_mut(pair_id(token(1), token(3)))
+            .is_err());
+    }
+
+    #[test]
+    fn test_pairs_execute_independently() {
+        let mut registry = PoolRegistry::default();
+        let pair_a = registry.create_pool(token(1), token(2)).unwrap();
+        let pair_b = registry.create_pool(token(3), token(4)).unwrap();
+        let owner = token(9);
+        let reserve = U256::from(1_000_000_000u64);
+
+        let order_a = registry
+            .order_pool_mut(pair_a)
+            .unwrap()
+            .create_long_term_order(
+                owner,
+                OrderDirection::SellToken0,
+                U256::from(1_000_000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+        let order_b = registry
+            .order_pool_mut(pair_b)
+            .unwrap()
+            .create_long_term_order(
+                owner,
+                OrderDirection::SellToken1,
+                U256::from(2_000_000u32),
+                U256::from(100u32),
+                U256::ZERO,
+                U256::ZERO,
+            )
+            .unwrap();
+
+        // Ids are allocated per pair
+        assert_eq!(order_a, order_b);
+        let before_b = registry.get_pool(pair_b).unwrap().order_pool.clone();
+
+        let result = registry
+            .order_pool_mut(pair_a)
+            .unwrap()
+            .execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        assert!(result.amount_0_sold > U256::ZERO);
+        assert_eq!(result.amount_1_sold, U256::ZERO);
+
+        // Executing pair A leaves pair B untouched
+        assert_eq!(registry.get_pool(pair_b).unwrap().order_pool, before_b);
+        let pool_a = &registry.get_pool(pair_a).unwrap().order_pool;
+        assert!(pool_a.get_order(order_a).unwrap().accumulated_out > U256::ZERO);
+
+        // Pair B then executes as if pair A did not exist
+        let result = registry
+            .order_pool_mut(pair_b)
+            .unwrap()
+            .execute_virtual_orders(U256::from(50u32), reserve, reserve)
+            .unwrap();
+        assert_eq!(result.amount_0_sold, U256::ZERO);
+        assert_eq!(result.amount_1_sold, U256::from(1_000_000u32));
+        assert_eq!(
+            registry
+                .get_pool(pair_b)
+                .unwrap()
+                .order_pool
+                .total_sell_rate_1,
+            U256::from(20_000u32)
+        );
+    }
+}
@@ -0,0 +1,140 @@
+// Order pool snapshot tests
+// These tests round-trip populated pools through to_bytes and from_bytes
+
+#[cfg(test)]
+mod snapshot_tests {
+    use stylus_sdk::alloy_primitives::{Address, U256};
+    use twamm_calculator::order_execution::{LimitSide, OrderDirection, OrderPool};
+    use twamm_calculator::snapshot::SNAPSHOT_VERSION;
+
+    fn owner() -> Address {
+        Address::repeat_byte(0x11)
+    }
+
+    /// A pool exercising every serialized field: open and finished orders,
+    /// beneficiaries, a price limit, fees and non-default configuration
+    fn populated_pool() -> OrderPool {
+        let mut pool = OrderPool::default();
+        pool.set_fee_bps(U256::from(30u32)).unwrap();
+        pool.set_min_reserve(U256::from(1000u32));
+        pool.set_keeper_reward(U256::from(5u32), U256::from(1u32))
+            .unwrap();
+        pool.set_max_total_sell_rate(OrderDirection::SellToken1, U256::from(1000000u32));
+        pool.set_saturating_execution(true);
+        pool.set_max_blocks_per_execution(U256::from(500u32));
+        pool.set_ramp_blocks(U256::from(20u32));
+
+        let mut order_ids = Vec::new();
+        for (direction, amount, duration) in [
+            (OrderDirection::SellToken0, 1000003u32, 100u32),
+            (OrderDirection::SellToken1, 2000000, 300),
+            (OrderDirection::SellToken0, 500000, 50),
+            (OrderDirection::SellToken1, 70000, 20),
+        ] {
+            order_ids.push(
+                pool.create_long_term_order(
+                    owner(),
+                    direction,
+                    U256::from(amount),
+                    U256::from(duration),
+                    U256::ZERO,
+                    U256::ZERO,
+                )
+                .unwrap(),
+            );
+        }
+
+        pool.set_order_price_limit(
+            order_ids[1],
+            owner(),
+            Some(U256::from(10u128.pow(17))),
+            LimitSide::Above,
+        )
+        .unwrap();
+        pool.set_order_target_price(order_ids[0], owner(), Some(U256::from(10u128.pow(18))))
+            .unwrap();
+        pool.set_order_beneficiaries(
+            order_ids[0],
+            owner(),
+            vec![
+                (Address::repeat_byte(0x22), U256::from(4000u32)),
+                (Address::repeat_byte(0x33), U256::from(6000u32)),
+            ],
+        )
+        .unwrap();
+
+        let reserve = U256::from(1000000000u64);
+        pool.execute_virtual_orders(U256::from(30u32), reserve, reserve)
+            .unwrap();
+        pool.cancel_order(order_ids[2], owner(), U256::from(30u32))
+            .unwrap();
+        pool
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut pool = populated_pool();
+        let bytes = pool.to_bytes();
+        assert_eq!(bytes[0], SNAPSHOT_VERSION);
+
+        let mut restored = OrderPool::from_bytes(&bytes).unwrap();
+        assert_eq!(restored, pool);
+        assert_eq!(restored.to_bytes(), bytes);
+        assert!(!restored.history.is_empty());
+
+        // Both copies keep executing identically
+        let reserve_0 = U256::from(1000500000u64);
+        let reserve_1 = U256::from(999800000u64);
+        for block in [60u32, 150, 400] {
+            let expected = pool
+                .execute_virtual_orders(U256::from(block), reserve_0, reserve_1)
+                .unwrap();
+            let actual = restored
+                .execute_virtual_orders(U256::from(block), reserve_0, reserve_1)
+                .unwrap();
+            assert_eq!(actual, expected);
+        }
+        assert_eq!(restored, pool);
+    }
+
+    #[test]
+    fn test_version_1_snapshot_rebuilds_unclaimed_proceeds() {
+        let pool = populated_pool();
+        assert_eq!(pool.unclaimed_proceeds.len(), 1);
+
+        // Version 1 ended before the unclaimed proceeds list
+        let mut bytes = pool.to_bytes();
+        bytes.truncate(bytes.len() - 8 - 84 * pool.unclaimed_proceeds.len());
+        bytes[0] = 1;
+
+        assert_eq!(OrderPool::from_bytes(&bytes).unwrap(), pool);
+    }
+
+    #[test]
+    fn test_snapshot_rejects_malformed_input() {
+        let bytes = populated_pool().to_bytes();
+
+        assert_eq!(
+            OrderPool::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(b"Snapshot truncated".to_vec())
+        );
+        assert_eq!(
+            OrderPool::from_bytes(&[]),
+            Err(b"Snapshot truncated".to_vec())
+        );
+
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert_eq!(
+            OrderPool::from_bytes(&extended),
+            Err(b"Trailing snapshot bytes".to_vec())
+        );
+
+        let mut future = bytes;
+        future[0] = SNAPSHOT_VERSION + 1;
+        assert_eq!(
+            OrderPool::from_bytes(&future),
+            Err(b"Unsupported snapshot version".to_vec())
+        );
+    }
+}
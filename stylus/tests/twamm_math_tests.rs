@@ -0,0 +1,1307 @@
+// TWAMM math tests
+// These tests verify the numeric helpers and closed-form formulas in twamm_math
+
+#[cfg(test)]
+mod twamm_math_tests {
+    use stylus_sdk::alloy_primitives::U256;
+    use twamm_calculator::order_execution::OrderDirection;
+    use twamm_calculator::twamm_math::{
+        DecayKind, MathError, MathFailure, PriceOracle, Scaled, SignedFixed, TWAMMath,
+        TwapAccumulator, Unscaled, DEFAULT_MAX_RATE_BPS_PER_BLOCK, DEFAULT_MIN_TWAP_WINDOW_BLOCKS,
+        EXP_TAYLOR_MAX_TERMS, LN_NEWTON_MAX_ITERATIONS, MAX_TRAJECTORY_SAMPLES, OP_BIDIRECTIONAL,
+        OP_TOTAL_SELL_0, OP_TOTAL_SELL_1, OP_UNIDIRECTIONAL_0_TO_1, OP_UNIDIRECTIONAL_1_TO_0,
+        POWER_GUARD_DIGITS,
+    };
+
+    #[test]
+    fn test_mul_div_wide_intermediate() {
+        // (2^200 * 2^100) / 2^150 == 2^150 although the product needs 300 bits
+        let a = U256::from(1u32) << 200usize;
+        let b = U256::from(1u32) << 100usize;
+        let c = U256::from(1u32) << 150usize;
+        assert!(a.checked_mul(b).is_none());
+        assert_eq!(TWAMMath::mul_div(a, b, c), Ok(U256::from(1u32) << 150usize));
+
+        // MAX * MAX / MAX and a floored non-power-of-two case
+        assert_eq!(
+            TWAMMath::mul_div(U256::MAX, U256::MAX, U256::MAX),
+            Ok(U256::MAX)
+        );
+        assert_eq!(
+            TWAMMath::mul_div(U256::MAX, U256::from(3u32), U256::from(4u32)),
+            Ok(U256::MAX / U256::from(4u32) * U256::from(3u32) + U256::from(2u32))
+        );
+
+        // Small products take the same path as the naive version
+        assert_eq!(
+            TWAMMath::mul_div(U256::from(7u32), U256::from(5u32), U256::from(2u32)),
+            Ok(U256::from(17u32))
+        );
+
+        assert_eq!(
+            TWAMMath::mul_div(U256::MAX, U256::from(2u32), U256::from(1u32)),
+            Err(MathError::Overflow)
+        );
+        assert_eq!(
+            TWAMMath::mul_div(U256::from(1u32), U256::from(1u32), U256::ZERO),
+            Err(MathError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn test_mul_div_rounding_up() {
+        let seven = U256::from(7u32);
+        assert_eq!(
+            TWAMMath::mul_div_rounding_up(seven, U256::from(5u32), U256::from(2u32)),
+            Ok(U256::from(18u32))
+        );
+        // Exact quotients are not bumped
+        assert_eq!(
+            TWAMMath::mul_div_rounding_up(seven, U256::from(4u32), U256::from(2u32)),
+            Ok(U256::from(14u32))
+        );
+
+        // Remainder detection also works past 256 bits: this quotient is exact...
+        let a = U256::from(1u32) << 200usize;
+        let b = (U256::from(1u32) << 100usize) + U256::from(1u32);
+        let c = U256::from(1u32) << 150usize;
+        assert_eq!(
+            TWAMMath::mul_div_rounding_up(a, b, c),
+            Ok((U256::from(1u32) << 150usize) + (U256::from(1u32) << 50usize))
+        );
+        // ...and this one is not
+        assert_eq!(
+            TWAMMath::mul_div_rounding_up(a, b, c - U256::from(1u32)).unwrap(),
+            TWAMMath::mul_div(a, b, c - U256::from(1u32)).unwrap() + U256::from(1u32)
+        );
+
+        assert_eq!(
+            TWAMMath::mul_div_rounding_up(U256::MAX, U256::from(2u32), U256::from(2u32)),
+            Ok(U256::MAX)
+        );
+        assert_eq!(
+            TWAMMath::mul_div_rounding_up(U256::MAX, U256::from(3u32), U256::from(2u32)),
+            Err(MathError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_compound_interest_large_principal() {
+        let one = U256::from(10u128.pow(18));
+        let principal = U256::MAX / U256::from(4u32);
+
+        // principal * multiplier overflows, but principal * 1.5 fits
+        let rate = SignedFixed::new(one / U256::from(2u32), false, 18);
+        assert_eq!(
+            TWAMMath::compound_interest(principal, rate, U256::from(1u32), 18),
+            Ok(principal + principal / U256::from(2u32))
+        );
+    }
+
+    #[test]
+    fn test_sqrt_floor() {
+        assert_eq!(TWAMMath::sqrt(U256::ZERO), U256::ZERO);
+        assert_eq!(TWAMMath::sqrt(U256::from(1u32)), U256::from(1u32));
+
+        // Perfect squares and their neighbours
+        for root in [2u64, 3, 10, 1_000_003, u32::MAX as u64] {
+            let root = U256::from(root);
+            let square = root * root;
+            assert_eq!(TWAMMath::sqrt(square), root);
+            assert_eq!(
+                TWAMMath::sqrt(square - U256::from(1u32)),
+                root - U256::from(1u32)
+            );
+            assert_eq!(TWAMMath::sqrt(square + U256::from(1u32)), root);
+        }
+
+        // Non-squares floor
+        assert_eq!(TWAMMath::sqrt(U256::from(2u32)), U256::from(1u32));
+        assert_eq!(TWAMMath::sqrt(U256::from(8u32)), U256::from(2u32));
+        assert_eq!(TWAMMath::sqrt(U256::from(99u32)), U256::from(9u32));
+
+        // floor(sqrt(2^256 - 1)) == 2^128 - 1
+        assert_eq!(TWAMMath::sqrt(U256::MAX), U256::from(u128::MAX));
+        let big = U256::from(u128::MAX) * U256::from(u128::MAX);
+        assert_eq!(TWAMMath::sqrt(big), U256::from(u128::MAX));
+    }
+
+    #[test]
+    fn test_sqrt_product_small_values() {
+        assert_eq!(
+            TWAMMath::sqrt_product(U256::ZERO, U256::from(5u32)),
+            U256::ZERO
+        );
+        assert_eq!(
+            TWAMMath::sqrt_product(U256::from(4u32), U256::from(9u32)),
+            U256::from(6u32)
+        );
+        assert_eq!(
+            TWAMMath::sqrt_product(U256::from(2u32), U256::from(3u32)),
+            U256::from(2u32)
+        );
+    }
+
+    #[test]
+    fn test_sqrt_product_beyond_u256() {
+        let a = U256::from(1u32) << 200usize;
+        let b = U256::from(1u32) << 180usize;
+        assert!(a.checked_mul(b).is_none());
+        assert_eq!(TWAMMath::sqrt_product(a, b), U256::from(1u32) << 190usize);
+
+        // sqrt(MAX * MAX) == MAX
+        assert_eq!(TWAMMath::sqrt_product(U256::MAX, U256::MAX), U256::MAX);
+
+        // a * (a - 2) = (a - 1)^2 - 1, so the root rounds down to a - 2
+        let a = (U256::from(1u32) << 200usize) + U256::from(1u32);
+        let root = TWAMMath::sqrt_product(a, a - U256::from(2u32));
+        assert_eq!(root, a - U256::from(2u32));
+    }
+
+    #[test]
+    fn test_large_reserves_do_not_overflow() {
+        let reserve = U256::from(1u32) << 200usize;
+        let trade = U256::from(10u128.pow(18));
+
+        let impact = TWAMMath::calculate_price_impact(trade, reserve, reserve, 18);
+        assert!(impact.is_ok());
+
+        let (new_x, new_y) = TWAMMath::calculate_virtual_amm_state(
+            Unscaled(reserve),
+            Unscaled(reserve),
+            Unscaled(trade),
+            Unscaled(U256::ZERO),
+            U256::from(100u32),
+            18,
+        )
+        .unwrap();
+        assert_eq!(new_x, reserve + trade * U256::from(100u32));
+        assert!(new_y < reserve);
+    }
+
+    #[test]
+    fn test_twap_overflow_is_an_error() {
+        let price = U256::MAX / U256::from(4u32);
+        let prices = vec![price; 100];
+        let weights = vec![U256::from(3u32); 100];
+
+        assert_eq!(
+            TWAMMath::calculate_twap(prices, weights, 18),
+            Err("TWAP overflow")
+        );
+    }
+
+    #[test]
+    fn test_twap_accumulator_handles_large_series() {
+        let high = U256::MAX / U256::from(4u32);
+        let low = high - U256::from(1_000_000u32);
+        let mut accumulator = TwapAccumulator::new();
+        assert!(accumulator.average().is_err());
+
+        for _ in 0..100 {
+            accumulator.observe(high, U256::from(3u32)).unwrap();
+            accumulator.observe(low, U256::from(1u32)).unwrap();
+        }
+
+        // The mean is exact: high - 250000
+        assert_eq!(accumulator.average(), Ok(high - U256::from(250_000u32)));
+        assert_eq!(accumulator.total_blocks, U256::from(400u32));
+    }
+
+    #[test]
+    fn test_twap_accumulator_matches_calculate_twap() {
+        let prices = [100u32, 250, 175, 300];
+        let weights = [10u32, 5, 20, 1];
+        let mut accumulator = TwapAccumulator::new();
+
+        for (price, weight) in prices.iter().zip(weights.iter()) {
+            accumulator
+                .observe(U256::from(*price * 1000), U256::from(*weight))
+                .unwrap();
+        }
+
+        let twap = TWAMMath::calculate_twap(
+            prices.iter().map(|p| U256::from(*p * 1000)).collect(),
+            weights.iter().map(|w| U256::from(*w)).collect(),
+            18,
+        )
+        .unwrap();
+        assert_eq!(accumulator.average(), Ok(twap));
+    }
+
+    #[test]
+    fn test_price_oracle_spike_weighs_less_than_sustained_price() {
+        let one = U256::from(10u128.pow(18));
+        let reserve = U256::from(1_000_000u32);
+        let spiked = reserve * U256::from(10u32);
+
+        // 100 blocks at price 1, then a one-block spike to price 10
+        let mut spike_oracle = PriceOracle::new(U256::ZERO);
+        spike_oracle.update(reserve, reserve, U256::ZERO).unwrap();
+        let start = spike_oracle.clone();
+        spike_oracle
+            .update(reserve, spiked, U256::from(100u32))
+            .unwrap();
+        spike_oracle
+            .update(reserve, reserve, U256::from(101u32))
+            .unwrap();
+        let (spike_twap, _) = PriceOracle::twap(&start, &spike_oracle).unwrap();
+
+        // Price 10 sustained over the whole window
+        let mut sustained_oracle = PriceOracle::new(U256::ZERO);
+        sustained_oracle
+            .update(reserve, spiked, U256::ZERO)
+            .unwrap();
+        sustained_oracle
+            .update(reserve, spiked, U256::from(101u32))
+            .unwrap();
+        let (sustained_twap, _) = PriceOracle::twap(&start, &sustained_oracle).unwrap();
+
+        assert_eq!(sustained_twap, U256::from(10u32) * one);
+        assert_eq!(spike_twap, U256::from(110u32) * one / U256::from(101u32));
+        assert!(spike_twap < sustained_twap);
+    }
+
+    #[test]
+    fn test_price_oracle_spike_at_update_does_not_set_the_gap() {
+        let one = U256::from(10u128.pow(18));
+        let reserve = U256::from(1_000_000u32);
+        let spiked = reserve * U256::from(10u32);
+
+        let mut oracle = PriceOracle::new(U256::ZERO);
+        oracle.update(reserve, reserve, U256::ZERO).unwrap();
+        let start = oracle.clone();
+
+        // The pool is moved right before the first update in 1000 blocks; the gap is still
+        // credited at the price that held through it
+        oracle.update(reserve, spiked, U256::from(1000u32)).unwrap();
+        assert_eq!(PriceOracle::twap(&start, &oracle), Ok((one, one)));
+        assert_eq!(
+            (oracle.last_reserve_0, oracle.last_reserve_1),
+            (reserve, spiked)
+        );
+
+        // Moved back a block later, the spike weighs one block of the 1001
+        oracle
+            .update(reserve, reserve, U256::from(1001u32))
+            .unwrap();
+        let (price_0, _) = PriceOracle::twap(&start, &oracle).unwrap();
+        assert_eq!(price_0, U256::from(1010u32) * one / U256::from(1001u32));
+    }
+
+    #[test]
+    fn test_price_oracle_window_between_snapshots() {
+        let one = U256::from(10u128.pow(18));
+        let mut oracle = PriceOracle::new(U256::from(10u32));
+        oracle
+            .update(U256::from(100u32), U256::from(400u32), U256::from(20u32))
+            .unwrap();
+        let snapshot = oracle.clone();
+        oracle
+            .update(U256::from(400u32), U256::from(100u32), U256::from(30u32))
+            .unwrap();
+
+        // Blocks 20-30 are credited at the reserves stored at block 20
+        let (price_0, price_1) = PriceOracle::twap(&snapshot, &oracle).unwrap();
+        assert_eq!(price_0, U256::from(4u32) * one);
+        assert_eq!(price_1, one / U256::from(4u32));
+
+        // Same-block updates only replace the stored reserves and empty windows are rejected
+        let cumulative_price_0 = oracle.cumulative_price_0;
+        oracle
+            .update(U256::from(1u32), U256::from(1u32), U256::from(30u32))
+            .unwrap();
+        assert_eq!(oracle.cumulative_price_0, cumulative_price_0);
+        assert_eq!(oracle.last_reserve_0, U256::from(1u32));
+        assert!(PriceOracle::twap(&oracle, &oracle).is_err());
+    }
+
+    #[test]
+    fn test_price_oracle_consult_windows() {
+        let one = U256::from(10u128.pow(18));
+        let mut oracle = PriceOracle::new(U256::ZERO);
+
+        // Price 1 over blocks 0-10, then price 4 over blocks 10-30
+        oracle
+            .update(U256::from(100u32), U256::from(100u32), U256::ZERO)
+            .unwrap();
+        oracle
+            .observe(U256::from(100u32), U256::from(400u32), U256::from(10u32))
+            .unwrap();
+        oracle
+            .observe(U256::from(100u32), U256::from(400u32), U256::from(30u32))
+            .unwrap();
+
+        // Exact hits on stored observations
+        assert_eq!(
+            oracle.consult(U256::from(20u32)),
+            Ok(U256::from(4u32) * one)
+        );
+        assert_eq!(
+            oracle.consult(U256::from(30u32)),
+            Ok(U256::from(3u32) * one)
+        );
+
+        // Block 5 sits halfway between the observations at 0 and 10
+        assert_eq!(
+            oracle.consult(U256::from(25u32)),
+            Ok(U256::from(85u32) * one / U256::from(25u32))
+        );
+
+        assert_eq!(
+            oracle.consult(U256::from(31u32)),
+            Err(b"Window predates oldest observation".to_vec())
+        );
+        assert_eq!(
+            oracle.consult(U256::ZERO),
+            Err(b"Invalid TWAP window".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_price_oracle_observation_ring_buffer() {
+        let reserve = U256::from(1000u32);
+        let mut oracle = PriceOracle::with_observation_capacity(U256::ZERO, 3);
+
+        for block in 1u32..=5 {
+            oracle
+                .observe(reserve, reserve, U256::from(block * 10))
+                .unwrap();
+        }
+
+        // Only blocks 30, 40 and 50 remain, so windows past block 30 are rejected
+        assert_eq!(oracle.observations.len(), 3);
+        assert_eq!(
+            oracle.consult(U256::from(20u32)),
+            Ok(U256::from(10u128.pow(18)))
+        );
+        assert!(oracle.consult(U256::from(21u32)).is_err());
+
+        // Observing the same block again adds nothing
+        oracle.observe(reserve, reserve, U256::from(50u32)).unwrap();
+        assert_eq!(
+            oracle.consult(U256::from(20u32)),
+            Ok(U256::from(10u128.pow(18)))
+        );
+    }
+
+    #[test]
+    fn test_optimal_rate_unreachable_target() {
+        // Even a rate of 1 moves this tiny pool by ~9%
+        let result = TWAMMath::calculate_optimal_rate(
+            U256::from(100u32),
+            U256::from(10u32),
+            U256::from(10u32),
+            U256::from(1000000u32),
+            U256::from(1u32),
+            18,
+        );
+        assert_eq!(result, Err("target impact unreachable"));
+    }
+
+    #[test]
+    fn test_optimal_rate_low_end_boundary() {
+        // Rate 1 meets the target but rate 2 does not, so the search must stop at 1
+        let target = U256::from(1000u32);
+        let rate_2_impact = TWAMMath::calculate_price_impact(
+            U256::from(2u32),
+            U256::from(10u32),
+            U256::from(1000000u32),
+            18,
+        )
+        .unwrap();
+        assert!(TWAMMath::fixed_to_bps(rate_2_impact, 18).unwrap() > target);
+
+        let rate = TWAMMath::calculate_optimal_rate(
+            U256::from(100u32),
+            U256::from(10u32),
+            U256::from(10u32),
+            U256::from(1000000u32),
+            target,
+            18,
+        )
+        .unwrap();
+        assert_eq!(rate, U256::from(1u32));
+    }
+
+    #[test]
+    fn test_constraints_allow_selling_more_than_reserve_over_long_horizon() {
+        // 2,000,000 sold against a 1,000,000 reserve at 20 per block over 100,000 blocks
+        let result = TWAMMath::validate_twamm_constraints(
+            U256::from(1000000u32),
+            U256::from(1000000u32),
+            U256::from(20u32),
+            U256::ZERO,
+            U256::from(100000u32),
+            U256::from(DEFAULT_MAX_RATE_BPS_PER_BLOCK),
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_constraints_rate_cap_is_configurable() {
+        let reserve = U256::from(1000000u32);
+
+        // 0.5% per block exceeds the default 0.1% cap but fits under a 1% cap
+        let rate = U256::from(5000u32);
+        let strict = TWAMMath::validate_twamm_constraints(
+            reserve,
+            reserve,
+            rate,
+            U256::ZERO,
+            U256::from(100u32),
+            U256::from(DEFAULT_MAX_RATE_BPS_PER_BLOCK),
+        );
+        assert_eq!(strict, Err("Sell rate too high"));
+
+        let relaxed = TWAMMath::validate_twamm_constraints(
+            reserve,
+            reserve,
+            rate,
+            U256::ZERO,
+            U256::from(100u32),
+            U256::from(100u32),
+        );
+        assert!(relaxed.is_ok());
+    }
+
+    #[test]
+    fn test_power_small_exponent() {
+        let one = U256::from(10u128.pow(18));
+        let result = TWAMMath::power(U256::from(3u32) * one, U256::from(5u32), 18).unwrap();
+        assert_eq!(result, U256::from(243u32) * one);
+    }
+
+    #[test]
+    fn test_power_large_result_near_max() {
+        // 2^196 * 10^18 is above U256::MAX / 2 but still fits
+        let one = U256::from(10u128.pow(18));
+        let result = TWAMMath::power(U256::from(2u32) * one, U256::from(196u32), 18).unwrap();
+        assert_eq!(result, (U256::from(1u32) << 196usize) * one);
+        assert!(result > U256::MAX / U256::from(2u32));
+
+        assert_eq!(
+            TWAMMath::power(U256::from(2u32) * one, U256::from(197u32), 18),
+            Err("Power calculation overflow")
+        );
+    }
+
+    fn decay_at(elapsed: u32, kind: DecayKind) -> U256 {
+        TWAMMath::calculate_time_decay_factor(U256::from(elapsed), U256::from(100u32), kind, 18)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_decay_profiles_at_checkpoints() {
+        let one = U256::from(10u128.pow(18));
+
+        for kind in [
+            DecayKind::Linear,
+            DecayKind::Exponential,
+            DecayKind::Quadratic,
+        ] {
+            assert_eq!(decay_at(0, kind), U256::ZERO);
+            assert_eq!(decay_at(100, kind), one);
+            assert_eq!(decay_at(150, kind), one);
+        }
+
+        assert_eq!(decay_at(50, DecayKind::Linear), one / U256::from(2u32));
+        assert_eq!(decay_at(50, DecayKind::Quadratic), one / U256::from(4u32));
+
+        // (e^3 - e^1.5) / (e^3 - 1) = 0.81757...
+        let expected = U256::from(817574476193643u64) * U256::from(1000u32);
+        let exponential = decay_at(50, DecayKind::Exponential);
+        assert!(exponential.abs_diff(expected) < U256::from(10u128.pow(9)));
+    }
+
+    #[test]
+    fn test_decay_profiles_are_monotonic() {
+        for kind in [
+            DecayKind::Linear,
+            DecayKind::Exponential,
+            DecayKind::Quadratic,
+        ] {
+            let mut previous = U256::ZERO;
+            for elapsed in 1..=100 {
+                let factor = decay_at(elapsed, kind);
+                assert!(factor > previous, "{:?} at {}", kind, elapsed);
+                previous = factor;
+            }
+        }
+    }
+
+    fn closed_form_failure(rate_0: U256, rate_1: U256, reserve_0: U256) -> MathFailure {
+        TWAMMath::execute_virtual_orders_closed_form(
+            rate_0,
+            rate_1,
+            U256::from(2u32),
+            reserve_0,
+            U256::from(1000000u32),
+        )
+        .unwrap_err()
+    }
+
+    #[test]
+    fn test_closed_form_failure_op_codes() {
+        let rate = U256::from(100u32);
+        let reserve = U256::from(1000000u32);
+
+        assert_eq!(
+            closed_form_failure(U256::MAX, U256::ZERO, reserve),
+            MathFailure::new(MathError::Overflow, OP_TOTAL_SELL_0)
+        );
+        assert_eq!(
+            closed_form_failure(rate, U256::MAX, reserve),
+            MathFailure::new(MathError::Overflow, OP_TOTAL_SELL_1)
+        );
+
+        // An empty reserve fails whichever branch runs
+        assert_eq!(
+            closed_form_failure(rate, U256::ZERO, U256::ZERO),
+            MathFailure::new(MathError::DivisionByZero, OP_UNIDIRECTIONAL_0_TO_1)
+        );
+        assert_eq!(
+            closed_form_failure(U256::ZERO, rate, U256::ZERO),
+            MathFailure::new(MathError::DivisionByZero, OP_UNIDIRECTIONAL_1_TO_0)
+        );
+        assert_eq!(
+            closed_form_failure(rate, rate, U256::ZERO),
+            MathFailure::new(MathError::DivisionByZero, OP_BIDIRECTIONAL)
+        );
+
+        // The bare error is still available for existing matches
+        let error: MathError = closed_form_failure(rate, rate, U256::ZERO).into();
+        assert_eq!(error, MathError::DivisionByZero);
+    }
+
+    #[test]
+    fn test_zero_reserves_rejected_by_state_functions() {
+        let rate = U256::from(100u32);
+        let reserve = U256::from(1000000u32);
+        let state = |x: U256, y: U256, rate_x: U256, rate_y: U256| {
+            TWAMMath::calculate_virtual_amm_state(
+                Unscaled(x),
+                Unscaled(y),
+                Unscaled(rate_x),
+                Unscaled(rate_y),
+                U256::from(10u32),
+                18,
+            )
+        };
+
+        // Unidirectional in each direction, then bidirectional, with either reserve empty
+        for (rate_x, rate_y) in [(rate, U256::ZERO), (U256::ZERO, rate), (rate, rate)] {
+            assert_eq!(
+                state(U256::ZERO, reserve, rate_x, rate_y),
+                Err("Invalid reserves")
+            );
+            assert_eq!(
+                state(reserve, U256::ZERO, rate_x, rate_y),
+                Err("Invalid reserves")
+            );
+        }
+    }
+
+    fn quality(expected: u32, actual: u32, impact_bps: u32) -> Result<U256, &'static str> {
+        TWAMMath::calculate_execution_quality(
+            U256::from(expected),
+            U256::from(actual),
+            U256::from(impact_bps),
+            18,
+        )
+    }
+
+    #[test]
+    fn test_execution_quality_fills() {
+        // Favorable fills are capped at the maximum score
+        assert_eq!(quality(1000, 1500, 0), Ok(U256::from(100u32)));
+        // Neutral
+        assert_eq!(quality(1000, 1000, 0), Ok(U256::from(100u32)));
+        // Adverse amount and a 15% impact: 40 + (50 - 10)
+        assert_eq!(quality(1000, 800, 1500), Ok(U256::from(80u32)));
+    }
+
+    #[test]
+    fn test_execution_quality_zero_expected() {
+        assert_eq!(quality(0, 100, 0), Err("Zero expected amount"));
+    }
+
+    #[test]
+    fn test_reserve_trajectory_ends_at_endpoint() {
+        let reserve = U256::from(1_000_000_000u64);
+        let (rate_x, rate_y) = (U256::from(10_000u32), U256::from(4_000u32));
+        let total_blocks = U256::from(1000u32);
+
+        let trajectory =
+            TWAMMath::reserve_trajectory(reserve, reserve, rate_x, rate_y, total_blocks, 10, 18)
+                .unwrap();
+        assert_eq!(trajectory.len(), 10);
+
+        let endpoint = TWAMMath::calculate_virtual_amm_state(
+            Unscaled(reserve),
+            Unscaled(reserve),
+            Unscaled(rate_x),
+            Unscaled(rate_y),
+            total_blocks,
+            18,
+        )
+        .unwrap();
+        assert_eq!(trajectory[9], endpoint);
+
+        // Net selling of x moves the reserves monotonically
+        for pair in trajectory.windows(2) {
+            assert!(pair[1].0 >= pair[0].0);
+            assert!(pair[1].1 <= pair[0].1);
+        }
+
+        let capped = TWAMMath::reserve_trajectory(
+            reserve,
+            reserve,
+            rate_x,
+            rate_y,
+            total_blocks,
+            u32::MAX,
+            18,
+        )
+        .unwrap();
+        assert_eq!(capped.len(), MAX_TRAJECTORY_SAMPLES as usize);
+        assert_eq!(capped[capped.len() - 1], endpoint);
+
+        assert!(TWAMMath::reserve_trajectory(
+            reserve,
+            reserve,
+            rate_x,
+            rate_y,
+            total_blocks,
+            0,
+            18
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_rate_schedule_front_loads_against_depleting_reserves() {
+        let total_amount = U256::from(200_000_000u64);
+        let reserve = U256::from(1_000_000_000u64);
+
+        let schedule = TWAMMath::calculate_rate_schedule(
+            total_amount,
+            U256::from(1000u32),
+            reserve,
+            reserve,
+            5,
+            18,
+        )
+        .unwrap();
+
+        // Five segments of 200 blocks, each rate streaming its share of the total
+        assert_eq!(schedule.len(), 5);
+        let streamed = schedule
+            .iter()
+            .fold(U256::ZERO, |sum, rate| sum + *rate * U256::from(200u32));
+        assert!(streamed <= total_amount);
+        assert!(total_amount - streamed < U256::from(1000u32));
+        for pair in schedule.windows(2) {
+            assert!(pair[1] < pair[0]);
+        }
+
+        // A single segment is the flat rate
+        assert_eq!(
+            TWAMMath::calculate_rate_schedule(
+                total_amount,
+                U256::from(1000u32),
+                reserve,
+                reserve,
+                1,
+                18
+            ),
+            Ok(vec![total_amount / U256::from(1000u32)])
+        );
+
+        // 1003 blocks give the last three segments 201 blocks
+        let uneven = TWAMMath::calculate_rate_schedule(
+            total_amount,
+            U256::from(1003u32),
+            reserve,
+            reserve,
+            5,
+            18,
+        )
+        .unwrap();
+        let streamed = uneven
+            .iter()
+            .enumerate()
+            .fold(U256::ZERO, |sum, (segment, rate)| {
+                sum + *rate * U256::from(if segment >= 2 { 201u32 } else { 200u32 })
+            });
+        assert!(streamed <= total_amount);
+        assert!(total_amount - streamed < U256::from(1003u32));
+        assert!(TWAMMath::calculate_rate_schedule(
+            total_amount,
+            U256::from(4u32),
+            reserve,
+            reserve,
+            5,
+            18
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_impact_unit_conversion_round_trip() {
+        let bps = U256::from(250u32);
+
+        // 250 bps is 2.5%
+        let fixed = TWAMMath::bps_to_fixed(bps, 18).unwrap();
+        assert_eq!(fixed, U256::from(25u32) * U256::from(10u128.pow(17)));
+        assert_eq!(TWAMMath::fixed_to_bps(fixed, 18), Ok(bps));
+
+        assert_eq!(TWAMMath::bps_to_fixed(bps, 6), Ok(U256::from(2_500_000u32)));
+        assert_eq!(TWAMMath::fixed_to_bps(U256::from(2_500_000u32), 6), Ok(bps));
+    }
+
+    #[test]
+    fn test_checked_to_u64_rejects_truncation() {
+        assert_eq!(TWAMMath::checked_to_u64(U256::from(1800u32)), Some(1800));
+        assert_eq!(
+            TWAMMath::checked_to_u64(U256::from(u64::MAX)),
+            Some(u64::MAX)
+        );
+
+        // One past u64::MAX would wrap to zero if narrowed
+        assert_eq!(
+            TWAMMath::checked_to_u64(U256::from(u64::MAX) + U256::from(1u32)),
+            None
+        );
+        assert_eq!(TWAMMath::checked_to_u64(U256::MAX), None);
+    }
+
+    #[test]
+    fn test_compound_interest_growth_and_decay() {
+        let one = U256::from(10u128.pow(18));
+        let principal = U256::from(1000u32) * one;
+        let five_percent = one / U256::from(20u32);
+
+        // 1.05^2 = 1.1025 and 0.95^2 = 0.9025
+        assert_eq!(
+            TWAMMath::compound_interest(
+                principal,
+                SignedFixed::new(five_percent, false, 18),
+                U256::from(2u32),
+                18
+            ),
+            Ok(U256::from(11025u32) * one / U256::from(10u32))
+        );
+        assert_eq!(
+            TWAMMath::compound_interest(
+                principal,
+                SignedFixed::new(five_percent, true, 18),
+                U256::from(2u32),
+                18
+            ),
+            Ok(U256::from(9025u32) * one / U256::from(10u32))
+        );
+
+        // Decay beyond -100% is meaningless
+        assert!(TWAMMath::compound_interest(
+            principal,
+            SignedFixed::new(one + U256::from(1u32), true, 18),
+            U256::from(1u32),
+            18
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_compound_interest_overflow_is_error() {
+        let five_percent = SignedFixed::new(U256::from(10u128.pow(18) / 20), false, 18);
+
+        assert_eq!(
+            TWAMMath::compound_interest(U256::MAX, five_percent, U256::from(1u32), 18),
+            Err("Compound interest overflow")
+        );
+    }
+
+    #[test]
+    fn test_get_amount_out_matches_uniswap_v2() {
+        let one = 10u128.pow(18);
+        let fee_bps = U256::from(30u32);
+
+        // getInputPrice cases from the Uniswap V2 pair tests: (swap, reserve in, reserve out)
+        for (swap, reserve_in, reserve_out, expected) in [
+            (1u128, 5u128, 10u128, 1662497915624478906u128),
+            (1, 10, 5, 453305446940074565),
+            (2, 5, 10, 2851015155847869602),
+            (2, 10, 5, 831248957812239453),
+            (1, 10, 10, 906610893880149131),
+            (1, 100, 100, 987158034397061298),
+            (1, 1000, 1000, 996006981039903216),
+        ] {
+            assert_eq!(
+                TWAMMath::get_amount_out(
+                    U256::from(swap * one),
+                    U256::from(reserve_in * one),
+                    U256::from(reserve_out * one),
+                    fee_bps
+                ),
+                Ok(U256::from(expected))
+            );
+        }
+
+        let reserve = U256::from(1000u32);
+        assert_eq!(
+            TWAMMath::get_amount_out(U256::ZERO, reserve, reserve, fee_bps),
+            Err(MathError::InvalidInput)
+        );
+        assert_eq!(
+            TWAMMath::get_amount_out(U256::from(1u32), reserve, U256::ZERO, fee_bps),
+            Err(MathError::DivisionByZero)
+        );
+        assert_eq!(
+            TWAMMath::get_amount_out(U256::from(1u32), reserve, reserve, U256::from(10001u32)),
+            Err(MathError::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn test_ema_decay() {
+        let one = 10u128.pow(18);
+        let prices: Vec<U256> = [100u128, 120, 90, 150]
+            .iter()
+            .map(|price| U256::from(price * one))
+            .collect();
+        let time_weights: Vec<U256> = [10u32, 20, 5, 10].iter().map(|w| U256::from(*w)).collect();
+
+        // Without decay the EMA is the flat TWAP
+        assert_eq!(
+            TWAMMath::calculate_ema(prices.clone(), time_weights.clone(), U256::ZERO, 18),
+            TWAMMath::calculate_twap(prices.clone(), time_weights.clone(), 18)
+        );
+
+        // Moderate decay leans toward the latest price
+        let twap = TWAMMath::calculate_twap(prices.clone(), time_weights.clone(), 18).unwrap();
+        let ema =
+            TWAMMath::calculate_ema(prices.clone(), time_weights.clone(), U256::from(500u32), 18)
+                .unwrap();
+        assert!(ema > twap && ema < prices[3]);
+
+        // Heavy decay tracks the latest price; older prices are at least 10 blocks old
+        let ema = TWAMMath::calculate_ema(
+            prices.clone(),
+            time_weights.clone(),
+            U256::from(20000u32),
+            18,
+        )
+        .unwrap();
+        let latest = prices[3];
+        assert!(latest - ema < latest / U256::from(1000000u32));
+
+        assert!(TWAMMath::calculate_ema(prices, Vec::new(), U256::ZERO, 18).is_err());
+        assert!(TWAMMath::calculate_ema(Vec::new(), Vec::new(), U256::ZERO, 18).is_err());
+    }
+
+    #[test]
+    fn test_scaled_values_carry_their_precision() {
+        // Two tokens at a price of 2, scaled to 18 decimals
+        let price = Scaled(U256::from(2u128 * 10u128.pow(18)), 18);
+
+        // Reading it at another precision is caught instead of silently misscaling
+        assert_eq!(price.value_at(18), Ok(price.0));
+        assert_eq!(price.value_at(6), Err(MathError::InvalidInput));
+
+        // A scaled value must be unscaled explicitly before it can stand in for a reserve
+        let reserve = price.to_unscaled();
+        assert_eq!(reserve, Unscaled(U256::from(2u32)));
+        assert_eq!(
+            TWAMMath::calculate_virtual_amm_state(
+                reserve,
+                reserve,
+                Unscaled(U256::ZERO),
+                Unscaled(U256::ZERO),
+                U256::from(10u32),
+                18
+            ),
+            Ok((U256::from(2u32), U256::from(2u32)))
+        );
+    }
+
+    #[test]
+    fn test_power_guard_digits_track_double_precision_reference() {
+        let one = U256::from(10u128.pow(18));
+        let base = one + U256::from(10u128.pow(14)); // 1.0001
+
+        for exp in [10000u32, 100000] {
+            let exp = U256::from(exp);
+            // Reference computed at twice the precision and rounded back to 18 decimals
+            let reference =
+                (TWAMMath::power(base * one, exp, 36).unwrap() + one / U256::from(2u32)) / one;
+            let plain = TWAMMath::power(base, exp, 18).unwrap();
+            let guarded =
+                TWAMMath::power_with_guard_digits(base, exp, 18, POWER_GUARD_DIGITS).unwrap();
+
+            // Relative error below 10^-18 with guard digits, well above it without
+            assert!(guarded.abs_diff(reference) * one <= reference);
+            assert!(plain.abs_diff(reference) * one > reference * U256::from(1000u32));
+        }
+
+        // (1.0001)^10000 ≈ e^0.99995
+        assert_eq!(
+            TWAMMath::power_with_guard_digits(base, U256::from(10000u32), 18, POWER_GUARD_DIGITS),
+            Ok(U256::from(2718145926825224864u64))
+        );
+    }
+
+    #[test]
+    fn test_power_guard_digits_reject_oversized_precision() {
+        assert_eq!(
+            TWAMMath::power_with_guard_digits(U256::from(2u32), U256::from(3u32), 30, 9),
+            Err("Precision too high")
+        );
+        assert_eq!(
+            TWAMMath::power_with_guard_digits(U256::from(2u32), U256::ZERO, 0, 9),
+            Ok(U256::from(1u32))
+        );
+    }
+
+    #[test]
+    fn test_marginal_impact_inverse_to_depth() {
+        let reserve = U256::from(10u64.pow(15));
+        let shallow = TWAMMath::marginal_impact(reserve, reserve, 18);
+        let deep = TWAMMath::marginal_impact(reserve * U256::from(10u32), reserve, 18);
+
+        // 100% / 10^15 per unit, a tenth of that at ten times the depth
+        assert_eq!(shallow, U256::from(100000u32));
+        assert_eq!(deep * U256::from(10u32), shallow);
+
+        // It is the slope of calculate_price_impact for small trades
+        let trade = U256::from(10u64.pow(11));
+        let impact = TWAMMath::calculate_price_impact(trade, reserve, reserve, 18).unwrap();
+        let estimate = shallow * trade;
+        assert!(estimate.abs_diff(impact) <= estimate / U256::from(1000u32));
+
+        assert_eq!(
+            TWAMMath::marginal_impact(U256::ZERO, reserve, 18),
+            U256::MAX
+        );
+    }
+
+    #[test]
+    fn test_depth_to_price_lands_on_target() {
+        let one = U256::from(10u128.pow(18));
+        let (reserve_in, reserve_out) = (U256::from(10u64.pow(12)), U256::from(4 * 10u64.pow(12)));
+
+        // From a price of 4 down to 1, 1.5, 3.99
+        for target in [
+            one,
+            one * U256::from(3u32) / U256::from(2u32),
+            one * U256::from(399u32) / U256::from(100u32),
+        ] {
+            let amount_in = TWAMMath::depth_to_price(reserve_in, reserve_out, target, 18).unwrap();
+            let new_reserve_in = reserve_in + amount_in;
+            let new_reserve_out = reserve_in * reserve_out / new_reserve_in;
+            let landed = new_reserve_out * one / new_reserve_in;
+            assert!(landed.abs_diff(target) * U256::from(10u64.pow(9)) <= target);
+        }
+
+        // Halving the price takes (sqrt(2) - 1) times the reserve
+        assert_eq!(
+            TWAMMath::depth_to_price(reserve_in, reserve_out, one * U256::from(2u32), 18),
+            Ok(U256::from(414213562373u64))
+        );
+        assert_eq!(
+            TWAMMath::depth_to_price(reserve_in, reserve_out, one * U256::from(4u32), 18),
+            Ok(U256::ZERO)
+        );
+        assert_eq!(
+            TWAMMath::depth_to_price(reserve_in, reserve_out, one * U256::from(5u32), 18),
+            Err(MathError::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn test_realized_volatility() {
+        let one = U256::from(10u128.pow(18));
+        let tenth = U256::from(10u128.pow(17));
+
+        assert_eq!(
+            TWAMMath::realized_volatility(vec![one], 18),
+            Err("Need at least two observations")
+        );
+        assert_eq!(
+            TWAMMath::realized_volatility(vec![one, U256::ZERO], 18),
+            Err("Invalid price")
+        );
+
+        // A flat series has no volatility
+        let flat = TWAMMath::realized_volatility(vec![one; 5], 18).unwrap();
+        assert_eq!(flat, U256::ZERO);
+
+        // Alternating +-ln(1.1) returns have zero mean and a deviation of ln(1.1) ~ 0.0953102
+        let oscillating =
+            TWAMMath::realized_volatility(vec![one, one + tenth, one, one + tenth, one], 18)
+                .unwrap();
+        let expected = U256::from(95310179804324860u64);
+        assert!(oscillating > flat);
+        assert!(oscillating.abs_diff(expected) < U256::from(10u64.pow(12)));
+
+        // A steady trend has constant returns, so it is calmer than the oscillation
+        let trend = TWAMMath::realized_volatility(
+            vec![one, one + tenth, one + tenth * U256::from(2u32)],
+            18,
+        )
+        .unwrap();
+        assert!(trend < oscillating);
+    }
+
+    #[test]
+    fn test_iteration_budgets() {
+        let one = U256::from(10u128.pow(18));
+        let e = U256::from(2718281828459045235u64);
+        let ln_2 = U256::from(693147180559945309u64);
+
+        // Typical inputs converge well under the default budgets
+        let exp =
+            TWAMMath::exp_taylor_with_budget(one / U256::from(10u32), 18, EXP_TAYLOR_MAX_TERMS)
+                .unwrap();
+        assert!(exp.converged);
+        assert!(exp.iterations <= 12);
+        assert_eq!(
+            exp.value,
+            TWAMMath::exp_taylor(one / U256::from(10u32), 18).unwrap()
+        );
+
+        let ln =
+            TWAMMath::ln_newton_with_budget(one * U256::from(2u32), 18, LN_NEWTON_MAX_ITERATIONS)
+                .unwrap();
+        assert!(ln.converged);
+        assert!(ln.iterations < 10);
+        assert!(ln.value.abs_diff(ln_2) < U256::from(10u64.pow(12)));
+        assert_eq!(
+            ln.value,
+            TWAMMath::ln_newton(one * U256::from(2u32), 18).unwrap()
+        );
+
+        // A tiny budget stops early with a bounded error: 1 + 1 + 1/2 falls short of e by
+        // less than the next term's e/3!
+        let exp = TWAMMath::exp_taylor_with_budget(one, 18, 2).unwrap();
+        assert!(!exp.converged);
+        assert_eq!(exp.iterations, 2);
+        assert_eq!(exp.value, one * U256::from(5u32) / U256::from(2u32));
+        assert!(e - exp.value < e / U256::from(6u32));
+
+        let ln = TWAMMath::ln_newton_with_budget(one * U256::from(2u32), 18, 1).unwrap();
+        assert!(!ln.converged);
+        assert_eq!(ln.iterations, 1);
+        assert!(ln.value.abs_diff(ln_2) < one / U256::from(100u32));
+    }
+
+    #[test]
+    fn test_bidirectional_state_with_large_reserves() {
+        // Reserves around 10^30 at precision 18 push the price and value products past 2^256
+        // unless they go through mul_div
+        let reserve = U256::from(10u128.pow(30));
+        let rate = U256::from(10u128.pow(24));
+        let (final_x, final_y) = TWAMMath::calculate_virtual_amm_state(
+            Unscaled(reserve),
+            Unscaled(reserve * U256::from(2u32)),
+            Unscaled(rate * U256::from(3u32)),
+            Unscaled(rate),
+            U256::from(1000u32),
+            18,
+        )
+        .unwrap();
+
+        // Token0 is sold harder in value terms, so its reserve grows and token1's shrinks
+        assert!(final_x > reserve);
+        assert!(final_y < reserve * U256::from(2u32));
+        let k = reserve * reserve * U256::from(2u32);
+        let k_after = final_x * final_y;
+        assert!(k_after.abs_diff(k) <= final_x.max(final_y));
+    }
+
+    #[test]
+    fn test_price_oracle_consult_min_window() {
+        let one = U256::from(10u128.pow(18));
+        let reserve = U256::from(1000u32);
+        let mut oracle = PriceOracle::new(U256::ZERO);
+        for block in 1u32..=3 {
+            oracle
+                .observe(reserve, reserve, U256::from(block * 5))
+                .unwrap();
+        }
+        assert_eq!(
+            oracle.min_window_blocks,
+            U256::from(DEFAULT_MIN_TWAP_WINDOW_BLOCKS)
+        );
+
+        // A 5 block window is available but shorter than the default minimum of 10
+        assert_eq!(
+            oracle.consult(U256::from(5u32)),
+            Err(b"window too short".to_vec())
+        );
+        assert_eq!(oracle.consult(U256::from(10u32)), Ok(one));
+
+        oracle.set_min_window_blocks(U256::from(15u32));
+        assert_eq!(
+            oracle.consult(U256::from(10u32)),
+            Err(b"window too short".to_vec())
+        );
+        assert_eq!(oracle.consult(U256::from(15u32)), Ok(one));
+    }
+
+    #[test]
+    fn test_price_impact_directional_matches_swapped_reserves() {
+        let trade = U256::from(10_000u32);
+        let reserve_0 = U256::from(1_000_000u32);
+        let reserve_1 = U256::from(4_000_000u32);
+
+        let sell_0 = TWAMMath::calculate_price_impact_directional(
+            trade,
+            reserve_0,
+            reserve_1,
+            OrderDirection::SellToken0,
+            18,
+        )
+        .unwrap();
+        let sell_1 = TWAMMath::calculate_price_impact_directional(
+            trade,
+            reserve_0,
+            reserve_1,
+            OrderDirection::SellToken1,
+            18,
+        )
+        .unwrap();
+
+        assert_eq!(
+            sell_0,
+            TWAMMath::calculate_price_impact(trade, reserve_0, reserve_1, 18).unwrap()
+        );
+        assert_eq!(
+            sell_1,
+            TWAMMath::calculate_price_impact(trade, reserve_1, reserve_0, 18).unwrap()
+        );
+        // The same trade moves the shallower token 0 side further
+        assert!(sell_0 > sell_1);
+
+        assert_eq!(
+            TWAMMath::calculate_price_impact_directional(
+                trade,
+                U256::ZERO,
+                reserve_1,
+                OrderDirection::SellToken1,
+                18,
+            ),
+            Err("Invalid reserves")
+        );
+    }
+
+    #[test]
+    fn test_unidirectional_state_overflow() {
+        let reserve = U256::from(1000000u32);
+
+        // rate * blocks does not fit in 256 bits
+        assert_eq!(
+            TWAMMath::calculate_virtual_amm_state(
+                Unscaled(reserve),
+                Unscaled(reserve),
+                Unscaled(U256::MAX / U256::from(2u32)),
+                Unscaled(U256::ZERO),
+                U256::from(3u32),
+                18,
+            ),
+            Err("Reserve calculation overflow")
+        );
+
+        // The total fits but the new input reserve does not
+        assert_eq!(
+            TWAMMath::calculate_unidirectional_twamm(U256::MAX, U256::from(1u32), reserve, reserve),
+            Err(MathError::ComputationFailed)
+        );
+        assert_eq!(
+            TWAMMath::calculate_virtual_amm_state(
+                Unscaled(reserve),
+                Unscaled(reserve),
+                Unscaled(U256::ZERO),
+                Unscaled(U256::MAX),
+                U256::from(1u32),
+                18,
+            ),
+            Err("Reserve calculation overflow")
+        );
+    }
+
+    #[test]
+    fn test_one_sided_flow_is_exact_swap() {
+        let reserve_x = U256::from(1_000_000u32);
+        let reserve_y = U256::from(4_000_000u32);
+        let blocks = U256::from(100u32);
+
+        // Small and large flows, relative to sqrt(k), both end at the single swap state
+        for rate in [10u32, 1_000, 50_000] {
+            let total = U256::from(rate) * blocks;
+            let (new_x, new_y) = TWAMMath::calculate_virtual_amm_state(
+                Unscaled(reserve_x),
+                Unscaled(reserve_y),
+                Unscaled(U256::from(rate)),
+                Unscaled(U256::ZERO),
+                blocks,
+                18,
+            )
+            .unwrap();
+
+            assert_eq!(new_x, reserve_x + total);
+            assert_eq!(new_y, reserve_x * reserve_y / (reserve_x + total));
+        }
+    }
+
+    #[test]
+    fn test_one_sided_flow_continuous_with_coupled_solution() {
+        let reserve_x = U256::from(1_000_000_000u64);
+        let reserve_y = U256::from(2_000_000_000u64);
+        let blocks = U256::from(1000u32);
+        let rate_x = U256::from(2_000_000u32);
+
+        let state = |rate_y: U256| {
+            TWAMMath::calculate_virtual_amm_state(
+                Unscaled(reserve_x),
+                Unscaled(reserve_y),
+                Unscaled(rate_x),
+                Unscaled(rate_y),
+                blocks,
+                18,
+            )
+            .unwrap()
+        };
+
+        // A vanishing opposing flow must not jump away from the one-sided result
+        let (one_sided_x, one_sided_y) = state(U256::ZERO);
+        let (coupled_x, coupled_y) = state(U256::from(1u32));
+        let tolerance = reserve_x / U256::from(10_000u32);
+        assert!(one_sided_x.abs_diff(coupled_x) <= tolerance);
+        assert!(one_sided_y.abs_diff(coupled_y) <= tolerance * U256::from(2u32));
+    }
+
+    #[test]
+    fn test_twap_accumulator_small_gaps_stay_exact() {
+        // One block at a time, each new price is far less than total_blocks away from the
+        // mean, which a running mean would round away
+        let base = U256::from(10u128.pow(18));
+        let mut accumulator = TwapAccumulator::new();
+        let mut weighted_sum = U256::ZERO;
+        let mut total_blocks = U256::ZERO;
+
+        accumulator.observe(base, U256::from(10000u32)).unwrap();
+        weighted_sum += base * U256::from(10000u32);
+        total_blocks += U256::from(10000u32);
+        for step in 1u32..=5000 {
+            let price = base + U256::from(step % 7 * 10);
+            accumulator.observe(price, U256::from(1u32)).unwrap();
+            weighted_sum += price;
+            total_blocks += U256::from(1u32);
+        }
+
+        assert_eq!(accumulator.total_blocks, total_blocks);
+        assert_eq!(accumulator.average(), Ok(weighted_sum / total_blocks));
+        assert!(accumulator.average().unwrap() > base);
+    }
+}